@@ -0,0 +1,71 @@
+//! Refresh intervals that follow the system load (`--adaptive-interval`).
+
+use std::time::Duration;
+
+/// Usage (memory used or CPU busy, in percent) up to which the system counts
+/// as calm and is refreshed at the ceiling.
+pub const CALM_PERCENT: f64 = 50.0;
+/// Usage from which the system is refreshed at the floor.
+pub const BUSY_PERCENT: f64 = 90.0;
+
+/// Bounds of the refresh interval: short under pressure, long when calm.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveInterval {
+    pub floor: Duration,
+    pub ceiling: Duration,
+}
+
+impl AdaptiveInterval {
+    /// Parses `<floor>:<ceiling>` in seconds, e.g. `0.5:5`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || format!("expected <floor>:<ceiling> in seconds, e.g. 0.5:5: {value:?}");
+        let (floor, ceiling) = value.split_once(':').ok_or_else(invalid)?;
+        let seconds = |text: &str| {
+            text.trim()
+                .parse()
+                .ok()
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                .filter(|duration| !duration.is_zero())
+                .ok_or_else(invalid)
+        };
+        let (floor, ceiling) = (seconds(floor)?, seconds(ceiling)?);
+        if floor > ceiling {
+            return Err(format!(
+                "the floor of --adaptive-interval is above its ceiling: {value:?}"
+            ));
+        }
+        Ok(Self { floor, ceiling })
+    }
+
+    /// The interval after a frame with `mem_percent` of the memory used and
+    /// the CPUs `cpu_percent` busy.
+    ///
+    /// Whichever is higher decides: up to [`CALM_PERCENT`] gives the
+    /// ceiling, from [`BUSY_PERCENT`] on the floor, and in between the
+    /// interval shrinks linearly.
+    pub fn select(&self, mem_percent: f64, cpu_percent: f64) -> Duration {
+        let usage = mem_percent.max(cpu_percent);
+        let pressure = ((usage - CALM_PERCENT) / (BUSY_PERCENT - CALM_PERCENT)).clamp(0.0, 1.0);
+        self.ceiling - (self.ceiling - self.floor).mul_f64(pressure)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_follows_the_load() {
+        let adaptive = AdaptiveInterval::parse("0.5:5").unwrap();
+        let (floor, ceiling) = (Duration::from_millis(500), Duration::from_secs(5));
+        assert_eq!(adaptive.select(95.0, 3.0), floor, "memory pressure");
+        assert_eq!(adaptive.select(20.0, 100.0), floor, "busy CPUs");
+        assert_eq!(adaptive.select(10.0, 5.0), ceiling);
+        assert_eq!(adaptive.select(50.0, 0.0), ceiling);
+        assert_eq!(adaptive.select(70.0, 0.0), Duration::from_millis(2750));
+
+        assert!(AdaptiveInterval::parse("5:0.5").is_err());
+        assert!(AdaptiveInterval::parse("0:5").is_err());
+        assert!(AdaptiveInterval::parse("2").is_err());
+    }
+}
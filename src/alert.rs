@@ -0,0 +1,95 @@
+//! Threshold alerts.
+
+use std::collections::HashMap;
+
+/// Debounced threshold alert.
+///
+/// The alert fires once when the value crosses above the threshold and only
+/// re-arms after the value has dropped back to or below it, so a value that
+/// stays high does not fire on every refresh.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThresholdAlert {
+    pub threshold: f64,
+    above: bool,
+}
+
+impl ThresholdAlert {
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            threshold,
+            above: false,
+        }
+    }
+
+    /// Feeds a new value and returns `true` if this is an upward crossing.
+    pub fn update(&mut self, value: f64) -> bool {
+        let above = value > self.threshold;
+        let fired = above && !self.above;
+        self.above = above;
+        fired
+    }
+}
+
+/// Tracks for how many frames in a row each pid has been in uninterruptible
+/// sleep (`D`), for `--warn-d-state`.
+///
+/// Like [`ThresholdAlert`], a pid fires once when its streak reaches
+/// `frames` and again only after it has left the `D` state in between.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StuckAlert {
+    pub frames: u32,
+    streaks: HashMap<u32, u32>,
+}
+
+impl StuckAlert {
+    pub fn new(frames: u32) -> Self {
+        Self {
+            frames,
+            streaks: HashMap::new(),
+        }
+    }
+
+    /// Feeds the pids that are in `D` state in this frame and returns those
+    /// whose streak has just reached `frames`. Pids not listed start over.
+    pub fn update(&mut self, blocked: impl IntoIterator<Item = u32>) -> Vec<u32> {
+        let mut streaks = HashMap::new();
+        let mut fired = Vec::new();
+        for pid in blocked {
+            let streak = self.streaks.get(&pid).copied().unwrap_or(0) + 1;
+            if streak == self.frames {
+                fired.push(pid);
+            }
+            streaks.insert(pid, streak);
+        }
+        self.streaks = streaks;
+        fired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threshold_alert_fires_on_upward_crossing_only() {
+        let mut alert = ThresholdAlert::new(80.0);
+        assert!(!alert.update(50.0));
+        assert!(alert.update(90.0));
+        assert!(!alert.update(95.0));
+        assert!(!alert.update(80.0));
+        assert!(alert.update(81.0));
+    }
+
+    #[test]
+    fn test_stuck_alert_fires_at_the_threshold() {
+        let mut alert = StuckAlert::new(3);
+        assert!(alert.update([10, 20]).is_empty());
+        assert!(alert.update([10, 20]).is_empty());
+        // 20 woke up, so its streak starts over.
+        assert_eq!(alert.update([10, 30]), [10]);
+        assert!(alert.update([10, 20, 30]).is_empty());
+        assert_eq!(alert.update([20, 30]), [30]);
+        assert!(alert.update([]).is_empty());
+        assert!(alert.update([10]).is_empty());
+    }
+}
@@ -0,0 +1,61 @@
+//! Smoothed system metrics (`--aggregate-interval`).
+
+use std::collections::VecDeque;
+
+/// Mean of the last `window` samples of a metric, kept in a ring buffer.
+///
+/// Until `window` samples have been seen, the mean is over the ones there
+/// are, so the first values are shown right away rather than after a
+/// warm-up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MovingAverage {
+    window: usize,
+    samples: VecDeque<f64>,
+}
+
+impl MovingAverage {
+    /// Averages over `window` samples; a window of 0 counts as 1, i.e. no
+    /// smoothing.
+    pub fn new(window: usize) -> Self {
+        let window = window.max(1);
+        Self {
+            window,
+            samples: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// Adds `value`, dropping the oldest sample once the window is full,
+    /// and returns the new mean.
+    pub fn push(&mut self, value: f64) -> f64 {
+        if self.samples.len() == self.window {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(value);
+        self.mean()
+    }
+
+    /// The mean of the samples in the window, 0 before the first one.
+    pub fn mean(&self) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        self.samples.iter().sum::<f64>() / self.samples.len() as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_moving_average() {
+        let mut average = MovingAverage::new(3);
+        assert_eq!(average.mean(), 0.0);
+        assert_eq!(average.push(30.0), 30.0);
+        assert_eq!(average.push(60.0), 45.0);
+        assert_eq!(average.push(90.0), 60.0);
+        // 30 leaves the window.
+        assert_eq!(average.push(0.0), 50.0);
+        assert_eq!(MovingAverage::new(0).push(7.0), 7.0);
+    }
+}
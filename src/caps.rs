@@ -0,0 +1,112 @@
+//! Effective capabilities from the `CapEff:` line of `/proc/<pid>/status`
+//! (`--show-caps`).
+
+use crate::parse::byte_lines;
+
+/// Capability names by bit number, as in `linux/capability.h`.
+pub const CAPABILITY_NAMES: [&str; 41] = [
+    "cap_chown",
+    "cap_dac_override",
+    "cap_dac_read_search",
+    "cap_fowner",
+    "cap_fsetid",
+    "cap_kill",
+    "cap_setgid",
+    "cap_setuid",
+    "cap_setpcap",
+    "cap_linux_immutable",
+    "cap_net_bind_service",
+    "cap_net_broadcast",
+    "cap_net_admin",
+    "cap_net_raw",
+    "cap_ipc_lock",
+    "cap_ipc_owner",
+    "cap_sys_module",
+    "cap_sys_rawio",
+    "cap_sys_chroot",
+    "cap_sys_ptrace",
+    "cap_sys_pacct",
+    "cap_sys_admin",
+    "cap_sys_boot",
+    "cap_sys_nice",
+    "cap_sys_resource",
+    "cap_sys_time",
+    "cap_sys_tty_config",
+    "cap_mknod",
+    "cap_lease",
+    "cap_audit_write",
+    "cap_audit_control",
+    "cap_setfcap",
+    "cap_mac_override",
+    "cap_mac_admin",
+    "cap_syslog",
+    "cap_wake_alarm",
+    "cap_block_suspend",
+    "cap_audit_read",
+    "cap_perfmon",
+    "cap_bpf",
+    "cap_checkpoint_restore",
+];
+
+/// Parses the hexadecimal mask of the `CapEff:` line, e.g.
+/// `CapEff:\t0000000000003000`.
+pub fn parse_cap_eff(status: &[u8]) -> Option<u64> {
+    let line = byte_lines(status).find_map(|line| line.strip_prefix(b"CapEff:"))?;
+    u64::from_str_radix(std::str::from_utf8(line).ok()?.trim(), 16).ok()
+}
+
+/// The names of the capabilities set in `mask`, lowest bit first. Bits the
+/// table does not know yet, from kernels newer than it, are named
+/// `cap_<bit>`.
+pub fn decode_capabilities(mask: u64) -> Vec<String> {
+    (0..64)
+        .filter(|bit| mask & (1 << bit) != 0)
+        .map(|bit| match CAPABILITY_NAMES.get(bit) {
+            Some(name) => name.to_string(),
+            None => format!("cap_{bit}"),
+        })
+        .collect()
+}
+
+/// Formats `mask` for the caps column: `all` when every known capability
+/// is set, as for root, `-` for none, and the names joined by commas
+/// otherwise. A mask with most capabilities set, as root in a container
+/// has, is written as `all` followed by the missing ones, e.g.
+/// `all,-cap_sys_module`.
+pub fn format_capabilities(mask: u64) -> String {
+    let all = (1u64 << CAPABILITY_NAMES.len()) - 1;
+    let missing = decode_capabilities(!mask & all);
+    match mask {
+        0 => "-".to_string(),
+        _ if missing.is_empty() => "all".to_string(),
+        _ if missing.len() * 2 < CAPABILITY_NAMES.len() => {
+            let missing: Vec<String> = missing.iter().map(|name| format!("-{name}")).collect();
+            format!("all,{}", missing.join(","))
+        }
+        mask => decode_capabilities(mask).join(","),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_capabilities() {
+        let status = b"Name:\tnginx\nCapInh:\t0000000000000000\nCapEff:\t0000000000003400\n";
+        let mask = parse_cap_eff(status).unwrap();
+        assert_eq!(
+            decode_capabilities(mask),
+            ["cap_net_bind_service", "cap_net_admin", "cap_net_raw"]
+        );
+        assert_eq!(
+            format_capabilities(mask),
+            "cap_net_bind_service,cap_net_admin,cap_net_raw"
+        );
+        assert_eq!(format_capabilities(0x1ff_ffff_ffff), "all");
+        assert_eq!(format_capabilities(0x1ff_fffd_ffff), "all,-cap_sys_rawio");
+        assert_eq!(format_capabilities(0), "-");
+        assert_eq!(decode_capabilities(1 << 45), ["cap_45"]);
+        assert_eq!(parse_cap_eff(b"Name:\tinit\n"), None);
+    }
+}
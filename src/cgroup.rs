@@ -0,0 +1,213 @@
+//! Container memory from cgroup v2 (`--cgroup-mem`) and the processes of a
+//! cgroup (`--cgroup`).
+//!
+//! Inside a container `/proc/meminfo` describes the host. The cgroup the
+//! monitor runs in knows the memory charged to the container
+//! (`memory.current`) and its limit (`memory.max`).
+
+use std::io;
+
+use crate::{MemInfo, ProcReader};
+
+/// Where the cgroup v2 hierarchy is mounted.
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Memory use and limit of a cgroup, in kB like [`MemInfo`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CgroupMemory {
+    /// `memory.current`
+    pub current: u64,
+    /// `memory.max`, `None` when it is `max` (no limit).
+    pub max: Option<u64>,
+}
+
+impl CgroupMemory {
+    /// `host` with the total, available and free memory replaced by the
+    /// cgroup figures. Without a limit, or with one above the host RAM, the
+    /// host total stays. Buffers and cache are not known per cgroup and
+    /// count as used.
+    pub fn apply(&self, host: &MemInfo) -> MemInfo {
+        let total = self.max.map_or(host.total, |max| max.min(host.total));
+        let available = total.saturating_sub(self.current);
+        MemInfo {
+            total,
+            available,
+            free: available,
+            buffers: 0,
+            cached: 0,
+            ..*host
+        }
+    }
+}
+
+/// Reads the memory of the cgroup the current process belongs to, found in
+/// `{base}/self/cgroup`, from the hierarchy mounted at `cgroup_root`.
+pub fn read_cgroup_memory(
+    reader: &dyn ProcReader,
+    base: &str,
+    cgroup_root: &str,
+) -> io::Result<CgroupMemory> {
+    let membership = reader.read_to_string(&format!("{base}/self/cgroup"))?;
+    let path = parse_cgroup_path(&membership).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no cgroup v2 entry in {base}/self/cgroup"),
+        )
+    })?;
+    let dir = format!("{cgroup_root}{}", path.trim_end_matches('/'));
+    let read = |name: &str| {
+        let file = format!("{dir}/{name}");
+        reader
+            .read_to_string(&file)
+            .map_err(|err| io::Error::new(err.kind(), format!("{file}: {err}")))
+    };
+    let invalid = |name: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid {dir}/{name} content"),
+        )
+    };
+    let current = parse_memory_max(&read("memory.current")?)
+        .ok()
+        .flatten()
+        .ok_or_else(|| invalid("memory.current"))?;
+    let max = parse_memory_max(&read("memory.max")?).map_err(|_| invalid("memory.max"))?;
+    Ok(CgroupMemory {
+        current: current / 1024,
+        max: max.map(|bytes| bytes / 1024),
+    })
+}
+
+/// Reads the pids in `cgroup.procs` of the cgroup at `path`, either inside
+/// the hierarchy mounted at `cgroup_root` (`/system.slice/nginx.service`)
+/// or including it.
+pub fn read_cgroup_procs(
+    reader: &dyn ProcReader,
+    cgroup_root: &str,
+    path: &str,
+) -> io::Result<Vec<u32>> {
+    let dir = if path.starts_with(cgroup_root) {
+        path.trim_end_matches('/').to_string()
+    } else {
+        format!("{cgroup_root}/{}", path.trim_matches('/'))
+    };
+    let file = format!("{dir}/cgroup.procs");
+    let content = reader
+        .read_to_string(&file)
+        .map_err(|err| io::Error::new(err.kind(), format!("{file}: {err}")))?;
+    Ok(parse_cgroup_procs(&content))
+}
+
+/// Parses `cgroup.procs`, one pid per line; other lines are skipped.
+pub fn parse_cgroup_procs(content: &str) -> Vec<u32> {
+    content
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+        .collect()
+}
+
+/// The path of the cgroup v2 entry (`0::<path>`) of `/proc/<pid>/cgroup`.
+pub fn parse_cgroup_path(content: &str) -> Option<&str> {
+    content.lines().find_map(|line| line.strip_prefix("0::"))
+}
+
+/// The systemd unit owning the cgroup at `path`: its outermost `*.service`
+/// or `*.scope` component, e.g. `nginx.service` for
+/// `/system.slice/nginx.service`. Like `ps -o unit`, a process of a user's
+/// service manager belongs to `user@<uid>.service`.
+pub fn parse_systemd_unit(path: &str) -> Option<&str> {
+    path.split('/')
+        .find(|part| part.ends_with(".service") || part.ends_with(".scope"))
+}
+
+/// Reads the systemd unit of process `pid` under `base` from its
+/// `/proc/<pid>/cgroup`; `None` when it is not under a unit or the file
+/// cannot be read.
+pub fn read_process_unit(reader: &dyn ProcReader, base: &str, pid: u32) -> Option<String> {
+    let membership = reader
+        .read_to_string(&format!("{base}/{pid}/cgroup"))
+        .ok()?;
+    parse_systemd_unit(parse_cgroup_path(&membership)?).map(str::to_string)
+}
+
+/// Parses `memory.max` or `memory.current`: a byte count, or `max` for no
+/// limit, which gives `None`.
+pub fn parse_memory_max(content: &str) -> Result<Option<u64>, std::num::ParseIntError> {
+    match content.trim() {
+        "max" => Ok(None),
+        bytes => bytes.parse().map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockReader;
+
+    #[test]
+    fn test_parse_memory_max() {
+        assert_eq!(parse_memory_max("max\n"), Ok(None));
+        assert_eq!(parse_memory_max("536870912\n"), Ok(Some(536_870_912)));
+        assert!(parse_memory_max("").is_err());
+        assert!(parse_memory_max("lots").is_err());
+    }
+
+    #[test]
+    fn test_parse_cgroup_procs() {
+        assert_eq!(parse_cgroup_procs("812\n813\n\n"), [812, 813]);
+        assert_eq!(parse_cgroup_procs(""), [0u32; 0]);
+    }
+
+    #[test]
+    fn test_parse_systemd_unit() {
+        let unit = parse_systemd_unit;
+        assert_eq!(unit("/system.slice/nginx.service"), Some("nginx.service"));
+        assert_eq!(
+            unit("/user.slice/user-1000.slice/session-2.scope"),
+            Some("session-2.scope")
+        );
+        assert_eq!(
+            unit("/user.slice/user-1000.slice/user@1000.service/app.slice/foo.service"),
+            Some("user@1000.service")
+        );
+        assert_eq!(
+            unit("/system.slice/docker-4f2a.scope/init"),
+            Some("docker-4f2a.scope")
+        );
+        assert_eq!(unit("/"), None);
+        assert_eq!(unit("/init.scope.d/x"), None);
+        assert_eq!(unit("/user.slice"), None);
+
+        let mut reader = MockReader::default();
+        reader.add_file("/proc/7/cgroup", "0::/system.slice/sshd.service\n");
+        assert_eq!(
+            read_process_unit(&reader, "/proc", 7).as_deref(),
+            Some("sshd.service")
+        );
+        assert_eq!(read_process_unit(&reader, "/proc", 8), None);
+    }
+
+    #[test]
+    fn test_cgroup_memory_replaces_host_figures() {
+        let mut reader = MockReader::default();
+        reader.add_file("/proc/self/cgroup", "0::/docker/abc\n");
+        let dir = "/sys/fs/cgroup/docker/abc";
+        reader.add_file(&format!("{dir}/memory.current"), "268435456\n");
+        reader.add_file(&format!("{dir}/memory.max"), "1073741824\n");
+        let host = MemInfo {
+            total: 16 << 20,
+            available: 8 << 20,
+            swap_total: 1024,
+            ..MemInfo::default()
+        };
+
+        let cgroup = read_cgroup_memory(&reader, "/proc", CGROUP_ROOT).unwrap();
+        let meminfo = cgroup.apply(&host);
+        assert_eq!((meminfo.total, meminfo.used()), (1 << 20, 256 << 10));
+        assert_eq!(meminfo.swap_total, 1024);
+
+        reader.add_file(&format!("{dir}/memory.max"), "max\n");
+        let cgroup = read_cgroup_memory(&reader, "/proc", CGROUP_ROOT).unwrap();
+        assert_eq!(cgroup.apply(&host).total, host.total);
+    }
+}
@@ -0,0 +1,1833 @@
+//! Command line parsing.
+
+use std::time::Duration;
+
+use crate::{
+    AdaptiveInterval, ColorMode, DeltaMetric, ExcludeList, FlushPolicy, MemField, MemMode, MemUnit,
+    NameSource, RssUnit, SortField, SortKey, SortOrder, TimeFormat, TopMetric, UnitBase,
+    render::{ClearMode, ColorScheme, Column, OutputFormat, ProcColumn},
+};
+
+/// Shortest time between refreshes; a shorter `--interval` is raised to it
+/// so the loop does not spin on `/proc`.
+pub const MIN_INTERVAL: Duration = Duration::from_millis(10);
+
+pub const USAGE: &str = "\
+Usage: procinfo-rust-exercise [OPTIONS]
+
+Options:
+  --sort <keys>             Order by mem (default), peak, vmswap, cpu, time,
+                            read, write, oom, majflt, sockets, pid or name; a
+                            comma-separated list breaks ties with the later
+                            keys, and a :asc or :desc suffix overrides a key's
+                            direction (e.g. mem,name:desc)
+  --tiebreak <key>          Order processes that are equal on all --sort keys
+                            by <key>, with an optional :asc or :desc suffix
+                            (default: ascending pid)
+  --no-sort                 Keep the processes in the order /proc lists them
+                            (roughly by pid, but directory-dependent) and
+                            show the first ones instead of the largest
+  --top-io                  Shortcut for --sort write --columns
+                            pid,name,read,write (storage I/O in kB/s); with
+                            --once, implies --delay-first
+  --top-by <metric>         Keep the processes with the most mem, cpu or io
+                            (read + write rate) and order only those by --sort,
+                            e.g. --top-by cpu --sort name
+  --sort-by-delta <metric>  Order by the change of mem or cpu since the
+                            previous refresh, largest first
+  --oom-risk                Shortcut for --sort oom --columns
+                            pid,name,rss,oom,risk: who the OOM killer picks
+                            next, with a HIGH/MED/LOW flag
+  --show-cpu                Show the per-process CPU usage column
+  --show-mem                Show RSS as a share of the total memory (%MEM),
+                            colored from 10% and 25% with --color; not with
+                            --processes-only or --quiet, which skip meminfo
+  --show-time               Show the total CPU time column (TIME+, M:SS.cc)
+  --show-faults             Show minor and major page faults per second; many
+                            major faults mean the process waits on paging
+                            from disk
+  --show-sockets            Show the number of open sockets of each process
+                            (counted in /proc/<pid>/fd, so only for your own
+                            processes without root)
+  --show-wchan              Show the kernel function each process sleeps in
+                            (WCHAN), e.g. io_schedule for one waiting on disk
+  --show-max                Show the highest RSS and CPU usage seen since
+                            monitoring started (MAXRSS, MAX%CPU), to catch
+                            spikes between refreshes; useful with --watch
+  --show-unit               Show the systemd unit each process runs in, taken
+                            from its cgroup (e.g. nginx.service), - when none
+  --show-caps               Show the effective capabilities of each process
+                            (e.g. cap_net_admin), all for the full set
+  --show-swap-ratio         Show the share of each process's memory that is
+                            swapped out, VmSwap / (VmRSS + VmSwap), to spot
+                            working sets that no longer fit in RAM
+  --show-numa               Show the NUMA node most of each process's memory
+                            is on and its share (e.g. N1 87%, from
+                            /proc/<pid>/numa_maps), - when unreadable
+  --show-sched-wait         Show how long each process waited for a CPU, in
+                            ms per second (from /proc/<pid>/schedstat); high
+                            values mean CPU starvation
+  --columns <list>          Comma-separated columns to show instead of the
+                            --show-* selection: pid, name, rss, peak, tty,
+                            cpu, time, cwd, anon, file, shmem, cmd, read,
+                            write, oom, risk, minflt, majflt, tgid, task,
+                            sockets, nspid, wchan, maxrss, maxcpu, unit, mem,
+                            schedwait, swapratio, caps, numa (read, write,
+                            caps and numa only in builds with the extras
+                            feature, the default)
+  --threads                 Show a row per thread (like top -H) with its
+                            thread id, process id (TGID), whether it is the
+                            main, another or a kernel thread, and its CPU usage
+  --merge-threads           Sum each process's CPU time over its threads in
+                            /proc/<pid>/task (one extra read per thread);
+                            /proc/<pid>/stat already counts all threads on
+                            Linux, so this is for procfs copies that do not
+  --include-kernel-threads  List kernel threads such as kworker, which have
+                            no memory and RSS 0; by default they are only
+                            counted in the Tasks line
+  --show-peak               Show the peak RSS (VmHWM) column
+  --show-tty                Show the controlling terminal column
+  --ns-pid                  Show the pid as seen inside the process's PID
+                            namespace (NSPID), e.g. in a container; the host
+                            pid on kernels without namespace pids
+  --rss-detail              Split RSS into anonymous, file-backed and shared
+                            memory columns
+  --show-cwd                Show each process's working directory
+  --name-source <source>    Take process names from comm (default, cut to 15
+                            characters by the kernel), cmdline (the program
+                            of the command line) or exe (the executable);
+                            kernel threads and, with --threads, all but the
+                            main thread keep their comm
+  --classify-by-arg <n>     Add the file name of command line argument <n>
+                            (1 for the first after the program) to the name,
+                            e.g. python (train.py)
+  --max-name-width <n>      Make the name column <n> characters wide instead of
+                            20, cutting longer names with an ellipsis
+  --cmd-width <n>           Cut the cmd column to <n> characters, keeping the
+                            program itself visible
+  --no-truncate-cmd         Never cut the cmd column, not even to fit $COLUMNS
+                            with --format ascii-table; long rows wrap
+  --exclude-pids-from <file>
+                            Leave out the processes listed in <file>, one pid
+                            or exact name per line (# starts a comment)
+  --min-cpu <percent>       Leave out processes using less CPU than <percent>;
+                            needs two samples, so with --once only together
+                            with --delay-first
+  --watch <pids>            Follow the comma-separated processes instead of
+                            the top list, showing exited ones as such; exits
+                            once all have exited
+  --by-user                 Show RSS and process count summed per user
+                            instead of the process table
+  --aggregate-top <n>       With --by-user, show the <n> users with the most
+                            RSS and sum the rest into an (other) row
+  --group-summary           Follow the process table with the RSS and
+                            process count summed per name, so the largest
+                            programs show next to the largest processes
+  --top-growth              Instead of drawing frames, watch every process for
+                            --iterations refreshes and then list those whose
+                            RSS grew the most from the first to the last,
+                            and which started or exited meanwhile
+  --histogram               Show how many processes fall into each RSS range
+                            (<1MB up to >1GB), with a bar per range, instead
+                            of the process table
+  --per-user-top <k>        Show the top <k> processes of each user instead of
+                            the overall top list, grouped by user
+  --subtree-mem <pid>       Show the RSS of <pid> and all its descendants
+                            summed, with the pids counted, instead of the
+                            process table
+  --limit-by-percent <p>    Instead of the top 5, show the largest processes
+                            until their RSS adds up to <p>% of used memory,
+                            ordered by --sort
+  --mem-fields <list>       Comma-separated figures for the memory header, in
+                            order: total, free, available, used, buffers,
+                            cached, swap (default total,free,used)
+  --mem-fraction            Show the memory header as used/total with the
+                            share used, e.g. 3.2/15.6 GiB (21%)
+  --mem-detail              Show the individual memory figures, including
+                            hugepage usage
+  --explain-mem             Like --mem-detail, with a note on how MemFree and
+                            MemAvailable differ
+  --explain                 Start with a line per displayed memory figure and
+                            column on what it means (a teaching aid)
+  --cpu-detail              Show CPU time since boot per category (user,
+                            system, idle, iowait, ...)
+  --show-timing             Show how long reading the processes took in each
+                            refresh (scan: <n>ms) below the Tasks line
+  --show-cpu-share          Show below the table how much of the system-wide
+                            CPU busy % the displayed processes account for
+                            (top 5 = 78% of 85% busy), from the second refresh
+  --show-global-fds         Show the file handles open system-wide against
+                            the limit (FDs: allocated/max) below the Tasks
+                            line, highlighted from 90% of the limit
+  --format <format>         Output the table (default), the table with
+                            box-drawing borders (ascii-table), one JSON
+                            object per refresh (json), or one with the
+                            processes keyed by pid (json-map)
+  --ascii-only              Draw the ascii-table borders with +, - and |
+  --json-fields <list>      With --format json or json-map, only write these
+                            comma-separated process fields (same names as
+                            --columns), e.g. pid,name,rss
+  --json-pretty             Like --format json, indented over several lines;
+                            --format may only make it json-map
+  --oneshot-json-array      Like --format json, but write the frames of a
+                            bounded run (--iterations or --once) as a single
+                            JSON array; --format may only make it json-map
+  --field-separator <char>  Separate columns with <char> instead of padding
+                            (use '\\t' or 'tab' for a tab character)
+  --once                    Print a single snapshot and exit
+  --processes-only          Skip /proc/meminfo and the header lines, print
+                            only the process table
+  -q, --quiet               Print only the process rows: like
+                            --processes-only, but without the column header
+                            and the --batch header either
+  --memory-only             Print only the memory header (and --mem-detail),
+                            without scanning processes
+  --summary-only            Print only the system-wide figures: memory, swap,
+                            load average, CPU busy % (since boot on the first
+                            frame) and task counts
+  --iterations <n>          Exit after <n> refreshes
+  --interval <seconds>      Time between refreshes (default 1, fractions
+                            down to 0.01 allowed, shorter ones are raised
+                            to 0.01 with a warning)
+  --interval-jitter <fraction>
+                            Vary each wait randomly by up to ±<fraction> of
+                            the interval (0 to 1), keeping the average, so
+                            monitors started together do not sample in step
+  --interval-align          Sample on whole multiples of the interval in wall
+                            clock time (e.g. every second on the second)
+                            instead of drifting by the time a frame takes
+  --adaptive-interval <floor>:<ceiling>
+                            Refresh every <ceiling> seconds while memory use
+                            and CPU busy % stay below 50%, shortening down to
+                            <floor> as the higher of them nears 90%; like
+                            --interval, never shorter than 0.01
+  --aggregate-interval <n>  Show the system CPU busy and iowait % (and use
+                            them for --alert-cpu and --adaptive-interval) as
+                            the mean of the last <n> refreshes, still
+                            sampling every --interval
+  --delay-first             Sample CPU usage once before the first frame so it
+                            already shows real CPU numbers; delays startup by
+                            one interval (no effect without CPU columns,
+                            --sort cpu or --alert-cpu)
+  --max-consecutive-errors <n>
+                            Exit with code 4 after <n> failed refreshes in a
+                            row (default 10)
+  --warn-on-parse-error-count <fraction>
+                            Warn on stderr when more than <fraction> (0 to 1,
+                            default 0.1) of the processes have files that do
+                            not parse, which hints at an unexpected /proc format
+  --max-fps <n>             Draw at most <n> frames per second; frames in
+                            between are still sampled, and the last one of
+                            --iterations is always drawn
+  --no-clear                Do not clear the screen between refreshes
+  --clear-mode <mode>       Clear the screen by moving the cursor home and
+                            erasing below (home, default), with a full
+                            terminal reset (full), or not at all, separating
+                            frames with a blank line (scroll)
+  --flush <policy>          Flush the output after every write (always), after
+                            each line (line, the default on a terminal) or
+                            only when the buffer is full (never, the default
+                            otherwise)
+  --output <file>           Write the frames to <file> instead of stdout,
+                            without colors or screen clearing, separated by
+                            blank lines
+  --render-to <file>        Also write each drawn frame, without colors, to
+                            <file>, replacing the previous one
+  --refresh-on-change       Only redraw when the memory figure or the listed
+                            processes changed
+  --batch                   Log-friendly mode like top -b: implies --no-clear
+                            and prints a timestamped header every cycle
+  --time-format <format>    Timestamps as utc (YYYY-MM-DD HH:MM:SS UTC,
+                            default), epoch (seconds), millis or rfc3339; JSON
+                            frames get a \"time\" field after the schema version
+  --mem-mode <mode>         What counts as used memory in the header and for
+                            --alert-mem: available (total - MemAvailable,
+                            default) or strict (total - free - buffers - cached)
+  --cgroup-mem              Inside a container, show the memory used by and the
+                            limit of its cgroup v2 (memory.current and
+                            memory.max) instead of the host memory; without a
+                            limit the host total stays
+  --cgroup <path>           List only the processes in the cgroup.procs of
+                            the cgroup v2 at <path> (e.g.
+                            /system.slice/nginx.service), reading just those
+                            instead of all of /proc; the Tasks line counts
+                            only them
+  --mem-unit <unit>         Show memory in the header and columns in kb
+                            (default), mb or gb with fixed decimals
+  --unit-base <base>        Count memory units in 1000s with kB, MB and GB
+                            (1000) or in 1024s with KiB, MiB and GiB (1024);
+                            by default the units are 1024-based but spelled
+                            kB, MB and GB like /proc does
+  --rss-unit <unit>         Show the RSS column in kb (default) or in memory
+                            pages (pages), rounding a partial page up
+  --precision <n>           Decimals (0 to 6) of CPU usage, rates, the CPU
+                            shares of --cpu-detail, alert messages and the
+                            --batch load averages (default 1, 2 for the load)
+  --compact-numbers         Show fault rates and socket counts with SI
+                            suffixes (1.2k, 3.4M, 5.6G) instead of in full
+  -H, --human               Show memory figures and I/O rates scaled to K, M,
+                            G or T per figure (e.g. 1.5M) instead of raw
+                            integers; JSON output stays raw
+  --alert-mem <percent>     Alert when used memory rises above <percent>
+  --alert-used-kb <kB>      Alert when used memory rises above <kB>, whatever
+                            the total (e.g. a fixed budget in a container)
+  --alert-cpu <percent>     Alert when system CPU busy % rises above <percent>
+                            (with --once, exit with 1 if an alert fired)
+  --warn-d-state <frames>   Warn on stderr when a process has been in
+                            uninterruptible sleep (D, usually waiting for I/O)
+                            for <frames> refreshes in a row
+  --fail-if-empty           Exit with 1 as soon as no process is left to show
+                            after filtering (--cgroup, --exclude-pids-from,
+                            --min-cpu, ...), e.g. when a service has gone
+  --highlight <pattern>     Mark rows whose name contains <pattern> (or
+                            matches it as a glob when it has * or ?)
+  --highlight-header        Show the memory line and the column header in
+                            reverse video, to stand out on shared screens
+                            (only where colors are used, see --color)
+  --color <when>            Use colors and clear the screen: auto (only on a
+                            terminal, default), always or never
+  --color-scheme <name>     Colors of the memory line (by how full memory is)
+                            and of a Tasks line with zombies: default,
+                            solarized or mono (bold and reverse video only)
+  --demo                    Show made-up memory figures and processes instead
+                            of reading /proc, e.g. for screenshots or on
+                            systems without /proc
+  --proc-root <dir>         Read /proc from <dir> instead, e.g. a saved copy
+                            (default /proc); warns when <dir> has no meminfo
+                            or no pid directories
+  --maps <pid>              Print the RSS of <pid> broken down into heap,
+                            stack, anonymous and file-backed mappings, then exit
+  --env <pid>               Print the environment of <pid> sorted, then exit
+  --limits <pid>            Print the soft and hard resource limits of <pid>,
+                            flagging open files near the limit, then exit
+  --pgrep <pattern>         Print the pids of processes whose name matches
+                            <pattern> (substring or glob), one per line, then
+                            exit; exits with 1 when none does
+  --pgrep-full <pattern>    Like --pgrep, but match the full command line
+  --compare <a.json> <b.json>
+                            Print the memory, RSS and CPU changes between two
+                            snapshots saved with --format json or json-map
+                            (the last frame of a file with several), then
+                            exit; reads no /proc files
+  --grep <key>              With --env, only print variables whose name
+                            contains <key>
+  --strict                  Fail on the first malformed /proc/meminfo or
+                            /proc/<pid>/status, naming the file and line, or
+                            pid listed twice, instead of skipping it (for
+                            testing and CI)
+  -v, --verbose             Report processes that could not be read, and why,
+                            on stderr
+  --check-config            Validate the arguments and print the resolved
+                            options without reading /proc, then exit
+  --print-config            Print the flags in effect after merging the
+                            config file, the environment and the command
+                            line as JSON, with the config file read, then exit
+  -h, --help                Print this help
+
+Environment:
+  PROCMON_INTERVAL, PROCMON_COUNT, PROCMON_SORT, PROCMON_COLUMNS and
+  PROCMON_MEM_UNIT set defaults for --interval, --iterations, --sort,
+  --columns and --mem-unit. Further defaults are read from
+  $XDG_CONFIG_HOME/procinfo-rust-exercise/config (or the file named by
+  PROCMON_CONFIG), one `flag = value` per line, e.g. `interval = 2` or
+  `show-cpu = true`. Flags on the command line override the environment,
+  which overrides the config file, also where they ask for something else:
+  --processes-only replaces `memory-only = true`, --show-cpu replaces
+  PROCMON_COLUMNS. SIGHUP re-reads the config file and
+  applies a changed interval, iterations, sort or columns; other changes
+  need a restart. Without a config file, and with --once or --demo, SIGHUP
+  ends the process as usual.";
+
+/// Options taken from the command line.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Options {
+    pub sort: SortOrder,
+    /// `--tiebreak`: appended to `sort` once the arguments are parsed.
+    pub tiebreak: Option<SortField>,
+    /// `--no-sort`: keep the `/proc` enumeration order instead of `sort`.
+    pub no_sort: bool,
+    /// `--top-by`: picks the kept rows before `sort` orders them.
+    pub top_by: Option<TopMetric>,
+    /// `--sort-by-delta`: takes precedence over `sort`, which only breaks
+    /// ties.
+    pub sort_by_delta: Option<DeltaMetric>,
+    /// `--columns`: explicit column names, overriding the `--show-*` flags.
+    pub columns: Option<Vec<String>>,
+    pub show_peak: bool,
+    pub show_tty: bool,
+    /// `--ns-pid`: the pid inside the process's PID namespace.
+    pub ns_pid: bool,
+    pub show_cwd: bool,
+    pub by_user: bool,
+    /// `--aggregate-top`: `--by-user` rows to keep before the `(other)` row.
+    pub aggregate_top: Option<usize>,
+    /// `--group-summary`: the process table and a per-name table.
+    pub group_summary: bool,
+    /// `--top-growth`: an RSS growth report at the end of the run instead of
+    /// frames.
+    pub top_growth: bool,
+    /// `--per-user-top`: processes to keep per user instead of the overall
+    /// top rows.
+    pub per_user_top: Option<usize>,
+    /// `--histogram`: process counts per RSS range instead of the table.
+    pub histogram: bool,
+    /// `--subtree-mem`: root of the process tree to sum instead of the table.
+    pub subtree_mem: Option<u32>,
+    /// `--threads`: one row per thread instead of per process.
+    pub threads: bool,
+    /// `--merge-threads`: per-process CPU time summed over the threads.
+    pub merge_threads: bool,
+    /// `--include-kernel-threads`: keep kernel threads in the listing.
+    pub include_kernel_threads: bool,
+    /// `--exclude-pids-from`: path of the exclude list, which a
+    /// [`Monitor`](crate::Monitor) loads into `exclude` when it starts.
+    pub exclude_pids_from: Option<String>,
+    /// Processes left out of the listing.
+    pub exclude: Option<ExcludeList>,
+    /// `--min-cpu`: leave out processes below this CPU usage, in percent.
+    pub min_cpu: Option<f64>,
+    /// `--limit-by-percent`: keep the largest processes until their RSS
+    /// reaches this share of used memory, instead of a fixed row count.
+    pub limit_by_percent: Option<f64>,
+    /// `--watch`: pids to follow instead of the top processes.
+    pub watch: Option<Vec<u32>>,
+    /// `--name-source`: where the name column comes from.
+    pub name_source: NameSource,
+    /// `--classify-by-arg`: cmdline argument appended to the name.
+    pub classify_by_arg: Option<usize>,
+    /// `--max-name-width`: width of the `name` column instead of 20.
+    pub max_name_width: Option<usize>,
+    /// `--cmd-width`: maximum width of the `cmd` column.
+    pub cmd_width: Option<usize>,
+    /// `--no-truncate-cmd`: the `cmd` column is never cut.
+    pub no_truncate_cmd: bool,
+    pub rss_detail: bool,
+    pub show_cpu: bool,
+    pub top_io: bool,
+    pub oom_risk: bool,
+    pub show_time: bool,
+    pub show_faults: bool,
+    /// `--show-sockets`: the open socket count column.
+    pub show_sockets: bool,
+    /// `--show-wchan`: the kernel function each process sleeps in.
+    pub show_wchan: bool,
+    /// `--show-sched-wait`: the run-queue wait rate column.
+    pub show_sched_wait: bool,
+    /// `--show-caps`: the effective capabilities column.
+    pub show_caps: bool,
+    /// `--show-swap-ratio`: the swapped-out share column.
+    pub show_swap_ratio: bool,
+    /// `--show-numa`: the NUMA placement column.
+    pub show_numa: bool,
+    /// `--show-max`: the session maximum RSS and CPU columns.
+    pub show_max: bool,
+    /// `--show-unit`: the systemd unit column.
+    pub show_unit: bool,
+    /// `--show-mem`: the %MEM column.
+    pub show_mem: bool,
+    /// `--mem-fields`; `None` shows [`MemField::DEFAULT`].
+    pub mem_fields: Option<Vec<MemField>>,
+    /// `--mem-fraction`: the header as [`MemInfo::fraction`](crate::MemInfo::fraction).
+    pub mem_fraction: bool,
+    pub mem_detail: bool,
+    pub explain_mem: bool,
+    /// `--explain`: print what each displayed figure means before the
+    /// first frame.
+    pub explain: bool,
+    pub cpu_detail: bool,
+    /// `--show-timing`: print how long the process scan took.
+    pub show_timing: bool,
+    /// `--show-cpu-share`: print the CPU usage of the displayed rows
+    /// against the system-wide busy percent below the table.
+    pub show_cpu_share: bool,
+    /// `--show-global-fds`: print the system-wide file handle count from
+    /// `/proc/sys/fs/file-nr`.
+    pub show_global_fds: bool,
+    pub format: OutputFormat,
+    pub json_pretty: bool,
+    /// `--json-fields`: the process fields of JSON frames, replacing
+    /// [`Options::column_names`].
+    pub json_fields: Option<Vec<String>>,
+    /// `--oneshot-json-array`: all frames as one JSON array instead of one
+    /// object per line.
+    pub json_array: bool,
+    /// `--time-format`: how the `--batch` header writes the time; JSON
+    /// frames only get a `time` field when it is given.
+    pub time_format: Option<TimeFormat>,
+    pub field_separator: Option<char>,
+    /// `--ascii-only`: `+-|` instead of box-drawing borders.
+    pub ascii_only: bool,
+    pub once: bool,
+    pub processes_only: bool,
+    /// `-q`/`--quiet`: implies `processes_only` and also drops the column
+    /// and `--batch` headers.
+    pub quiet: bool,
+    pub memory_only: bool,
+    pub summary_only: bool,
+    pub iterations: Option<u64>,
+    /// `--interval`; see [`Options::interval`] for the default.
+    pub interval: Option<Duration>,
+    /// `--interval-jitter`: largest random offset of a wait, as a fraction
+    /// of the interval.
+    pub interval_jitter: Option<f64>,
+    /// `--interval-align`: wait until the next multiple of the interval
+    /// since the epoch.
+    pub interval_align: bool,
+    /// `--adaptive-interval`: bounds of a load-dependent interval, which
+    /// replaces `--interval` after the first frame.
+    pub adaptive_interval: Option<AdaptiveInterval>,
+    /// `--aggregate-interval`: samples the system CPU figures are averaged
+    /// over.
+    pub aggregate_interval: Option<usize>,
+    pub delay_first: bool,
+    /// `--max-consecutive-errors`; see [`Options::max_consecutive_errors`].
+    pub max_consecutive_errors: Option<u32>,
+    /// `--warn-on-parse-error-count`; see
+    /// [`Options::parse_error_threshold`] for the default.
+    pub warn_parse_errors: Option<f64>,
+    /// `--max-fps`: cap on drawn frames per second.
+    pub max_fps: Option<f64>,
+    pub no_clear: bool,
+    /// `--output`: file the frames are written to instead of stdout.
+    pub output: Option<String>,
+    /// `--render-to`: file that always holds the latest frame.
+    pub render_to: Option<String>,
+    pub refresh_on_change: bool,
+    pub batch: bool,
+    pub mem_mode: MemMode,
+    /// `--cgroup-mem`: take the header figures from the cgroup instead of
+    /// `/proc/meminfo`.
+    pub cgroup_mem: bool,
+    /// `--cgroup`: cgroup whose `cgroup.procs` restricts the listing.
+    pub cgroup: Option<String>,
+    /// `--precision`; see [`Options::precision`] for the default.
+    pub precision: Option<usize>,
+    /// `--compact-numbers`: SI suffixes for the count columns.
+    pub compact_numbers: bool,
+    /// `--mem-unit`, or [`MemUnit::Human`] for `-H`/`--human`.
+    pub mem_unit: MemUnit,
+    /// `--unit-base`; `None` keeps 1024-based units with the kernel's spelling.
+    pub unit_base: Option<UnitBase>,
+    pub rss_unit: RssUnit,
+    pub alert_mem: Option<f64>,
+    /// `--alert-used-kb`: an absolute threshold for used memory, in kB.
+    pub alert_used_kb: Option<u64>,
+    pub alert_cpu: Option<f64>,
+    /// `--warn-d-state`: frames in uninterruptible sleep before a warning.
+    pub warn_d_state: Option<u32>,
+    /// `--fail-if-empty`: end the run once a frame has no processes.
+    pub fail_if_empty: bool,
+    pub highlight: Option<String>,
+    /// `--highlight-header`: the header lines in reverse video.
+    pub highlight_header: bool,
+    pub color: ColorMode,
+    /// `--clear-mode`; only used when the screen is cleared at all.
+    pub clear_mode: ClearMode,
+    /// `--flush`; `None` picks [`FlushPolicy::for_terminal`].
+    pub flush: Option<FlushPolicy>,
+    pub color_scheme: ColorScheme,
+    /// `--proc-root`: where procfs is read from instead of `/proc`; see
+    /// [`Options::proc_root`].
+    pub proc_root: Option<String>,
+    /// `--demo`: monitor a synthetic procfs, see
+    /// [`DemoReader`](crate::DemoReader).
+    pub demo: bool,
+    /// `--maps <pid>`: print the smaps summary of one process instead of
+    /// running the monitor.
+    pub maps: Option<u32>,
+    /// `--env <pid>`: print the environment of one process instead of
+    /// monitoring.
+    pub env: Option<u32>,
+    /// `--limits <pid>`: print the resource limits of one process instead
+    /// of monitoring.
+    pub limits: Option<u32>,
+    /// `--pgrep <pattern>` or `--pgrep-full <pattern>`: print the matching
+    /// pids instead of monitoring.
+    pub pgrep: Option<String>,
+    /// `--pgrep-full`: match [`Options::pgrep`] against the command line.
+    pub pgrep_full: bool,
+    /// `--compare <a> <b>`: paths of two saved snapshots to diff instead of
+    /// monitoring.
+    pub compare: Option<(String, String)>,
+    /// `--grep <key>` filter for `--env`.
+    pub grep: Option<String>,
+    /// `--strict`: malformed `/proc` data ends the run with an error
+    /// instead of being skipped.
+    pub strict: bool,
+    /// `-v`/`--verbose`; the caller passes it to
+    /// [`log::set_verbose`](crate::log::set_verbose).
+    pub verbose: bool,
+    /// `--check-config`: the caller runs [`Options::check`] and prints the
+    /// options instead of monitoring.
+    pub check_config: bool,
+    /// `--print-config`: the caller prints [`Config::to_json`](crate::Config::to_json)
+    /// instead of monitoring.
+    pub print_config: bool,
+    /// `-h`/`--help` was given; the caller prints [`USAGE`].
+    pub help: bool,
+}
+
+impl Options {
+    /// Parses the arguments following the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut format_given = false;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--sort" => {
+                    let value = value(&mut args, "--sort")?;
+                    options.sort = SortOrder::parse(&value)?;
+                }
+                "--tiebreak" => {
+                    options.tiebreak = Some(SortField::parse(&value(&mut args, "--tiebreak")?)?);
+                }
+                "--no-sort" => options.no_sort = true,
+                "--top-by" => {
+                    let value = value(&mut args, "--top-by")?;
+                    options.top_by = Some(TopMetric::parse(&value)?);
+                }
+                "--sort-by-delta" => {
+                    let value = value(&mut args, "--sort-by-delta")?;
+                    options.sort_by_delta = Some(DeltaMetric::parse(&value)?);
+                }
+                "--top-io" => options.top_io = true,
+                "--oom-risk" => options.oom_risk = true,
+                "--columns" => {
+                    let value = value(&mut args, "--columns")?;
+                    options.columns = Some(parse_columns(&value)?);
+                }
+                "--show-peak" => options.show_peak = true,
+                "--show-tty" => options.show_tty = true,
+                "--ns-pid" => options.ns_pid = true,
+                "--show-cwd" => options.show_cwd = true,
+                "--by-user" => options.by_user = true,
+                "--group-summary" => options.group_summary = true,
+                "--top-growth" => options.top_growth = true,
+                "--threads" => options.threads = true,
+                "--merge-threads" => options.merge_threads = true,
+                "--include-kernel-threads" => options.include_kernel_threads = true,
+                "--limit-by-percent" => {
+                    let value = value(&mut args, "--limit-by-percent")?;
+                    options.limit_by_percent = Some(
+                        parse_percent(&value)
+                            .ok()
+                            .filter(|&percent| percent > 0.0)
+                            .ok_or_else(|| {
+                                format!("invalid --limit-by-percent value: {value:?}")
+                            })?,
+                    );
+                }
+                "--watch" => {
+                    let value = value(&mut args, "--watch")?;
+                    let pids = value
+                        .split(',')
+                        .map(|pid| pid.trim().parse())
+                        .collect::<Result<Vec<u32>, _>>()
+                        .map_err(|_| format!("invalid --watch pid list: {value:?}"))?;
+                    options.watch = Some(pids);
+                }
+                "--name-source" => {
+                    let value = value(&mut args, "--name-source")?;
+                    options.name_source = NameSource::parse(&value)?;
+                }
+                "--classify-by-arg" => {
+                    let value = value(&mut args, "--classify-by-arg")?;
+                    options.classify_by_arg =
+                        Some(value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                            format!("invalid --classify-by-arg value: {value:?}")
+                        })?);
+                }
+                "--aggregate-top" => {
+                    let value = value(&mut args, "--aggregate-top")?;
+                    options.aggregate_top = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid --aggregate-top value: {value:?}"))?,
+                    );
+                }
+                "--histogram" => options.histogram = true,
+                "--per-user-top" => {
+                    let value = value(&mut args, "--per-user-top")?;
+                    options.per_user_top =
+                        Some(
+                            value.parse().ok().filter(|&k| k > 0).ok_or_else(|| {
+                                format!("invalid --per-user-top value: {value:?}")
+                            })?,
+                        );
+                }
+                "--subtree-mem" => {
+                    let value = value(&mut args, "--subtree-mem")?;
+                    options.subtree_mem = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid --subtree-mem pid: {value:?}"))?,
+                    );
+                }
+                "--max-name-width" => {
+                    let value = value(&mut args, "--max-name-width")?;
+                    options.max_name_width =
+                        Some(
+                            value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                                format!("invalid --max-name-width value: {value:?}")
+                            })?,
+                        );
+                }
+                "--min-cpu" => {
+                    let value = value(&mut args, "--min-cpu")?;
+                    options.min_cpu = Some(parse_percent(&value)?);
+                }
+                "--exclude-pids-from" => {
+                    options.exclude_pids_from = Some(value(&mut args, "--exclude-pids-from")?);
+                }
+                "--cmd-width" => {
+                    let value = value(&mut args, "--cmd-width")?;
+                    options.cmd_width = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|&n| n > 0)
+                            .ok_or_else(|| format!("invalid --cmd-width value: {value:?}"))?,
+                    );
+                }
+                "--no-truncate-cmd" => options.no_truncate_cmd = true,
+                "--rss-detail" => options.rss_detail = true,
+                "--show-cpu" => options.show_cpu = true,
+                "--show-time" => options.show_time = true,
+                "--show-faults" => options.show_faults = true,
+                "--show-sockets" => options.show_sockets = true,
+                "--show-wchan" => options.show_wchan = true,
+                "--show-sched-wait" => options.show_sched_wait = true,
+                "--show-caps" => options.show_caps = true,
+                "--show-swap-ratio" => options.show_swap_ratio = true,
+                "--show-numa" => options.show_numa = true,
+                "--show-max" => options.show_max = true,
+                "--show-unit" => options.show_unit = true,
+                "--show-mem" => options.show_mem = true,
+                "--mem-fields" => {
+                    let value = value(&mut args, "--mem-fields")?;
+                    options.mem_fields = Some(
+                        value
+                            .split(',')
+                            .map(MemField::parse)
+                            .collect::<Result<_, _>>()?,
+                    );
+                }
+                "--mem-detail" => options.mem_detail = true,
+                "--cpu-detail" => options.cpu_detail = true,
+                "--show-timing" => options.show_timing = true,
+                "--show-cpu-share" => options.show_cpu_share = true,
+                "--show-global-fds" => options.show_global_fds = true,
+                "--explain" => options.explain = true,
+                "--explain-mem" => {
+                    options.explain_mem = true;
+                    options.mem_detail = true;
+                }
+                "--format" => {
+                    let value = value(&mut args, "--format")?;
+                    options.format = OutputFormat::parse(&value)?;
+                    format_given = true;
+                }
+                "--json-pretty" => options.json_pretty = true,
+                "--oneshot-json-array" => options.json_array = true,
+                "--json-fields" => {
+                    let value = value(&mut args, "--json-fields")?;
+                    options.json_fields = Some(parse_columns(&value)?);
+                }
+                "--ascii-only" => options.ascii_only = true,
+                "--mem-fraction" => options.mem_fraction = true,
+                "--field-separator" => {
+                    let value = value(&mut args, "--field-separator")?;
+                    options.field_separator = Some(parse_separator(&value)?);
+                }
+                "--once" => options.once = true,
+                "--processes-only" => options.processes_only = true,
+                "-q" | "--quiet" => options.quiet = true,
+                "--memory-only" => options.memory_only = true,
+                "--summary-only" => options.summary_only = true,
+                "--iterations" => {
+                    let value = value(&mut args, "--iterations")?;
+                    options.iterations = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid --iterations value: {value:?}"))?,
+                    );
+                }
+                "--interval" => {
+                    let value = value(&mut args, "--interval")?;
+                    options.interval = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                            .ok_or_else(|| format!("invalid --interval value: {value:?}"))?,
+                    );
+                }
+                "--adaptive-interval" => {
+                    let value = value(&mut args, "--adaptive-interval")?;
+                    options.adaptive_interval = Some(AdaptiveInterval::parse(&value)?);
+                }
+                "--aggregate-interval" => {
+                    let value = value(&mut args, "--aggregate-interval")?;
+                    options.aggregate_interval =
+                        Some(value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                            format!("invalid --aggregate-interval value: {value:?}")
+                        })?);
+                }
+                "--interval-align" => options.interval_align = true,
+                "--interval-jitter" => {
+                    let value = value(&mut args, "--interval-jitter")?;
+                    options.interval_jitter = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|fraction| (0.0..=1.0).contains(fraction))
+                            .ok_or_else(|| format!("invalid --interval-jitter value: {value:?}"))?,
+                    );
+                }
+                "--warn-on-parse-error-count" => {
+                    let value = value(&mut args, "--warn-on-parse-error-count")?;
+                    options.warn_parse_errors = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|fraction| (0.0..=1.0).contains(fraction))
+                            .ok_or_else(|| {
+                                format!("invalid --warn-on-parse-error-count value: {value:?}")
+                            })?,
+                    );
+                }
+                "--time-format" => {
+                    let value = value(&mut args, "--time-format")?;
+                    options.time_format = Some(TimeFormat::parse(&value)?);
+                }
+                "--delay-first" => options.delay_first = true,
+                "--max-consecutive-errors" => {
+                    let value = value(&mut args, "--max-consecutive-errors")?;
+                    options.max_consecutive_errors =
+                        Some(value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                            format!("invalid --max-consecutive-errors value: {value:?}")
+                        })?);
+                }
+                "--max-fps" => {
+                    let value = value(&mut args, "--max-fps")?;
+                    options.max_fps = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|fps: &f64| fps.is_finite() && *fps > 0.0)
+                            .ok_or_else(|| format!("invalid --max-fps value: {value:?}"))?,
+                    );
+                }
+                "--no-clear" => options.no_clear = true,
+                "--output" => options.output = Some(value(&mut args, "--output")?),
+                "--render-to" => options.render_to = Some(value(&mut args, "--render-to")?),
+                "--refresh-on-change" => options.refresh_on_change = true,
+                "--batch" => {
+                    options.batch = true;
+                    options.no_clear = true;
+                }
+                "--mem-mode" => {
+                    let value = value(&mut args, "--mem-mode")?;
+                    options.mem_mode = MemMode::parse(&value)?;
+                }
+                "--cgroup-mem" => options.cgroup_mem = true,
+                "--cgroup" => options.cgroup = Some(value(&mut args, "--cgroup")?),
+                "--rss-unit" => {
+                    let value = value(&mut args, "--rss-unit")?;
+                    options.rss_unit = RssUnit::parse(&value)?;
+                }
+                "--mem-unit" => {
+                    let value = value(&mut args, "--mem-unit")?;
+                    options.mem_unit = MemUnit::parse(&value)?;
+                }
+                "--unit-base" => {
+                    let value = value(&mut args, "--unit-base")?;
+                    options.unit_base = Some(UnitBase::parse(&value)?);
+                }
+                "--precision" => {
+                    let value = value(&mut args, "--precision")?;
+                    options.precision =
+                        Some(value.parse().ok().filter(|&n| n <= 6).ok_or_else(|| {
+                            format!("invalid --precision value: {value:?} (expected 0 to 6)")
+                        })?);
+                }
+                "-H" | "--human" => options.mem_unit = MemUnit::Human,
+                "--compact-numbers" => options.compact_numbers = true,
+                "--alert-mem" => {
+                    let value = value(&mut args, "--alert-mem")?;
+                    options.alert_mem = Some(parse_percent(&value)?);
+                }
+                "--alert-used-kb" => {
+                    let value = value(&mut args, "--alert-used-kb")?;
+                    options.alert_used_kb = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid --alert-used-kb value: {value:?}"))?,
+                    );
+                }
+                "--fail-if-empty" => options.fail_if_empty = true,
+                "--warn-d-state" => {
+                    let value = value(&mut args, "--warn-d-state")?;
+                    options.warn_d_state =
+                        Some(
+                            value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                                format!("invalid --warn-d-state value: {value:?}")
+                            })?,
+                        );
+                }
+                "--alert-cpu" => {
+                    let value = value(&mut args, "--alert-cpu")?;
+                    options.alert_cpu = Some(parse_percent(&value)?);
+                }
+                "--highlight-header" => options.highlight_header = true,
+                "--highlight" => {
+                    let value = value(&mut args, "--highlight")?;
+                    options.highlight = Some(value);
+                }
+                "--color-scheme" => {
+                    let value = value(&mut args, "--color-scheme")?;
+                    options.color_scheme = ColorScheme::parse(&value)?;
+                }
+                "--flush" => {
+                    let value = value(&mut args, "--flush")?;
+                    options.flush = Some(FlushPolicy::parse(&value)?);
+                }
+                "--clear-mode" => {
+                    let value = value(&mut args, "--clear-mode")?;
+                    options.clear_mode = ClearMode::parse(&value)?;
+                }
+                "--color" => {
+                    let value = value(&mut args, "--color")?;
+                    options.color = ColorMode::parse(&value)?;
+                }
+                "--compare" => {
+                    let a = value(&mut args, "--compare")?;
+                    let b = value(&mut args, "--compare")?;
+                    options.compare = Some((a, b));
+                }
+                "--demo" => options.demo = true,
+                "--proc-root" => options.proc_root = Some(value(&mut args, "--proc-root")?),
+                "--maps" => {
+                    let value = value(&mut args, "--maps")?;
+                    options.maps = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid --maps pid: {value:?}"))?,
+                    );
+                }
+                "--env" => {
+                    let value = value(&mut args, "--env")?;
+                    options.env = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid --env pid: {value:?}"))?,
+                    );
+                }
+                "--limits" => {
+                    let value = value(&mut args, "--limits")?;
+                    options.limits = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid --limits pid: {value:?}"))?,
+                    );
+                }
+                "--pgrep" => options.pgrep = Some(value(&mut args, "--pgrep")?),
+                "--pgrep-full" => {
+                    options.pgrep = Some(value(&mut args, "--pgrep-full")?);
+                    options.pgrep_full = true;
+                }
+                "--grep" => options.grep = Some(value(&mut args, "--grep")?),
+                "--strict" => options.strict = true,
+                "-v" | "--verbose" => options.verbose = true,
+                "--check-config" => options.check_config = true,
+                "--print-config" => options.print_config = true,
+                "-h" | "--help" => options.help = true,
+                other => return Err(format!("unknown argument: {other}")),
+            }
+        }
+        for (flag, given) in [
+            ("--json-pretty", options.json_pretty),
+            ("--oneshot-json-array", options.json_array),
+        ] {
+            if !given {
+                continue;
+            }
+            if !format_given {
+                options.format = OutputFormat::Json;
+            } else if !options.format.is_json() {
+                return Err(format!(
+                    "{flag} cannot be combined with a --format other than json or json-map"
+                ));
+            }
+        }
+        if options.json_fields.is_some() {
+            if !options.format.is_json() {
+                return Err("--json-fields requires --format json or json-map".to_string());
+            }
+            if options.columns.is_some() {
+                return Err("--json-fields cannot be combined with --columns".to_string());
+            }
+        }
+        if options.top_io {
+            options.sort = SortKey::Write.into();
+            options.columns = Some(["pid", "name", "read", "write"].map(String::from).to_vec());
+            // I/O rates need two samples; take the first one silently.
+            options.delay_first |= options.once;
+        }
+        if options.oom_risk {
+            options.sort = SortKey::Oom.into();
+            options.columns = Some(
+                ["pid", "name", "rss", "oom", "risk"]
+                    .map(String::from)
+                    .to_vec(),
+            );
+        }
+        if options.format.is_json() {
+            options.no_clear = true;
+        }
+        if options.demo
+            && (options.proc_root.is_some()
+                || options.maps.is_some()
+                || options.env.is_some()
+                || options.limits.is_some()
+                || options.pgrep.is_some()
+                || options.cgroup.is_some())
+        {
+            return Err(
+                "--demo cannot be combined with --proc-root, --maps, --env, --limits, --pgrep \
+                 or --cgroup"
+                    .to_string(),
+            );
+        }
+        if options.grep.is_some() && options.env.is_none() {
+            return Err("--grep requires --env".to_string());
+        }
+        if options.by_user && (options.memory_only || options.format.is_json()) {
+            return Err(
+                "--by-user cannot be combined with --memory-only or --format json".to_string(),
+            );
+        }
+        if options.group_summary
+            && (options.by_user
+                || options.per_user_top.is_some()
+                || options.subtree_mem.is_some()
+                || options.histogram
+                || options.watch.is_some()
+                || options.memory_only
+                || options.summary_only
+                || options.format.is_json())
+        {
+            return Err(
+                "--group-summary cannot be combined with --by-user, --per-user-top, \
+                 --subtree-mem, --histogram, --watch, --memory-only, --summary-only or \
+                 --format json"
+                    .to_string(),
+            );
+        }
+        if options.top_growth && (options.once || options.iterations.is_none()) {
+            return Err("--top-growth needs --iterations".to_string());
+        }
+        if options.top_growth
+            && (options.by_user
+                || options.per_user_top.is_some()
+                || options.subtree_mem.is_some()
+                || options.histogram
+                || options.group_summary
+                || options.watch.is_some()
+                || options.memory_only
+                || options.summary_only
+                || options.format.is_json())
+        {
+            return Err(
+                "--top-growth cannot be combined with --by-user, --per-user-top, --subtree-mem, \
+                 --histogram, --group-summary, --watch, --memory-only, --summary-only or \
+                 --format json"
+                    .to_string(),
+            );
+        }
+        if options.limit_by_percent.is_some()
+            && (options.no_sort
+                || options.top_by.is_some()
+                || options.sort_by_delta.is_some()
+                || options.per_user_top.is_some()
+                || options.watch.is_some()
+                || options.processes_only)
+        {
+            return Err(
+                "--limit-by-percent cannot be combined with --no-sort, --top-by, --sort-by-delta, \
+                 --per-user-top, --watch or --processes-only"
+                    .to_string(),
+            );
+        }
+        if options.watch.is_some()
+            && (options.by_user || options.memory_only || options.format.is_json())
+        {
+            return Err(
+                "--watch cannot be combined with --by-user, --memory-only or --format json"
+                    .to_string(),
+            );
+        }
+        if options.rss_unit == RssUnit::Pages
+            && (options.mem_unit != MemUnit::Kb || options.unit_base.is_some())
+        {
+            return Err(
+                "--rss-unit pages cannot be combined with --mem-unit, --human or --unit-base"
+                    .to_string(),
+            );
+        }
+        if options.quiet {
+            if options.memory_only
+                || options.summary_only
+                || options.by_user
+                || options.watch.is_some()
+            {
+                return Err(
+                    "--quiet cannot be combined with --memory-only, --summary-only, --by-user \
+                     or --watch"
+                        .to_string(),
+                );
+            }
+            options.processes_only = true;
+        }
+        if options.json_array && !options.once && options.iterations.is_none() {
+            return Err("--oneshot-json-array needs --iterations or --once".to_string());
+        }
+        if options.threads && (options.by_user || options.watch.is_some() || options.summary_only) {
+            return Err(
+                "--threads cannot be combined with --by-user, --watch or --summary-only"
+                    .to_string(),
+            );
+        }
+        if options.merge_threads && options.threads {
+            return Err("--merge-threads cannot be combined with --threads".to_string());
+        }
+        if options.cgroup.is_some() && (options.threads || options.watch.is_some()) {
+            return Err("--cgroup cannot be combined with --threads or --watch".to_string());
+        }
+        if options.no_sort
+            && (options.sort != SortOrder::default()
+                || options.top_by.is_some()
+                || options.sort_by_delta.is_some()
+                || options.min_cpu.is_some())
+        {
+            return Err(
+                "--no-sort cannot be combined with --sort, --top-io, --top-by, --sort-by-delta \
+                 or --min-cpu"
+                    .to_string(),
+            );
+        }
+        if options.mem_fraction && options.mem_fields.is_some() {
+            return Err("--mem-fraction cannot be combined with --mem-fields".to_string());
+        }
+        if options.ascii_only && options.format != OutputFormat::AsciiTable {
+            return Err("--ascii-only needs --format ascii-table".to_string());
+        }
+        if options.format == OutputFormat::AsciiTable && options.field_separator.is_some() {
+            return Err(
+                "--format ascii-table cannot be combined with --field-separator".to_string(),
+            );
+        }
+        if options.aggregate_top.is_some() && !options.by_user {
+            return Err("--aggregate-top needs --by-user".to_string());
+        }
+        if options.per_user_top.is_some()
+            && (options.by_user
+                || options.watch.is_some()
+                || options.memory_only
+                || options.summary_only)
+        {
+            return Err(
+                "--per-user-top cannot be combined with --by-user, --watch, --memory-only or \
+                 --summary-only"
+                    .to_string(),
+            );
+        }
+        if options.subtree_mem.is_some()
+            && (options.by_user
+                || options.per_user_top.is_some()
+                || options.watch.is_some()
+                || options.threads
+                || options.memory_only
+                || options.summary_only
+                || options.format.is_json())
+        {
+            return Err(
+                "--subtree-mem cannot be combined with --by-user, --per-user-top, --watch, \
+                 --threads, --memory-only, --summary-only or --format json"
+                    .to_string(),
+            );
+        }
+        if options.histogram
+            && (options.by_user
+                || options.per_user_top.is_some()
+                || options.subtree_mem.is_some()
+                || options.watch.is_some()
+                || options.memory_only
+                || options.summary_only
+                || options.format.is_json())
+        {
+            return Err(
+                "--histogram cannot be combined with --by-user, --per-user-top, --subtree-mem, \
+                 --watch, --memory-only, --summary-only or --format json"
+                    .to_string(),
+            );
+        }
+        if options.summary_only
+            && (options.processes_only
+                || options.memory_only
+                || options.by_user
+                || options.watch.is_some()
+                || options.format.is_json())
+        {
+            return Err(
+                "--summary-only cannot be combined with --processes-only, --memory-only, \
+                 --by-user, --watch or --format json"
+                    .to_string(),
+            );
+        }
+        if options.fail_if_empty && (options.memory_only || options.summary_only) {
+            return Err(
+                "--fail-if-empty cannot be combined with --memory-only or --summary-only"
+                    .to_string(),
+            );
+        }
+        if options.no_truncate_cmd && options.cmd_width.is_some() {
+            return Err("--no-truncate-cmd and --cmd-width are mutually exclusive".to_string());
+        }
+        if options.interval_align && options.interval_jitter.is_some() {
+            return Err(
+                "--interval-align and --interval-jitter are mutually exclusive".to_string(),
+            );
+        }
+        if options.show_cpu_share && options.memory_only {
+            return Err("--show-cpu-share and --memory-only are mutually exclusive".to_string());
+        }
+        if options.processes_only && options.memory_only {
+            return Err("--processes-only and --memory-only are mutually exclusive".to_string());
+        }
+        // They need the memory totals, which are not read.
+        if options.processes_only
+            && (options.mem_detail
+                || options.alert_mem.is_some()
+                || options.alert_used_kb.is_some()
+                || options.column_names().iter().any(|name| name == "mem"))
+        {
+            return Err(
+                "--processes-only cannot be combined with --mem-detail, --explain-mem, --alert-mem, \
+                 --alert-used-kb or the %MEM column (--show-mem)"
+                    .to_string(),
+            );
+        }
+        if let Some(tiebreak) = options.tiebreak {
+            if options.no_sort {
+                return Err("--tiebreak cannot be combined with --no-sort".to_string());
+            }
+            // After --top-io and --oom-risk, which replace the sort order.
+            options.sort.fields.push(tiebreak);
+        }
+        if !cfg!(feature = "extras") {
+            if let Some(name) = options
+                .column_names()
+                .into_iter()
+                .find(|name| EXTRAS_COLUMNS.contains(&name.as_str()))
+            {
+                return Err(needs_extras(&format!("the {name} column")));
+            }
+            if options.sort.contains(SortKey::Read) || options.sort.contains(SortKey::Write) {
+                return Err(needs_extras("sorting by I/O"));
+            }
+            if options.top_by == Some(TopMetric::Io) {
+                return Err(needs_extras("--top-by io"));
+            }
+        }
+        Ok(options)
+    }
+
+    /// Stricter validation for `--check-config`: also rejects combinations
+    /// that [`Options::parse`] accepts but that cannot do what they ask for,
+    /// which a normal run only warns about.
+    pub fn check(&self) -> Result<(), String> {
+        if self.once && self.uses_rates() && !self.delay_first {
+            return Err(
+                "CPU usage, I/O and page fault rates (--sort cpu, read, write or majflt, \
+                 --alert-cpu and their columns) need two samples and have no data with --once; \
+                 add --delay-first"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether the refresh loop should stop after `frames` frames.
+    pub fn is_last_frame(&self, frames: u64) -> bool {
+        self.once || self.iterations.is_some_and(|n| frames >= n)
+    }
+
+    /// Whether `-H`/`--human` scales figures for reading rather than
+    /// printing raw integers.
+    pub fn human(&self) -> bool {
+        self.mem_unit == MemUnit::Human
+    }
+
+    /// Decimals of percentages and rates, one unless `--precision` is given.
+    pub fn precision(&self) -> usize {
+        self.precision.unwrap_or(1)
+    }
+
+    /// Time to sleep between refreshes, one second unless `--interval` is
+    /// given, and never less than [`MIN_INTERVAL`].
+    pub fn interval(&self) -> Duration {
+        self.interval
+            .unwrap_or(Duration::from_secs(1))
+            .max(MIN_INTERVAL)
+    }
+
+    /// Fraction of unparsable processes above which a scan warns, 0.1
+    /// unless `--warn-on-parse-error-count` is given.
+    pub fn parse_error_threshold(&self) -> f64 {
+        self.warn_parse_errors.unwrap_or(0.1)
+    }
+
+    /// The `--proc-root` directory, `/proc` by default.
+    pub fn proc_root(&self) -> &str {
+        self.proc_root.as_deref().unwrap_or("/proc")
+    }
+
+    /// Failed refreshes in a row after which the loop gives up, 10 unless
+    /// `--max-consecutive-errors` is given.
+    pub fn max_consecutive_errors(&self) -> u32 {
+        self.max_consecutive_errors.unwrap_or(10)
+    }
+
+    /// Figures of the memory header line, in display order.
+    pub fn mem_fields(&self) -> &[MemField] {
+        self.mem_fields.as_deref().unwrap_or(&MemField::DEFAULT)
+    }
+
+    /// Whether the output depends on CPU usage, I/O or page fault rates,
+    /// which need two samples.
+    pub fn uses_rates(&self) -> bool {
+        self.sort.contains(SortKey::Cpu)
+            || self.sort.contains(SortKey::MajFlt)
+            || self.sort_by_delta == Some(DeltaMetric::Cpu)
+            || self.alert_cpu.is_some()
+            || self.min_cpu.is_some()
+            || self.top_by == Some(TopMetric::Cpu)
+            || self.column_names().iter().any(|name| {
+                matches!(
+                    name.as_str(),
+                    "cpu" | "minflt" | "majflt" | "maxcpu" | "schedwait"
+                )
+            })
+            || self.uses_io()
+    }
+
+    /// Whether `/proc/<pid>/io` has to be read for the sort keys or columns.
+    pub fn uses_io(&self) -> bool {
+        self.top_by == Some(TopMetric::Io)
+            || self.sort.contains(SortKey::Read)
+            || self.sort.contains(SortKey::Write)
+            || self
+                .column_names()
+                .iter()
+                .any(|name| name == "read" || name == "write")
+    }
+
+    /// Whether the `maxrss` or `maxcpu` column is shown, which need the usage
+    /// range of each process tracked over the session.
+    pub fn shows_max(&self) -> bool {
+        self.column_names()
+            .iter()
+            .any(|name| name == "maxrss" || name == "maxcpu")
+    }
+
+    /// Whether ordering needs [`read_process_details`](crate::read_process_details)
+    /// for every process, not just the displayed ones.
+    pub fn sort_needs_details(&self) -> bool {
+        // Deltas come from the sampler, which only sees processes with details.
+        self.sort.needs_details()
+            || self.sort_by_delta.is_some()
+            || self.top_by.is_some_and(TopMetric::needs_details)
+            || self.min_cpu.is_some()
+    }
+
+    /// Whether any requested column, the sort key or `--show-cpu-share`
+    /// needs [`read_process_details`](crate::read_process_details).
+    pub fn needs_details(&self) -> bool {
+        self.sort_needs_details()
+            || self.show_cpu_share
+            || self
+                .column_names()
+                .iter()
+                .any(|name| Column::from_name(name).is_some_and(|c| c.needs_details()))
+    }
+
+    /// Whether a frame works on every process rather than the top rows:
+    /// `--by-user`, `--per-user-top`, `--subtree-mem`, `--histogram`,
+    /// `--group-summary` and `--top-growth`.
+    pub fn scans_all(&self) -> bool {
+        self.by_user
+            || self.per_user_top.is_some()
+            || self.subtree_mem.is_some()
+            || self.histogram
+            || self.group_summary
+            || self.top_growth
+    }
+
+    /// Whether processes must be read from `/proc/<pid>/status` rather than
+    /// just `statm`: for the Tasks line, the uid of `--by-user` and
+    /// `--per-user-top`, the parent pid of `--subtree-mem`, the peak and
+    /// vmswap sort keys or a peak, RSS breakdown, caps or swapratio column.
+    /// See [`list_processes_rss_only`](crate::list_processes_rss_only).
+    pub fn needs_status(&self) -> bool {
+        !self.processes_only
+            || self.by_user
+            || self.per_user_top.is_some()
+            || self.subtree_mem.is_some()
+            || self.warn_d_state.is_some()
+            || self.sort.contains(SortKey::Peak)
+            || self.sort.contains(SortKey::VmSwap)
+            || self.column_names().iter().any(|name| {
+                matches!(
+                    name.as_str(),
+                    "peak" | "anon" | "file" | "shmem" | "nspid" | "caps" | "swapratio"
+                )
+            })
+    }
+
+    /// Names of the columns of the process table, in display order.
+    ///
+    /// `--json-fields` and `--columns` are used as given, so only those
+    /// fields are read; otherwise PID, name and RSS are followed by the
+    /// columns enabled with `--show-*` or needed by the sort key.
+    pub fn column_names(&self) -> Vec<String> {
+        if let Some(fields) = &self.json_fields {
+            return fields.clone();
+        }
+        if let Some(columns) = &self.columns {
+            return columns.clone();
+        }
+        let mut columns = vec!["pid", "name", "rss"];
+        if self.ns_pid {
+            columns.insert(1, "nspid");
+        }
+        if self.threads {
+            columns.splice(1..1, ["tgid", "task"]);
+        }
+        if self.show_mem {
+            columns.push("mem");
+        }
+        if self.show_cpu || self.threads || self.sort.contains(SortKey::Cpu) {
+            columns.push("cpu");
+        }
+        if self.show_time || self.sort.contains(SortKey::Time) {
+            columns.push("time");
+        }
+        if self.show_faults {
+            columns.extend(["minflt", "majflt"]);
+        } else if self.sort.contains(SortKey::MajFlt) {
+            columns.push("majflt");
+        }
+        if self.show_sockets || self.sort.contains(SortKey::Sockets) {
+            columns.push("sockets");
+        }
+        if self.show_wchan {
+            columns.push("wchan");
+        }
+        if self.show_sched_wait {
+            columns.push("schedwait");
+        }
+        if self.show_caps {
+            columns.push("caps");
+        }
+        if self.show_swap_ratio {
+            columns.push("swapratio");
+        }
+        if self.show_numa {
+            columns.push("numa");
+        }
+        if self.show_max {
+            columns.extend(["maxrss", "maxcpu"]);
+        }
+        if self.show_unit {
+            columns.push("unit");
+        }
+        if self.rss_detail {
+            columns.extend(["anon", "file", "shmem"]);
+        }
+        if self.show_peak {
+            columns.push("peak");
+        }
+        if self.show_tty {
+            columns.push("tty");
+        }
+        if self.show_cwd {
+            columns.push("cwd");
+        }
+        columns.into_iter().map(str::to_string).collect()
+    }
+}
+
+/// Columns, and with them the `/proc` files behind them, that only builds
+/// with the `extras` feature have. Without it, asking for one is an error
+/// rather than an unknown column; `read` and `write` also cover the I/O
+/// sort keys, `--top-io` and `--top-by io`.
+const EXTRAS_COLUMNS: [&str; 4] = ["read", "write", "caps", "numa"];
+
+/// The error for using `what` in a build without the `extras` feature.
+fn needs_extras(what: &str) -> String {
+    format!("{what} needs a build with the extras feature")
+}
+
+/// Flags that take values, with how many; every other flag is a switch.
+const VALUE_FLAGS: &[(&str, usize)] = &[
+    ("--sort", 1),
+    ("--tiebreak", 1),
+    ("--top-by", 1),
+    ("--sort-by-delta", 1),
+    ("--columns", 1),
+    ("--name-source", 1),
+    ("--classify-by-arg", 1),
+    ("--max-name-width", 1),
+    ("--cmd-width", 1),
+    ("--exclude-pids-from", 1),
+    ("--min-cpu", 1),
+    ("--watch", 1),
+    ("--aggregate-top", 1),
+    ("--per-user-top", 1),
+    ("--subtree-mem", 1),
+    ("--limit-by-percent", 1),
+    ("--mem-fields", 1),
+    ("--format", 1),
+    ("--json-fields", 1),
+    ("--field-separator", 1),
+    ("--iterations", 1),
+    ("--interval", 1),
+    ("--interval-jitter", 1),
+    ("--adaptive-interval", 1),
+    ("--aggregate-interval", 1),
+    ("--max-consecutive-errors", 1),
+    ("--warn-on-parse-error-count", 1),
+    ("--max-fps", 1),
+    ("--clear-mode", 1),
+    ("--flush", 1),
+    ("--output", 1),
+    ("--render-to", 1),
+    ("--time-format", 1),
+    ("--mem-mode", 1),
+    ("--cgroup", 1),
+    ("--mem-unit", 1),
+    ("--unit-base", 1),
+    ("--rss-unit", 1),
+    ("--precision", 1),
+    ("--alert-mem", 1),
+    ("--alert-used-kb", 1),
+    ("--alert-cpu", 1),
+    ("--warn-d-state", 1),
+    ("--highlight", 1),
+    ("--color", 1),
+    ("--color-scheme", 1),
+    ("--proc-root", 1),
+    ("--maps", 1),
+    ("--env", 1),
+    ("--limits", 1),
+    ("--pgrep", 1),
+    ("--pgrep-full", 1),
+    ("--compare", 2),
+    ("--grep", 1),
+];
+
+/// Switches that add a column to the default process table.
+const COLUMN_SWITCHES: &[&str] = &[
+    "--ns-pid",
+    "--show-mem",
+    "--show-cpu",
+    "--show-time",
+    "--show-faults",
+    "--show-sockets",
+    "--show-wchan",
+    "--show-sched-wait",
+    "--show-caps",
+    "--show-swap-ratio",
+    "--show-numa",
+    "--show-max",
+    "--show-unit",
+    "--rss-detail",
+    "--show-peak",
+    "--show-tty",
+    "--show-cwd",
+];
+
+/// Flags that set the whole column list.
+const COLUMN_LISTS: &[&str] = &["--columns", "--json-fields", "--top-io", "--oom-risk"];
+
+/// Flags that choose the order of the processes.
+const ORDERINGS: &[&str] = &[
+    "--sort",
+    "--no-sort",
+    "--top-by",
+    "--sort-by-delta",
+    "--top-io",
+    "--oom-risk",
+];
+
+/// For each entry, a flag of the first list given by a source of higher
+/// precedence replaces a flag of the second one given by a lower source.
+const OVERRIDES: &[(&[&str], &[&str])] = &[
+    (&["--once", "--iterations"], &["--once", "--iterations"]),
+    (
+        &["--processes-only", "--memory-only", "--summary-only"],
+        &["--processes-only", "--memory-only", "--summary-only"],
+    ),
+    (ORDERINGS, ORDERINGS),
+    (COLUMN_LISTS, COLUMN_LISTS),
+    (COLUMN_LISTS, COLUMN_SWITCHES),
+    (COLUMN_SWITCHES, COLUMN_LISTS),
+    (&["--mem-unit", "--human"], &["--mem-unit", "--human"]),
+    (&["--format"], &["--json-pretty", "--oneshot-json-array"]),
+    (
+        &["--interval-align", "--interval-jitter"],
+        &["--interval-align", "--interval-jitter"],
+    ),
+    (
+        &["--no-truncate-cmd", "--cmd-width"],
+        &["--no-truncate-cmd", "--cmd-width"],
+    ),
+    (&["--pgrep", "--pgrep-full"], &["--pgrep", "--pgrep-full"]),
+];
+
+/// How many values follow `flag`; 0 for a switch or an unknown flag.
+pub(crate) fn flag_arity(flag: &str) -> usize {
+    VALUE_FLAGS
+        .iter()
+        .find(|(name, _)| *name == long_flag(flag))
+        .map_or(0, |&(_, arity)| arity)
+}
+
+/// The long form of `flag`, e.g. `--human` for `-H`.
+pub(crate) fn long_flag(flag: &str) -> &str {
+    match flag {
+        "-H" => "--human",
+        "-q" => "--quiet",
+        "-v" => "--verbose",
+        "-h" => "--help",
+        flag => flag,
+    }
+}
+
+/// Whether `higher`, set by a source of higher precedence such as the
+/// command line, replaces `lower` set by one below it such as the config
+/// file: the same flag, or one setting what `lower` sets.
+pub(crate) fn overrides(higher: &str, lower: &str) -> bool {
+    let (higher, lower) = (long_flag(higher), long_flag(lower));
+    higher == lower
+        || OVERRIDES
+            .iter()
+            .any(|(high, low)| high.contains(&higher) && low.contains(&lower))
+}
+
+/// Takes the value following `flag`.
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("{flag} requires a value"))
+}
+
+/// Parses a comma-separated `--columns` list against the column registry.
+fn parse_columns(value: &str) -> Result<Vec<String>, String> {
+    value
+        .split(',')
+        .map(|name| match Column::from_name(name) {
+            Some(column) => Ok(column.name().to_string()),
+            None if EXTRAS_COLUMNS.contains(&name) => {
+                Err(needs_extras(&format!("the {name} column")))
+            }
+            None => Err(format!(
+                "unknown column: {name:?} (expected {})",
+                Column::ALL
+                    .iter()
+                    .map(|c| c.name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        })
+        .collect()
+}
+
+/// Parses a separator argument, accepting `\t` and `tab` as spellings of TAB.
+fn parse_separator(value: &str) -> Result<char, String> {
+    if matches!(value, "\\t" | "tab") {
+        return Ok('\t');
+    }
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(format!(
+            "field separator must be a single character: {value:?}"
+        )),
+    }
+}
+
+/// Parses a percentage threshold in the range 0–100.
+fn parse_percent(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(percent),
+        _ => Err(format!(
+            "expected a percentage between 0 and 100: {value:?}"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Process, sort_processes, testing::process};
+
+    #[cfg(feature = "extras")]
+    #[test]
+    fn test_extras_columns() {
+        let args = [
+            "--show-caps",
+            "--show-numa",
+            "--columns",
+            "pid,read,caps,numa",
+        ];
+        let options = Options::parse(args.map(String::from)).unwrap();
+        assert_eq!(options.column_names(), ["pid", "read", "caps", "numa"]);
+        assert!(Options::parse(["--top-io"].map(String::from)).is_ok());
+    }
+
+    #[cfg(not(feature = "extras"))]
+    #[test]
+    fn test_extras_columns_need_the_feature() {
+        let err =
+            |args: &[&str]| Options::parse(args.iter().map(|arg| arg.to_string())).unwrap_err();
+        assert_eq!(
+            err(&["--show-numa"]),
+            "the numa column needs a build with the extras feature"
+        );
+        assert_eq!(
+            err(&["--columns", "pid,caps"]),
+            "the caps column needs a build with the extras feature"
+        );
+        assert_eq!(
+            err(&["--top-io"]),
+            "the read column needs a build with the extras feature"
+        );
+        assert_eq!(
+            err(&["--sort", "mem", "--tiebreak", "write"]),
+            "sorting by I/O needs a build with the extras feature"
+        );
+        assert!(Options::parse(["--show-cpu-share"].map(String::from)).is_ok());
+    }
+
+    #[test]
+    fn test_tiebreak_orders_equal_processes() {
+        let procs: Vec<Process> = [(1, "zsh", 5.0), (2, "bash", 5.0), (3, "vim", 9.0)]
+            .into_iter()
+            .map(|(pid, name, cpu_percent)| Process {
+                cpu_percent,
+                ..process(pid, name, 100)
+            })
+            .collect();
+        let order = |args: &[&str]| {
+            let options = Options::parse(args.iter().map(|arg| arg.to_string())).unwrap();
+            let mut procs = procs.clone();
+            sort_processes(&mut procs, options.sort);
+            procs.iter().map(|p| p.pid).collect::<Vec<u32>>()
+        };
+        assert_eq!(order(&["--sort", "cpu"]), [3, 1, 2]);
+        assert_eq!(order(&["--sort", "cpu", "--tiebreak", "name"]), [3, 2, 1]);
+        assert_eq!(
+            order(&["--sort", "cpu", "--tiebreak", "pid:desc"]),
+            [3, 2, 1]
+        );
+        assert!(Options::parse(["--no-sort", "--tiebreak", "name"].map(String::from)).is_err());
+    }
+
+    #[test]
+    fn test_flag_arity_matches_usage() {
+        let options = USAGE.lines().skip_while(|line| *line != "Options:");
+        let options = options.take_while(|line| !line.is_empty());
+        for line in options.filter(|line| line.starts_with("  -")) {
+            let synopsis = line.trim().split("  ").next().unwrap();
+            let mut words = synopsis.split(' ');
+            let flag = words.next().unwrap().trim_end_matches(',');
+            let flag = if flag.starts_with("--") {
+                flag
+            } else {
+                words.next().unwrap()
+            };
+            let arity = words.filter(|word| word.starts_with('<')).count();
+            assert_eq!(flag_arity(flag), arity, "{line}");
+        }
+        assert_eq!(flag_arity("-H"), 0);
+        assert_eq!(flag_arity("--no-such-flag"), 0);
+    }
+
+    #[test]
+    fn test_check_config() {
+        let args = ["--check-config", "--sort", "cpu", "--once"].map(String::from);
+        let options = Options::parse(args).unwrap();
+        assert!(options.check_config);
+        assert!(options.check().unwrap_err().contains("--delay-first"));
+
+        let args = ["--check-config", "--sort", "cpu", "--once", "--delay-first"];
+        assert_eq!(
+            Options::parse(args.map(String::from)).unwrap().check(),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_interval_floor() {
+        let interval = |value: &str| {
+            Options::parse(["--interval", value].map(String::from))
+                .unwrap()
+                .interval()
+        };
+        assert_eq!(interval("0.01"), Duration::from_millis(10));
+        assert_eq!(interval("0.25"), Duration::from_millis(250));
+        assert_eq!(interval("0.0001"), MIN_INTERVAL);
+        assert_eq!(interval("0"), MIN_INTERVAL);
+    }
+
+    #[test]
+    fn test_json_pretty_format() {
+        let format = |args: &[&str]| {
+            Options::parse(args.iter().map(|arg| arg.to_string())).map(|options| options.format)
+        };
+        assert_eq!(format(&["--json-pretty"]), Ok(OutputFormat::Json));
+        assert_eq!(
+            format(&["--format", "json-map", "--json-pretty"]),
+            Ok(OutputFormat::JsonMap)
+        );
+        assert_eq!(
+            format(&["--json-pretty", "--format", "json-map"]),
+            Ok(OutputFormat::JsonMap)
+        );
+        for args in [
+            ["--json-pretty", "--format", "table"],
+            ["--format", "ascii-table", "--json-pretty"],
+        ] {
+            assert_eq!(
+                format(&args),
+                Err(
+                    "--json-pretty cannot be combined with a --format other than json or json-map"
+                        .to_string()
+                ),
+                "{args:?}"
+            );
+        }
+    }
+
+    #[cfg(feature = "extras")]
+    #[test]
+    fn test_top_io() {
+        let options = Options::parse(["--top-io", "--once"].map(String::from)).unwrap();
+        assert_eq!(options.sort, SortKey::Write.into());
+        assert_eq!(options.column_names(), ["pid", "name", "read", "write"]);
+        assert!(options.delay_first && options.uses_io());
+        assert_eq!(options.check(), Ok(()));
+    }
+}
@@ -0,0 +1,94 @@
+//! Time source of the refresh loop, replaceable for deterministic tests.
+
+use std::{
+    rc::Rc,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Where the monitor gets the current time from and how it waits.
+pub trait Clock {
+    /// Monotonic time, for measuring intervals.
+    fn now(&self) -> Instant;
+
+    /// Wall-clock time, for timestamps in the output.
+    fn system_now(&self) -> SystemTime;
+
+    /// Waits for `duration`.
+    fn sleep(&self, duration: Duration);
+}
+
+/// The real clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// How long from `now` to the next multiple of `interval` since the Unix
+/// epoch, for `--interval-align`: a whole `interval` when `now` is exactly
+/// on a boundary.
+pub fn until_next_boundary(now: SystemTime, interval: Duration) -> Duration {
+    let since_epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let interval_nanos = interval.as_nanos().max(1);
+    let into = since_epoch.as_nanos() % interval_nanos;
+    Duration::from_nanos((interval_nanos - into) as u64)
+}
+
+/// Lets a caller keep a handle on a clock it hands to the monitor.
+impl<C: Clock> Clock for Rc<C> {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        (**self).system_now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        (**self).sleep(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockClock;
+
+    #[test]
+    fn test_mock_clock_advances_on_sleep() {
+        let clock = MockClock::new(UNIX_EPOCH);
+        let start = clock.now();
+        clock.sleep(Duration::from_secs(2));
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(clock.now() - start, Duration::from_millis(2500));
+        assert_eq!(clock.system_now(), UNIX_EPOCH + Duration::from_millis(2500));
+    }
+
+    #[test]
+    fn test_until_next_boundary() {
+        let clock = MockClock::new(UNIX_EPOCH + Duration::from_millis(12_300));
+        let secs = Duration::from_secs;
+        assert_eq!(
+            until_next_boundary(clock.system_now(), secs(1)),
+            Duration::from_millis(700)
+        );
+        assert_eq!(
+            until_next_boundary(clock.system_now(), secs(5)),
+            Duration::from_millis(2700)
+        );
+        clock.advance(Duration::from_millis(700));
+        assert_eq!(until_next_boundary(clock.system_now(), secs(1)), secs(1));
+    }
+}
@@ -0,0 +1,404 @@
+//! Offline comparison of two saved `--format json` snapshots (`--compare`),
+//! and of the first and last frame of a run (`--top-growth`).
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    json::Json,
+    process::{DeltaMetric, Process, compare_delta},
+    render::{JSON_SCHEMA_VERSION, truncate_name},
+};
+
+/// The parts of a saved snapshot that `--compare` looks at.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SavedSnapshot {
+    /// The `memory` figures in kB, in file order, with the `_kb` suffix
+    /// dropped from the names.
+    pub memory: Vec<(String, u64)>,
+    pub processes: Vec<SavedProcess>,
+}
+
+/// One row of a saved snapshot; columns that were not saved are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SavedProcess {
+    pub pid: u32,
+    pub name: String,
+    pub rss_kb: Option<u64>,
+    pub cpu_percent: Option<f64>,
+}
+
+impl SavedSnapshot {
+    /// Parses the output of `--format json`, `--json-pretty`, `json-map` or
+    /// `--oneshot-json-array`.
+    ///
+    /// A file holding several frames, one per line or in an array, is read
+    /// as its last frame. Rows need a `pid`; `name`, `rss` and `cpu` are
+    /// optional.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let json = Json::parse(text).or_else(|err| {
+            text.lines()
+                .rev()
+                .find(|line| !line.trim().is_empty())
+                .map_or(Err(err), Json::parse)
+        })?;
+        Self::from_value(&json)
+    }
+
+    /// Parses a single JSON document as written by [`SavedSnapshot::to_json`]
+    /// or by one of the JSON formats, see [`SavedSnapshot::parse`].
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        Self::from_value(&Json::parse(text)?)
+    }
+
+    /// Serializes the snapshot in the layout of a `--format json` frame,
+    /// with only the fields it has, so that [`SavedSnapshot::from_json`]
+    /// gives back an equal snapshot.
+    pub fn to_json(&self) -> Json {
+        let mut fields = vec![(
+            "schema_version".to_string(),
+            Json::UInt(JSON_SCHEMA_VERSION),
+        )];
+        if !self.memory.is_empty() {
+            let memory = self
+                .memory
+                .iter()
+                .map(|(name, kb)| (format!("{name}_kb"), Json::UInt(*kb)));
+            fields.push(("memory".to_string(), Json::object(memory)));
+        }
+        let processes = self.processes.iter().map(|p| {
+            let mut row = vec![
+                ("pid", Json::UInt(p.pid.into())),
+                ("name", Json::String(p.name.clone())),
+            ];
+            row.extend(p.rss_kb.map(|kb| ("rss", Json::UInt(kb))));
+            row.extend(p.cpu_percent.map(|cpu| ("cpu", Json::Float(cpu))));
+            Json::object(row)
+        });
+        fields.push(("processes".to_string(), Json::Array(processes.collect())));
+        Json::Object(fields)
+    }
+
+    /// The pid, name, RSS and CPU usage of `procs`, as a frame of the
+    /// running monitor would be saved; no memory figures.
+    pub fn from_processes(procs: &[Process]) -> Self {
+        let processes = procs
+            .iter()
+            .map(|p| SavedProcess {
+                pid: p.pid,
+                name: p.name.clone(),
+                rss_kb: Some(p.rss_kb),
+                cpu_percent: Some(p.cpu_percent),
+            })
+            .collect();
+        Self {
+            memory: Vec::new(),
+            processes,
+        }
+    }
+
+    fn from_value(json: &Json) -> Result<Self, String> {
+        if let Some(frames) = json.as_array() {
+            return frames
+                .last()
+                .ok_or_else(|| "no frame in the snapshot array".to_string())
+                .and_then(Self::from_value);
+        }
+        let memory = match json.get("memory") {
+            Some(Json::Object(fields)) => fields
+                .iter()
+                .filter_map(|(key, value)| {
+                    let name = key.strip_suffix("_kb").unwrap_or(key);
+                    Some((name.to_string(), value.as_u64()?))
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        // `json-map` frames key the rows by pid instead.
+        let rows: Vec<&Json> = match (json.get("processes"), json) {
+            (Some(rows), _) => rows.as_array().unwrap_or_default().iter().collect(),
+            (None, Json::Object(fields)) => fields
+                .iter()
+                .filter(|(key, _)| key.parse::<u32>().is_ok())
+                .map(|(_, row)| row)
+                .collect(),
+            (None, _) => Vec::new(),
+        };
+        let processes = rows
+            .into_iter()
+            .map(|row| {
+                let pid = row
+                    .get("pid")
+                    .and_then(Json::as_u64)
+                    .and_then(|pid| u32::try_from(pid).ok())
+                    .ok_or_else(|| format!("process without a valid pid: {row}"))?;
+                Ok(SavedProcess {
+                    pid,
+                    name: row
+                        .get("name")
+                        .and_then(Json::as_str)
+                        .unwrap_or("?")
+                        .to_string(),
+                    rss_kb: row.get("rss").and_then(Json::as_u64),
+                    cpu_percent: row.get("cpu").and_then(Json::as_f64),
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self { memory, processes })
+    }
+}
+
+/// Formats the changes from snapshot `a` to snapshot `b`.
+///
+/// The first line lists each memory figure of `b` with its signed change,
+/// e.g. `Memory: total=2048kB (+0) used=1536kB (+512)`. It is followed by
+/// one row per process found in either snapshot, with the RSS and CPU usage
+/// of `b` and their changes, ordered by the largest RSS change and then the
+/// largest CPU change. Processes only in `a` have exited and show `-`;
+/// processes only in `b` count their full figures as the change.
+pub fn format_comparison(a: &SavedSnapshot, b: &SavedSnapshot) -> String {
+    let mut out = String::new();
+    if !b.memory.is_empty() {
+        out.push_str("Memory:");
+        let before: BTreeMap<&str, u64> = a.memory.iter().map(|(k, v)| (k.as_str(), *v)).collect();
+        for (name, value) in &b.memory {
+            let change = before.get(name.as_str()).map_or_else(
+                || "new".to_string(),
+                |&old| signed(*value as f64 - old as f64, 0),
+            );
+            out.push_str(&format!(" {name}={value}kB ({change})"));
+        }
+        out.push('\n');
+    }
+
+    let before: BTreeMap<u32, &SavedProcess> = a.processes.iter().map(|p| (p.pid, p)).collect();
+    let after: BTreeMap<u32, &SavedProcess> = b.processes.iter().map(|p| (p.pid, p)).collect();
+    let mut rows: Vec<Row> = before
+        .keys()
+        .chain(after.keys())
+        .copied()
+        .collect::<BTreeSet<u32>>()
+        .into_iter()
+        .map(|pid| Row::new(before.get(&pid).copied(), after.get(&pid).copied()))
+        .collect();
+    // Largest RSS change first; the CPU change breaks ties.
+    rows.sort_by(|x, y| {
+        compare_delta(DeltaMetric::Mem, &x.delta, &y.delta)
+            .then_with(|| compare_delta(DeltaMetric::Cpu, &x.delta, &y.delta))
+    });
+
+    out.push_str(&format!(
+        "{:<6} {:<20} {:>10} {:>10} {:>6} {:>6}\n",
+        "PID", "NAME", "RSS(kB)", "ΔRSS", "%CPU", "Δ%CPU"
+    ));
+    for row in &rows {
+        out.push_str(&format!(
+            "{:<6} {:<20} {:>10} {:>10} {:>6} {:>6}\n",
+            row.delta.pid,
+            truncate_name(&row.delta.name, 20),
+            row.after
+                .and_then(|p| p.rss_kb)
+                .map_or_else(|| "-".to_string(), |kb| kb.to_string()),
+            row.rss_change
+                .map_or_else(|| "-".to_string(), |d| signed(d, 0)),
+            row.after
+                .and_then(|p| p.cpu_percent)
+                .map_or_else(|| "-".to_string(), |cpu| format!("{cpu:.1}")),
+            row.cpu_change
+                .map_or_else(|| "-".to_string(), |d| signed(d, 1)),
+        ));
+    }
+    out
+}
+
+/// A process in the comparison. `delta` carries the absolute changes in
+/// [`Process::rss_delta`] and [`Process::cpu_delta`] so that the rows sort
+/// with [`compare_delta`] like `--sort-by-delta` does.
+struct Row<'a> {
+    delta: Process,
+    /// The process in `b`, `None` once it has exited.
+    after: Option<&'a SavedProcess>,
+    rss_change: Option<f64>,
+    cpu_change: Option<f64>,
+}
+
+impl<'a> Row<'a> {
+    fn new(before: Option<&'a SavedProcess>, after: Option<&'a SavedProcess>) -> Self {
+        let latest = after.or(before).expect("process in one of the snapshots");
+        let change = |field: fn(&SavedProcess) -> Option<f64>| {
+            let old = before.map_or(Some(0.0), field)?;
+            let new = after.map_or(Some(0.0), field)?;
+            Some(new - old)
+        };
+        let rss_change = change(|p| p.rss_kb.map(|kb| kb as f64));
+        let cpu_change = change(|p| p.cpu_percent);
+        Self {
+            delta: Process {
+                pid: latest.pid,
+                name: latest.name.clone(),
+                rss_delta: rss_change.map_or(0, |d| d.abs() as u64),
+                cpu_delta: cpu_change.map_or(0.0, f64::abs),
+                ..Process::default()
+            },
+            after,
+            rss_change,
+            cpu_change,
+        }
+    }
+}
+
+/// Formats the `--top-growth` report from the `first` to the `last` frame
+/// of a run.
+///
+/// The `top` processes present in both are listed by how much their RSS
+/// grew, largest first, then by pid. Processes only in `last` or only in
+/// `first` started or exited during the window and are listed on their own
+/// lines instead; a pid that comes back under another name counts as both.
+pub fn format_growth_report(first: &SavedSnapshot, last: &SavedSnapshot, top: usize) -> String {
+    let identity = |p: &SavedProcess| (p.pid, p.name.clone());
+    let before: BTreeMap<(u32, String), &SavedProcess> =
+        first.processes.iter().map(|p| (identity(p), p)).collect();
+    let after: BTreeMap<(u32, String), &SavedProcess> =
+        last.processes.iter().map(|p| (identity(p), p)).collect();
+    let rss = |p: &SavedProcess| p.rss_kb.unwrap_or(0) as i64;
+    let mut grown: Vec<(&SavedProcess, i64)> = after
+        .iter()
+        .filter_map(|(id, p)| Some((*p, rss(p) - rss(before.get(id)?))))
+        .collect();
+    grown.sort_by(|(a, x), (b, y)| y.cmp(x).then(a.pid.cmp(&b.pid)));
+    grown.truncate(top);
+
+    let mut out = format!(
+        "{:<6} {:<20} {:>10} {:>10}\n",
+        "PID", "NAME", "RSS(kB)", "ΔRSS"
+    );
+    for (p, growth) in grown {
+        out.push_str(&format!(
+            "{:<6} {:<20} {:>10} {:>10}\n",
+            p.pid,
+            truncate_name(&p.name, 20),
+            rss(p),
+            signed(growth as f64, 0),
+        ));
+    }
+    let list = |of: &BTreeMap<(u32, String), &SavedProcess>,
+                without: &BTreeMap<(u32, String), &SavedProcess>| {
+        of.keys()
+            .filter(|id| !without.contains_key(*id))
+            .map(|(pid, name)| format!("{pid} ({name})"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    for (label, names) in [
+        ("Started", list(&after, &before)),
+        ("Exited", list(&before, &after)),
+    ] {
+        if !names.is_empty() {
+            out.push_str(&format!("{label}: {names}\n"));
+        }
+    }
+    out
+}
+
+/// Formats a change with an explicit sign and `decimals` decimals.
+fn signed(change: f64, decimals: usize) -> String {
+    format!("{change:+.decimals$}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_fixture_snapshots() {
+        let a = SavedSnapshot::parse(include_str!("testdata/snapshot-a.json")).unwrap();
+        let b = SavedSnapshot::parse(include_str!("testdata/snapshot-b.json")).unwrap();
+        assert_eq!(a.processes.len(), 3);
+        assert_eq!(
+            format_comparison(&a, &b),
+            "Memory: total=8000000kB (+0) available=5000000kB (-1000000) free=3000000kB (-500000) \
+             used=3000000kB (+1000000)\n\
+             PID    NAME                    RSS(kB)       ΔRSS   %CPU  Δ%CPU\n\
+             4242   firefox                  900000    +400000   35.5  +25.0\n\
+             77     cron                          -      -2048      -   -0.5\n\
+             9001   make                       1024      +1024   99.0  +99.0\n\
+             1      systemd                   12000         +0    0.0   +0.0\n"
+        );
+        assert!(SavedSnapshot::parse(r#"{"processes":[{"name":"x"}]}"#).is_err());
+    }
+
+    #[test]
+    fn test_growth_report() {
+        let snapshot = |rows: &[(u32, &str, u64)]| {
+            let procs: Vec<Process> = rows
+                .iter()
+                .map(|&(pid, name, rss)| crate::testing::process(pid, name, rss))
+                .collect();
+            SavedSnapshot::from_processes(&procs)
+        };
+        let first = snapshot(&[
+            (1, "systemd", 12_000),
+            (77, "cron", 2048),
+            (300, "leaky", 50_000),
+            (400, "java", 900_000),
+            (500, "shrinking", 9000),
+            (600, "old", 100),
+        ]);
+        let last = snapshot(&[
+            (1, "systemd", 12_000),
+            (300, "leaky", 250_000),
+            (400, "java", 950_000),
+            (500, "shrinking", 1000),
+            (600, "new", 100),
+            (9001, "make", 1024),
+        ]);
+        assert_eq!(
+            format_growth_report(&first, &last, 3),
+            "PID    NAME                    RSS(kB)       ΔRSS\n\
+             300    leaky                    250000    +200000\n\
+             400    java                     950000     +50000\n\
+             1      systemd                   12000         +0\n\
+             Started: 600 (new), 9001 (make)\n\
+             Exited: 77 (cron), 600 (old)\n"
+        );
+    }
+
+    #[test]
+    fn test_snapshot_json_round_trip() {
+        let snapshot = SavedSnapshot {
+            memory: vec![("total".to_string(), 8_000_000), ("used".to_string(), 1234)],
+            processes: vec![
+                SavedProcess {
+                    pid: 7,
+                    name: "postgres".to_string(),
+                    rss_kb: Some(4096),
+                    cpu_percent: Some(12.25),
+                },
+                SavedProcess {
+                    pid: 8,
+                    name: "idle \"worker\"".to_string(),
+                    rss_kb: None,
+                    cpu_percent: Some(0.0),
+                },
+            ],
+        };
+        let json = snapshot.to_json();
+        assert_eq!(
+            json.get("schema_version"),
+            Some(&Json::UInt(JSON_SCHEMA_VERSION))
+        );
+        for text in [json.to_compact(), json.to_pretty()] {
+            assert_eq!(SavedSnapshot::from_json(&text), Ok(snapshot.clone()));
+        }
+        let array = Json::Array(vec![
+            Json::object([("processes", Json::Array(vec![]))]),
+            json,
+        ]);
+        assert_eq!(SavedSnapshot::parse(&array.to_compact()), Ok(snapshot));
+
+        let map = r#"{"schema_version":1,"3":{"pid":3,"name":"sh","rss":10}}"#;
+        assert_eq!(
+            SavedSnapshot::from_json(map).unwrap().processes[0].rss_kb,
+            Some(10)
+        );
+    }
+}
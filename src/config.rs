@@ -0,0 +1,403 @@
+//! Defaults for the command line options from environment variables and a
+//! config file.
+//!
+//! Each source is turned into arguments and parsed by [`Options::parse`], so
+//! a setting means the same wherever it comes from. Precedence, highest
+//! first: command line, environment, config file, built-in defaults. A
+//! source replaces what a lower one set with the same flag or with a flag
+//! for the same thing, like `--processes-only` for `--memory-only`.
+
+use std::{collections::BTreeMap, env, fs, io};
+
+use crate::{
+    Json, Options,
+    cli::{flag_arity, long_flag, overrides},
+};
+
+/// Environment variables that set a default for a flag.
+pub const ENV_FLAGS: &[(&str, &str)] = &[
+    ("PROCMON_INTERVAL", "--interval"),
+    ("PROCMON_COUNT", "--iterations"),
+    ("PROCMON_SORT", "--sort"),
+    ("PROCMON_COLUMNS", "--columns"),
+    ("PROCMON_MEM_UNIT", "--mem-unit"),
+];
+
+/// Environment variable naming the config file to read instead of
+/// `$XDG_CONFIG_HOME/procinfo-rust-exercise/config` (or `~/.config/...`).
+pub const CONFIG_ENV: &str = "PROCMON_CONFIG";
+
+/// The raw settings from every source, before [`Config::resolve`] merges
+/// them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    /// The arguments following the program name.
+    pub args: Vec<String>,
+    /// The environment; only the names in [`ENV_FLAGS`] are looked at.
+    pub env: BTreeMap<String, String>,
+    /// Path and contents of the config file, if there is one.
+    pub file: Option<(String, String)>,
+}
+
+/// One flag and its values, with the config file line or environment
+/// variable it came from; `None` for the command line.
+#[derive(Debug, Clone, PartialEq)]
+struct Setting {
+    origin: Option<String>,
+    args: Vec<String>,
+}
+
+impl Setting {
+    /// The long form of the flag.
+    fn flag(&self) -> &str {
+        long_flag(&self.args[0])
+    }
+}
+
+impl Config {
+    /// Collects the settings of the running process: `args`, its
+    /// environment and the config file.
+    ///
+    /// A missing config file is ignored unless [`CONFIG_ENV`] names it.
+    pub fn from_process(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let env: BTreeMap<String, String> = env::vars().collect();
+        Ok(Self {
+            args: args.into_iter().collect(),
+            file: read_config_file(&env)?,
+            env,
+        })
+    }
+
+    /// The same settings with the config file read again, for `SIGHUP`.
+    /// The arguments and the environment stay as they were at startup.
+    pub fn reload(&self) -> Result<Self, String> {
+        Ok(Self {
+            file: read_config_file(&self.env)?,
+            ..self.clone()
+        })
+    }
+
+    /// Merges the sources into one set of options.
+    ///
+    /// Errors in the environment or the config file name the variable or
+    /// the file and line they came from.
+    pub fn resolve(&self) -> Result<Options, String> {
+        self.merged().map(|(options, _)| options)
+    }
+
+    /// The effective settings for `--print-config`: the config file that
+    /// was read, if any, and every flag that takes effect with the value
+    /// that wins, as a JSON object from flag name (without the dashes) to
+    /// its value, `true` for a switch, or an array for a flag that takes
+    /// two values. Flags left at their built-in default, and those replaced
+    /// by a source of higher precedence, are not listed.
+    pub fn to_json(&self) -> Result<Json, String> {
+        let mut settings: Vec<(String, Json)> = Vec::new();
+        for setting in self.merged()?.1 {
+            // Named like in the config file, by the long flag.
+            let name = setting.flag().trim_start_matches('-').to_string();
+            let mut values: Vec<Json> = setting.args[1..]
+                .iter()
+                .cloned()
+                .map(Json::String)
+                .collect();
+            let value = match values.len() {
+                0 => Json::Bool(true),
+                1 => values.remove(0),
+                _ => Json::Array(values),
+            };
+            if name == "print-config" {
+                continue;
+            }
+            match settings.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, old)) => *old = value,
+                None => settings.push((name, value)),
+            }
+        }
+        let file = self
+            .file
+            .as_ref()
+            .map_or(Json::Null, |(path, _)| Json::String(path.clone()));
+        Ok(Json::object([
+            ("config_file", file),
+            ("settings", Json::Object(settings)),
+        ]))
+    }
+
+    /// The options parsed from the settings that take effect, and those
+    /// settings in order of precedence, lowest first.
+    ///
+    /// The settings are parsed as one command line, so a flag may depend on
+    /// one set elsewhere. An error is blamed on the first config file line or
+    /// environment variable after which the arguments so far fail the same
+    /// way.
+    fn merged(&self) -> Result<(Options, Vec<Setting>), String> {
+        let settings = self.settings()?;
+        // The last occurrence of a flag wins, so later sources override.
+        let merged = settings.iter().flat_map(|s| s.args.iter().cloned());
+        let err = match Options::parse(merged) {
+            Ok(options) => return Ok((options, settings)),
+            Err(err) => err,
+        };
+        let mut prefix = Vec::new();
+        for setting in &settings {
+            let Some(origin) = &setting.origin else { break };
+            prefix.extend(setting.args.iter().cloned());
+            if Options::parse(prefix.iter().cloned()).err().as_ref() == Some(&err) {
+                return Err(format!("{origin}: {err}"));
+            }
+        }
+        Err(err)
+    }
+
+    /// The settings that take effect, lowest precedence first. A setting is
+    /// dropped when a source of higher precedence sets the same flag or one
+    /// that replaces it, e.g. `--processes-only` for `--memory-only` or
+    /// `--show-cpu` for `--columns`.
+    fn settings(&self) -> Result<Vec<Setting>, String> {
+        let mut settings: Vec<Setting> = Vec::new();
+        for source in self.sources()? {
+            settings.retain(|old| !source.iter().any(|new| overrides(new.flag(), old.flag())));
+            settings.extend(source);
+        }
+        Ok(settings)
+    }
+
+    /// The settings of the config file, the environment and the command
+    /// line, in this order. Only the syntax of the config file is checked
+    /// here.
+    fn sources(&self) -> Result<[Vec<Setting>; 3], String> {
+        let mut file = Vec::new();
+        if let Some((path, text)) = &self.file {
+            for (number, line) in text.lines().enumerate() {
+                let origin = format!("{path}:{}", number + 1);
+                let args = parse_config_line(line).map_err(|err| format!("{origin}: {err}"))?;
+                if !args.is_empty() {
+                    file.push(Setting {
+                        origin: Some(origin),
+                        args,
+                    });
+                }
+            }
+        }
+        let env = ENV_FLAGS
+            .iter()
+            .filter_map(|(name, flag)| {
+                Some(Setting {
+                    origin: Some(name.to_string()),
+                    args: vec![flag.to_string(), self.env.get(*name)?.clone()],
+                })
+            })
+            .collect();
+        let mut cli = Vec::new();
+        let mut args = self.args.iter().cloned();
+        while let Some(flag) = args.next() {
+            let mut setting = vec![flag];
+            setting.extend(args.by_ref().take(flag_arity(&setting[0])));
+            cli.push(Setting {
+                origin: None,
+                args: setting,
+            });
+        }
+        Ok([file, env, cli])
+    }
+}
+
+/// Reads the config file named by [`CONFIG_ENV`] in `env`, or the default
+/// one if it exists.
+fn read_config_file(env: &BTreeMap<String, String>) -> Result<Option<(String, String)>, String> {
+    let (path, explicit) = match env.get(CONFIG_ENV) {
+        Some(path) => (Some(path.clone()), true),
+        None => (default_config_path(env), false),
+    };
+    match path {
+        Some(path) => match fs::read_to_string(&path) {
+            Ok(text) => Ok(Some((path, text))),
+            Err(err) if err.kind() == io::ErrorKind::NotFound && !explicit => Ok(None),
+            Err(err) => Err(format!("{path}: {err}")),
+        },
+        None => Ok(None),
+    }
+}
+
+/// `$XDG_CONFIG_HOME/procinfo-rust-exercise/config`, falling back to
+/// `$HOME/.config`.
+fn default_config_path(env: &BTreeMap<String, String>) -> Option<String> {
+    let dir = match env.get("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => dir.clone(),
+        _ => format!("{}/.config", env.get("HOME")?),
+    };
+    Some(format!("{dir}/procinfo-rust-exercise/config"))
+}
+
+/// Turns a `name = value` line into arguments. Names are long flags without
+/// the dashes; `true` and `false` switch a flag without a value on or off.
+/// Blank lines and `#` comments give no arguments.
+fn parse_config_line(line: &str) -> Result<Vec<String>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(Vec::new());
+    }
+    let (name, value) = line
+        .split_once('=')
+        .ok_or_else(|| format!("expected name = value: {line:?}"))?;
+    let flag = format!("--{}", name.trim());
+    Ok(match value.trim() {
+        "true" => vec![flag],
+        "false" => Vec::new(),
+        value => vec![flag, value.to_string()],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::SortKey;
+
+    #[test]
+    fn test_resolve_precedence() {
+        let mut config = Config {
+            file: Some((
+                "config".to_string(),
+                "# defaults\ninterval = 5\niterations = 3\nsort = cpu\nshow-cpu = true\n"
+                    .to_string(),
+            )),
+            ..Config::default()
+        };
+        config.env.insert("PROCMON_INTERVAL".into(), "2".into());
+        config.env.insert("PROCMON_SORT".into(), "rss".into());
+        config.env.insert("PROCMON_UNRELATED".into(), "x".into());
+        config.args = ["--sort", "pid"].map(String::from).to_vec();
+
+        let options = config.resolve().unwrap();
+        assert_eq!(options.sort, SortKey::Pid.into(), "CLI beats env");
+        assert_eq!(
+            options.interval,
+            Some(Duration::from_secs(2)),
+            "env beats file"
+        );
+        assert_eq!(options.iterations, Some(3), "file beats default");
+        assert!(options.show_cpu);
+        assert_eq!(options.precision, None, "built-in default");
+
+        config.env.insert("PROCMON_COUNT".into(), "lots".into());
+        assert!(config.resolve().unwrap_err().starts_with("PROCMON_COUNT: "));
+        config.env.remove("PROCMON_COUNT");
+        config.file = Some(("config".to_string(), "\nsort\n".to_string()));
+        assert!(config.resolve().unwrap_err().starts_with("config:2: "));
+    }
+
+    #[test]
+    fn test_sources_are_checked_together() {
+        let mut config = Config {
+            file: Some((
+                "config".to_string(),
+                "format = json\njson-fields = pid,rss\n".to_string(),
+            )),
+            ..Config::default()
+        };
+        assert!(config.resolve().unwrap().format.is_json());
+
+        config.file = Some((
+            "config".to_string(),
+            "# rss only\njson-fields = rss\n".to_string(),
+        ));
+        assert_eq!(
+            config.resolve().unwrap_err(),
+            "config:2: --json-fields requires --format json or json-map"
+        );
+        config.args = ["--format", "json"].map(String::from).to_vec();
+        assert!(config.resolve().is_ok(), "the CLI completes the file");
+
+        config.file = Some((
+            "config".to_string(),
+            "format = json\njson-fields = rss\n".to_string(),
+        ));
+        config.args = ["--format", "table"].map(String::from).to_vec();
+        assert_eq!(
+            config.resolve().unwrap_err(),
+            "config:2: --json-fields requires --format json or json-map",
+            "the CLI format replaces the file's"
+        );
+    }
+
+    #[test]
+    fn test_higher_sources_replace_settings() {
+        let config = |file: &str, cli: &[&str]| Config {
+            file: Some(("config".to_string(), file.to_string())),
+            args: cli.iter().map(|arg| arg.to_string()).collect(),
+            ..Config::default()
+        };
+
+        let options = config("memory-only = true\n", &["--processes-only"])
+            .resolve()
+            .unwrap();
+        assert!(options.processes_only && !options.memory_only);
+        let options = config("once = true\n", &["--iterations", "3"])
+            .resolve()
+            .unwrap();
+        assert!(!options.once);
+        assert_eq!(options.iterations, Some(3));
+        let options = config("top-io = true\n", &["--sort", "cpu"])
+            .resolve()
+            .unwrap();
+        assert_eq!(options.sort, SortKey::Cpu.into(), "--top-io is dropped");
+        assert!(
+            config("", &["--processes-only", "--memory-only"])
+                .resolve()
+                .is_err(),
+            "one source still cannot ask for both"
+        );
+
+        let mut config = config("show-tty = true\n", &["--show-cpu"]);
+        config
+            .env
+            .insert("PROCMON_COLUMNS".into(), "pid,rss".into());
+        let options = config.resolve().unwrap();
+        assert_eq!(options.column_names(), ["pid", "name", "rss", "cpu"]);
+        config.args.clear();
+        assert_eq!(config.resolve().unwrap().column_names(), ["pid", "rss"]);
+    }
+
+    #[test]
+    fn test_print_config() {
+        let config = Config {
+            file: Some((
+                "/etc/procmon".to_string(),
+                "interval = 5\nshow-cpu = true\nsort = cpu\n".to_string(),
+            )),
+            args: "--interval 2 -H --compare a.json b.json --print-config"
+                .split(' ')
+                .map(String::from)
+                .collect(),
+            ..Config::default()
+        };
+        let json = config.to_json().unwrap();
+        assert_eq!(
+            json.get("config_file").and_then(Json::as_str),
+            Some("/etc/procmon")
+        );
+        assert_eq!(
+            json.get("settings").unwrap().to_compact(),
+            r#"{"show-cpu":true,"sort":"cpu","interval":"2","human":true,"#.to_string()
+                + r#""compare":["a.json","b.json"]}"#,
+            "the CLI interval overrides the file's"
+        );
+
+        let mut config = Config {
+            file: Some(("config".to_string(), "show-cpu = true\n".to_string())),
+            ..Config::default()
+        };
+        config
+            .env
+            .insert("PROCMON_COLUMNS".into(), "pid,rss".into());
+        let json = config.to_json().unwrap();
+        assert_eq!(
+            json.get("settings").unwrap().to_compact(),
+            r#"{"columns":"pid,rss"}"#,
+            "the columns replace --show-cpu"
+        );
+    }
+}
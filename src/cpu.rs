@@ -0,0 +1,423 @@
+//! System-wide and per-process CPU usage from `/proc/stat`.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+};
+
+use crate::{
+    Process,
+    process::{IoCounters, UsageRange},
+    system::clock_ticks_per_second,
+};
+
+/// Aggregate CPU counters from the first (`cpu`) line of `/proc/stat`, in
+/// jiffies since boot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CpuStat {
+    pub user: u64,
+    pub nice: u64,
+    pub system: u64,
+    pub idle: u64,
+    pub iowait: u64,
+    pub irq: u64,
+    pub softirq: u64,
+    pub steal: u64,
+    /// Number of per-CPU `cpuN` lines, i.e. online CPUs.
+    pub cpus: u64,
+}
+
+impl CpuStat {
+    /// Reads `/proc/stat` from a path and delegates to parse_from_str.
+    pub fn from_file(path: &str) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        Self::parse_from_str(&content)
+    }
+
+    /// Parses the aggregate `cpu` line of `/proc/stat`.
+    ///
+    /// Older kernels report fewer columns; missing ones are treated as zero.
+    pub fn parse_from_str(content: &str) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "missing cpu line in stat");
+        let line = content
+            .lines()
+            .find(|line| line.starts_with("cpu "))
+            .ok_or_else(invalid)?;
+        let values = line
+            .split_whitespace()
+            .skip(1)
+            .map(str::parse)
+            .collect::<Result<Vec<u64>, _>>()
+            .map_err(|_| invalid())?;
+        let field = |i: usize| values.get(i).copied().unwrap_or(0);
+        Ok(Self {
+            user: field(0),
+            nice: field(1),
+            system: field(2),
+            idle: field(3),
+            iowait: field(4),
+            irq: field(5),
+            softirq: field(6),
+            steal: field(7),
+            cpus: content
+                .lines()
+                .filter(|line| {
+                    line.strip_prefix("cpu")
+                        .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+                })
+                .count() as u64,
+        })
+    }
+
+    /// Jiffies spent idle, including waiting for I/O.
+    pub fn idle_total(&self) -> u64 {
+        self.idle + self.iowait
+    }
+
+    /// All jiffies accounted for.
+    pub fn total(&self) -> u64 {
+        self.user
+            + self.nice
+            + self.system
+            + self.idle
+            + self.iowait
+            + self.irq
+            + self.softirq
+            + self.steal
+    }
+
+    /// Percentage of time the CPUs were busy since the `prev` sample.
+    pub fn busy_percent(&self, prev: &CpuStat) -> f64 {
+        let total = self.total().saturating_sub(prev.total());
+        if total == 0 {
+            return 0.0;
+        }
+        let idle = self.idle_total().saturating_sub(prev.idle_total());
+        (total.saturating_sub(idle)) as f64 * 100.0 / total as f64
+    }
+
+    /// Percentage of time the CPUs sat idle waiting for I/O since the `prev`
+    /// sample. This counts as idle in [`CpuStat::busy_percent`], so an
+    /// I/O-bound system can look quiet there.
+    pub fn iowait_percent(&self, prev: &CpuStat) -> f64 {
+        let total = self.total().saturating_sub(prev.total());
+        if total == 0 {
+            return 0.0;
+        }
+        self.iowait.saturating_sub(prev.iowait) as f64 * 100.0 / total as f64
+    }
+}
+
+/// The shortest interval per CPU, in jiffies, that rates are computed over.
+/// The counters only advance in whole jiffies, so a shorter interval says
+/// more about rounding than about usage.
+pub const MIN_ELAPSED_TICKS: f64 = 1.0;
+
+/// Identifies a process across frames: its pid and [`Process::start_time`],
+/// so that a recycled pid does not inherit the previous owner's counters.
+type ProcessKey = (u32, u64);
+
+fn key(p: &Process) -> ProcessKey {
+    (p.pid, p.start_time)
+}
+
+/// Remembers each process's CPU ticks, I/O counters and page faults between
+/// frames to derive CPU usage, I/O rates, fault rates and run-queue wait.
+#[derive(Debug, Default)]
+pub struct CpuSampler {
+    prev_ticks: HashMap<ProcessKey, u64>,
+    prev_io: HashMap<ProcessKey, IoCounters>,
+    /// Minor and major page fault counts.
+    prev_faults: HashMap<ProcessKey, (u64, u64)>,
+    /// Run-queue wait from `schedstat`, in nanoseconds.
+    prev_sched_wait: HashMap<ProcessKey, u64>,
+    /// RSS and CPU usage, for the `--sort-by-delta` deltas.
+    prev_usage: HashMap<ProcessKey, (u64, f64)>,
+    /// Only kept with `track_ranges`, and only for the processes of the
+    /// latest update.
+    ranges: HashMap<ProcessKey, UsageRange>,
+    track_ranges: bool,
+    prev_stat: Option<CpuStat>,
+}
+
+impl CpuSampler {
+    /// Whether [`CpuSampler::update`] fills in [`Process::usage_range`], for
+    /// the `maxrss` and `maxcpu` columns; off by default. Turning it off
+    /// forgets the ranges.
+    pub fn track_ranges(&mut self, on: bool) {
+        self.track_ranges = on;
+        if !on {
+            self.ranges.clear();
+        }
+    }
+
+    /// `true` until the first call to [`CpuSampler::update`].
+    pub fn is_first_sample(&self) -> bool {
+        self.prev_stat.is_none()
+    }
+
+    /// System-wide counters from the previous call, if any.
+    pub fn previous_stat(&self) -> Option<CpuStat> {
+        self.prev_stat
+    }
+
+    /// Fills in `cpu_percent` for every process from the ticks consumed since
+    /// the previous call and stores the current values for the next one.
+    ///
+    /// Elapsed time is taken from the system-wide jiffies in `stat`, so a
+    /// process saturating one CPU reads 100%. Processes seen for the first
+    /// time report 0%, and so does a process whose pid was seen before under
+    /// a different start time, as the pid has been recycled. The I/O rates of
+    /// processes with [`Process::io`] are filled in the same way, in kB/s, as
+    /// are the page fault rates per second, the run-queue wait of processes
+    /// with [`Process::sched_wait_ns`] in ms per second, and the RSS and CPU
+    /// deltas.
+    ///
+    /// With [`CpuSampler::track_ranges`], [`Process::usage_range`] is widened
+    /// by the new sample; a recycled pid starts a new range, and the ranges
+    /// of processes not passed in are dropped.
+    ///
+    /// Counters that went backwards count as no change, an interval shorter
+    /// than [`MIN_ELAPSED_TICKS`] per CPU is measured as that long, and a
+    /// share above all CPUs together is capped, so the rates stay finite
+    /// and plausible however close together two samples are taken.
+    pub fn update(&mut self, procs: &mut [Process], stat: CpuStat) {
+        if let Some(prev) = self.prev_stat {
+            let elapsed_per_cpu =
+                stat.total().saturating_sub(prev.total()) as f64 / stat.cpus.max(1) as f64;
+            let elapsed_per_cpu = if elapsed_per_cpu > 0.0 {
+                elapsed_per_cpu.max(MIN_ELAPSED_TICKS)
+            } else {
+                0.0
+            };
+            let max_percent = 100.0 * stat.cpus.max(1) as f64;
+            let elapsed_secs = elapsed_per_cpu / clock_ticks_per_second() as f64;
+            for p in procs.iter_mut() {
+                let prev_ticks = self.prev_ticks.get(&key(p)).copied();
+                p.cpu_percent = match prev_ticks {
+                    Some(prev_ticks) if elapsed_per_cpu > 0.0 => {
+                        (p.cpu_ticks.saturating_sub(prev_ticks) as f64 * 100.0 / elapsed_per_cpu)
+                            .min(max_percent)
+                    }
+                    _ => 0.0,
+                };
+                if let (Some(io), Some(prev_io)) = (p.io, self.prev_io.get(&key(p)))
+                    && elapsed_secs > 0.0
+                {
+                    let rate = |now: u64, before: u64| {
+                        now.saturating_sub(before) as f64 / 1024.0 / elapsed_secs
+                    };
+                    p.io_read_rate = rate(io.read_bytes, prev_io.read_bytes);
+                    p.io_write_rate = rate(io.write_bytes, prev_io.write_bytes);
+                }
+                if let Some(&(min, maj)) = self.prev_faults.get(&key(p))
+                    && elapsed_secs > 0.0
+                {
+                    p.min_fault_rate = p.min_faults.saturating_sub(min) as f64 / elapsed_secs;
+                    p.maj_fault_rate = p.maj_faults.saturating_sub(maj) as f64 / elapsed_secs;
+                }
+                if let (Some(wait), Some(&prev_wait)) =
+                    (p.sched_wait_ns, self.prev_sched_wait.get(&key(p)))
+                    && elapsed_secs > 0.0
+                {
+                    p.sched_wait_rate = wait.saturating_sub(prev_wait) as f64 / 1e6 / elapsed_secs;
+                }
+            }
+        }
+        if self.track_ranges {
+            let keys: HashSet<ProcessKey> = procs.iter().map(key).collect();
+            self.ranges.retain(|k, _| keys.contains(k));
+            for p in procs.iter_mut() {
+                let measured = self.prev_stat.is_some() && self.prev_ticks.contains_key(&key(p));
+                let cpu = measured.then_some(p.cpu_percent);
+                let range = UsageRange::observe(self.ranges.get(&key(p)).copied(), p.rss_kb, cpu);
+                self.ranges.insert(key(p), range);
+                p.usage_range = Some(range);
+            }
+        }
+        for p in procs.iter_mut() {
+            let (rss, cpu) = self.prev_usage.get(&key(p)).copied().unwrap_or_default();
+            p.rss_delta = p.rss_kb.abs_diff(rss);
+            p.cpu_delta = (p.cpu_percent - cpu).abs();
+        }
+        self.prev_ticks = procs.iter().map(|p| (key(p), p.cpu_ticks)).collect();
+        self.prev_usage = procs
+            .iter()
+            .map(|p| (key(p), (p.rss_kb, p.cpu_percent)))
+            .collect();
+        self.prev_io = procs.iter().filter_map(|p| Some((key(p), p.io?))).collect();
+        self.prev_faults = procs
+            .iter()
+            .map(|p| (key(p), (p.min_faults, p.maj_faults)))
+            .collect();
+        self.prev_sched_wait = procs
+            .iter()
+            .filter_map(|p| Some((key(p), p.sched_wait_ns?)))
+            .collect();
+        self.prev_stat = Some(stat);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::process;
+
+    #[test]
+    fn test_cpu_busy_percent() {
+        let prev = CpuStat::parse_from_str("cpu  100 0 100 800 0 0 0 0 0 0\n").unwrap();
+        let cpu = CpuStat::parse_from_str("cpu  150 0 150 900 0 0 0 0 0 0\n").unwrap();
+        assert_eq!(cpu.busy_percent(&prev), 50.0);
+    }
+
+    #[test]
+    fn test_cpu_iowait_percent() {
+        let prev = CpuStat::parse_from_str("cpu  100 0 100 700 100 0 0 0 0 0\n").unwrap();
+        let cpu = CpuStat::parse_from_str("cpu  110 0 110 760 220 0 0 0 0 0\n").unwrap();
+        assert_eq!(cpu.iowait_percent(&prev), 60.0);
+        assert_eq!(cpu.busy_percent(&prev), 10.0);
+        assert_eq!(cpu.iowait_percent(&cpu), 0.0, "no time passed");
+    }
+
+    #[test]
+    fn test_cpu_sampler_rates() {
+        let stat = |total: u64| CpuStat {
+            user: total,
+            cpus: 2,
+            ..CpuStat::default()
+        };
+        let mut sampler = CpuSampler::default();
+        let mut procs = vec![process(1, "busy", 1)];
+        procs[0].cpu_ticks = 100;
+        sampler.update(&mut procs, stat(1000));
+        assert_eq!(procs[0].cpu_percent, 0.0);
+
+        // 200 jiffies over 2 CPUs is 100 ticks of wall time per CPU.
+        procs[0].cpu_ticks = 150;
+        sampler.update(&mut procs, stat(1200));
+        assert_eq!(procs[0].cpu_percent, 50.0);
+    }
+
+    #[test]
+    fn test_cpu_sampler_tracks_usage_range() {
+        let stat = |total: u64| CpuStat {
+            user: total,
+            cpus: 1,
+            ..CpuStat::default()
+        };
+        let mut sampler = CpuSampler::default();
+        sampler.track_ranges(true);
+        let mut procs = vec![process(1, "spiky", 1000)];
+        // RSS and ticks per frame: a spike in the second frame only.
+        for (total, rss, ticks) in [(100, 1000, 0), (200, 9000, 80), (300, 2000, 90)] {
+            procs[0].rss_kb = rss;
+            procs[0].cpu_ticks = ticks;
+            sampler.update(&mut procs, stat(total));
+        }
+        let range = procs[0].usage_range.unwrap();
+        assert_eq!((range.rss_min, range.rss_max), (1000, 9000));
+        assert_eq!((range.cpu_min, range.cpu_max), (Some(10.0), Some(80.0)));
+
+        // A new process with the same pid starts over.
+        let mut procs = vec![process(1, "new", 500)];
+        procs[0].start_time = 900;
+        sampler.update(&mut procs, stat(400));
+        let range = procs[0].usage_range.unwrap();
+        assert_eq!((range.rss_max, range.cpu_max), (500, None));
+
+        // Exited processes are forgotten.
+        sampler.update(&mut [process(2, "other", 100)], stat(500));
+        assert_eq!(sampler.ranges.len(), 1);
+
+        sampler.track_ranges(false);
+        let mut procs = vec![process(2, "other", 100)];
+        sampler.update(&mut procs, stat(600));
+        assert_eq!(procs[0].usage_range, None);
+        assert!(sampler.ranges.is_empty());
+    }
+
+    #[test]
+    fn test_cpu_sampler_detects_pid_reuse() {
+        let stat = |total: u64| CpuStat {
+            user: total,
+            cpus: 1,
+            ..CpuStat::default()
+        };
+        let mut sampler = CpuSampler::default();
+        let mut procs = vec![process(1, "old", 500)];
+        procs[0].cpu_ticks = 1000;
+        procs[0].start_time = 10;
+        sampler.update(&mut procs, stat(1000));
+
+        // The old process exited and a new one got pid 1: its few ticks
+        // must not be measured against the old process's 1000.
+        let mut procs = vec![process(1, "new", 100)];
+        procs[0].cpu_ticks = 1030;
+        procs[0].start_time = 900;
+        sampler.update(&mut procs, stat(1100));
+        assert_eq!(procs[0].cpu_percent, 0.0);
+        assert_eq!(procs[0].rss_delta, 100);
+
+        procs[0].cpu_ticks = 1080;
+        sampler.update(&mut procs, stat(1200));
+        assert_eq!(procs[0].cpu_percent, 50.0);
+    }
+
+    #[test]
+    fn test_cpu_sampler_io_rates() {
+        let hz = clock_ticks_per_second();
+        // One CPU, so `hz` jiffies are one second.
+        let stat = |secs: u64| CpuStat {
+            user: secs * hz,
+            cpus: 1,
+            ..CpuStat::default()
+        };
+        let io = |read_kb: u64, write_kb: u64| IoCounters {
+            read_bytes: read_kb * 1024,
+            write_bytes: write_kb * 1024,
+        };
+        let mut sampler = CpuSampler::default();
+        let mut procs = vec![process(1, "dd", 1), process(2, "secret", 1)];
+        procs[0].io = Some(io(100, 0));
+        sampler.update(&mut procs, stat(10));
+
+        procs[0].io = Some(io(400, 2048));
+        sampler.update(&mut procs, stat(12));
+        assert_eq!(procs[0].io_read_rate, 150.0);
+        assert_eq!(procs[0].io_write_rate, 1024.0);
+        assert_eq!(procs[1].io_write_rate, 0.0);
+    }
+
+    #[test]
+    fn test_cpu_sampler_short_interval_and_wrapped_counters() {
+        let hz = clock_ticks_per_second() as f64;
+        // Four CPUs but only two jiffies in total: half a jiffy each.
+        let stat = |total: u64| CpuStat {
+            user: total,
+            cpus: 4,
+            ..CpuStat::default()
+        };
+        let mut sampler = CpuSampler::default();
+        let mut procs = vec![process(1, "busy", 1), process(2, "reset", 1)];
+        procs[0].cpu_ticks = 100;
+        procs[0].min_faults = 10;
+        procs[1].cpu_ticks = 500;
+        procs[1].io = Some(IoCounters {
+            read_bytes: u64::MAX,
+            ..IoCounters::default()
+        });
+        sampler.update(&mut procs, stat(1000));
+
+        procs[0].cpu_ticks = 110;
+        procs[0].min_faults = 20;
+        procs[1].cpu_ticks = 5;
+        procs[1].io = Some(IoCounters::default());
+        sampler.update(&mut procs, stat(1002));
+        // Measured over one jiffy and capped at all four CPUs.
+        assert_eq!(procs[0].cpu_percent, 400.0);
+        assert_eq!(procs[0].min_fault_rate, 10.0 * hz);
+        // Counters that went backwards read as idle.
+        assert_eq!(procs[1].cpu_percent, 0.0);
+        assert_eq!(procs[1].io_read_rate, 0.0);
+    }
+}
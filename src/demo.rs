@@ -0,0 +1,133 @@
+//! Synthetic procfs for `--demo`: plausible figures for screenshots and for
+//! students on systems without `/proc`.
+
+use std::{cell::Cell, io};
+
+use crate::ProcReader;
+
+/// The demo processes: pid, name, command line, RSS in kB, and CPU ticks
+/// used per frame.
+const PROCESSES: [(u32, &str, &str, u64, u64); 8] = [
+    (1, "systemd", "/sbin/init splash", 12_840, 1),
+    (412, "sshd", "sshd: /usr/sbin/sshd -D", 8_204, 0),
+    (1290, "postgres", "postgres -D /srv/pg", 245_760, 12),
+    (1312, "nginx", "nginx: master process", 18_432, 3),
+    (2044, "firefox", "/usr/lib/firefox/firefox", 1_482_752, 35),
+    (2088, "code", "/usr/share/code/code", 893_440, 20),
+    (3100, "bash", "-bash", 5_120, 0),
+    (3101, "cargo", "cargo build --release", 412_672, 60),
+];
+
+/// Clock ticks that pass per frame on each of the four demo CPUs.
+const TICKS_PER_FRAME: u64 = 100;
+
+/// A [`ProcReader`] that makes up `/proc` instead of reading it.
+///
+/// The figures are fixed except for CPU times, which advance by one frame
+/// each time `/proc/stat` is read, and the RSS of `cargo`, which grows, so
+/// a running demo shows changing CPU usage. No file is ever opened.
+#[derive(Debug, Default)]
+pub struct DemoReader {
+    frame: Cell<u64>,
+}
+
+impl DemoReader {
+    fn file(&self, path: &str) -> Option<String> {
+        let frame = self.frame.get();
+        match path.strip_prefix("/proc/")? {
+            "meminfo" => Some(
+                "MemTotal:       16318412 kB\n\
+                 MemFree:         2921480 kB\n\
+                 MemAvailable:    9683312 kB\n\
+                 Buffers:          412088 kB\n\
+                 Cached:          6120344 kB\n\
+                 SwapTotal:       2097148 kB\n\
+                 SwapFree:        2097148 kB\n\
+                 HugePages_Total:       0\n\
+                 HugePages_Free:        0\n\
+                 Hugepagesize:       2048 kB\n"
+                    .to_string(),
+            ),
+            "stat" => {
+                let frame = frame + 1;
+                self.frame.set(frame);
+                let cpu = |n: u64| {
+                    let busy = n * TICKS_PER_FRAME / 4;
+                    format!(
+                        "{busy} 0 {} {} 10 0 0 0 0 0",
+                        busy / 3,
+                        n * TICKS_PER_FRAME - busy
+                    )
+                };
+                let mut stat = format!("cpu  {}\n", cpu(4 * frame));
+                for n in 0..4 {
+                    stat.push_str(&format!("cpu{n} {}\n", cpu(frame)));
+                }
+                Some(stat)
+            }
+            "loadavg" => Some("0.82 0.64 0.51 2/311 3101\n".to_string()),
+            "uptime" => Some(format!("{}.00 100000.00\n", 86_400 + frame)),
+            rest => {
+                let (pid, file) = rest.split_once('/')?;
+                let &(pid, name, cmdline, rss_kb, ticks) =
+                    PROCESSES.iter().find(|p| p.0.to_string() == pid)?;
+                let rss_kb = if name == "cargo" {
+                    rss_kb + frame * 2048
+                } else {
+                    rss_kb
+                };
+                match file {
+                    "comm" => Some(format!("{name}\n")),
+                    "status" => Some(format!(
+                        "Name:\t{name}\nState:\tS (sleeping)\nPPid:\t1\nUid:\t1000\t1000\t1000\t1000\n\
+                         VmHWM:\t{rss_kb} kB\nVmRSS:\t{rss_kb} kB\nThreads:\t1\n"
+                    )),
+                    "stat" => Some(format!(
+                        "{pid} ({name}) S 1 {pid} {pid} 34816 {pid} 0 {} 0 0 0 {} 0 0 0 20 0 1 0 {}",
+                        frame * 10,
+                        frame * ticks,
+                        1000 + pid,
+                    )),
+                    "cmdline" => Some(cmdline.replace(' ', "\0")),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+impl ProcReader for DemoReader {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.file(path)
+            .map(String::into_bytes)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{path} (demo)")))
+    }
+
+    fn read_dir(&self, path: &str) -> io::Result<Vec<String>> {
+        match path.trim_end_matches('/') {
+            "/proc" => Ok(PROCESSES.iter().map(|p| p.0.to_string()).collect()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{path} (demo)"),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Monitor, Options};
+
+    #[test]
+    fn test_demo_snapshot() {
+        let options = Options::parse(["--demo", "--once"].map(String::from)).unwrap();
+        let mut monitor = Monitor::demo(options);
+        let mut out = Vec::new();
+        monitor.run(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.starts_with("Memory: total=16318412kB"), "{out}");
+        assert!(out.contains("Tasks: 8 total"), "{out}");
+        assert!(out.contains("firefox"), "{out}");
+    }
+}
@@ -0,0 +1,77 @@
+//! Environment of a process from `/proc/<pid>/environ` (`--env`).
+
+use std::io;
+
+use crate::reader::ProcReader;
+
+/// Splits the NUL-separated `KEY=value` entries of `/proc/<pid>/environ`
+/// and sorts them.
+///
+/// Invalid UTF-8 is replaced rather than rejected.
+pub fn parse_environ(content: &[u8]) -> Vec<String> {
+    let mut vars: Vec<String> = content
+        .split(|&b| b == 0)
+        .filter(|var| !var.is_empty())
+        .map(|var| String::from_utf8_lossy(var).into_owned())
+        .collect();
+    vars.sort();
+    vars
+}
+
+/// Keeps the entries whose key contains `pattern` (`--grep`).
+pub fn filter_environ(vars: Vec<String>, pattern: &str) -> Vec<String> {
+    vars.into_iter()
+        .filter(|var| {
+            var.split('=')
+                .next()
+                .is_some_and(|key| key.contains(pattern))
+        })
+        .collect()
+}
+
+/// Reads the sorted environment of process `pid` under `base`.
+///
+/// Like smaps, the environment of other users' processes is only readable by
+/// root, so a permission error is reported with that hint.
+pub fn read_environ(reader: &dyn ProcReader, base: &str, pid: u32) -> io::Result<Vec<String>> {
+    let path = format!("{base}/{pid}/environ");
+    match reader.read(&path) {
+        Ok(content) => Ok(parse_environ(&content)),
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => Err(io::Error::new(
+            err.kind(),
+            format!(
+                "cannot read {path}: permission denied (only the owner and root can read a process's environment)"
+            ),
+        )),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Err(io::Error::new(
+            err.kind(),
+            format!("no process with pid {pid}"),
+        )),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_filter_environ() {
+        let content = b"USER=alice\0PATH=/usr/bin:/bin\0LANG=C.UTF-8\0PAGER=less\0";
+        let vars = parse_environ(content);
+        assert_eq!(
+            vars,
+            [
+                "LANG=C.UTF-8",
+                "PAGER=less",
+                "PATH=/usr/bin:/bin",
+                "USER=alice"
+            ]
+        );
+        assert_eq!(
+            filter_environ(vars, "PA"),
+            ["PAGER=less", "PATH=/usr/bin:/bin"]
+        );
+        assert!(parse_environ(b"").is_empty());
+    }
+}
@@ -0,0 +1,72 @@
+//! Processes left out of the listing (`--exclude-pids-from`).
+
+use std::{collections::HashSet, fs};
+
+use crate::Process;
+
+/// Pids and names to leave out, read once at startup.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExcludeList {
+    pub pids: HashSet<u32>,
+    pub names: HashSet<String>,
+}
+
+impl ExcludeList {
+    /// Reads and parses the file at `path`, see [`ExcludeList::parse`].
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+        Ok(Self::parse(&text))
+    }
+
+    /// Parses one pid or process name per line. Surrounding whitespace,
+    /// blank lines and lines starting with `#` are ignored; a line that is a
+    /// number is a pid, anything else a name matched exactly.
+    pub fn parse(text: &str) -> Self {
+        let mut list = Self::default();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.parse() {
+                Ok(pid) => {
+                    list.pids.insert(pid);
+                }
+                Err(_) => {
+                    list.names.insert(line.to_string());
+                }
+            }
+        }
+        list
+    }
+
+    /// Whether `p` is on the list by pid or by name.
+    pub fn contains(&self, p: &Process) -> bool {
+        self.pids.contains(&p.pid) || self.names.contains(&p.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::process;
+
+    #[test]
+    fn test_exclude_list() {
+        let list = ExcludeList::parse("# noisy agents\n42\n  node_exporter \n\n#7\n");
+        assert_eq!(list.pids, HashSet::from([42]));
+        assert_eq!(list.names, HashSet::from(["node_exporter".to_string()]));
+
+        let procs = [
+            process(1, "init", 10),
+            process(7, "sshd", 20),
+            process(42, "agent", 30),
+            process(99, "node_exporter", 40),
+        ];
+        let kept: Vec<u32> = procs
+            .iter()
+            .filter(|p| !list.contains(p))
+            .map(|p| p.pid)
+            .collect();
+        assert_eq!(kept, [1, 7]);
+    }
+}
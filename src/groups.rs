@@ -0,0 +1,74 @@
+//! Memory use summed per program name (`--group-summary`).
+
+use std::collections::HashMap;
+
+use crate::{Process, render::truncate_name};
+
+/// RSS summed over the processes that share a name, such as the workers of
+/// one program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameUsage {
+    pub name: String,
+    pub rss_kb: u64,
+    pub processes: usize,
+}
+
+/// Sums the RSS of `procs` per name, largest first, ties by name, and keeps
+/// the first `top` names.
+pub fn aggregate_by_name(procs: &[Process], top: usize) -> Vec<NameUsage> {
+    let mut totals: HashMap<&str, (u64, usize)> = HashMap::new();
+    for p in procs {
+        let entry = totals.entry(&p.name).or_default();
+        entry.0 += p.rss_kb;
+        entry.1 += 1;
+    }
+    let mut usage: Vec<NameUsage> = totals
+        .into_iter()
+        .map(|(name, (rss_kb, processes))| NameUsage {
+            name: name.to_string(),
+            rss_kb,
+            processes,
+        })
+        .collect();
+    usage.sort_by(|a, b| b.rss_kb.cmp(&a.rss_kb).then_with(|| a.name.cmp(&b.name)));
+    usage.truncate(top);
+    usage
+}
+
+/// Formats the by-name table of `--group-summary`.
+pub fn format_name_table(usage: &[NameUsage]) -> String {
+    let mut out = format!("{:<20} {:>10} {:>6}\n", "NAME", "RSS(kB)", "PROCS");
+    for u in usage {
+        out.push_str(&format!(
+            "{:<20} {:>10} {:>6}\n",
+            truncate_name(&u.name, 20),
+            u.rss_kb,
+            u.processes
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::process;
+
+    #[test]
+    fn test_aggregate_by_name() {
+        let procs = [
+            process(1, "postgres", 300),
+            process(2, "firefox", 900),
+            process(3, "postgres", 400),
+            process(4, "bash", 10),
+            process(5, "postgres", 500),
+        ];
+        let usage = aggregate_by_name(&procs, 2);
+        let rows: Vec<(&str, u64, usize)> = usage
+            .iter()
+            .map(|u| (u.name.as_str(), u.rss_kb, u.processes))
+            .collect();
+        assert_eq!(rows, [("postgres", 1200, 3), ("firefox", 900, 1)]);
+        assert_eq!(aggregate_by_name(&procs, 10).len(), 3);
+    }
+}
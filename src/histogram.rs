@@ -0,0 +1,66 @@
+//! How process memory is distributed by size (`--histogram`).
+
+use crate::Process;
+
+/// Labels of the [`bucket_by_rss`] buckets, smallest first.
+pub const RSS_BUCKETS: [&str; 5] = ["<1MB", "1-10MB", "10-100MB", "100MB-1GB", ">1GB"];
+
+/// Upper bounds (exclusive) of the first four buckets, in kB.
+const BUCKET_LIMITS_KB: [u64; 4] = [1 << 10, 10 << 10, 100 << 10, 1 << 20];
+
+/// Width of the bar of the fullest bucket.
+const BAR_WIDTH: usize = 40;
+
+/// Counts `procs` per RSS range of [`RSS_BUCKETS`].
+pub fn bucket_by_rss(procs: &[Process]) -> [usize; 5] {
+    let mut counts = [0; 5];
+    for p in procs {
+        let bucket = BUCKET_LIMITS_KB
+            .iter()
+            .position(|&limit| p.rss_kb < limit)
+            .unwrap_or(BUCKET_LIMITS_KB.len());
+        counts[bucket] += 1;
+    }
+    counts
+}
+
+/// Formats the `--histogram` table: one line per bucket with its count and
+/// a bar scaled to the fullest bucket.
+pub fn format_histogram(counts: &[usize; 5]) -> String {
+    let max = counts.iter().copied().max().unwrap_or(0).max(1);
+    let mut out = format!("{:<10} {:>6}\n", "RSS", "PROCS");
+    for (label, &count) in RSS_BUCKETS.iter().zip(counts) {
+        // Any non-empty bucket gets at least one mark.
+        let bar = (count * BAR_WIDTH).div_ceil(max);
+        out.push_str(&format!("{label:<10} {count:>6} {}\n", "#".repeat(bar)));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::process;
+
+    #[test]
+    fn test_bucket_by_rss() {
+        let procs: Vec<Process> = [0, 1023, 1024, 10_239, 10_240, 500_000, 1 << 20, 8 << 20]
+            .into_iter()
+            .zip(1..)
+            .map(|(rss, pid)| process(pid, "p", rss))
+            .collect();
+        assert_eq!(bucket_by_rss(&procs), [2, 2, 1, 1, 2]);
+        assert_eq!(bucket_by_rss(&[]), [0; 5]);
+    }
+
+    #[test]
+    fn test_format_histogram() {
+        let out = format_histogram(&[80, 20, 1, 0, 0]);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "RSS         PROCS");
+        assert_eq!(lines[1], format!("<1MB           80 {}", "#".repeat(40)));
+        assert_eq!(lines[2], format!("1-10MB         20 {}", "#".repeat(10)));
+        assert_eq!(lines[3], "10-100MB        1 #");
+        assert_eq!(lines[5], ">1GB            0 ");
+    }
+}
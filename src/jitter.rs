@@ -0,0 +1,70 @@
+//! Randomized refresh intervals (`--interval-jitter`).
+
+use std::{
+    process,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Spreads sleeps uniformly over `interval × (1 ± fraction)`, so that many
+/// monitors started together drift apart instead of sampling in lockstep.
+///
+/// The offsets are symmetric around zero, so the average interval stays at
+/// the configured value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Jitter {
+    pub fraction: f64,
+    state: u64,
+}
+
+impl Jitter {
+    /// Jitter of up to `±fraction` with a fixed `seed`.
+    pub fn new(fraction: f64, seed: u64) -> Self {
+        Self {
+            fraction,
+            state: seed,
+        }
+    }
+
+    /// Jitter seeded from the current time and pid, which differ between
+    /// monitors even when they start in the same second.
+    pub fn from_process_start(fraction: f64) -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos() as u64);
+        Self::new(fraction, nanos ^ u64::from(process::id()).rotate_left(32))
+    }
+
+    /// The next sleep for a configured `interval`.
+    pub fn next(&mut self, interval: Duration) -> Duration {
+        // Uniform in [-1, 1) from the top 53 bits.
+        let unit = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0;
+        interval.mul_f64((1.0 + self.fraction * unit).max(0.0))
+    }
+
+    /// splitmix64: tiny, fast and fine for spreading timers.
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jitter_stays_in_bounds_and_keeps_the_mean() {
+        let interval = Duration::from_secs(1);
+        let mut jitter = Jitter::new(0.2, 42);
+        let sleeps: Vec<f64> = (0..10_000)
+            .map(|_| jitter.next(interval).as_secs_f64())
+            .collect();
+        assert!(sleeps.iter().all(|&s| (0.8..=1.2).contains(&s)));
+        let mean = sleeps.iter().sum::<f64>() / sleeps.len() as f64;
+        assert!((mean - 1.0).abs() < 0.01, "mean {mean}");
+        assert!(sleeps.iter().any(|&s| s < 0.85) && sleeps.iter().any(|&s| s > 1.15));
+    }
+}
@@ -0,0 +1,424 @@
+//! Minimal JSON values, serialization for `--format json` and parsing for
+//! `--compare`.
+
+use std::fmt::{self, Display, Write};
+
+/// A JSON value. Objects keep their keys in insertion order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    UInt(u64),
+    Int(i64),
+    /// Non-finite values are written as `null`.
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Builds an object from `(key, value)` pairs.
+    pub fn object<K: Into<String>>(fields: impl IntoIterator<Item = (K, Json)>) -> Self {
+        Self::Object(fields.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    /// Serializes on a single line without any whitespace.
+    pub fn to_compact(&self) -> String {
+        self.to_string_indented(None)
+    }
+
+    /// Serializes with one key or element per line, indented by two spaces
+    /// per level.
+    pub fn to_pretty(&self) -> String {
+        self.to_string_indented(Some(2))
+    }
+
+    /// Serializes with `indent` spaces per nesting level, or on one line if
+    /// `indent` is `None`.
+    pub fn to_string_indented(&self, indent: Option<usize>) -> String {
+        let mut out = String::new();
+        self.write(&mut out, indent, 0)
+            .expect("writing to a String cannot fail");
+        out
+    }
+
+    /// Parses a single JSON value, allowing whitespace around it.
+    ///
+    /// Integers become [`Json::UInt`] or, when negative, [`Json::Int`]; any
+    /// number with a fraction or exponent becomes [`Json::Float`].
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = Parser { text, pos: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos < text.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    /// The value of `key` if this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Self::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Self::UInt(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Any number as a float.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Self::UInt(n) => Some(n as f64),
+            Self::Int(n) => Some(n as f64),
+            Self::Float(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Self::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    fn write(&self, out: &mut String, indent: Option<usize>, depth: usize) -> fmt::Result {
+        match self {
+            Self::Null => out.write_str("null"),
+            Self::Bool(b) => write!(out, "{b}"),
+            Self::UInt(n) => write!(out, "{n}"),
+            Self::Int(n) => write!(out, "{n}"),
+            Self::Float(x) if x.is_finite() => write!(out, "{x}"),
+            Self::Float(_) => out.write_str("null"),
+            Self::String(s) => write_string(out, s),
+            Self::Array(items) => {
+                write_container(out, ('[', ']'), items, indent, depth, |out, item| {
+                    item.write(out, indent, depth + 1)
+                })
+            }
+            Self::Object(fields) => write_container(
+                out,
+                ('{', '}'),
+                fields,
+                indent,
+                depth,
+                |out, (key, value)| {
+                    write_string(out, key)?;
+                    out.write_str(if indent.is_some() { ": " } else { ":" })?;
+                    value.write(out, indent, depth + 1)
+                },
+            ),
+        }
+    }
+}
+
+/// Writes `[a,b]` or `{..}`, putting each entry on its own line when
+/// indenting. Empty containers stay on one line.
+fn write_container<T>(
+    out: &mut String,
+    (open, close): (char, char),
+    entries: &[T],
+    indent: Option<usize>,
+    depth: usize,
+    mut write_entry: impl FnMut(&mut String, &T) -> fmt::Result,
+) -> fmt::Result {
+    out.push(open);
+    for (i, entry) in entries.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        if let Some(width) = indent {
+            write!(out, "\n{:1$}", "", width * (depth + 1))?;
+        }
+        write_entry(out, entry)?;
+    }
+    if let Some(width) = indent
+        && !entries.is_empty()
+    {
+        write!(out, "\n{:1$}", "", width * depth)?;
+    }
+    out.push(close);
+    Ok(())
+}
+
+fn write_string(out: &mut String, s: &str) -> fmt::Result {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if u32::from(c) < 0x20 => write!(out, "\\u{:04x}", u32::from(c))?,
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    Ok(())
+}
+
+/// Recursive-descent parser over `text`, positioned at byte `pos`.
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, what: &str) -> String {
+        format!("invalid JSON at byte {}: {what}", self.pos)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        if self.peek() != Some(byte) {
+            return Err(self.error(&format!("expected '{}'", byte as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => {
+                for (word, value) in [
+                    ("null", Json::Null),
+                    ("true", Json::Bool(true)),
+                    ("false", Json::Bool(false)),
+                ] {
+                    if self.text[self.pos..].starts_with(word) {
+                        self.pos += word.len();
+                        return Ok(value);
+                    }
+                }
+                Err(self.error("expected a value"))
+            }
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect(b'{')?;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(b':')?;
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut out = String::new();
+        loop {
+            let rest = &self.text[self.pos..];
+            let Some(c) = rest.chars().next() else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escape = self
+                        .peek()
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += 1;
+                    match escape {
+                        b'"' => out.push('"'),
+                        b'\\' => out.push('\\'),
+                        b'/' => out.push('/'),
+                        b'b' => out.push('\u{8}'),
+                        b'f' => out.push('\u{c}'),
+                        b'n' => out.push('\n'),
+                        b'r' => out.push('\r'),
+                        b't' => out.push('\t'),
+                        b'u' => out.push(self.unicode_escape()?),
+                        _ => return Err(self.error("unknown escape")),
+                    }
+                }
+                c if u32::from(c) < 0x20 => return Err(self.error("control character in string")),
+                c => out.push(c),
+            }
+        }
+    }
+
+    /// The character of a `\uXXXX` escape whose `\u` has been consumed,
+    /// combining a UTF-16 surrogate pair.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if !self.text[self.pos..].starts_with("\\u") {
+                return Err(self.error("unpaired surrogate"));
+            }
+            self.pos += 2;
+            let low = self.hex4()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(self.error("unpaired surrogate"));
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("invalid \\u escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .text
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| self.error("short \\u escape"))?;
+        let code = u32::from_str_radix(digits, 16).map_err(|_| self.error("invalid \\u escape"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        while matches!(
+            self.peek(),
+            Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+        ) {
+            self.pos += 1;
+        }
+        let text = &self.text[start..self.pos];
+        let integer = !text.contains(['.', 'e', 'E']);
+        let value = if integer && !text.starts_with('-') {
+            text.parse().map(Json::UInt).ok()
+        } else if integer {
+            text.parse().map(Json::Int).ok()
+        } else {
+            None
+        };
+        value
+            .or_else(|| text.parse().ok().map(Json::Float))
+            .ok_or_else(|| format!("invalid JSON at byte {start}: invalid number {text:?}"))
+    }
+}
+
+/// Compact serialization.
+impl Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_compact())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compact_and_pretty() {
+        let value = Json::object([
+            ("name", Json::String("tmux: \"server\"\n".to_string())),
+            ("rss_kb", Json::UInt(3896)),
+            ("cpu", Json::Float(1.5)),
+            ("tags", Json::Array(vec![])),
+            ("nested", Json::object([("ok", Json::Bool(true))])),
+        ]);
+        assert_eq!(
+            value.to_compact(),
+            r#"{"name":"tmux: \"server\"\n","rss_kb":3896,"cpu":1.5,"tags":[],"nested":{"ok":true}}"#
+        );
+        assert_eq!(
+            value.to_pretty(),
+            "{\n  \"name\": \"tmux: \\\"server\\\"\\n\",\n  \"rss_kb\": 3896,\n  \"cpu\": 1.5,\n  \
+             \"tags\": [],\n  \"nested\": {\n    \"ok\": true\n  }\n}"
+        );
+        assert_eq!(Json::Float(f64::NAN).to_compact(), "null");
+    }
+
+    #[test]
+    fn test_parse() {
+        let value = Json::object([
+            ("name", Json::String("tmux: \"server\"\n\u{1}é".to_string())),
+            ("rss_kb", Json::UInt(3896)),
+            ("delta", Json::Int(-12)),
+            ("cpu", Json::Float(1.5)),
+            ("cwd", Json::Null),
+            (
+                "tags",
+                Json::Array(vec![Json::Bool(true), Json::Bool(false)]),
+            ),
+            ("nested", Json::object([("ok", Json::Array(vec![]))])),
+        ]);
+        assert_eq!(Json::parse(&value.to_compact()), Ok(value.clone()));
+        assert_eq!(Json::parse(&value.to_pretty()), Ok(value));
+        assert_eq!(
+            Json::parse(r#" ["\ud83d\ude00", 2e3, -0.5] "#),
+            Ok(Json::Array(vec![
+                Json::String("😀".to_string()),
+                Json::Float(2000.0),
+                Json::Float(-0.5),
+            ]))
+        );
+        assert!(Json::parse(r#"{"a":1,}"#).is_err());
+        assert!(Json::parse("[1] 2").is_err());
+        assert!(Json::parse(r#""\ud83d""#).is_err());
+    }
+}
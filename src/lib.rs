@@ -0,0 +1,315 @@
+//! A minimal, educational "top-like" system monitor implemented in Rust.
+//!
+//! # Overview
+//!
+//! This crate demonstrates how to read basic system information from the
+//! Linux `/proc` filesystem and present it in a simple, continuously updating
+//! terminal view.
+//!
+//! It performs three main tasks:
+//!
+//! 1. Reads system memory usage from `/proc/meminfo`
+//! 2. Reads running processes from numeric directories in `/proc/<pid>/`
+//! 3. Extracts process name and resident memory size (RSS) from:
+//!    - `/proc/<pid>/comm`
+//!    - `/proc/<pid>/status`
+//!
+//! The printing logic displays the top N processes by memory usage.
+//!
+//! The modules correspond to the teaching worksheet Parts 1–8 (see
+//! `exercise.md`). The pure parsers such as [`MemInfo::parse_from_str`] and
+//! [`parse_process_status`] work on caller-supplied text, so the same logic
+//! can be embedded in applications that obtain `/proc` contents some other
+//! way, e.g. over SSH.
+
+pub mod adaptive;
+pub mod alert;
+pub mod average;
+#[cfg(feature = "extras")]
+pub mod caps;
+pub mod cgroup;
+pub mod cli;
+pub mod clock;
+pub mod compare;
+pub mod config;
+pub mod cpu;
+pub mod demo;
+pub mod environ;
+pub mod exclude;
+pub mod groups;
+pub mod histogram;
+pub mod jitter;
+pub mod json;
+pub mod limits;
+pub mod log;
+pub mod meminfo;
+pub mod monitor;
+#[cfg(feature = "extras")]
+pub mod numa;
+pub mod oom;
+pub mod output;
+mod parse;
+pub mod pgrep;
+pub mod process;
+pub mod reader;
+pub mod reload;
+pub mod render;
+pub mod smaps;
+pub mod subtree;
+pub mod system;
+pub mod time;
+pub mod users;
+pub mod watch;
+
+pub use adaptive::AdaptiveInterval;
+pub use alert::{StuckAlert, ThresholdAlert};
+pub use average::MovingAverage;
+#[cfg(feature = "extras")]
+pub use caps::{CAPABILITY_NAMES, decode_capabilities, format_capabilities, parse_cap_eff};
+pub use cgroup::{
+    CgroupMemory, parse_cgroup_procs, parse_systemd_unit, read_cgroup_memory, read_cgroup_procs,
+    read_process_unit,
+};
+pub use cli::{MIN_INTERVAL, Options, USAGE};
+pub use clock::{Clock, SystemClock};
+pub use compare::{SavedProcess, SavedSnapshot, format_comparison, format_growth_report};
+pub use config::Config;
+pub use cpu::{CpuSampler, CpuStat};
+pub use demo::DemoReader;
+pub use environ::{filter_environ, parse_environ, read_environ};
+pub use exclude::ExcludeList;
+pub use groups::{NameUsage, aggregate_by_name, format_name_table};
+pub use histogram::{RSS_BUCKETS, bucket_by_rss, format_histogram};
+pub use jitter::Jitter;
+pub use json::Json;
+pub use limits::{Limit, NEAR_EXHAUSTION_PERCENT, format_limits, parse_limits, read_limits};
+pub use meminfo::{
+    MemDelta, MemField, MemInfo, MemMode, MemUnit, RssUnit, SWAPPING_THRESHOLD, UnitBase,
+    convert_kb,
+};
+pub use monitor::{
+    EXIT_ALERT, EXIT_BAD_EXCLUDE, EXIT_EMPTY, EXIT_WATCHDOG, Monitor, ProcessSnapshot, TOP_N,
+    collect_processes, snapshot_fingerprint,
+};
+#[cfg(feature = "extras")]
+pub use numa::{NumaPlacement, dominant_node, parse_numa_maps, read_numa_placement};
+pub use oom::{OomRisk, read_oom_score};
+pub use output::{FlushPolicy, FlushWriter};
+pub use pgrep::find_pids;
+pub use process::{
+    DeltaMetric, IoCounters, NameSource, ProcStat, Process, RssBreakdown, SortField, SortKey,
+    SortOrder, StateSummary, TopMetric, UsageRange, apply_classify_by_arg, apply_name_source,
+    apply_sort_key, classify_by_arg, compare_delta, compare_top, count_sockets,
+    count_until_percent, decode_tty, list_processes_from, list_processes_rss_only, parse_cmdline,
+    parse_ns_pid, parse_process_io, parse_process_status, parse_process_status_from_bytes,
+    parse_rss_breakdown, parse_schedstat, parse_stat, parse_statm, parse_wchan, read_one_process,
+    read_one_process_from, read_process, read_process_cmdline, read_process_comm, read_process_cwd,
+    read_process_details, read_process_io, read_process_rss_only, read_process_stat,
+    read_process_statm, read_process_status, read_process_wchan, read_schedstat,
+    read_thread_cpu_ticks, resolve_name, retain_min_cpu, sort_key_warning, sort_processes,
+    sort_processes_by, summarize_states, top_process, top_processes,
+};
+pub use reader::{CallbackReader, FsReader, ProcReader, looks_like_procfs};
+pub use render::{
+    ClearMode, ColorMode, ColorRole, ColorScheme, ColumnRegistry, JSON_SCHEMA_VERSION,
+    OutputFormat, ProcColumn, column_explanation, format_batch_header, format_bordered_table,
+    format_cpu_detail, format_cpu_share, format_explanation, format_mem_detail,
+    format_mem_explanation, format_si, format_top_processes, format_top_processes_with,
+    matches_pattern, mem_cell_color, print_top_processes, snapshot_json, stdout_is_tty, strip_ansi,
+    truncate_cmdline, truncate_name,
+};
+pub use smaps::{MapKind, SmapsSummary, read_smaps};
+pub use subtree::{Subtree, format_subtree, subtree};
+pub use system::{FileNr, LoadAvg};
+pub use time::TimeFormat;
+pub use users::{
+    OTHER_USERS, UserUsage, aggregate_by_user, format_user_table, parse_passwd, read_passwd,
+    top_per_user,
+};
+pub use watch::{WatchSet, Watched, format_watch_table};
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::{
+        cell::{Cell, RefCell},
+        collections::BTreeMap,
+        env, fs, io,
+        ops::Deref,
+        path::{Path, PathBuf},
+        process,
+        time::{Duration, Instant, SystemTime},
+    };
+
+    use crate::{Clock, ProcReader, Process};
+
+    /// Builds a process with the given identity and RSS, everything else default.
+    pub fn process(pid: u32, name: &str, rss_kb: u64) -> Process {
+        Process {
+            pid,
+            name: name.to_string(),
+            rss_kb,
+            vm_hwm: rss_kb,
+            ..Process::default()
+        }
+    }
+
+    /// Compares `actual` byte for byte with `src/testdata/<name>`, or
+    /// rewrites that file instead when the `BLESS` environment variable is
+    /// set to `1`.
+    pub fn assert_golden(name: &str, actual: &str) {
+        let path = format!("{}/src/testdata/{name}", env!("CARGO_MANIFEST_DIR"));
+        if env::var("BLESS").as_deref() == Ok("1") {
+            fs::write(&path, actual).unwrap_or_else(|err| panic!("writing {path}: {err}"));
+            return;
+        }
+        let expected = fs::read_to_string(&path)
+            .unwrap_or_else(|err| panic!("reading {path}: {err} (run with BLESS=1 to create it)"));
+        assert!(
+            actual == expected,
+            "{name} does not match the golden file (run with BLESS=1 to update it)\n\
+             --- expected\n{expected}--- actual\n{actual}"
+        );
+    }
+
+    /// A path in the temporary directory, unique to the test process, that
+    /// is removed with everything below it when dropped, even if the test
+    /// panics.
+    #[derive(Debug)]
+    pub struct TempPath(PathBuf);
+
+    impl TempPath {
+        pub fn new(name: &str) -> Self {
+            Self(env::temp_dir().join(format!("procinfo-{name}-{}", process::id())))
+        }
+    }
+
+    impl Deref for TempPath {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl AsRef<Path> for TempPath {
+        fn as_ref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            // Never created is fine too.
+            let _ = fs::remove_dir_all(&self.0).or_else(|_| fs::remove_file(&self.0));
+        }
+    }
+
+    /// In-memory procfs that records every file and directory read.
+    #[derive(Debug, Default)]
+    pub struct MockReader {
+        files: BTreeMap<String, Vec<u8>>,
+        reads: RefCell<Vec<String>>,
+        dir_reads: RefCell<Vec<String>>,
+    }
+
+    impl MockReader {
+        pub fn add_file(&mut self, path: &str, content: impl Into<Vec<u8>>) {
+            self.files.insert(path.to_string(), content.into());
+        }
+
+        /// Adds `/proc/<pid>/{comm,status,stat}` for a process on pts/0.
+        pub fn add_process(&mut self, pid: u32, name: &str, rss_kb: u64) {
+            self.add_file(&format!("/proc/{pid}/comm"), format!("{name}\n"));
+            self.add_file(
+                &format!("/proc/{pid}/status"),
+                format!("Name:\t{name}\nVmHWM:\t{rss_kb} kB\nVmRSS:\t{rss_kb} kB\n"),
+            );
+            self.add_file(
+                &format!("/proc/{pid}/stat"),
+                format!(
+                    "{pid} ({name}) S 1 {pid} {pid} 34816 {pid} 0 0 0 0 0 10 5 0 0 20 0 1 0 100"
+                ),
+            );
+        }
+
+        /// Paths read so far, in order.
+        pub fn reads(&self) -> Vec<String> {
+            self.reads.borrow().clone()
+        }
+
+        /// Directories listed so far, in order.
+        pub fn dir_reads(&self) -> Vec<String> {
+            self.dir_reads.borrow().clone()
+        }
+    }
+
+    impl ProcReader for MockReader {
+        fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+            self.reads.borrow_mut().push(path.to_string());
+            self.files
+                .get(path)
+                .cloned()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+        }
+
+        fn read_dir(&self, path: &str) -> io::Result<Vec<String>> {
+            self.dir_reads.borrow_mut().push(path.to_string());
+            let prefix = format!("{}/", path.trim_end_matches('/'));
+            let mut names: Vec<String> = self
+                .files
+                .keys()
+                .filter_map(|key| key.strip_prefix(&prefix))
+                .filter_map(|rest| rest.split('/').next())
+                .map(str::to_string)
+                .collect();
+            names.dedup();
+            if names.is_empty() {
+                return Err(io::ErrorKind::NotFound.into());
+            }
+            Ok(names)
+        }
+    }
+
+    /// A clock that only moves when told to: by [`MockClock::advance`], or by
+    /// `sleep`, which returns immediately after advancing by the requested time.
+    #[derive(Debug)]
+    pub struct MockClock {
+        start: Instant,
+        system_start: SystemTime,
+        elapsed: Cell<Duration>,
+    }
+
+    impl MockClock {
+        /// A clock whose wall time starts at `system_start`.
+        pub fn new(system_start: SystemTime) -> Self {
+            Self {
+                start: Instant::now(),
+                system_start,
+                elapsed: Cell::new(Duration::ZERO),
+            }
+        }
+
+        pub fn advance(&self, duration: Duration) {
+            self.elapsed.set(self.elapsed.get() + duration);
+        }
+
+        /// Total time advanced so far.
+        pub fn elapsed(&self) -> Duration {
+            self.elapsed.get()
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            self.start + self.elapsed.get()
+        }
+
+        fn system_now(&self) -> SystemTime {
+            self.system_start + self.elapsed.get()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            self.advance(duration);
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! Resource limits of a process from `/proc/<pid>/limits` (`--limits`).
+
+use std::io;
+
+use crate::reader::ProcReader;
+
+/// Percentage of the open files limit from which it is flagged as near
+/// exhaustion.
+pub const NEAR_EXHAUSTION_PERCENT: f64 = 80.0;
+
+/// One row of `/proc/<pid>/limits`; `None` stands for `unlimited`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Limit {
+    /// e.g. `Max open files`
+    pub name: String,
+    pub soft: Option<u64>,
+    pub hard: Option<u64>,
+    /// e.g. `files`, empty for limits without a unit such as `Max nice priority`.
+    pub units: String,
+}
+
+/// Parses `/proc/<pid>/limits`.
+///
+/// The file is a fixed-width table whose column names have spaces in them
+/// (`Soft Limit`), and so do the limit names (`Max open files`), so the
+/// rows are cut at the offsets the columns have in the header line rather
+/// than split on whitespace. Rows with a value that is neither a number
+/// nor `unlimited` are skipped.
+pub fn parse_limits(content: &str) -> Result<Vec<Limit>, String> {
+    let mut lines = content.lines();
+    let header = lines.next().unwrap_or_default();
+    let column = |name: &str| {
+        header
+            .find(name)
+            .ok_or_else(|| format!("no {name:?} column in the limits header: {header:?}"))
+    };
+    let (soft_at, hard_at, units_at) = (
+        column("Soft Limit")?,
+        column("Hard Limit")?,
+        column("Units")?,
+    );
+    let value = |text: &str| match text {
+        "unlimited" => Some(None),
+        n => n.parse().ok().map(Some),
+    };
+    Ok(lines
+        .filter_map(|line| {
+            let cut = |from: usize, to: usize| {
+                line.get(from.min(line.len())..to.min(line.len()))
+                    .unwrap_or_default()
+                    .trim()
+            };
+            Some(Limit {
+                name: cut(0, soft_at).to_string(),
+                soft: value(cut(soft_at, hard_at))?,
+                hard: value(cut(hard_at, units_at))?,
+                units: cut(units_at, line.len()).to_string(),
+            })
+        })
+        .filter(|limit| !limit.name.is_empty())
+        .collect())
+}
+
+/// Reads the limits of process `pid` under `base`, see [`parse_limits`].
+pub fn read_limits(reader: &dyn ProcReader, base: &str, pid: u32) -> io::Result<Vec<Limit>> {
+    let path = format!("{base}/{pid}/limits");
+    match reader.read_to_string(&path) {
+        Ok(content) => parse_limits(&content)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{path}: {err}"))),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Err(io::Error::new(
+            err.kind(),
+            format!("no process with pid {pid}"),
+        )),
+        Err(err) => Err(err),
+    }
+}
+
+/// Formats `limits` as an aligned table.
+///
+/// With `open_fds`, the number of entries in `/proc/<pid>/fd`, the
+/// `Max open files` row gets the usage of its soft limit appended, flagged
+/// with `!` from [`NEAR_EXHAUSTION_PERCENT`] on.
+pub fn format_limits(limits: &[Limit], open_fds: Option<usize>) -> String {
+    let show =
+        |value: Option<u64>| value.map_or_else(|| "unlimited".to_string(), |n| n.to_string());
+    let rows: Vec<[String; 4]> = limits
+        .iter()
+        .map(|limit| {
+            [
+                limit.name.clone(),
+                show(limit.soft),
+                show(limit.hard),
+                limit.units.clone(),
+            ]
+        })
+        .collect();
+    let header = ["Limit", "Soft", "Hard", "Units"].map(String::from);
+    let mut widths = header.clone().map(|h| h.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let line = |row: &[String; 4]| {
+        let [w0, w1, w2, w3] = widths;
+        let line = format!(
+            "{:<w0$}  {:>w1$}  {:>w2$}  {:<w3$}",
+            row[0], row[1], row[2], row[3]
+        );
+        line.trim_end().to_string()
+    };
+    let mut out = line(&header);
+    out.push('\n');
+    for (row, limit) in rows.iter().zip(limits) {
+        out.push_str(&line(row));
+        if limit.name == "Max open files"
+            && let (Some(open), Some(soft)) = (open_fds, limit.soft)
+            && soft > 0
+        {
+            let percent = open as f64 * 100.0 / soft as f64;
+            let flag = if percent >= NEAR_EXHAUSTION_PERCENT {
+                " !"
+            } else {
+                ""
+            };
+            out.push_str(&format!("  ({open} open, {percent:.0}%){flag}"));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: &str = "\
+Limit                     Soft Limit           Hard Limit           Units
+Max cpu time              unlimited            unlimited            seconds
+Max stack size            8388608              unlimited            bytes
+Max processes             63353                63353                processes
+Max open files            1024                 524288               files
+Max nice priority         0                    0
+";
+
+    #[test]
+    fn test_parse_limits() {
+        let limits = parse_limits(LIMITS).unwrap();
+        assert_eq!(limits.len(), 5);
+        assert_eq!(
+            limits[1],
+            Limit {
+                name: "Max stack size".to_string(),
+                soft: Some(8_388_608),
+                hard: None,
+                units: "bytes".to_string(),
+            }
+        );
+        let open_files = limits.iter().find(|l| l.name == "Max open files").unwrap();
+        assert_eq!(
+            (open_files.soft, open_files.hard),
+            (Some(1024), Some(524_288))
+        );
+        assert_eq!(limits[4].units, "");
+
+        assert!(parse_limits("Max cpu time  unlimited\n").is_err());
+    }
+
+    #[test]
+    fn test_format_limits_flags_open_files() {
+        let limits = parse_limits(LIMITS).unwrap();
+        let table = format_limits(&limits, Some(1000));
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "Limit                   Soft       Hard  Units");
+        assert_eq!(
+            lines[4],
+            "Max open files          1024     524288  files  (1000 open, 98%) !"
+        );
+        assert!(!format_limits(&limits, Some(10)).contains('!'));
+    }
+}
@@ -0,0 +1,47 @@
+//! Diagnostics about skipped reads on stderr (`--verbose`).
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// Turns [`warn`] on or off for the whole process.
+pub fn set_verbose(verbose: bool) {
+    VERBOSE.store(verbose, Ordering::Relaxed);
+}
+
+/// Prints `warning: {msg}` to stderr if `--verbose` is on.
+pub fn warn(msg: &str) {
+    #[cfg(test)]
+    if testing::capture(msg) {
+        return;
+    }
+    if VERBOSE.load(Ordering::Relaxed) {
+        eprintln!("warning: {msg}");
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::cell::RefCell;
+
+    thread_local! {
+        static CAPTURED: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+    }
+
+    /// Records `msg` if the current thread is inside [`captured`].
+    pub(super) fn capture(msg: &str) -> bool {
+        CAPTURED.with_borrow_mut(|captured| {
+            captured
+                .as_mut()
+                .map(|messages| messages.push(msg.to_string()))
+                .is_some()
+        })
+    }
+
+    /// Runs `f` as if `--verbose` was given and returns what it warned about.
+    pub fn captured(f: impl FnOnce()) -> Vec<String> {
+        CAPTURED.set(Some(Vec::new()));
+        f();
+        CAPTURED.take().unwrap_or_default()
+    }
+}
@@ -1,201 +1,180 @@
-//! A minimal, educational "top-like" system monitor implemented in Rust.
+//! Command line entry point.
 //!
-//! # Overview
-//!
-//! This program demonstrates how to read basic system information from the
-//! Linux `/proc` filesystem and present it in a simple, continuously updating
-//! terminal view.
-//!
-//! It performs three main tasks:
-//!
-//! 1. Reads system memory usage from `/proc/meminfo`
-//! 2. Reads running processes from numeric directories in `/proc/<pid>/`
-//! 3. Extracts process name and resident memory size (RSS) from:
-//!    - `/proc/<pid>/comm`
-//!    - `/proc/<pid>/status`
-//!
-//! The printing logic displays the top N processes by memory usage.
-//!
-//! This file contains only TODO markers that correspond to the teaching
-//! worksheet Parts 1–8 (e.g., TODO-Part3).
-
-use std::{fmt::Display, fs, io, thread, time::Duration};
+//! See the library crate for the parsing, formatting and refresh loop.
 
-// ============================================================================
-// Part 2 – Memory parsing
-// ============================================================================
+use std::{
+    env, fs,
+    io::{self, Write},
+    process::{self, ExitCode},
+};
 
-/// Struct to store information from `/proc/meminfo`.
-struct MemInfo {
-    total: u64,
-    available: u64,
-}
+use procinfo_rust_exercise::{
+    Config, FsReader, Monitor, ProcReader, SavedSnapshot, USAGE, filter_environ, find_pids,
+    format_comparison, format_limits, log, looks_like_procfs, read_environ, read_limits,
+    read_smaps, reload,
+};
 
-impl MemInfo {
-    /// Reads `/proc/meminfo` from a path and delegates to parse_meminfo.
-    ///
-    /// TODO-Part2:
-    /// - Read file with fs::read_to_string
-    /// - Call parse_meminfo
-    fn from_file(_path: &str) -> io::Result<Self> {
-        // TODO-Part2
-        unimplemented!()
+/// Runs the monitor.
+///
+/// Exit codes: 0 on success, 1 when `--once` is combined with an alert
+/// threshold that the snapshot exceeded, `--maps`, `--env`, `--limits` or
+/// `--compare` failed, `--pgrep` matched nothing or `--fail-if-empty` found
+/// no process, 2 for invalid arguments, 4 when the loop gave up after
+/// repeated read failures.
+fn main() -> io::Result<ExitCode> {
+    let config = Config::from_process(env::args().skip(1));
+    let (config, options) = match config.and_then(|c| Ok((c.resolve()?, c))) {
+        Ok((options, config)) => (config, options),
+        Err(err) => {
+            eprintln!("error: {err}\n\n{USAGE}");
+            return Ok(ExitCode::from(2));
+        }
+    };
+    log::set_verbose(options.verbose);
+    if options.help {
+        println!("{USAGE}");
+        return Ok(ExitCode::SUCCESS);
     }
-
-    /// Parses the content of `/proc/meminfo`.
-    ///
-    /// TODO-Part2:
-    /// - Parse the MemTotal line
-    /// - Parse the MemAvailable line
-    /// - Use iterators and split_whitespace
-    fn parse_from_str(_content: &str) -> io::Result<Self> {
-        // TODO-Part2
-        unimplemented!()
+    if options.print_config {
+        return Ok(match config.to_json() {
+            Ok(json) => {
+                println!("{}", json.to_pretty());
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                eprintln!("error: {err}");
+                ExitCode::from(2)
+            }
+        });
     }
-
-    /// Calculate the amount of used memory.
-    ///
-    /// TODO-Part2:
-    /// - Implement calculation of used memory.
-    fn used(&self) -> u64 {
-        // TODO-Part2
-        unimplemented!()
+    if options.check_config {
+        return Ok(match options.check() {
+            Ok(()) => {
+                println!("{options:#?}");
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                eprintln!("error: {err}");
+                ExitCode::from(2)
+            }
+        });
     }
-}
 
-// Make `MemInfo` printable
-impl Display for MemInfo {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // TODO-Part2:
-        // - Implement fmt to return 'Memory: total=123kB free=456kB used=789kB'
-        write!(f, "",)
+    let root = options.proc_root().to_string();
+    if options.proc_root.is_some() && !looks_like_procfs(&FsReader, &root) {
+        eprintln!("warning: {root} doesn't look like a procfs (no meminfo or no pid directories)");
     }
-}
-
-// ============================================================================
-// Part 3 + Part 4 + Part 5 – Process parsing
-// ============================================================================
-
-/// Parses `VmRSS` from `/proc/<pid>/status`.
-///
-/// Example line: `VmRSS:   1234 kB`
-///
-/// TODO-Part3:
-/// - Find the line beginning with "VmRSS:". Use .lines().find(...)
-/// - Extract the number from the split fields
-/// - Convert to u64
-pub fn parse_process_status(_status: &str) -> Option<u64> {
-    // TODO-Part3
-    unimplemented!()
-}
-
-/// Reads the `/proc/<pid>/status` file.
-///
-/// TODO-Part3:
-/// - Read file
-/// - Call parse_process_status
-pub fn read_process_status(_base: &str, _pid: &str) -> Option<u64> {
-    // TODO-Part3
-    unimplemented!()
-}
-
-/// Reads the `/proc/<pid>/comm` file to get the process name.
-///
-/// TODO-Part4:
-/// - Read file using fs::read_to_string
-/// - Trim newline
-pub fn read_process_comm(_base: &str, _pid: &str) -> String {
-    // TODO-Part4
-    unimplemented!()
-}
 
-/// Combines the process name and memory usage.
-///
-/// TODO-Part5:
-/// - Call read_process_comm
-/// - Call read_process_status
-/// - Return Some((name, rss_kb)) or None
-pub fn read_process(_base: &str, _pid: &str) -> Option<(String, u64)> {
-    // TODO-Part5
-    unimplemented!()
-}
-
-// ============================================================================
-// Part 6 – Process listing
-// ============================================================================
-
-/// Scans `/proc`, filters numeric directories, and collects process info.
-///
-/// TODO-Part6:
-/// - Iterate through fs::read_dir
-/// - Filter entries where the directory name is numeric
-/// - Call read_process for each PID
-/// - Collect into a Vec<(pid, name, rss_kb)>
-pub fn list_processes_from(_base: &str) -> io::Result<Vec<(String, String, u64)>> {
-    // TODO-Part6
-    unimplemented!()
-}
-
-// ============================================================================
-// Part 7 – Printing
-// ============================================================================
-
-/// Prints the top N processes using iter().take(n).
-///
-/// TODO-Part7:
-/// - Use procs.iter().take(n)
-/// - Print formatted rows
-pub fn print_top_processes(_procs: &[(String, String, u64)], _n: usize) {
-    // TODO-Part7
-    unimplemented!()
-}
-
-// ============================================================================
-// Part 8 – Main loop
-// ============================================================================
-
-fn main() -> io::Result<()> {
-    loop {
-        // TODO-Part8: Call read_meminfo_from("/proc/meminfo")
-        // let meminfo = read_meminfo_from("/proc/meminfo")?;
-
-        // TODO-Part8: Print memory summary
-
-        // TODO-Part8: Call list_processes_from("/proc")
-        // let mut procs = list_processes_from("/proc")?;
-
-        // TODO-Part8: Sort descending by memory usage
-
-        // TODO-Part8: print_top_processes(&procs, 5);
+    if let Some(pid) = options.maps {
+        return match read_smaps(&FsReader, &root, pid) {
+            Ok(summary) => {
+                write_stdout(|out| writeln!(out, "{summary}"))?;
+                Ok(ExitCode::SUCCESS)
+            }
+            Err(err) => {
+                eprintln!("error: {err}");
+                Ok(ExitCode::FAILURE)
+            }
+        };
+    }
 
-        thread::sleep(Duration::from_secs(1));
+    if let Some(pid) = options.env {
+        return match read_environ(&FsReader, &root, pid) {
+            Ok(vars) => {
+                let vars = match &options.grep {
+                    Some(key) => filter_environ(vars, key),
+                    None => vars,
+                };
+                write_stdout(|out| vars.iter().try_for_each(|var| writeln!(out, "{var}")))?;
+                Ok(ExitCode::SUCCESS)
+            }
+            Err(err) => {
+                eprintln!("error: {err}");
+                Ok(ExitCode::FAILURE)
+            }
+        };
     }
-}
 
-// ============================================================================
-// Tests – Part 2 and Part 3 starting point
-// ============================================================================
+    if let Some(pid) = options.limits {
+        return match read_limits(&FsReader, &root, pid) {
+            Ok(limits) => {
+                let open_fds = FsReader.read_dir(&format!("{root}/{pid}/fd")).ok();
+                let table = format_limits(&limits, open_fds.map(|fds| fds.len()));
+                write_stdout(|out| write!(out, "{table}"))?;
+                Ok(ExitCode::SUCCESS)
+            }
+            Err(err) => {
+                eprintln!("error: {err}");
+                Ok(ExitCode::FAILURE)
+            }
+        };
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    if let Some(pattern) = &options.pgrep {
+        // Like pgrep, never list ourselves.
+        let pids = find_pids(&FsReader, &root, pattern, options.pgrep_full)
+            .map(|pids| pids.into_iter().filter(|&pid| pid != process::id()));
+        return match pids.map(Vec::from_iter) {
+            Ok(pids) if pids.is_empty() => Ok(ExitCode::FAILURE),
+            Ok(pids) => {
+                write_stdout(|out| pids.iter().try_for_each(|pid| writeln!(out, "{pid}")))?;
+                Ok(ExitCode::SUCCESS)
+            }
+            Err(err) => {
+                eprintln!("error: {err}");
+                Ok(ExitCode::FAILURE)
+            }
+        };
+    }
 
-    #[test]
-    fn test_parse_meminfo() {
-        let input = "\
-MemTotal:       16384256 kB
-SomeOtherValue:  123567 kB
-MemAvailable:    2345678 kB";
+    if let Some((a, b)) = &options.compare {
+        let load = |path: &str| {
+            let text = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+            SavedSnapshot::parse(&text).map_err(|err| format!("{path}: {err}"))
+        };
+        return match load(a).and_then(|a| Ok((a, load(b)?))) {
+            Ok((a, b)) => {
+                let report = format_comparison(&a, &b);
+                write_stdout(|out| write!(out, "{report}"))?;
+                Ok(ExitCode::SUCCESS)
+            }
+            Err(err) => {
+                eprintln!("error: {err}");
+                Ok(ExitCode::FAILURE)
+            }
+        };
+    }
 
-        let meminfo = MemInfo::parse_from_str(input).unwrap();
-        assert_eq!(meminfo.total, 16384256);
-        assert_eq!(meminfo.available, 2345678);
+    // Flushed as --flush says and once more at the end; a closed pipe ends
+    // the run with 0.
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    // Only worth keeping the process alive on SIGHUP if there is a file to
+    // re-read and a loop to apply it in.
+    if config.file.is_some() && !options.demo && !options.is_last_frame(1) {
+        reload::install_sighup_handler();
     }
+    let code = if options.demo {
+        Monitor::demo(options)
+            .with_config(config)
+            .run_to_output(&mut out)?
+    } else {
+        Monitor::new(options)
+            .with_config(config)
+            .run_to_output(&mut out)?
+    };
+    match out.flush() {
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {}
+        result => result?,
+    }
+    Ok(ExitCode::from(code))
+}
 
-    #[test]
-    fn test_parse_process_status() {
-        let input = "Name: myproc\nVmRSS:   1234 kB\n";
-        let mem = parse_process_status(input);
-        assert_eq!(mem, Some(1234));
+/// Writes to the locked stdout with `write`. As in the refresh loop, a
+/// closed pipe counts as success.
+fn write_stdout(write: impl FnOnce(&mut io::StdoutLock) -> io::Result<()>) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    match write(&mut out).and_then(|()| out.flush()) {
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
     }
 }
@@ -0,0 +1,819 @@
+//! Part 2 – Memory parsing.
+
+use std::{fmt::Display, fs, io};
+
+use crate::parse::{describe_line, malformed_line, parse_field_bytes};
+
+/// The `/proc/meminfo` lines that [`MemInfo`] reads.
+const FIELDS: [&str; 10] = [
+    "MemTotal:",
+    "MemAvailable:",
+    "MemFree:",
+    "Buffers:",
+    "Cached:",
+    "SwapTotal:",
+    "SwapFree:",
+    "HugePages_Total:",
+    "HugePages_Free:",
+    "Hugepagesize:",
+];
+
+/// Struct to store information from `/proc/meminfo`.
+///
+/// Memory fields are in kB (KiB, 1024 bytes) exactly as the kernel reports
+/// them; use the `*_bytes` accessors to get bytes. The hugepage counts are
+/// numbers of pages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemInfo {
+    /// `MemTotal`: usable physical RAM in kB.
+    pub total: u64,
+    /// `MemAvailable`: estimate of memory available without swapping, in kB.
+    pub available: u64,
+    /// `MemFree`: memory not used for anything, in kB.
+    pub free: u64,
+    /// `Buffers`: temporary storage for raw disk blocks, in kB.
+    pub buffers: u64,
+    /// `Cached`: page cache, in kB.
+    pub cached: u64,
+    /// `SwapTotal`: swap space, in kB.
+    pub swap_total: u64,
+    /// `SwapFree`: unused swap space, in kB.
+    pub swap_free: u64,
+    /// `HugePages_Total`: size of the hugepage pool, in pages.
+    pub hugepages_total: u64,
+    /// `HugePages_Free`: hugepages not yet allocated, in pages.
+    pub hugepages_free: u64,
+    /// `Hugepagesize`: size of one hugepage in kB.
+    pub hugepage_size_kb: u64,
+}
+
+/// [`MemInfo::swap_pressure`] above which [`MemInfo::is_swapping`] is true.
+pub const SWAPPING_THRESHOLD: f64 = 0.05;
+
+impl MemInfo {
+    /// Reads `/proc/meminfo` from a path and delegates to [`MemInfo::parse_from_str`].
+    pub fn from_file(path: &str) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        Self::parse_from_str(&content)
+    }
+
+    /// Parses the content of `/proc/meminfo`.
+    ///
+    /// Both `MemTotal` and `MemAvailable` must be present, otherwise an
+    /// `InvalidData` error is returned. Other lines are ignored, so the input
+    /// may be a full copy of the file or just an excerpt.
+    ///
+    /// ```
+    /// use procinfo_rust_exercise::MemInfo;
+    ///
+    /// let meminfo = MemInfo::parse_from_str("MemTotal: 2048 kB\nMemAvailable: 512 kB\n")?;
+    /// assert_eq!(meminfo.total, 2048);
+    /// assert_eq!(meminfo.used(), 1536);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn parse_from_str(content: &str) -> io::Result<Self> {
+        Self::parse_from_bytes(content.as_bytes())
+    }
+
+    /// Like [`MemInfo::parse_from_str`], but for `--strict`: a line of one
+    /// of the read fields whose value does not parse is an `InvalidData`
+    /// error naming that line, instead of counting as a missing field.
+    pub fn parse_strict(content: &str) -> io::Result<Self> {
+        if let Some((number, line)) = malformed_line(content.as_bytes(), &FIELDS) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("malformed meminfo {}", describe_line(number, line)),
+            ));
+        }
+        Self::parse_from_str(content)
+    }
+
+    /// Parses the raw bytes of `/proc/meminfo` without allocating.
+    ///
+    /// Optional fields (`MemFree`, `Buffers`, `Cached`, swap and the hugepage
+    /// statistics, which are missing on kernels without hugetlbfs) default to
+    /// zero.
+    pub fn parse_from_bytes(content: &[u8]) -> io::Result<Self> {
+        let optional = |prefix: &str| parse_field_bytes(content, prefix.as_bytes()).unwrap_or(0);
+        Ok(Self {
+            total: parse_kb_field(content, "MemTotal:")?,
+            available: parse_kb_field(content, "MemAvailable:")?,
+            free: optional("MemFree:"),
+            buffers: optional("Buffers:"),
+            cached: optional("Cached:"),
+            swap_total: optional("SwapTotal:"),
+            swap_free: optional("SwapFree:"),
+            hugepages_total: optional("HugePages_Total:"),
+            hugepages_free: optional("HugePages_Free:"),
+            hugepage_size_kb: optional("Hugepagesize:"),
+        })
+    }
+
+    /// Calculate the amount of used memory.
+    pub fn used(&self) -> u64 {
+        self.total.saturating_sub(self.available)
+    }
+
+    /// `MemTotal` in bytes, saturating at `u64::MAX`.
+    pub fn total_bytes(&self) -> u64 {
+        self.total.saturating_mul(1024)
+    }
+
+    /// `MemAvailable` in bytes, saturating at `u64::MAX`.
+    pub fn available_bytes(&self) -> u64 {
+        self.available.saturating_mul(1024)
+    }
+
+    /// [`MemInfo::used`] in bytes, saturating at `u64::MAX`.
+    pub fn used_bytes(&self) -> u64 {
+        self.used().saturating_mul(1024)
+    }
+
+    /// Used memory not counting buffers and page cache, like `free` reports
+    /// it: total − free − buffers − cached.
+    pub fn used_strict(&self) -> u64 {
+        self.total
+            .saturating_sub(self.free)
+            .saturating_sub(self.buffers)
+            .saturating_sub(self.cached)
+    }
+
+    /// Used memory according to `mode`.
+    pub fn used_by(&self, mode: MemMode) -> u64 {
+        match mode {
+            MemMode::Available => self.used(),
+            MemMode::Strict => self.used_strict(),
+        }
+    }
+
+    /// Used memory according to `mode` as a percentage of the total.
+    pub fn used_percent(&self, mode: MemMode) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.used_by(mode) as f64 * 100.0 / self.total as f64
+    }
+
+    /// Swap space in use, in kB.
+    pub fn swap_used(&self) -> u64 {
+        self.swap_total.saturating_sub(self.swap_free)
+    }
+
+    /// Fraction (0 to 1) of the swap space in use; 0 without swap.
+    pub fn swap_pressure(&self) -> f64 {
+        if self.swap_total == 0 {
+            return 0.0;
+        }
+        self.swap_used() as f64 / self.swap_total as f64
+    }
+
+    /// Whether more than [`SWAPPING_THRESHOLD`] of the swap space is in use.
+    ///
+    /// A few pages always end up in swap on a long-running system, so a
+    /// barely used swap does not count as memory pressure.
+    pub fn is_swapping(&self) -> bool {
+        self.swap_pressure() > SWAPPING_THRESHOLD
+    }
+
+    /// The changes since the earlier reading `prev`, with
+    /// [`MemInfo::used`] as the used figure.
+    pub fn delta(&self, prev: &MemInfo) -> MemDelta {
+        let change = |now: u64, before: u64| now as i64 - before as i64;
+        MemDelta {
+            total: change(self.total, prev.total),
+            available: change(self.available, prev.available),
+            used: change(self.used(), prev.used()),
+            swap_used: change(self.swap_used(), prev.swap_used()),
+        }
+    }
+
+    /// The one-line memory summary with the default
+    /// [`MemField::DEFAULT`] figures.
+    pub fn summary(&self, mode: MemMode) -> String {
+        self.summary_fields(&MemField::DEFAULT, mode, MemUnit::Kb)
+    }
+
+    /// The one-line memory summary listing `fields` in order and in `unit`,
+    /// e.g. `Memory: total=2048kB used=1536kB`.
+    pub fn summary_fields(&self, fields: &[MemField], mode: MemMode, unit: MemUnit) -> String {
+        self.summary_fields_in(fields, mode, unit, None)
+    }
+
+    /// [`MemInfo::summary_fields`] with the prefixes and divisors of `base`
+    /// (`--unit-base`), see [`MemUnit::format_in`].
+    pub fn summary_fields_in(
+        &self,
+        fields: &[MemField],
+        mode: MemMode,
+        unit: MemUnit,
+        base: Option<UnitBase>,
+    ) -> String {
+        let mut out = String::from("Memory:");
+        for field in fields {
+            out.push_str(&format!(
+                " {}={}{}",
+                field.name(),
+                unit.format_in(field.value(self, mode), base),
+                unit.suffix_in(base)
+            ));
+        }
+        out
+    }
+
+    /// The memory header as one fraction of used over total memory, e.g.
+    /// `Memory: 3.2/15.6 GiB (21%)` (`--mem-fraction`).
+    ///
+    /// Both figures share the largest unit in which the total is at least
+    /// 1, so they read as one expression. The units are binary unless
+    /// `base` is [`UnitBase::Decimal`].
+    pub fn fraction(&self, mode: MemMode, base: Option<UnitBase>) -> String {
+        let (divisor, units) = match base {
+            Some(UnitBase::Decimal) => (1000.0, ["kB", "MB", "GB", "TB"]),
+            _ => (1024.0, ["KiB", "MiB", "GiB", "TiB"]),
+        };
+        let bytes = |kb: u64| kb as f64 * 1024.0;
+        let mut scale = divisor;
+        let mut unit = units[0];
+        for next in &units[1..] {
+            if bytes(self.total) < scale * divisor {
+                break;
+            }
+            scale *= divisor;
+            unit = next;
+        }
+        format!(
+            "Memory: {:.1}/{:.1} {unit} ({:.0}%)",
+            bytes(self.used_by(mode)) / scale,
+            bytes(self.total) / scale,
+            self.used_percent(mode)
+        )
+    }
+
+    /// Number of hugepages in use (total minus free).
+    pub fn hugepages_used(&self) -> u64 {
+        self.hugepages_total.saturating_sub(self.hugepages_free)
+    }
+}
+
+/// Finds the line starting with `prefix` and parses the first number after it.
+fn parse_kb_field(content: &[u8], prefix: &str) -> io::Result<u64> {
+    parse_field_bytes(content, prefix.as_bytes()).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("missing or invalid {prefix} in meminfo"),
+        )
+    })
+}
+
+// Make `MemInfo` printable
+impl Display for MemInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.summary(MemMode::Available))
+    }
+}
+
+/// A figure of the memory header line, selected with `--mem-fields`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemField {
+    Total,
+    /// `MemAvailable`, or `MemFree` with [`MemMode::Strict`].
+    Free,
+    Available,
+    /// See [`MemInfo::used_by`].
+    Used,
+    Buffers,
+    Cached,
+    /// Swap in use.
+    Swap,
+}
+
+impl MemField {
+    pub const ALL: [Self; 7] = [
+        Self::Total,
+        Self::Free,
+        Self::Available,
+        Self::Used,
+        Self::Buffers,
+        Self::Cached,
+        Self::Swap,
+    ];
+
+    /// The header shown without `--mem-fields`.
+    pub const DEFAULT: [Self; 3] = [Self::Total, Self::Free, Self::Used];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Total => "total",
+            Self::Free => "free",
+            Self::Available => "available",
+            Self::Used => "used",
+            Self::Buffers => "buffers",
+            Self::Cached => "cached",
+            Self::Swap => "swap",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|field| field.name() == value)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Self::ALL.iter().map(|f| f.name()).collect();
+                format!(
+                    "unknown memory field: {value:?} (expected {})",
+                    names.join(", ")
+                )
+            })
+    }
+
+    /// What the figure means, for `--explain`.
+    pub fn explanation(self, mode: MemMode) -> &'static str {
+        match (self, mode) {
+            (Self::Total, _) => "MemTotal: the RAM the kernel manages",
+            (Self::Free, MemMode::Available) | (Self::Available, _) => {
+                "MemAvailable: free memory plus cache the kernel can reclaim, \
+                 what new programs can still get"
+            }
+            (Self::Free, MemMode::Strict) => "MemFree: memory nothing uses at all",
+            (Self::Used, MemMode::Available) => "total - available",
+            (Self::Used, MemMode::Strict) => "total - free - buffers - cached",
+            (Self::Buffers, _) => "Buffers: cached raw disk blocks",
+            (Self::Cached, _) => "Cached: file contents kept in memory (page cache)",
+            (Self::Swap, _) => "swap space in use, SwapTotal - SwapFree",
+        }
+    }
+
+    /// The figure in kB.
+    pub fn value(self, meminfo: &MemInfo, mode: MemMode) -> u64 {
+        match self {
+            Self::Total => meminfo.total,
+            Self::Free => match mode {
+                MemMode::Available => meminfo.available,
+                MemMode::Strict => meminfo.free,
+            },
+            Self::Available => meminfo.available,
+            Self::Used => meminfo.used_by(mode),
+            Self::Buffers => meminfo.buffers,
+            Self::Cached => meminfo.cached,
+            Self::Swap => meminfo.swap_used(),
+        }
+    }
+}
+
+/// Which figure counts as used memory (`--mem-mode`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MemMode {
+    /// Total minus `MemAvailable`, see [`MemInfo::used`].
+    #[default]
+    Available,
+    /// Total minus free, buffers and cache, see [`MemInfo::used_strict`].
+    Strict,
+}
+
+impl MemMode {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "available" => Ok(Self::Available),
+            "strict" => Ok(Self::Strict),
+            other => Err(format!(
+                "unknown memory mode: {other} (expected available or strict)"
+            )),
+        }
+    }
+}
+
+/// Unit every memory figure is shown in (`--mem-unit`).
+///
+/// Like the kernel's `kB`, the larger units are binary: 1 MB is 1024 kB.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MemUnit {
+    /// Whole kB, as read from `/proc`.
+    #[default]
+    Kb,
+    Mb,
+    Gb,
+    /// The largest of K, M, G and T that keeps the figure at 1 or more,
+    /// chosen per figure (`--human`).
+    Human,
+}
+
+impl MemUnit {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "kb" => Ok(Self::Kb),
+            "mb" => Ok(Self::Mb),
+            "gb" => Ok(Self::Gb),
+            other => Err(format!(
+                "unknown memory unit: {other} (expected kb, mb or gb)"
+            )),
+        }
+    }
+
+    /// The unit written after a figure; empty for [`MemUnit::Human`], whose
+    /// figures carry their own.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Self::Kb => "kB",
+            Self::Mb => "MB",
+            Self::Gb => "GB",
+            Self::Human => "",
+        }
+    }
+
+    /// Formats a kB figure in this unit without the suffix, with a fixed
+    /// number of decimals: none for kB, two for MB and GB. Human figures
+    /// below 1 MB are whole kB (`512K`), larger ones have one decimal and
+    /// their unit letter (`1.5M`).
+    pub fn format(self, value_kb: u64) -> String {
+        self.format_in(value_kb, None)
+    }
+
+    /// [`MemUnit::suffix`] with the prefixes of `base`: `KiB`, `MiB` and
+    /// `GiB` for [`UnitBase::Binary`], `kB`, `MB` and `GB` for
+    /// [`UnitBase::Decimal`]. Without a base the kernel's `kB` spelling is
+    /// kept for binary units.
+    pub fn suffix_in(self, base: Option<UnitBase>) -> &'static str {
+        match (base, self) {
+            (None, _) | (_, Self::Human) => self.suffix(),
+            (Some(UnitBase::Binary), Self::Kb) => "KiB",
+            (Some(UnitBase::Binary), Self::Mb) => "MiB",
+            (Some(UnitBase::Binary), Self::Gb) => "GiB",
+            (Some(UnitBase::Decimal), unit) => unit.suffix(),
+        }
+    }
+
+    /// [`MemUnit::format`] with the divisors of `base`. For
+    /// [`UnitBase::Decimal`] the figures from `/proc`, which are KiB, are
+    /// converted to 1000-byte kB first, so 1024 kB read `1049` kB or
+    /// `1.05` MB.
+    pub fn format_in(self, value_kb: u64, base: Option<UnitBase>) -> String {
+        let Some(base) = base else {
+            return match self {
+                Self::Kb => value_kb.to_string(),
+                Self::Human => format_human_kb(value_kb, None),
+                _ => format!("{:.2}", convert_kb(value_kb, self)),
+            };
+        };
+        let units = value_kb as f64 * 1024.0 / base.divisor();
+        match self {
+            Self::Kb => format!("{units:.0}"),
+            Self::Mb => format!("{:.2}", units / base.divisor()),
+            Self::Gb => format!("{:.2}", units / base.divisor() / base.divisor()),
+            Self::Human => format_human_kb(value_kb, Some(base)),
+        }
+    }
+}
+
+/// What a memory unit is a multiple of (`--unit-base`): 1000 for decimal
+/// prefixes, 1024 for binary ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnitBase {
+    Decimal,
+    Binary,
+}
+
+impl UnitBase {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "1000" => Ok(Self::Decimal),
+            "1024" => Ok(Self::Binary),
+            other => Err(format!(
+                "unknown unit base: {other} (expected 1000 or 1024)"
+            )),
+        }
+    }
+
+    fn divisor(self) -> f64 {
+        match self {
+            Self::Decimal => 1000.0,
+            Self::Binary => 1024.0,
+        }
+    }
+}
+
+/// Signed changes between two [`MemInfo`] readings in kB; negative values
+/// are decreases.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemDelta {
+    pub total: i64,
+    pub available: i64,
+    pub used: i64,
+    pub swap_used: i64,
+}
+
+/// Unit of the RSS column selected with `--rss-unit`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RssUnit {
+    /// kB, or whatever `--mem-unit` asks for.
+    #[default]
+    Kb,
+    /// Memory pages, see [`page_size`](crate::system::page_size).
+    Pages,
+}
+
+impl RssUnit {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "kb" => Ok(Self::Kb),
+            "pages" => Ok(Self::Pages),
+            other => Err(format!("unknown RSS unit: {other} (expected pages or kb)")),
+        }
+    }
+}
+
+/// `512K`, `1.5M`, ...; with a `base` the prefixes are `Ki`, `Mi`, ... or
+/// `k`, `M`, ... and the steps 1024 or 1000.
+fn format_human_kb(value_kb: u64, base: Option<UnitBase>) -> String {
+    let (divisor, prefixes) = match base {
+        None => (1024.0, ["K", "M", "G", "T"]),
+        Some(UnitBase::Binary) => (1024.0, ["Ki", "Mi", "Gi", "Ti"]),
+        Some(UnitBase::Decimal) => (1000.0, ["k", "M", "G", "T"]),
+    };
+    let mut value = value_kb as f64 * 1024.0 / divisor;
+    if value < divisor {
+        return format!("{value:.0}{}", prefixes[0]);
+    }
+    for prefix in &prefixes[1..3] {
+        value /= divisor;
+        if value < divisor {
+            return format!("{value:.1}{prefix}");
+        }
+    }
+    format!("{:.1}{}", value / divisor, prefixes[3])
+}
+
+/// Converts a kB figure to `unit`; [`MemUnit::Human`] has no fixed unit and
+/// keeps kB.
+pub fn convert_kb(value_kb: u64, unit: MemUnit) -> f64 {
+    let kb = value_kb as f64;
+    match unit {
+        MemUnit::Kb | MemUnit::Human => kb,
+        MemUnit::Mb => kb / 1024.0,
+        MemUnit::Gb => kb / (1024.0 * 1024.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_meminfo() {
+        let input = "\
+MemTotal:       16384256 kB
+SomeOtherValue:  123567 kB
+MemAvailable:    2345678 kB";
+
+        let meminfo = MemInfo::parse_from_str(input).unwrap();
+        assert_eq!(meminfo.total, 16384256);
+        assert_eq!(meminfo.available, 2345678);
+    }
+
+    #[test]
+    fn test_unit_base() {
+        let kb = 1_500_000; // 1.43 GiB, 1.54 GB
+        let decimal = Some(UnitBase::Decimal);
+        let binary = Some(UnitBase::Binary);
+        assert_eq!(MemUnit::Gb.format_in(kb, binary), "1.43");
+        assert_eq!(MemUnit::Gb.suffix_in(binary), "GiB");
+        assert_eq!(MemUnit::Gb.format_in(kb, decimal), "1.54");
+        assert_eq!(MemUnit::Gb.suffix_in(decimal), "GB");
+        assert_eq!(MemUnit::Kb.format_in(1000, binary), "1000");
+        assert_eq!(MemUnit::Kb.format_in(1000, decimal), "1024");
+        assert_eq!(MemUnit::Human.format_in(kb, binary), "1.4Gi");
+        assert_eq!(MemUnit::Human.format_in(kb, decimal), "1.5G");
+        assert_eq!(MemUnit::Human.format_in(kb, None), "1.4G");
+
+        let meminfo =
+            MemInfo::parse_from_str("MemTotal:  16384256 kB\nMemAvailable:  2345678 kB\n").unwrap();
+        assert_eq!(
+            meminfo.fraction(MemMode::Available, decimal),
+            "Memory: 14.4/16.8 GB (86%)"
+        );
+        assert_eq!(
+            meminfo.summary_fields_in(&[MemField::Total], MemMode::Available, MemUnit::Mb, binary),
+            "Memory: total=16000.25MiB"
+        );
+        assert!(UnitBase::parse("1023").is_err());
+    }
+
+    #[test]
+    fn test_fraction() {
+        let meminfo =
+            MemInfo::parse_from_str("MemTotal:  16384256 kB\nMemAvailable:  2345678 kB\n").unwrap();
+        assert_eq!(
+            meminfo.fraction(MemMode::Available, None),
+            "Memory: 13.4/15.6 GiB (86%)"
+        );
+        let small = MemInfo {
+            total: 512,
+            available: 128,
+            ..MemInfo::default()
+        };
+        assert_eq!(
+            small.fraction(MemMode::Available, None),
+            "Memory: 384.0/512.0 KiB (75%)"
+        );
+    }
+
+    #[test]
+    fn test_parse_strict_names_the_bad_line() {
+        let input = "MemTotal: 2048 kB\nMemAvailable: 512 kB\nSwapFree: ?? kB\n";
+        assert_eq!(MemInfo::parse_from_str(input).unwrap().swap_free, 0);
+        let err = MemInfo::parse_strict(input).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            r#"malformed meminfo line 3: "SwapFree: ?? kB""#
+        );
+        assert!(MemInfo::parse_strict("MemTotal: 2048 kB\nMemAvailable: 512 kB\n").is_ok());
+    }
+
+    #[test]
+    fn test_parse_meminfo_with_crlf() {
+        let input = "MemTotal:  16384256 kB\r\nMemFree:  1000 kB\r\nMemAvailable:  2345678 kB\r\n\
+                     HugePages_Total:  4\r\nHugePages_Free:  1\r\n";
+        let meminfo = MemInfo::parse_from_str(input).unwrap();
+        assert_eq!(
+            (meminfo.total, meminfo.free, meminfo.available),
+            (16384256, 1000, 2345678)
+        );
+        assert_eq!((meminfo.hugepages_total, meminfo.hugepages_free), (4, 1));
+    }
+
+    #[test]
+    fn test_parse_from_bytes_skips_invalid_utf8() {
+        // A line that is not UTF-8 and a last line cut off without a newline
+        // do not hide the fields around them.
+        let meminfo = b"MemTotal:  16384256 kB\n\
+            Direct\xff\xfeMap: 7 kB\n\
+            MemAvailable:  2345678 kB\n\
+            Cached: 12";
+        let parsed = MemInfo::parse_from_bytes(meminfo).unwrap();
+        assert_eq!((parsed.total, parsed.available), (16384256, 2345678));
+        assert_eq!(parsed.cached, 12);
+        let garbled = b"MemTotal: 16\xff kB\nMemAvailable: 8 kB\n";
+        assert!(MemInfo::parse_from_bytes(garbled).is_err());
+    }
+
+    #[test]
+    fn test_used_and_used_strict() {
+        let input = "\
+MemTotal:        1000000 kB
+MemFree:          100000 kB
+MemAvailable:     600000 kB
+Buffers:           50000 kB
+Cached:           350000 kB
+SwapCached:         9999 kB";
+
+        let meminfo = MemInfo::parse_from_str(input).unwrap();
+        assert_eq!(meminfo.used(), 400000);
+        assert_eq!(meminfo.used_strict(), 500000);
+        assert_eq!(meminfo.used_by(MemMode::Strict), 500000);
+        assert_eq!(meminfo.used_percent(MemMode::Available), 40.0);
+        assert_eq!(
+            meminfo.summary(MemMode::Strict),
+            "Memory: total=1000000kB free=100000kB used=500000kB"
+        );
+    }
+
+    #[test]
+    fn test_byte_accessors() {
+        let meminfo = MemInfo {
+            total: 2048,
+            available: 512,
+            ..MemInfo::default()
+        };
+        assert_eq!(meminfo.total_bytes(), 2048 * 1024);
+        assert_eq!(meminfo.available_bytes(), 512 * 1024);
+        assert_eq!(meminfo.used_bytes(), 1536 * 1024);
+
+        let huge = MemInfo {
+            total: u64::MAX / 2,
+            ..MemInfo::default()
+        };
+        assert_eq!(huge.total_bytes(), u64::MAX);
+        assert_eq!(huge.used_bytes(), u64::MAX);
+    }
+
+    #[test]
+    fn test_convert_kb() {
+        assert_eq!(convert_kb(1536, MemUnit::Kb), 1536.0);
+        assert_eq!(convert_kb(1536, MemUnit::Mb), 1.5);
+        assert_eq!(convert_kb(3 * 1024 * 1024, MemUnit::Gb), 3.0);
+        assert_eq!(MemUnit::Kb.format(1536), "1536");
+        assert_eq!(MemUnit::Mb.format(1000), "0.98");
+        assert_eq!(MemUnit::Gb.format(1024 * 1024 / 2), "0.50");
+        let meminfo = MemInfo::parse_from_str("MemTotal: 2048 kB\nMemAvailable: 512 kB\n").unwrap();
+        assert_eq!(
+            meminfo.summary_fields(&MemField::DEFAULT, MemMode::Available, MemUnit::Mb),
+            "Memory: total=2.00MB free=0.50MB used=1.50MB"
+        );
+        assert!(MemUnit::parse("tb").is_err());
+    }
+
+    #[test]
+    fn test_human_unit() {
+        assert_eq!(MemUnit::Human.format(512), "512K");
+        assert_eq!(MemUnit::Human.format(1536), "1.5M");
+        assert_eq!(MemUnit::Human.format(3 * 1024 * 1024), "3.0G");
+        assert_eq!(MemUnit::Human.format(5 << 30), "5.0T");
+        let meminfo = MemInfo::parse_from_str("MemTotal: 2048 kB\nMemAvailable: 512 kB\n").unwrap();
+        assert_eq!(
+            meminfo.summary_fields(&MemField::DEFAULT, MemMode::Available, MemUnit::Human),
+            "Memory: total=2.0M free=512K used=1.5M"
+        );
+    }
+
+    #[test]
+    fn test_summary_fields() {
+        let input = "MemTotal: 1000 kB\nMemAvailable: 600 kB\nCached: 300 kB\n\
+                     SwapTotal: 512 kB\nSwapFree: 412 kB\n";
+        let meminfo = MemInfo::parse_from_str(input).unwrap();
+        let fields = [
+            MemField::Used,
+            MemField::Swap,
+            MemField::Cached,
+            MemField::Total,
+        ];
+        assert_eq!(
+            meminfo.summary_fields(&fields, MemMode::Available, MemUnit::Kb),
+            "Memory: used=400kB swap=100kB cached=300kB total=1000kB"
+        );
+        assert_eq!(
+            meminfo.summary(MemMode::Available),
+            "Memory: total=1000kB free=600kB used=400kB"
+        );
+        assert!(
+            MemField::parse("shared")
+                .unwrap_err()
+                .contains("total, free, available")
+        );
+    }
+
+    #[test]
+    fn test_parse_hugepages() {
+        let input = "\
+MemTotal:       16384256 kB
+MemAvailable:    2345678 kB
+HugePages_Total:     512
+HugePages_Free:      128
+HugePages_Rsvd:        0
+Hugepagesize:       2048 kB";
+
+        let meminfo = MemInfo::parse_from_str(input).unwrap();
+        assert_eq!(meminfo.hugepages_total, 512);
+        assert_eq!(meminfo.hugepages_free, 128);
+        assert_eq!(meminfo.hugepage_size_kb, 2048);
+        assert_eq!(meminfo.hugepages_used(), 384);
+    }
+
+    #[test]
+    fn test_delta() {
+        let before = MemInfo {
+            total: 1000,
+            available: 200,
+            swap_total: 500,
+            swap_free: 400,
+            ..MemInfo::default()
+        };
+        let after = MemInfo {
+            available: 700,
+            swap_free: 450,
+            ..before
+        };
+        let freed = after.delta(&before);
+        assert_eq!(
+            freed,
+            MemDelta {
+                total: 0,
+                available: 500,
+                used: -500,
+                swap_used: -50,
+            }
+        );
+        assert_eq!(before.delta(&after).used, 500);
+    }
+
+    #[test]
+    fn test_swap_pressure() {
+        let no_swap = MemInfo::default();
+        assert_eq!(no_swap.swap_pressure(), 0.0);
+        assert!(!no_swap.is_swapping());
+
+        let light = MemInfo {
+            swap_total: 1000,
+            swap_free: 990,
+            ..MemInfo::default()
+        };
+        assert!((light.swap_pressure() - 0.01).abs() < 1e-9);
+        assert!(!light.is_swapping());
+
+        let heavy = MemInfo {
+            swap_free: 200,
+            ..light
+        };
+        assert!((heavy.swap_pressure() - 0.8).abs() < 1e-9);
+        assert!(heavy.is_swapping());
+    }
+}
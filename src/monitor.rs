@@ -0,0 +1,2540 @@
+//! Part 8 – the refresh loop, and gathering the process list for one frame.
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{self, Write},
+    sync::atomic::AtomicBool,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "extras")]
+use crate::numa::read_numa_placement;
+use crate::{
+    Config, CpuSampler, CpuStat, ExcludeList, FlushPolicy, FlushWriter, Json, LoadAvg,
+    MIN_INTERVAL, MemInfo, MemMode, Options, Process, SortKey, StuckAlert, ThresholdAlert,
+    average::MovingAverage,
+    cgroup::{CGROUP_ROOT, read_cgroup_memory, read_cgroup_procs, read_process_unit},
+    clock::{Clock, SystemClock, until_next_boundary},
+    compare::{SavedSnapshot, format_growth_report},
+    demo::DemoReader,
+    groups::{aggregate_by_name, format_name_table},
+    histogram::{bucket_by_rss, format_histogram},
+    jitter::Jitter,
+    oom::read_oom_score,
+    process::{
+        StateSummary, apply_classify_by_arg, apply_name_source, compare_delta, compare_top,
+        count_sockets, count_until_percent, list_processes_from, read_one_process_from,
+        read_process_cmdline, read_process_cwd, read_process_details, read_process_io,
+        read_process_wchan, read_schedstat, read_thread_cpu_ticks, retain_min_cpu, scan_pids,
+        scan_processes, scan_threads, sort_key_warning, sort_processes, summarize_states,
+        top_processes,
+    },
+    reader::{FsReader, ProcReader},
+    reload::{RELOAD_REQUESTED, apply_reload, take_reload_request},
+    render::{
+        ColorRole, ColumnRegistry, OutputFormat, RESET, REVERSE, format_batch_header,
+        format_bordered_table, format_cpu_detail, format_cpu_share, format_explanation,
+        format_mem_detail, format_mem_explanation, format_summary, format_top_processes, heartbeat,
+        snapshot_json, stdout_is_tty, strip_ansi,
+    },
+    subtree::{format_subtree, subtree},
+    system::{FileNr, clock_ticks_per_second, parse_uptime},
+    users::{aggregate_by_user, format_user_table, read_passwd, top_per_user},
+    watch::{WatchSet, Watched, format_watch_table},
+};
+
+/// Number of processes shown per refresh.
+pub const TOP_N: usize = 5;
+
+/// Exit code when a `--once` snapshot exceeded an alert threshold.
+pub const EXIT_ALERT: u8 = 1;
+
+/// Exit code when `--fail-if-empty` found no process left after filtering.
+pub const EXIT_EMPTY: u8 = 1;
+
+/// Exit code when the `--exclude-pids-from` file cannot be read, like for
+/// other invalid arguments.
+pub const EXIT_BAD_EXCLUDE: u8 = 2;
+
+/// Exit code when too many consecutive frames failed, see
+/// `--max-consecutive-errors`.
+pub const EXIT_WATCHDOG: u8 = 4;
+
+/// Runs the monitor loop against a procfs source.
+pub struct Monitor<R: ProcReader = FsReader> {
+    options: Options,
+    reader: R,
+    root: String,
+    /// Whether to emit ANSI escapes: colors, highlighting and clearing.
+    color: bool,
+    /// Whether to end the memory line with a [`heartbeat`] spinner.
+    heartbeat: bool,
+    /// Width the `--format ascii-table` borders fit into, from `$COLUMNS`.
+    term_width: Option<usize>,
+    /// When the output is flushed; see [`FlushPolicy::for_terminal`].
+    flush: FlushPolicy,
+    /// Frames produced so far, which advance the heartbeat.
+    frame_count: u64,
+    clock: Box<dyn Clock>,
+    mem_alert: Option<ThresholdAlert>,
+    used_kb_alert: Option<ThresholdAlert>,
+    cpu_alert: Option<ThresholdAlert>,
+    d_state_alert: Option<StuckAlert>,
+    sampler: CpuSampler,
+    warned: bool,
+    /// Whether the `--warn-on-parse-error-count` warning was printed.
+    parse_warned: bool,
+    alert_fired: bool,
+    /// Whether the latest frame had no processes left after filtering.
+    empty: bool,
+    /// Process count of the previous frame's `Tasks:` line, to show how it
+    /// changed.
+    prev_tasks_total: Option<usize>,
+    /// The processes of the first and the latest frame, for `--top-growth`.
+    growth: Option<(SavedSnapshot, SavedSnapshot)>,
+    /// User names for `--by-user`, by uid.
+    users: HashMap<u32, String>,
+    watch: Option<WatchSet>,
+    jitter: Option<Jitter>,
+    /// The `--aggregate-interval` means of the CPU busy and iowait percent.
+    busy_average: Option<MovingAverage>,
+    iowait_average: Option<MovingAverage>,
+    /// Memory used and CPU busy percent of the latest frame, which
+    /// `--adaptive-interval` picks the next wait by.
+    load: Option<(f64, f64)>,
+    /// Where the options came from, re-read on `SIGHUP`.
+    config: Option<Config>,
+    /// Set when `config` should be re-read.
+    reload_flag: &'static AtomicBool,
+}
+
+impl Monitor {
+    /// Monitors the live `/proc`, with ANSI escapes decided by whether stdout
+    /// is a terminal. With `--output` there are none, as the frames go to a
+    /// file.
+    pub fn new(options: Options) -> Self {
+        let users = if options.by_user || options.per_user_top.is_some() {
+            read_passwd("/etc/passwd").unwrap_or_default()
+        } else {
+            HashMap::new()
+        };
+        let root = options.proc_root().to_string();
+        let mut monitor = Self::with_reader(options, FsReader, &root).for_stdout();
+        monitor.users = users;
+        monitor
+    }
+}
+
+impl Monitor<DemoReader> {
+    /// Monitors the synthetic procfs of `--demo`, otherwise like
+    /// [`Monitor::new`].
+    pub fn demo(options: Options) -> Self {
+        Self::with_reader(options, DemoReader::default(), "/proc").for_stdout()
+    }
+}
+
+/// Whether the frames go to a terminal: stdout is one and there is no
+/// `--output` file.
+fn output_is_tty(options: &Options) -> bool {
+    options.output.is_none() && stdout_is_tty()
+}
+
+impl<R: ProcReader> Monitor<R> {
+    /// Monitors the procfs rooted at `root` as seen through `reader`.
+    ///
+    /// ANSI escapes are only used with `--color always` and no `--output`,
+    /// there is no heartbeat, output is only flushed with `--flush`, and
+    /// `--by-user` shows numeric uids.
+    pub fn with_reader(options: Options, reader: R, root: &str) -> Self {
+        let mut sampler = CpuSampler::default();
+        sampler.track_ranges(options.shows_max());
+        Self {
+            color: options.output.is_none() && options.color.enabled(false),
+            heartbeat: false,
+            term_width: None,
+            flush: options.flush.unwrap_or(FlushPolicy::Never),
+            frame_count: 0,
+            mem_alert: options.alert_mem.map(ThresholdAlert::new),
+            used_kb_alert: options
+                .alert_used_kb
+                .map(|kb| ThresholdAlert::new(kb as f64)),
+            cpu_alert: options.alert_cpu.map(ThresholdAlert::new),
+            d_state_alert: options.warn_d_state.map(StuckAlert::new),
+            watch: options.watch.clone().map(WatchSet::new),
+            jitter: options.interval_jitter.map(Jitter::from_process_start),
+            busy_average: options.aggregate_interval.map(MovingAverage::new),
+            iowait_average: options.aggregate_interval.map(MovingAverage::new),
+            load: None,
+            config: None,
+            reload_flag: &RELOAD_REQUESTED,
+            options,
+            reader,
+            root: root.to_string(),
+            sampler,
+            warned: false,
+            parse_warned: false,
+            alert_fired: false,
+            empty: false,
+            prev_tasks_total: None,
+            growth: None,
+            clock: Box::new(SystemClock),
+            users: HashMap::new(),
+        }
+    }
+
+    /// Sets up the output for stdout: ANSI escapes as `--color` says, and
+    /// on a terminal the heartbeat and flushing after each line unless
+    /// `--flush` says otherwise. The table is fitted to `$COLUMNS`.
+    fn for_stdout(mut self) -> Self {
+        let tty = output_is_tty(&self.options);
+        self.color = self.options.color.enabled(tty);
+        self.heartbeat = tty && !self.options.once;
+        self.term_width = env::var("COLUMNS").ok().and_then(|cols| cols.parse().ok());
+        self.flush = self.options.flush.unwrap_or(FlushPolicy::for_terminal(tty));
+        self
+    }
+
+    /// Uses `clock` instead of the system clock for timestamps and waiting.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Reloads the interval, iterations, sort and columns from `config`
+    /// when a reload is requested (`SIGHUP`), see [`apply_reload`].
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Reloads when `flag` is set instead of on `SIGHUP`.
+    pub fn with_reload_flag(mut self, flag: &'static AtomicBool) -> Self {
+        self.reload_flag = flag;
+        self
+    }
+
+    /// Applies the reloaded config if a reload was requested. A config that
+    /// no longer parses is reported and the running settings stay.
+    fn reload_if_requested(&mut self) {
+        let Some(config) = &self.config else {
+            return;
+        };
+        if !take_reload_request(self.reload_flag) {
+            return;
+        }
+        match config.reload().and_then(|c| Ok((c.resolve()?, c))) {
+            Ok((reloaded, config)) => {
+                for note in apply_reload(&mut self.options, &reloaded) {
+                    eprintln!("warning: {note}");
+                }
+                self.sampler.track_ranges(self.options.shows_max());
+                self.config = Some(config);
+            }
+            Err(err) => eprintln!("error: reloading the config: {err}"),
+        }
+    }
+
+    fn read_file(&self, name: &str) -> io::Result<String> {
+        self.reader.read_to_string(&format!("{}/{name}", self.root))
+    }
+
+    /// Runs the refresh loop, writing frames to `out`, and returns the
+    /// process exit code.
+    ///
+    /// In `--once` mode the exit code is [`EXIT_ALERT`] when an alert
+    /// threshold is exceeded by the snapshot and 0 otherwise. A looping run
+    /// reports alerts on stderr as they fire and exits with 0.
+    ///
+    /// With `--fail-if-empty` any run stops with [`EXIT_EMPTY`] after the
+    /// first frame that has no processes left after filtering.
+    ///
+    /// The `--exclude-pids-from` file is read before the first frame; the
+    /// run ends with [`EXIT_BAD_EXCLUDE`] if it cannot be.
+    ///
+    /// A looping run survives failed frames, reporting each on stderr, but
+    /// gives up with [`EXIT_WATCHDOG`] after `--max-consecutive-errors`
+    /// failures in a row. A one-shot run returns the error.
+    ///
+    /// With `--delay-first`, CPU usage is sampled once silently and the first
+    /// frame is shown one interval later. With `--max-fps`, frames that come
+    /// sooner than `1/N` seconds after the last drawn one are sampled but not
+    /// drawn. With `--interval-jitter`, each wait is drawn at random around
+    /// the interval; with `--interval-align`, it ends on the next multiple of
+    /// the interval in wall-clock time. With `--adaptive-interval`, the
+    /// interval follows the load of the latest frame.
+    ///
+    /// `out` is flushed as `--flush` says: by default after each line on a
+    /// terminal and otherwise only by its own buffering.
+    ///
+    /// When `out` is a closed pipe, e.g. the output is piped into `head`,
+    /// the run stops quietly with exit code 0 like other Unix tools do.
+    ///
+    /// See [`Monitor::run_to_output`] for `--output`.
+    pub fn run(&mut self, out: &mut dyn Write) -> io::Result<u8> {
+        let mut out = FlushWriter::new(out, self.flush);
+        match self.run_frames(&mut out) {
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(0),
+            result => result,
+        }
+    }
+
+    fn run_frames(&mut self, out: &mut dyn Write) -> io::Result<u8> {
+        if let Some(path) = &self.options.exclude_pids_from
+            && self.options.exclude.is_none()
+        {
+            match ExcludeList::from_file(path) {
+                Ok(list) => self.options.exclude = Some(list),
+                Err(err) => {
+                    eprintln!("error: {err}");
+                    return Ok(EXIT_BAD_EXCLUDE);
+                }
+            }
+        }
+        let delay_first = self.options.delay_first && self.options.uses_rates();
+        if self.options.once && self.options.alert_cpu.is_some() && !delay_first {
+            eprintln!("warning: --alert-cpu needs two samples and has no effect with --once");
+        }
+        if self.options.once && self.options.min_cpu.is_some() && !delay_first {
+            eprintln!(
+                "warning: --min-cpu needs two samples and leaves out every process with --once \
+                 (add --delay-first)"
+            );
+        }
+        if !self.options.once
+            && self
+                .options
+                .interval
+                .is_some_and(|interval| interval < MIN_INTERVAL)
+        {
+            eprintln!(
+                "warning: --interval is below {}s and would busy-loop; using {0}s",
+                MIN_INTERVAL.as_secs_f64()
+            );
+        }
+        if !self.options.once
+            && self
+                .options
+                .adaptive_interval
+                .is_some_and(|adaptive| adaptive.floor < MIN_INTERVAL)
+        {
+            eprintln!(
+                "warning: the floor of --adaptive-interval is below {}s and would busy-loop; \
+                 using {0}s",
+                MIN_INTERVAL.as_secs_f64()
+            );
+        }
+        if delay_first {
+            self.prime_cpu_sampler()?;
+            self.sleep_interval();
+        }
+
+        if self.options.json_array {
+            write!(out, "[")?;
+        }
+        let code = if self.options.json_array && self.options.iterations == Some(0) {
+            0
+        } else {
+            self.frame_loop(out)?
+        };
+        if self.options.json_array {
+            writeln!(out, "]")?;
+        }
+        Ok(code)
+    }
+
+    /// Like [`Monitor::run`], but writes the frames to the `--output` file,
+    /// created or truncated first, instead of `stdout` when one is given.
+    pub fn run_to_output(&mut self, stdout: &mut dyn Write) -> io::Result<u8> {
+        let Some(path) = self.options.output.clone() else {
+            return self.run(stdout);
+        };
+        let with_path = |err: io::Error| io::Error::new(err.kind(), format!("{path}: {err}"));
+        let mut file = io::BufWriter::new(fs::File::create(&path).map_err(with_path)?);
+        let code = self.run(&mut file)?;
+        file.flush().map_err(with_path)?;
+        Ok(code)
+    }
+
+    /// Draws frames until the run ends, returning the exit code.
+    fn frame_loop(&mut self, out: &mut dyn Write) -> io::Result<u8> {
+        if self.options.explain && !self.options.format.is_json() && !self.options.quiet {
+            write!(out, "{}", format_explanation(&self.options))?;
+        }
+        let mut frames = 0;
+        let mut consecutive_errors = 0;
+        let mut last_fingerprint = None;
+        let mut last_drawn: Option<Instant> = None;
+        let min_gap = self
+            .options
+            .max_fps
+            .map(|fps| Duration::from_secs_f64(1.0 / fps));
+        loop {
+            self.reload_if_requested();
+            frames += 1;
+            let mut frame = Vec::new();
+            match self.frame(&mut frame) {
+                Ok(fingerprint) => {
+                    consecutive_errors = 0;
+                    let now = self.clock.now();
+                    // `--top-growth` only reports at the end, and `--max-fps`
+                    // never throttles the final frame away.
+                    let throttled = !self.options.is_last_frame(frames)
+                        && min_gap
+                            .zip(last_drawn)
+                            .is_some_and(|(gap, drawn)| now - drawn < gap);
+                    let skip = self.options.top_growth
+                        || (self.options.refresh_on_change
+                            && last_fingerprint == Some(fingerprint))
+                        || throttled;
+                    if !skip {
+                        if let Some(path) = &self.options.render_to {
+                            let text = strip_ansi(&String::from_utf8_lossy(&frame));
+                            fs::write(path, text).map_err(|err| {
+                                io::Error::new(err.kind(), format!("{path}: {err}"))
+                            })?;
+                        }
+                        // Text frames that are not drawn over each other get a
+                        // blank line in between.
+                        let separated = self.options.batch
+                            || (self.options.output.is_some() && !self.options.format.is_json());
+                        if last_fingerprint.is_some() && separated && !self.options.quiet {
+                            writeln!(out)?;
+                        }
+                        if self.color
+                            && !self.options.once
+                            && !self.options.no_clear
+                            && !self.options.json_array
+                        {
+                            let first = last_fingerprint.is_none();
+                            write!(out, "{}", self.options.clear_mode.sequence(first))?;
+                        }
+                        if self.options.json_array {
+                            // The closing bracket follows the last frame.
+                            if last_fingerprint.is_some() {
+                                writeln!(out, ",")?;
+                            }
+                            frame.pop_if(|byte| *byte == b'\n');
+                        }
+                        out.write_all(&frame)?;
+                        out.flush()?;
+                        last_fingerprint = Some(fingerprint);
+                        last_drawn = Some(now);
+                    }
+                }
+                Err(err) if self.options.once || self.options.strict => return Err(err),
+                Err(err) => {
+                    consecutive_errors += 1;
+                    eprintln!("error: {err}");
+                    if consecutive_errors >= self.options.max_consecutive_errors() {
+                        eprintln!(
+                            "error: giving up after {consecutive_errors} consecutive failed \
+                             snapshots; is {} still readable?",
+                            self.root
+                        );
+                        return Ok(EXIT_WATCHDOG);
+                    }
+                }
+            }
+
+            if self.options.fail_if_empty && self.empty {
+                eprintln!("error: no processes left after filtering");
+                return Ok(EXIT_EMPTY);
+            }
+            if let Some(watch) = &self.watch
+                && watch.all_exited()
+            {
+                if !self.options.json_array {
+                    writeln!(out, "all watched processes have exited")?;
+                }
+                return Ok(0);
+            }
+            if self.options.is_last_frame(frames) {
+                if let Some((first, last)) = &self.growth {
+                    writeln!(out, "== Top growth over {frames} frames ==")?;
+                    write!(out, "{}", format_growth_report(first, last, TOP_N))?;
+                }
+                let code = if self.options.once && self.alert_fired {
+                    EXIT_ALERT
+                } else {
+                    0
+                };
+                return Ok(code);
+            }
+            self.sleep_interval();
+        }
+    }
+
+    fn sleep_interval(&mut self) {
+        let interval = match (self.options.adaptive_interval, self.load) {
+            (Some(adaptive), Some((mem, cpu))) => adaptive.select(mem, cpu).max(MIN_INTERVAL),
+            _ => self.options.interval(),
+        };
+        let wait = match &mut self.jitter {
+            Some(jitter) => jitter.next(interval),
+            None if self.options.interval_align => {
+                until_next_boundary(self.clock.system_now(), interval)
+            }
+            None => interval,
+        };
+        self.clock.sleep(wait);
+    }
+
+    /// Takes the CPU sample of every process that `--delay-first` uses as the
+    /// baseline for the first visible frame.
+    fn prime_cpu_sampler(&mut self) -> io::Result<()> {
+        let cpu = CpuStat::parse_from_str(&self.read_file("stat")?)?;
+        let mut procs = list_processes_from(&self.reader, &self.root)?;
+        let io = self.options.uses_io();
+        for p in procs.iter_mut() {
+            read_process_details(&self.reader, &self.root, p);
+            if self.options.merge_threads {
+                merge_thread_cpu(&self.reader, &self.root, p);
+            }
+            if io {
+                p.io = read_process_io(&self.reader, &self.root, p.pid);
+            }
+        }
+        self.sampler.update(&mut procs, cpu);
+        Ok(())
+    }
+
+    /// Reads one frame and renders it into `out`, returning the
+    /// [`snapshot_fingerprint`] of what was shown.
+    fn frame(&mut self, out: &mut dyn Write) -> io::Result<u64> {
+        let result = self.frame_contents(out);
+        self.frame_count += 1;
+        result
+    }
+
+    fn frame_contents(&mut self, out: &mut dyn Write) -> io::Result<u64> {
+        let options = &self.options;
+        let meminfo = if options.processes_only {
+            None
+        } else {
+            let content = self.read_file("meminfo")?;
+            let host = if options.strict {
+                MemInfo::parse_strict(&content).map_err(|err| {
+                    io::Error::new(err.kind(), format!("{}/meminfo: {err}", self.root))
+                })?
+            } else {
+                MemInfo::parse_from_str(&content)?
+            };
+            Some(if options.cgroup_mem {
+                read_cgroup_memory(&self.reader, &self.root, CGROUP_ROOT)?.apply(&host)
+            } else {
+                host
+            })
+        };
+        if let Some(meminfo) = &meminfo
+            && let Some(alert) = self.mem_alert.as_mut()
+        {
+            let used = meminfo.used_percent(options.mem_mode);
+            if alert.update(used) {
+                let decimals = options.precision();
+                eprintln!(
+                    "ALERT: memory used {used:.decimals$}% exceeds {}%",
+                    alert.threshold
+                );
+                self.alert_fired = true;
+            }
+        }
+        if let Some(meminfo) = &meminfo
+            && let Some(alert) = self.used_kb_alert.as_mut()
+        {
+            let used = meminfo.used_by(options.mem_mode);
+            if alert.update(used as f64) {
+                eprintln!("ALERT: memory used {used}kB exceeds {}kB", alert.threshold);
+                self.alert_fired = true;
+            }
+        }
+
+        let cpu = if options.memory_only && self.cpu_alert.is_none() && !options.cpu_detail {
+            None
+        } else {
+            Some(CpuStat::parse_from_str(&self.read_file("stat")?)?)
+        };
+        let measured_busy = cpu
+            .as_ref()
+            .zip(self.sampler.previous_stat())
+            .map(|(cpu, prev)| smoothed(&mut self.busy_average, cpu.busy_percent(&prev)));
+        let cpu_busy = measured_busy.unwrap_or(0.0);
+        let iowait = cpu
+            .as_ref()
+            .zip(self.sampler.previous_stat())
+            .map(|(cpu, prev)| smoothed(&mut self.iowait_average, cpu.iowait_percent(&prev)));
+        let mem_used = meminfo
+            .as_ref()
+            .map_or(0.0, |m| m.used_percent(options.mem_mode));
+        self.load = Some((mem_used, cpu_busy));
+        if let Some(busy) = measured_busy {
+            self.alert_fired |= check_cpu_alert(&mut self.cpu_alert, busy, options.precision());
+        }
+
+        if options.summary_only {
+            let cpu = cpu.expect("/proc/stat is read for --summary-only");
+            // Since boot on the first frame.
+            let busy = measured_busy.unwrap_or_else(|| cpu.busy_percent(&CpuStat::default()));
+            self.sampler.update(&mut [], cpu);
+            let tasks = summarize_states(&list_processes_from(&self.reader, &self.root)?);
+            let load = LoadAvg::parse_from_str(&self.read_file("loadavg")?)?;
+            let meminfo = meminfo.expect("/proc/meminfo is read for --summary-only");
+            write!(
+                out,
+                "{}",
+                format_summary(
+                    &meminfo,
+                    &load,
+                    busy,
+                    &tasks,
+                    self.prev_tasks_total,
+                    options
+                )
+            )?;
+            self.prev_tasks_total = Some(tasks.total);
+            return Ok(snapshot_fingerprint(Some(&meminfo), options.mem_mode, &[]));
+        }
+
+        let mut procs = Vec::new();
+        let mut tasks = None;
+        let mut watched = None;
+        let mut scan_time = None;
+        match cpu {
+            Some(cpu) if options.memory_only => self.sampler.update(&mut [], cpu),
+            Some(cpu) if let Some(watch) = self.watch.as_mut() => {
+                let rows = collect_watched(
+                    &self.reader,
+                    &self.root,
+                    options,
+                    watch,
+                    &mut self.sampler,
+                    cpu,
+                );
+                procs = rows
+                    .iter()
+                    .filter_map(|row| match row {
+                        Watched::Running(p) => Some(Process::clone(p)),
+                        Watched::Exited(_) => None,
+                    })
+                    .collect();
+                watched = Some(rows);
+            }
+            Some(cpu) => {
+                let first_sample = self.sampler.is_first_sample();
+                let limit = if options.scans_all() || options.limit_by_percent.is_some() {
+                    0
+                } else {
+                    TOP_N
+                };
+                let started = self.clock.now();
+                let mut snapshot = collect_processes(
+                    &self.reader,
+                    &self.root,
+                    options,
+                    limit,
+                    &mut self.sampler,
+                    cpu,
+                )?;
+                snapshot.scan_time = self.clock.now() - started;
+                scan_time = Some(snapshot.scan_time);
+                if !self.parse_warned
+                    && let Some(warning) =
+                        snapshot.parse_error_warning(options.parse_error_threshold())
+                {
+                    eprintln!("{warning}");
+                    self.parse_warned = true;
+                }
+                if let Some(alert) = self.d_state_alert.as_mut() {
+                    let fired = alert.update(snapshot.blocked.iter().map(|(pid, _)| *pid));
+                    for (pid, name) in snapshot
+                        .blocked
+                        .iter()
+                        .filter(|(pid, _)| fired.contains(pid))
+                    {
+                        let waiting = read_process_wchan(&self.reader, &self.root, *pid)
+                            .map_or_else(String::new, |wchan| format!(", waiting in {wchan}"));
+                        eprintln!(
+                            "ALERT: process {pid} ({name}) has been in uninterruptible sleep for \
+                             {} refreshes{waiting}",
+                            alert.frames
+                        );
+                        self.alert_fired = true;
+                    }
+                }
+                procs = match options.per_user_top {
+                    Some(k) => top_per_user(snapshot.processes, &self.users, k),
+                    None => snapshot.processes,
+                };
+                if let Some(percent) = options.limit_by_percent
+                    && let Some(meminfo) = &meminfo
+                {
+                    // Select by memory first, then order only the kept rows.
+                    sort_processes(&mut procs, SortKey::Mem);
+                    let used = meminfo.used_by(options.mem_mode);
+                    procs.truncate(count_until_percent(&procs, used, percent));
+                    sort_processes(&mut procs, options.sort.clone());
+                }
+                tasks = Some(snapshot.tasks);
+                // A looping run has CPU data from the second frame on, so only a
+                // one-shot run is judged on its first frame. JSON and
+                // delimited output are for scripts and get no warning.
+                if !self.warned
+                    && !options.format.is_json()
+                    && options.field_separator.is_none()
+                    && (options.once || !first_sample)
+                    && let Some(warning) =
+                        sort_key_warning(options.sort.primary(), &procs, first_sample)
+                {
+                    eprintln!("{warning}");
+                    self.warned = true;
+                }
+            }
+            None => {}
+        }
+        self.empty = procs.is_empty();
+        if let Some(total) = meminfo.as_ref().map(|m| m.total).filter(|&total| total > 0) {
+            for p in &mut procs {
+                p.mem_percent = Some(p.rss_kb as f64 * 100.0 / total as f64);
+            }
+        }
+        if options.top_growth {
+            let latest = SavedSnapshot::from_processes(&procs);
+            match &mut self.growth {
+                Some((_, last)) => *last = latest,
+                None => self.growth = Some((latest.clone(), latest)),
+            }
+        }
+
+        match options.format {
+            OutputFormat::Json | OutputFormat::JsonMap => {
+                let mut json = snapshot_json(
+                    meminfo.as_ref(),
+                    &procs,
+                    shown_rows(options, &procs),
+                    options,
+                );
+                if let Some(format) = options.time_format
+                    && let Json::Object(fields) = &mut json
+                {
+                    let time = format.json(self.clock.system_now());
+                    // Right after the schema version.
+                    fields.insert(1, ("time".to_string(), time));
+                }
+                let indent = options.json_pretty.then_some(2);
+                writeln!(out, "{}", json.to_string_indented(indent))?;
+            }
+            OutputFormat::Table | OutputFormat::AsciiTable => {
+                let frame = TableFrame {
+                    meminfo: meminfo.as_ref(),
+                    tasks,
+                    cpu: cpu.as_ref(),
+                    procs: &procs,
+                    watched: watched.as_deref(),
+                    scan_time,
+                    iowait,
+                    cpu_busy: measured_busy,
+                };
+                self.write_table(out, frame)?
+            }
+        }
+        if let Some(tasks) = tasks {
+            self.prev_tasks_total = Some(tasks.total);
+        }
+        Ok(snapshot_fingerprint(
+            meminfo.as_ref(),
+            options.mem_mode,
+            &procs,
+        ))
+    }
+
+    /// Writes the text output of a frame: the header lines and the table.
+    fn write_table(&self, out: &mut dyn Write, frame: TableFrame<'_>) -> io::Result<()> {
+        let TableFrame {
+            meminfo,
+            tasks,
+            cpu,
+            procs,
+            watched,
+            scan_time,
+            iowait,
+            cpu_busy,
+        } = frame;
+        let options = &self.options;
+        if options.batch && !options.quiet {
+            let uptime = parse_uptime(&self.read_file("uptime")?).unwrap_or(0.0);
+            let load = LoadAvg::parse_from_str(&self.read_file("loadavg")?)?;
+            write!(
+                out,
+                "{}",
+                format_batch_header(self.clock.system_now(), uptime, &load, meminfo, options)
+            )?;
+        } else if let Some(meminfo) = meminfo {
+            let summary = if options.mem_fraction {
+                meminfo.fraction(options.mem_mode, options.unit_base)
+            } else {
+                meminfo.summary_fields_in(
+                    options.mem_fields(),
+                    options.mem_mode,
+                    options.mem_unit,
+                    options.unit_base,
+                )
+            };
+            if self.color {
+                let role = ColorRole::for_memory(meminfo.used_percent(options.mem_mode));
+                let summary = options.color_scheme.paint(role, &summary);
+                if options.highlight_header {
+                    write!(out, "{REVERSE}{summary}{RESET}")?;
+                } else {
+                    write!(out, "{summary}")?;
+                }
+            } else {
+                write!(out, "{summary}")?;
+            }
+            if self.heartbeat {
+                write!(out, " {}", heartbeat(self.frame_count))?;
+            }
+            writeln!(out)?;
+        }
+        if let Some(tasks) = tasks
+            && !options.processes_only
+        {
+            let line = tasks.format_with_change(self.prev_tasks_total);
+            if self.color && tasks.zombie > 0 {
+                writeln!(
+                    out,
+                    "{}",
+                    options.color_scheme.paint(ColorRole::Critical, &line)
+                )?;
+            } else {
+                writeln!(out, "{line}")?;
+            }
+        }
+        if let Some(iowait) = iowait
+            && iowait > 0.0
+            && !options.processes_only
+        {
+            writeln!(out, "wa: {iowait:.*}%", options.precision())?;
+        }
+        if options.show_global_fds && !options.processes_only {
+            let fds = FileNr::parse_from_str(&self.read_file("sys/fs/file-nr")?)?;
+            let mut line = fds.to_string();
+            if self.color && fds.used_percent() >= 90.0 {
+                line = options.color_scheme.paint(ColorRole::Critical, &line);
+            }
+            writeln!(out, "{line}")?;
+        }
+        if let Some(scan_time) = scan_time
+            && options.show_timing
+            && !options.processes_only
+        {
+            writeln!(out, "scan: {}ms", scan_time.as_millis())?;
+        }
+        if let Some(meminfo) = meminfo {
+            if options.mem_detail {
+                write!(out, "{}", format_mem_detail(meminfo))?;
+            }
+            if options.explain_mem {
+                write!(out, "{}", format_mem_explanation(meminfo))?;
+            }
+        }
+        if let Some(cpu) = cpu
+            && options.cpu_detail
+            && !options.quiet
+        {
+            write!(
+                out,
+                "{}",
+                format_cpu_detail(cpu, clock_ticks_per_second(), options.precision())
+            )?;
+        }
+        if let Some(rows) = watched {
+            let registry = ColumnRegistry::for_options(options);
+            write!(out, "{}", format_watch_table(rows, &registry, options))?;
+        } else if options.histogram {
+            write!(out, "{}", format_histogram(&bucket_by_rss(procs)))?;
+        } else if let Some(root) = options.subtree_mem {
+            write!(
+                out,
+                "{}",
+                format_subtree(subtree(procs, root).as_ref(), root)
+            )?;
+        } else if options.by_user {
+            let usage = aggregate_by_user(procs, &self.users, options.aggregate_top);
+            write!(out, "{}", format_user_table(&usage))?;
+        } else if !options.memory_only {
+            if options.group_summary {
+                writeln!(out, "== Top processes ==")?;
+            }
+            if options.format == OutputFormat::AsciiTable {
+                let registry = ColumnRegistry::for_options(options);
+                let table = format_bordered_table(
+                    &registry,
+                    procs,
+                    shown_rows(options, procs),
+                    options,
+                    self.color,
+                    self.term_width,
+                );
+                write!(out, "{table}")?;
+            } else {
+                write!(
+                    out,
+                    "{}",
+                    format_top_processes(procs, shown_rows(options, procs), options, self.color)
+                )?;
+            }
+            if options.show_cpu_share
+                && let (Some(cpu), Some(busy)) = (cpu, cpu_busy)
+            {
+                let shown = &procs[..shown_rows(options, procs).min(procs.len())];
+                let line = format_cpu_share(shown, busy, cpu.cpus, options.precision());
+                writeln!(out, "{line}")?;
+            }
+            if options.group_summary {
+                writeln!(out, "\n== Top programs ==")?;
+                let usage = aggregate_by_name(procs, TOP_N);
+                write!(out, "{}", format_name_table(&usage))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How many of `procs` a frame shows: all of them with `--per-user-top` or
+/// `--limit-by-percent`, which have already picked the rows, otherwise
+/// [`TOP_N`].
+fn shown_rows(options: &Options, procs: &[Process]) -> usize {
+    if options.per_user_top.is_some() || options.limit_by_percent.is_some() {
+        procs.len()
+    } else {
+        TOP_N
+    }
+}
+
+/// What [`Monitor::write_table`] shows of one frame.
+struct TableFrame<'a> {
+    meminfo: Option<&'a MemInfo>,
+    tasks: Option<StateSummary>,
+    cpu: Option<&'a CpuStat>,
+    procs: &'a [Process],
+    /// The `--watch` rows, shown instead of `procs`.
+    watched: Option<&'a [Watched]>,
+    /// How long collecting `procs` took, for `--show-timing`.
+    scan_time: Option<Duration>,
+    /// CPU time spent waiting for I/O since the previous frame, in percent.
+    iowait: Option<f64>,
+    /// System-wide CPU busy percent since the previous frame, for
+    /// `--show-cpu-share`.
+    cpu_busy: Option<f64>,
+}
+
+/// `value` averaged with the previous ones by `average` with
+/// `--aggregate-interval`, as it is otherwise.
+fn smoothed(average: &mut Option<MovingAverage>, value: f64) -> f64 {
+    match average {
+        Some(average) => average.push(value),
+        None => value,
+    }
+}
+
+/// Cheap fingerprint of what a frame shows: the used memory as `mode`
+/// counts it, rounded to MiB, and the PID, RSS and CPU usage (to one
+/// decimal) of each listed process. The mode is part of it, so a reload
+/// switching `--mem-mode` redraws.
+///
+/// `--refresh-on-change` skips redrawing frames whose fingerprint matches
+/// the previous one.
+pub fn snapshot_fingerprint(meminfo: Option<&MemInfo>, mode: MemMode, procs: &[Process]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    meminfo
+        .map(|m| (mode, m.used_by(mode) / 1024))
+        .hash(&mut hasher);
+    for p in procs {
+        (p.pid, p.rss_kb, (p.cpu_percent * 10.0).round() as i64).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Feeds the system CPU busy percentage since the previous sample into the
+/// `--alert-cpu` alert and returns whether it fired.
+fn check_cpu_alert(alert: &mut Option<ThresholdAlert>, busy: f64, decimals: usize) -> bool {
+    let Some(alert) = alert.as_mut() else {
+        return false;
+    };
+    let fired = alert.update(busy);
+    if fired {
+        eprintln!(
+            "ALERT: CPU busy {busy:.decimals$}% exceeds {}%",
+            alert.threshold
+        );
+    }
+    fired
+}
+
+/// Collects, sorts and limits the process list for one frame, and tallies
+/// the states of all processes.
+///
+/// Cheap fields (name, RSS) are read for every process first; the list is
+/// then sorted and truncated to `limit` (0 keeps everything), and only the
+/// surviving rows get the more expensive per-process reads. When the sort key
+/// itself depends on those fields they are read for all processes up front.
+///
+/// Unless the sort needs those details or `--sort-by-delta`, the limit is
+/// applied while scanning with [`top_processes`], so memory stays
+/// proportional to `limit` rather than to the number of processes.
+///
+/// With `--no-sort` nothing is sorted: the first `limit` processes are kept
+/// in the order the directory listing returned them.
+///
+/// When CPU usage is only read for the displayed rows, a process that has just
+/// entered the top `limit` shows 0% for one frame because the sampler has no
+/// earlier value for it.
+pub fn collect_processes(
+    reader: &dyn ProcReader,
+    base: &str,
+    options: &Options,
+    limit: usize,
+    sampler: &mut CpuSampler,
+    cpu: CpuStat,
+) -> io::Result<ProcessSnapshot> {
+    let details_first = options.sort_needs_details();
+    let mut tasks = StateSummary::default();
+    let mut blocked = Vec::new();
+    let (mut skipped, mut parse_errors) = (0, 0);
+    let mut first_malformed = None;
+    let on_skip = |err: &io::Error| {
+        skipped += 1;
+        if err.kind() == io::ErrorKind::InvalidData {
+            parse_errors += 1;
+            first_malformed.get_or_insert_with(|| err.to_string());
+        }
+    };
+    let scanned: Box<dyn Iterator<Item = Process>> = if options.threads {
+        Box::new(scan_threads(reader, base, on_skip)?)
+    } else if let Some(cgroup) = &options.cgroup {
+        let pids = read_cgroup_procs(reader, CGROUP_ROOT, cgroup)?;
+        Box::new(scan_pids(
+            reader,
+            base,
+            pids.iter().map(u32::to_string).collect::<Vec<_>>(),
+            options.needs_status(),
+            on_skip,
+        ))
+    } else {
+        Box::new(scan_processes(
+            reader,
+            base,
+            options.needs_status(),
+            on_skip,
+        )?)
+    };
+    let scanned = scanned.map(|mut p| {
+        // The command line and executable belong to the whole process, so
+        // threads other than the main one keep their own comm.
+        if p.tgid.is_none_or(|tgid| tgid == p.pid) {
+            apply_name_source(reader, base, options.name_source, &mut p);
+        }
+        if let Some(n) = options.classify_by_arg {
+            apply_classify_by_arg(reader, base, n, &mut p);
+        }
+        p
+    });
+    // The task counts stay system-wide (or cgroup-wide with `--cgroup`).
+    let scanned = scanned
+        .inspect(|p| {
+            tasks.add(p);
+            if p.state == 'D' {
+                blocked.push((p.pid, p.name.clone()));
+            }
+        })
+        .filter(|p| options.include_kernel_threads || !p.is_kernel_thread)
+        .filter(|p| {
+            !options
+                .exclude
+                .as_ref()
+                .is_some_and(|list| list.contains(p))
+        });
+    let streaming =
+        options.sort_by_delta.is_none() && options.top_by.is_none() && options.min_cpu.is_none();
+    let mut procs = if options.no_sort {
+        // The first `limit` rows in the order the directory was listed.
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        scanned.take(limit).collect()
+    } else if limit > 0 && !details_first && streaming {
+        // The sort key is known for every process right away, so only the
+        // top `limit` rows ever need to be held.
+        top_processes(scanned, &options.sort, limit)
+    } else {
+        let mut procs: Vec<Process> = scanned.collect();
+        if details_first {
+            read_details(reader, base, options, &mut procs, sampler, cpu);
+        }
+        if let Some(min) = options.min_cpu {
+            retain_min_cpu(&mut procs, min);
+        }
+        if let Some(metric) = options.top_by
+            && limit > 0
+        {
+            // Select by the metric first, then order only the kept rows.
+            procs.sort_by(|a, b| compare_top(metric, a, b));
+            procs.truncate(limit);
+        }
+        sort_processes(&mut procs, options.sort.clone());
+        if let Some(metric) = options.sort_by_delta {
+            // Stable, so the regular order breaks ties.
+            procs.sort_by(|a, b| compare_delta(metric, a, b));
+        }
+        if limit > 0 {
+            procs.truncate(limit);
+        }
+        procs
+    };
+    if options.strict
+        && let Some(err) = first_malformed
+    {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+    }
+    if !details_first && options.needs_details() {
+        read_details(reader, base, options, &mut procs, sampler, cpu);
+    }
+    Ok(ProcessSnapshot {
+        processes: procs,
+        tasks,
+        pids: tasks.total + skipped,
+        parse_errors,
+        blocked,
+        scan_time: Duration::ZERO,
+    })
+}
+
+/// Reads the `--watch` processes that are still running with
+/// [`read_one_process_from`] and updates `watch` with them.
+fn collect_watched(
+    reader: &dyn ProcReader,
+    base: &str,
+    options: &Options,
+    watch: &mut WatchSet,
+    sampler: &mut CpuSampler,
+    cpu: CpuStat,
+) -> Vec<Watched> {
+    let mut procs: Vec<Process> = watch
+        .running()
+        .filter_map(|pid| read_one_process_from(reader, base, pid).ok())
+        .collect();
+    for p in &mut procs {
+        apply_name_source(reader, base, options.name_source, p);
+        if let Some(n) = options.classify_by_arg {
+            apply_classify_by_arg(reader, base, n, p);
+        }
+    }
+    sampler.update(&mut procs, cpu);
+    watch.update(procs)
+}
+
+/// The result of [`collect_processes`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProcessSnapshot {
+    /// The sorted and limited rows.
+    pub processes: Vec<Process>,
+    /// States of all listed processes, before limiting.
+    pub tasks: StateSummary,
+    /// Process directories found, including those that could not be read.
+    pub pids: usize,
+    /// Processes skipped because their files did not parse, as opposed to
+    /// having exited or being unreadable.
+    pub parse_errors: usize,
+    /// Pid and name of the processes in uninterruptible sleep (`D`), before
+    /// limiting.
+    pub blocked: Vec<(u32, String)>,
+    /// How long the scan took, filled in by [`Monitor`] from its clock for
+    /// `--show-timing`.
+    pub scan_time: Duration,
+}
+
+impl ProcessSnapshot {
+    /// The warning for `--warn-on-parse-error-count` when more than
+    /// `threshold` of the pids failed to parse.
+    pub fn parse_error_warning(&self, threshold: f64) -> Option<String> {
+        let fraction = self.parse_errors as f64 / self.pids.max(1) as f64;
+        (self.parse_errors > 0 && fraction > threshold).then(|| {
+            format!(
+                "WARNING: {} of {} processes could not be parsed ({:.0}%); the /proc format \
+                 may not match what this tool expects (run with --verbose for details)",
+                self.parse_errors,
+                self.pids,
+                fraction * 100.0
+            )
+        })
+    }
+
+    /// A copy that keeps only the rows for which `pred` holds.
+    ///
+    /// The figures about the scan as a whole (`tasks`, `pids`,
+    /// `parse_errors`, `blocked` and `scan_time`) are kept as they were, since
+    /// they describe the system rather than the rows.
+    pub fn filter(&self, pred: impl Fn(&Process) -> bool) -> ProcessSnapshot {
+        ProcessSnapshot {
+            processes: self.processes.iter().filter(|p| pred(p)).cloned().collect(),
+            tasks: self.tasks,
+            pids: self.pids,
+            parse_errors: self.parse_errors,
+            blocked: self.blocked.clone(),
+            scan_time: self.scan_time,
+        }
+    }
+}
+
+/// Replaces the CPU time of `p` with the sum over its threads for
+/// `--merge-threads`; keeps it when the threads cannot be read.
+fn merge_thread_cpu(reader: &dyn ProcReader, base: &str, p: &mut Process) {
+    if let Some(ticks) = read_thread_cpu_ticks(reader, base, p.pid) {
+        p.cpu_ticks = ticks;
+    }
+}
+
+fn read_details(
+    reader: &dyn ProcReader,
+    base: &str,
+    options: &Options,
+    procs: &mut [Process],
+    sampler: &mut CpuSampler,
+    cpu: CpuStat,
+) {
+    let names = options.column_names();
+    let cwd = names.iter().any(|name| name == "cwd");
+    let cmd = names.iter().any(|name| name == "cmd");
+    let io = options.uses_io();
+    let oom = options.sort.contains(SortKey::Oom)
+        || names.iter().any(|name| name == "oom" || name == "risk");
+    let sockets = options.sort.contains(SortKey::Sockets) || names.iter().any(|n| n == "sockets");
+    let wchan = names.iter().any(|name| name == "wchan");
+    let unit = names.iter().any(|name| name == "unit");
+    let sched_wait = names.iter().any(|name| name == "schedwait");
+    #[cfg(feature = "extras")]
+    let numa = names.iter().any(|name| name == "numa");
+    for p in procs.iter_mut() {
+        if oom {
+            p.oom_score = read_oom_score(reader, base, p.pid);
+        }
+        if sockets {
+            p.sockets = count_sockets(reader, base, p.pid);
+        }
+        if wchan {
+            p.wchan = read_process_wchan(reader, base, p.pid);
+        }
+        if unit {
+            p.unit = read_process_unit(reader, base, p.pid);
+        }
+        if sched_wait {
+            p.sched_wait_ns = read_schedstat(reader, base, p.pid).map(|(_, wait, _)| wait);
+        }
+        #[cfg(feature = "extras")]
+        if numa {
+            p.numa = read_numa_placement(reader, base, p.pid);
+        }
+        if io {
+            p.io = read_process_io(reader, base, p.pid);
+        }
+        read_process_details(reader, base, p);
+        if options.merge_threads {
+            merge_thread_cpu(reader, base, p);
+        }
+        if cwd {
+            p.cwd = read_process_cwd(reader, base, p.pid);
+        }
+        if cmd {
+            p.cmdline = read_process_cmdline(reader, base, p.pid);
+        }
+    }
+    sampler.update(procs, cpu);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use crate::{
+        reader::CallbackReader,
+        testing::{MockClock, MockReader, TempPath, process},
+    };
+
+    fn mock_root(used_percent: u64) -> MockReader {
+        let mut reader = MockReader::default();
+        reader.add_file(
+            "/proc/meminfo",
+            format!(
+                "MemTotal: 1000 kB\nMemAvailable: {} kB\n",
+                1000 - used_percent * 10
+            ),
+        );
+        reader.add_file(
+            "/proc/stat",
+            "cpu  10 0 10 100 0 0 0 0\ncpu0 10 0 10 100 0 0 0 0\n",
+        );
+        reader.add_process(1, "init", 100);
+        reader
+    }
+
+    /// Fails every `/proc/meminfo` read whose index is in `failures`.
+    struct FlakyReader {
+        inner: MockReader,
+        failures: Vec<usize>,
+        meminfo_reads: Cell<usize>,
+    }
+
+    impl ProcReader for FlakyReader {
+        fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+            if path == "/proc/meminfo" {
+                let n = self.meminfo_reads.get();
+                self.meminfo_reads.set(n + 1);
+                if self.failures.contains(&n) {
+                    return Err(io::ErrorKind::NotFound.into());
+                }
+            }
+            self.inner.read(path)
+        }
+
+        fn read_dir(&self, path: &str) -> io::Result<Vec<String>> {
+            self.inner.read_dir(path)
+        }
+    }
+
+    /// Accepts `capacity` bytes, then fails like a pipe whose reader has
+    /// gone away.
+    struct ClosedPipe {
+        capacity: usize,
+    }
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.capacity == 0 {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            let n = buf.len().min(self.capacity);
+            self.capacity -= n;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_broken_pipe_ends_the_run_cleanly() {
+        let options = Options::parse(["--interval", "0"].map(String::from)).unwrap();
+        let mut monitor = Monitor::with_reader(options, mock_root(50), "/proc")
+            .with_clock(MockClock::new(UNIX_EPOCH));
+        let mut out = io::BufWriter::new(ClosedPipe { capacity: 100 });
+        assert_eq!(monitor.run(&mut out).unwrap(), 0);
+    }
+
+    /// Counts the flushes it is asked for.
+    #[derive(Default)]
+    struct CountingFlushes {
+        writes: usize,
+        line_writes: usize,
+        flushes: usize,
+    }
+
+    impl Write for CountingFlushes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writes += 1;
+            self.line_writes += usize::from(buf.contains(&b'\n'));
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_flush_policy() {
+        let flushes = |policy: &str| {
+            let args = ["--iterations", "3", "--interval", "0", "--flush", policy];
+            let options = Options::parse(args.map(String::from)).unwrap();
+            let mut monitor = Monitor::with_reader(options, mock_root(50), "/proc")
+                .with_clock(MockClock::new(UNIX_EPOCH));
+            // The clear sequence is a write without a newline.
+            monitor.color = true;
+            let mut out = CountingFlushes::default();
+            monitor.run(&mut out).unwrap();
+            out
+        };
+        // Besides the flushes the policy adds, each frame is flushed once.
+        let always = flushes("always");
+        assert!(always.writes > always.line_writes);
+        assert_eq!(always.flushes, always.writes + 3);
+        let line = flushes("line");
+        assert_eq!(line.flushes, line.line_writes + 3);
+        assert_eq!(flushes("never").flushes, 0);
+    }
+
+    #[test]
+    fn test_no_escapes_without_a_terminal() {
+        let args = [
+            "--iterations",
+            "2",
+            "--interval",
+            "0",
+            "--highlight",
+            "init",
+        ];
+        let options = Options::parse(args.map(String::from)).unwrap();
+        assert!(!options.color.enabled(false));
+        let mut out = Vec::new();
+        Monitor::with_reader(options, mock_root(50), "/proc")
+            .run(&mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("init"));
+        assert!(!out.contains('\u{1b}'), "{out:?}");
+    }
+
+    #[test]
+    fn test_heartbeat_cycles_each_frame() {
+        let options = Options::parse(["--iterations", "5", "--interval", "0"].map(String::from));
+        let mut monitor = Monitor::with_reader(options.unwrap(), mock_root(50), "/proc");
+        monitor.heartbeat = true;
+        let mut out = Vec::new();
+        monitor.run(&mut out).unwrap();
+        let beats: String = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .filter(|line| line.starts_with("Memory:"))
+            .filter_map(|line| line.chars().last())
+            .collect();
+        assert_eq!(beats, "|/-\\|");
+    }
+
+    fn flaky_monitor(args: &[&str], failures: Vec<usize>) -> Monitor<FlakyReader> {
+        let mut args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        args.extend(["--interval", "0", "--no-clear"].map(String::from));
+        let reader = FlakyReader {
+            inner: mock_root(50),
+            failures,
+            meminfo_reads: Cell::new(0),
+        };
+        Monitor::with_reader(Options::parse(args).unwrap(), reader, "/proc")
+    }
+
+    #[test]
+    fn test_watchdog_gives_up_after_consecutive_failures() {
+        let mut monitor = flaky_monitor(&["--max-consecutive-errors", "3"], (0..100).collect());
+        assert_eq!(monitor.run(&mut Vec::new()).unwrap(), EXIT_WATCHDOG);
+        assert_eq!(monitor.reader.meminfo_reads.get(), 3);
+
+        // Two failures in a row, then a success resets the count.
+        let failures = vec![0, 1, 3, 4, 6, 7];
+        let mut monitor = flaky_monitor(
+            &["--max-consecutive-errors", "3", "--iterations", "9"],
+            failures,
+        );
+        assert_eq!(monitor.run(&mut Vec::new()).unwrap(), 0);
+        assert_eq!(monitor.reader.meminfo_reads.get(), 9);
+    }
+
+    #[test]
+    fn test_snapshot_fingerprint() {
+        let meminfo = MemInfo {
+            total: 8 << 20,
+            available: 4 << 20,
+            ..MemInfo::default()
+        };
+        let procs = vec![process(1, "init", 100), process(2, "sshd", 200)];
+        let available = MemMode::Available;
+        let fingerprint = snapshot_fingerprint(Some(&meminfo), available, &procs);
+        assert_eq!(
+            fingerprint,
+            snapshot_fingerprint(Some(&meminfo), available, &procs.clone())
+        );
+
+        let mut changed = procs.clone();
+        changed[1].rss_kb += 4;
+        assert_ne!(
+            fingerprint,
+            snapshot_fingerprint(Some(&meminfo), available, &changed)
+        );
+        assert_ne!(
+            fingerprint,
+            snapshot_fingerprint(Some(&meminfo), MemMode::Strict, &procs)
+        );
+
+        // Only strict mode counts the page cache as free.
+        let strict = |cached| {
+            let meminfo = MemInfo { cached, ..meminfo };
+            snapshot_fingerprint(Some(&meminfo), MemMode::Strict, &procs)
+        };
+        assert_ne!(strict(0), strict(1 << 20));
+    }
+
+    #[test]
+    fn test_delay_first_primes_cpu_sampler() {
+        let stat_reads = |args: &[&str]| {
+            let mut args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            args.extend(["--once", "--delay-first", "--interval", "0"].map(String::from));
+            let options = Options::parse(args).unwrap();
+            let mut monitor = Monitor::with_reader(options, mock_root(50), "/proc");
+            let mut out = Vec::new();
+            monitor.run(&mut out).unwrap();
+            let out = String::from_utf8(out).unwrap();
+            assert_eq!(out.matches("PID").count(), 1);
+            let reads = monitor.reader.reads();
+            reads.iter().filter(|path| *path == "/proc/1/stat").count()
+        };
+        assert_eq!(stat_reads(&["--show-cpu"]), 2);
+        assert_eq!(stat_reads(&[]), 0);
+    }
+
+    #[test]
+    fn test_json_frame() {
+        let options = Options::parse(["--once", "--format", "json"].map(String::from)).unwrap();
+        let mut monitor = Monitor::with_reader(options, mock_root(50), "/proc");
+        let mut out = Vec::new();
+        monitor.run(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"schema_version\":1,\"memory\":{\"total_kb\":1000,\"available_kb\":500,\"free_kb\":0,\"buffers_kb\":0,\
+             \"cached_kb\":0,\"used_kb\":500},\"processes\":[{\"pid\":1,\"name\":\"init\",\"rss\":100}]}\n"
+        );
+    }
+
+    #[test]
+    fn test_oneshot_json_array() {
+        let capture = |iterations: &str| {
+            let args = ["--oneshot-json-array", "--iterations", iterations];
+            let options = Options::parse(args.map(String::from)).unwrap();
+            let mut monitor = Monitor::with_reader(options, mock_root(50), "/proc")
+                .with_clock(MockClock::new(UNIX_EPOCH));
+            let mut out = Vec::new();
+            monitor.run(&mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let out = capture("2");
+        let frames = Json::parse(&out).unwrap();
+        let frames = frames.as_array().unwrap();
+        assert_eq!(frames.len(), 2, "{out}");
+        for frame in frames {
+            let procs = frame.get("processes").and_then(Json::as_array).unwrap();
+            assert_eq!(procs[0].get("name").and_then(Json::as_str), Some("init"));
+        }
+        assert_eq!(
+            Json::parse(&capture("1"))
+                .unwrap()
+                .as_array()
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(capture("0"), "[]\n");
+        assert!(Options::parse(["--oneshot-json-array"].map(String::from)).is_err());
+        for args in [
+            ["--oneshot-json-array", "--once", "--format", "table"],
+            ["--format", "ascii-table", "--oneshot-json-array", "--once"],
+        ] {
+            assert!(Options::parse(args.map(String::from)).is_err(), "{args:?}");
+        }
+        let args = ["--format", "json-map", "--oneshot-json-array", "--once"];
+        let options = Options::parse(args.map(String::from)).unwrap();
+        assert_eq!(options.format, OutputFormat::JsonMap);
+    }
+
+    #[test]
+    fn test_render_to_keeps_the_latest_frame() {
+        let path = TempPath::new("render");
+        let mut reader = mock_root(50);
+        reader.add_file("/proc/uptime", "100.0 50.0\n");
+        reader.add_file("/proc/loadavg", "0.00 0.00 0.00 1/1 1\n");
+        let args = [
+            "--batch",
+            "--iterations",
+            "2",
+            "--interval",
+            "30",
+            "--color",
+            "always",
+            "--highlight",
+            "init",
+            "--render-to",
+            path.to_str().unwrap(),
+        ];
+        let options = Options::parse(args.map(String::from)).unwrap();
+        let mut monitor =
+            Monitor::with_reader(options, reader, "/proc").with_clock(MockClock::new(UNIX_EPOCH));
+        let mut out = Vec::new();
+        monitor.run(&mut out).unwrap();
+
+        let rendered = std::fs::read_to_string(&path).unwrap();
+        assert!(
+            rendered.starts_with("top - 1970-01-01 00:00:30 UTC"),
+            "{rendered}"
+        );
+        assert_eq!(rendered.matches("top - ").count(), 1);
+        assert!(rendered.contains("init"));
+        assert!(!rendered.contains('\u{001b}'), "{rendered:?}");
+        assert!(String::from_utf8(out).unwrap().contains('\u{001b}'));
+    }
+
+    #[test]
+    fn test_highlight_header_follows_color() {
+        let run = |color: &str| {
+            let args = ["--once", "--highlight-header", "--color", color];
+            let options = Options::parse(args.map(String::from)).unwrap();
+            let mut out = Vec::new();
+            Monitor::with_reader(options, mock_root(50), "/proc")
+                .run(&mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let colored = run("always");
+        let memory = colored.lines().next().unwrap();
+        assert!(
+            memory.starts_with(REVERSE) && memory.ends_with(RESET),
+            "{memory:?}"
+        );
+        assert!(!run("never").contains('\u{001b}'));
+    }
+
+    #[test]
+    fn test_output_file_gets_plain_frames() {
+        let path = TempPath::new("output");
+        let args = [
+            "--iterations",
+            "2",
+            "--color",
+            "always",
+            "--highlight",
+            "init",
+            "--output",
+            path.to_str().unwrap(),
+        ];
+        let options = Options::parse(args.map(String::from)).unwrap();
+        let mut monitor = Monitor::with_reader(options, mock_root(50), "/proc")
+            .with_clock(MockClock::new(UNIX_EPOCH));
+        let mut out = Vec::new();
+        monitor.run_to_output(&mut out).unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(out.is_empty());
+        let frame = "Memory: total=1000kB free=500kB used=500kB\n";
+        assert!(written.starts_with(frame), "{written}");
+        assert_eq!(written.matches(frame).count(), 2);
+        assert!(written.contains("init                        100\n\nMemory:"));
+        assert!(!written.contains('\u{001b}'), "{written:?}");
+    }
+
+    #[test]
+    fn test_batch_timestamps_follow_the_clock() {
+        let mut reader = mock_root(50);
+        reader.add_file("/proc/uptime", "100.0 50.0\n");
+        reader.add_file("/proc/loadavg", "0.00 0.00 0.00 1/1 1\n");
+        let args = ["--batch", "--iterations", "3", "--interval", "30"];
+        let options = Options::parse(args.map(String::from)).unwrap();
+        let clock = Rc::new(MockClock::new(UNIX_EPOCH));
+        let mut monitor = Monitor::with_reader(options, reader, "/proc").with_clock(clock.clone());
+        let mut out = Vec::new();
+        monitor.run(&mut out).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        let stamps: Vec<&str> = out.lines().filter(|l| l.starts_with("top - ")).collect();
+        assert_eq!(stamps.len(), 3);
+        assert!(stamps[0].starts_with("top - 1970-01-01 00:00:00 UTC"));
+        assert!(stamps[2].starts_with("top - 1970-01-01 00:01:00 UTC"));
+        assert_eq!(clock.elapsed(), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_interval_align_waits_for_the_next_boundary() {
+        let args = ["--iterations", "3", "--interval", "1", "--interval-align"];
+        let options = Options::parse(args.map(String::from)).unwrap();
+        let clock = Rc::new(MockClock::new(UNIX_EPOCH + Duration::from_millis(250)));
+        let mut monitor =
+            Monitor::with_reader(options, mock_root(50), "/proc").with_clock(clock.clone());
+        monitor.run(&mut Vec::new()).unwrap();
+        // To 00:00:01 first, then one whole second to 00:00:02.
+        assert_eq!(clock.elapsed(), Duration::from_millis(1750));
+    }
+
+    /// Lets `clock` run `per_read` forward on every file read.
+    struct SlowReader {
+        inner: MockReader,
+        clock: Rc<MockClock>,
+        per_read: Duration,
+    }
+
+    impl ProcReader for SlowReader {
+        fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+            self.clock.advance(self.per_read);
+            self.inner.read(path)
+        }
+
+        fn read_dir(&self, path: &str) -> io::Result<Vec<String>> {
+            self.inner.read_dir(path)
+        }
+    }
+
+    #[test]
+    fn test_show_timing_measures_the_scan() {
+        let clock = Rc::new(MockClock::new(UNIX_EPOCH));
+        let reader = SlowReader {
+            inner: mock_root(50),
+            clock: clock.clone(),
+            per_read: Duration::from_millis(2),
+        };
+        let options = Options::parse(["--once", "--show-timing"].map(String::from)).unwrap();
+        let mut monitor = Monitor::with_reader(options, reader, "/proc").with_clock(clock.clone());
+        let mut out = Vec::new();
+        monitor.run(&mut out).unwrap();
+
+        // comm and status of the one process, after meminfo and stat.
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("\nscan: 4ms\n"), "{out}");
+    }
+
+    #[test]
+    fn test_cpu_share_from_the_second_frame() {
+        let args = ["--iterations", "2", "--interval", "1", "--show-cpu-share"];
+        let options = Options::parse(args.map(String::from)).unwrap();
+        let mut monitor = Monitor::with_reader(options, mock_root(50), "/proc")
+            .with_clock(Rc::new(MockClock::new(UNIX_EPOCH)));
+        let mut out = Vec::new();
+        monitor.run(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            out.matches("top 1 = 0.0% of 0.0% busy\n").count(),
+            1,
+            "{out}"
+        );
+    }
+
+    #[test]
+    fn test_max_fps_throttles_drawing() {
+        let args = ["--iterations", "10", "--interval", "0.1", "--max-fps", "2"];
+        let options = Options::parse(args.map(String::from)).unwrap();
+        let clock = Rc::new(MockClock::new(UNIX_EPOCH));
+        let mut monitor =
+            Monitor::with_reader(options, mock_root(50), "/proc").with_clock(clock.clone());
+        let mut out = Vec::new();
+        monitor.run(&mut out).unwrap();
+
+        // Frames at 0.0, 0.1, ..., 0.9 s; only those at 0.0 and 0.5 s and
+        // the last one are drawn.
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.matches("Memory:").count(), 3);
+        assert_eq!(
+            monitor
+                .reader
+                .reads()
+                .iter()
+                .filter(|p| *p == "/proc/meminfo")
+                .count(),
+            10
+        );
+    }
+
+    #[test]
+    fn test_max_fps_draws_the_last_iteration() {
+        let args = ["--iterations", "2", "--interval", "0.5", "--max-fps", "1"];
+        let options = Options::parse(args.map(String::from)).unwrap();
+        let mut monitor = Monitor::with_reader(options, mock_root(50), "/proc")
+            .with_clock(MockClock::new(UNIX_EPOCH));
+        let mut out = Vec::new();
+        monitor.run(&mut out).unwrap();
+
+        // The second frame comes 0.5 s after the first, within the 1 s gap.
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.matches("Memory:").count(), 2, "{out}");
+    }
+
+    /// A [`MockClock`] that records every sleep.
+    struct RecordingClock {
+        inner: MockClock,
+        sleeps: RefCell<Vec<Duration>>,
+    }
+
+    impl Clock for RecordingClock {
+        fn now(&self) -> Instant {
+            self.inner.now()
+        }
+
+        fn system_now(&self) -> std::time::SystemTime {
+            self.inner.system_now()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            self.sleeps.borrow_mut().push(duration);
+            self.inner.sleep(duration);
+        }
+    }
+
+    #[test]
+    fn test_interval_jitter_bounds_each_sleep() {
+        let args = [
+            "--iterations",
+            "501",
+            "--interval",
+            "2",
+            "--interval-jitter",
+            "0.25",
+        ];
+        let options = Options::parse(args.map(String::from)).unwrap();
+        let clock = Rc::new(RecordingClock {
+            inner: MockClock::new(UNIX_EPOCH),
+            sleeps: RefCell::default(),
+        });
+        let mut monitor =
+            Monitor::with_reader(options, mock_root(50), "/proc").with_clock(clock.clone());
+        monitor.jitter = Some(Jitter::new(0.25, 7));
+        monitor.run(&mut io::sink()).unwrap();
+
+        let sleeps = clock.sleeps.borrow();
+        assert_eq!(sleeps.len(), 500);
+        let (low, high) = (Duration::from_millis(1500), Duration::from_millis(2500));
+        assert!(sleeps.iter().all(|s| (low..=high).contains(s)));
+        assert!(sleeps.iter().any(|s| *s != Duration::from_secs(2)));
+        let mean = sleeps.iter().sum::<Duration>().as_secs_f64() / 500.0;
+        assert!((mean - 2.0).abs() < 0.05, "mean {mean}");
+    }
+
+    #[test]
+    fn test_exclude_pids_from_file() {
+        let path = TempPath::new("exclude");
+        fs::write(&path, "# agents\nsshd\n").unwrap();
+        let mut reader = mock_root(50);
+        reader.add_process(7, "sshd", 200);
+        let args = ["--once", "--exclude-pids-from", path.to_str().unwrap()];
+        let options = Options::parse(args.map(String::from)).unwrap();
+        let mut monitor = Monitor::with_reader(options, reader, "/proc");
+        let mut out = Vec::new();
+        let code = monitor.run(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(code, 0);
+        assert!(out.contains("init") && !out.contains("sshd"), "{out}");
+
+        let args = ["--once", "--exclude-pids-from", "/nonexistent/exclude"];
+        let options = Options::parse(args.map(String::from)).unwrap();
+        let mut monitor = Monitor::with_reader(options, mock_root(50), "/proc");
+        assert_eq!(monitor.run(&mut Vec::new()).unwrap(), EXIT_BAD_EXCLUDE);
+    }
+
+    #[test]
+    fn test_adaptive_interval_floor() {
+        let args = ["--adaptive-interval", "0.0001:0.0002", "--iterations", "3"];
+        let options = Options::parse(args.map(String::from)).unwrap();
+        let clock = Rc::new(RecordingClock {
+            inner: MockClock::new(UNIX_EPOCH),
+            sleeps: RefCell::default(),
+        });
+        let mut monitor =
+            Monitor::with_reader(options, mock_root(50), "/proc").with_clock(clock.clone());
+        monitor.run(&mut Vec::new()).unwrap();
+        assert_eq!(*clock.sleeps.borrow(), [MIN_INTERVAL; 2]);
+    }
+
+    #[test]
+    fn test_sighup_reload_applies_new_interval() {
+        let path = TempPath::new("reload");
+        fs::write(&path, "interval = 2\niterations = 3\n").unwrap();
+        let mut config = Config::default();
+        let path_str = path.to_str().unwrap().to_string();
+        config.env.insert("PROCMON_CONFIG".into(), path_str);
+        let config = config.reload().unwrap();
+        let options = config.resolve().unwrap();
+        assert_eq!(options.interval, Some(Duration::from_secs(2)));
+
+        fs::write(&path, "interval = 5\niterations = 3\nformat = json\n").unwrap();
+        static REQUESTED: AtomicBool = AtomicBool::new(true);
+        let clock = Rc::new(RecordingClock {
+            inner: MockClock::new(UNIX_EPOCH),
+            sleeps: RefCell::default(),
+        });
+        let mut monitor = Monitor::with_reader(options, mock_root(50), "/proc")
+            .with_clock(clock.clone())
+            .with_config(config)
+            .with_reload_flag(&REQUESTED);
+        let mut out = Vec::new();
+        monitor.run(&mut out).unwrap();
+
+        assert_eq!(*clock.sleeps.borrow(), [Duration::from_secs(5); 2]);
+        assert!(
+            !String::from_utf8(out).unwrap().contains('{'),
+            "format stays"
+        );
+    }
+
+    #[test]
+    fn test_processes_only_skips_meminfo() {
+        let options = Options::parse(["--once", "--processes-only"].map(String::from)).unwrap();
+        let mut monitor = Monitor::with_reader(options, mock_root(50), "/proc");
+        let mut out = Vec::new();
+        monitor.run(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("PID "), "{out}");
+        assert!(
+            !monitor
+                .reader
+                .reads()
+                .contains(&"/proc/meminfo".to_string())
+        );
+
+        for args in [
+            &["--processes-only", "--show-mem"][..],
+            &["--processes-only", "--columns", "pid,mem"],
+        ] {
+            let err = Options::parse(args.iter().map(|arg| arg.to_string())).unwrap_err();
+            assert!(err.contains("%MEM"), "{err}");
+        }
+    }
+
+    #[test]
+    fn test_snapshot_through_callback_reader() {
+        let files: HashMap<String, &str> = [
+            (
+                "/remote/meminfo",
+                "MemTotal: 2000 kB\nMemAvailable: 500 kB\n",
+            ),
+            ("/remote/stat", "cpu  10 0 10 100 0 0 0 0\n"),
+            ("/remote/7/comm", "nginx\n"),
+            (
+                "/remote/7/status",
+                "Name:\tnginx\nVmHWM:\t900 kB\nVmRSS:\t800 kB\n",
+            ),
+            ("/remote/9/comm", "redis\n"),
+            (
+                "/remote/9/status",
+                "Name:\tredis\nVmHWM:\t300 kB\nVmRSS:\t300 kB\n",
+            ),
+        ]
+        .into_iter()
+        .map(|(path, content)| (path.to_string(), content))
+        .collect();
+        let reader = CallbackReader::new(
+            |path: &str| {
+                files
+                    .get(path)
+                    .map(|content| content.as_bytes().to_vec())
+                    .ok_or_else(|| io::ErrorKind::NotFound.into())
+            },
+            |path: &str| match path {
+                "/remote" => Ok(vec!["7".to_string(), "9".to_string(), "self".to_string()]),
+                _ => Err(io::ErrorKind::NotFound.into()),
+            },
+        );
+        let options = Options::parse(["--once", "--no-clear"].map(String::from)).unwrap();
+        let mut monitor = Monitor::with_reader(options, reader, "/remote");
+        let mut out = Vec::new();
+        assert_eq!(monitor.run(&mut out).unwrap(), 0);
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.starts_with("Memory: total=2000kB free=500kB used=1500kB\n"),
+            "{out}"
+        );
+        let nginx = out.find("nginx").expect("nginx listed");
+        let redis = out.find("redis").expect("redis listed");
+        assert!(nginx < redis, "{out}");
+    }
+
+    #[test]
+    fn test_summary_only() {
+        let mut reader = mock_root(50);
+        reader.add_file(
+            "/proc/meminfo",
+            "MemTotal: 1000 kB\nMemAvailable: 500 kB\nSwapTotal: 300 kB\nSwapFree: 100 kB\n",
+        );
+        reader.add_file("/proc/loadavg", "0.15 0.10 0.05 1/1 1\n");
+        reader.add_process(2, "sshd", 50);
+        let options = Options::parse(["--once", "--summary-only"].map(String::from)).unwrap();
+        let mut out = Vec::new();
+        Monitor::with_reader(options, reader, "/proc")
+            .run(&mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        for field in [
+            "total=1000kB",
+            "used=500kB",
+            "swap=200kB",
+            "Load average: 0.15, 0.10, 0.05",
+            // Busy time since boot: 20 of 120 jiffies.
+            "CPU: 16.7% busy",
+            "Tasks: 2 total",
+        ] {
+            assert!(out.contains(field), "{field} missing from {out}");
+        }
+        assert!(!out.contains("sshd"), "{out}");
+    }
+
+    #[test]
+    fn test_memory_only_does_not_scan_processes() {
+        let options = Options::parse(["--once", "--memory-only"].map(String::from)).unwrap();
+        let mut monitor = Monitor::with_reader(options, mock_root(50), "/proc");
+        let mut out = Vec::new();
+        monitor.run(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Memory: total=1000kB free=500kB used=500kB\n"
+        );
+        assert!(monitor.reader.dir_reads().is_empty());
+        assert_eq!(monitor.reader.reads(), ["/proc/meminfo"]);
+    }
+
+    #[test]
+    fn test_group_summary_shows_processes_and_programs() {
+        let mut reader = mock_root(50);
+        for (pid, name, rss) in [
+            (2, "firefox", 900),
+            (3, "postgres", 300),
+            (4, "postgres", 400),
+            (5, "postgres", 500),
+        ] {
+            reader.add_process(pid, name, rss);
+        }
+        let args = ["--once", "--processes-only", "--group-summary"];
+        let options = Options::parse(args.map(String::from)).unwrap();
+        let mut monitor = Monitor::with_reader(options, reader, "/proc");
+        let mut out = Vec::new();
+        monitor.run(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        let (processes, programs) = out.split_once("\n== Top programs ==\n").unwrap();
+        assert!(processes.starts_with("== Top processes ==\nPID"), "{out}");
+        assert_eq!(processes.lines().count(), 7, "{out}");
+        assert_eq!(
+            programs,
+            "NAME                    RSS(kB)  PROCS\n\
+             postgres                   1200      3\n\
+             firefox                     900      1\n\
+             init                        100      1\n"
+        );
+    }
+
+    #[test]
+    fn test_once_exit_code_reflects_memory_alert() {
+        let options = Options::parse(["--once", "--alert-mem", "10"].map(String::from)).unwrap();
+        let mut monitor = Monitor::with_reader(options.clone(), mock_root(50), "/proc");
+        let mut out = Vec::new();
+        assert_eq!(monitor.run(&mut out).unwrap(), EXIT_ALERT);
+        assert!(String::from_utf8(out).unwrap().contains("init"));
+
+        let mut monitor = Monitor::with_reader(options, mock_root(5), "/proc");
+        assert_eq!(monitor.run(&mut Vec::new()).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_tasks_line_shows_change_since_previous_frame() {
+        let options = Options::parse(Vec::new()).unwrap();
+        let mut monitor = Monitor::with_reader(options, mock_root(50), "/proc");
+        let frame = |monitor: &mut Monitor<MockReader>| {
+            let mut out = Vec::new();
+            monitor.frame(&mut out).unwrap();
+            let out = String::from_utf8(out).unwrap();
+            out.lines()
+                .find(|l| l.starts_with("Tasks:"))
+                .unwrap()
+                .to_string()
+        };
+        assert!(frame(&mut monitor).starts_with("Tasks: 1 total, "));
+        monitor.reader.add_process(2, "sshd", 50);
+        monitor.reader.add_process(3, "bash", 20);
+        assert!(frame(&mut monitor).starts_with("Tasks: 3 total (+2), "));
+        assert!(frame(&mut monitor).starts_with("Tasks: 3 total (+0), "));
+    }
+
+    #[test]
+    fn test_top_growth_reports_at_the_end_only() {
+        let args = ["--top-growth", "--iterations", "2", "--interval", "0"];
+        let options = Options::parse(args.map(String::from)).unwrap();
+        let mut monitor = Monitor::with_reader(options, mock_root(50), "/proc");
+        let mut out = Vec::new();
+        monitor.run(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.starts_with("== Top growth over 2 frames ==\nPID"),
+            "{out}"
+        );
+        assert!(out.contains("init"), "{out}");
+        assert!(!out.contains("Memory:"), "{out}");
+        assert!(Options::parse(["--top-growth"].map(String::from)).is_err());
+    }
+
+    #[test]
+    fn test_used_kb_alert_fires_on_crossing() {
+        let options = Options::parse(["--alert-used-kb", "600"].map(String::from)).unwrap();
+        let mut monitor = Monitor::with_reader(options, mock_root(50), "/proc");
+        // Used memory per frame, in kB of the 1000 kB total.
+        let fired: Vec<bool> = [500, 700, 900, 600, 650]
+            .into_iter()
+            .map(|used| {
+                let meminfo = format!("MemTotal: 1000 kB\nMemAvailable: {} kB\n", 1000 - used);
+                monitor.reader.add_file("/proc/meminfo", meminfo);
+                monitor.alert_fired = false;
+                monitor.frame(&mut Vec::new()).unwrap();
+                monitor.alert_fired
+            })
+            .collect();
+        assert_eq!(fired, [false, true, false, false, true]);
+    }
+
+    #[test]
+    fn test_fail_if_empty() {
+        let options = Options::parse(
+            ["--fail-if-empty", "--cgroup", "/system.slice/gone.service"].map(String::from),
+        )
+        .unwrap();
+        let mut reader = mock_root(50);
+        let procs = "/sys/fs/cgroup/system.slice/gone.service/cgroup.procs";
+        reader.add_file(procs, "");
+        let mut monitor = Monitor::with_reader(options.clone(), reader, "/proc");
+        assert_eq!(monitor.run(&mut Vec::new()).unwrap(), EXIT_EMPTY);
+
+        let mut reader = mock_root(50);
+        reader.add_file(procs, "1\n");
+        let options = Options {
+            once: true,
+            ..options
+        };
+        let mut monitor = Monitor::with_reader(options, reader, "/proc");
+        assert_eq!(monitor.run(&mut Vec::new()).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_details_are_read_only_for_kept_rows() {
+        let mut reader = MockReader::default();
+        for (pid, rss) in [(1, 100), (2, 300), (3, 200)] {
+            reader.add_process(pid, &format!("proc{pid}"), rss);
+        }
+        let options = Options {
+            show_tty: true,
+            ..Options::default()
+        };
+        let mut sampler = CpuSampler::default();
+        let procs = collect_processes(
+            &reader,
+            "/proc",
+            &options,
+            1,
+            &mut sampler,
+            CpuStat::default(),
+        )
+        .unwrap()
+        .processes;
+
+        assert_eq!(procs.len(), 1);
+        assert_eq!(procs[0].pid, 2);
+        assert_eq!(procs[0].tty_nr, 34816);
+        let stat_reads: Vec<String> = reader
+            .reads()
+            .into_iter()
+            .filter(|path| path.ends_with("/stat"))
+            .collect();
+        assert_eq!(stat_reads, ["/proc/2/stat"]);
+    }
+
+    #[test]
+    fn test_strict_fails_on_malformed_status() {
+        let run = |args: &[&str]| {
+            let mut reader = mock_root(50);
+            reader.add_file("/proc/5/status", "Name:\tbad\nVmRSS:\tlots kB\n");
+            let options = Options::parse(args.iter().map(|arg| arg.to_string())).unwrap();
+            let mut out = Vec::new();
+            Monitor::with_reader(options, reader, "/proc")
+                .run(&mut out)
+                .map(|_| String::from_utf8(out).unwrap())
+        };
+        let lenient = run(&["--once"]).unwrap();
+        assert!(lenient.contains("init"), "{lenient}");
+        let err = run(&["--once", "--strict"]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            r#"malformed VmRSS in /proc/5/status (line 2: "VmRSS:\tlots kB")"#
+        );
+    }
+
+    #[test]
+    fn test_duplicate_pids_in_a_snapshot_root() {
+        let root = || {
+            let mut reader = mock_root(50);
+            reader.add_file("/proc/01/comm", "copy\n");
+            reader.add_file("/proc/01/status", "Name:\tcopy\nVmRSS:\t100 kB\n");
+            reader
+        };
+        let procs = list_processes_from(&root(), "/proc").unwrap();
+        let names: Vec<(u32, &str)> = procs.iter().map(|p| (p.pid, p.name.as_str())).collect();
+        assert_eq!(names, [(1, "copy")]);
+
+        let run = |args: &[&str]| {
+            let options = Options::parse(args.iter().map(|arg| arg.to_string())).unwrap();
+            let mut out = Vec::new();
+            Monitor::with_reader(options, root(), "/proc")
+                .run(&mut out)
+                .map(|_| String::from_utf8(out).unwrap())
+        };
+        let lenient = run(&["--once"]).unwrap();
+        assert_eq!(lenient.matches("\n1 ").count(), 1, "{lenient}");
+        let err = run(&["--once", "--strict"]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "duplicate pid 1 in /proc");
+    }
+
+    #[test]
+    fn test_snapshot_filter() {
+        let snapshot = ProcessSnapshot {
+            processes: vec![
+                process(1, "init", 100),
+                process(2, "postgres", 300),
+                process(3, "postgres", 200),
+            ],
+            tasks: summarize_states(&[process(1, "init", 100)]),
+            pids: 3,
+            blocked: vec![(2, "postgres".to_string())],
+            ..ProcessSnapshot::default()
+        };
+        let filtered = snapshot.filter(|p| p.name == "postgres");
+        let pids: Vec<u32> = filtered.processes.iter().map(|p| p.pid).collect();
+        assert_eq!(pids, [2, 3]);
+        assert_eq!(
+            ProcessSnapshot {
+                processes: snapshot.processes.clone(),
+                ..filtered
+            },
+            snapshot
+        );
+    }
+
+    #[test]
+    fn test_cgroup_restricts_the_listing() {
+        let mut reader = MockReader::default();
+        for (pid, rss) in [(1, 100), (20, 300), (21, 200), (30, 400)] {
+            reader.add_process(pid, &format!("proc{pid}"), rss);
+        }
+        // 22 exited after the cgroup was read.
+        reader.add_file(
+            "/sys/fs/cgroup/system.slice/web.service/cgroup.procs",
+            "20\n21\n22\n",
+        );
+        let options =
+            Options::parse(["--cgroup", "/system.slice/web.service/"].map(String::from)).unwrap();
+        let snapshot = collect_processes(
+            &reader,
+            "/proc",
+            &options,
+            TOP_N,
+            &mut CpuSampler::default(),
+            CpuStat::default(),
+        )
+        .unwrap();
+        let pids: Vec<u32> = snapshot.processes.iter().map(|p| p.pid).collect();
+        assert_eq!(pids, [20, 21]);
+        assert_eq!((snapshot.tasks.total, snapshot.pids), (2, 3));
+        assert!(reader.dir_reads().is_empty(), "/proc is not listed");
+    }
+
+    #[test]
+    fn test_no_sort_keeps_enumeration_order() {
+        let mut reader = MockReader::default();
+        for (pid, rss) in [(1, 100), (2, 300), (3, 200), (4, 400)] {
+            reader.add_process(pid, &format!("proc{pid}"), rss);
+        }
+        let options = Options::parse(["--no-sort".to_string()]).unwrap();
+        let mut sampler = CpuSampler::default();
+        let mut pids = |limit| {
+            collect_processes(
+                &reader,
+                "/proc",
+                &options,
+                limit,
+                &mut sampler,
+                CpuStat::default(),
+            )
+            .unwrap()
+            .processes
+            .iter()
+            .map(|p| p.pid)
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(pids(0), [1, 2, 3, 4]);
+        assert_eq!(pids(2), [1, 2], "the first rows, not the largest");
+        assert!(Options::parse(["--no-sort", "--sort", "cpu"].map(String::from)).is_err());
+    }
+
+    #[test]
+    fn test_cpu_sort_reads_details_for_every_process() {
+        let mut reader = MockReader::default();
+        for pid in 1..=3 {
+            reader.add_process(pid, "p", 10);
+        }
+        let options = Options {
+            sort: crate::SortKey::Cpu.into(),
+            ..Options::default()
+        };
+        let mut sampler = CpuSampler::default();
+        collect_processes(
+            &reader,
+            "/proc",
+            &options,
+            1,
+            &mut sampler,
+            CpuStat::default(),
+        )
+        .unwrap();
+        let stat_reads = reader
+            .reads()
+            .iter()
+            .filter(|p| p.ends_with("/stat"))
+            .count();
+        assert_eq!(stat_reads, 3);
+    }
+
+    #[test]
+    fn test_limit_by_percent_of_used_memory() {
+        let names = |args: &[&str]| {
+            // 800 kB used.
+            let mut reader = mock_root(80);
+            for (pid, name, rss) in [(2, "db", 300), (3, "web", 200), (4, "cache", 150)] {
+                reader.add_process(pid, name, rss);
+            }
+            for pid in 5..=9 {
+                reader.add_process(pid, "worker", 10);
+            }
+            let mut args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            args.extend(["--once", "--columns", "name"].map(String::from));
+            let options = Options::parse(args).unwrap();
+            let mut out = Vec::new();
+            Monitor::with_reader(options, reader, "/proc")
+                .run(&mut out)
+                .unwrap();
+            let out = String::from_utf8(out).unwrap();
+            let rows = out.lines().skip_while(|line| line.trim() != "NAME").skip(1);
+            rows.map(|line| line.trim().to_string()).collect::<Vec<_>>()
+        };
+        // 300 + 200 reach 50% of 800.
+        assert_eq!(names(&["--limit-by-percent", "50"]), ["db", "web"]);
+        // 750 kB, then 50 kB of workers: more rows than the top 5.
+        assert_eq!(names(&["--limit-by-percent", "99"]).len(), 9);
+        assert_eq!(
+            names(&["--limit-by-percent", "85", "--sort", "name"]),
+            ["cache", "db", "init", "web"]
+        );
+        assert!(Options::parse(["--limit-by-percent", "0"].map(String::from)).is_err());
+        let args = ["--limit-by-percent", "50", "--processes-only"];
+        assert!(Options::parse(args.map(String::from)).is_err());
+    }
+
+    #[test]
+    fn test_parse_error_count_and_warning() {
+        let mut reader = MockReader::default();
+        for pid in 1..=4 {
+            reader.add_process(pid, "p", 10);
+        }
+        for pid in [2, 4] {
+            reader.add_file(
+                &format!("/proc/{pid}/status"),
+                "Name:\tp\nVmRSS:\tlots kB\n",
+            );
+        }
+        let mut sampler = CpuSampler::default();
+        let snapshot = collect_processes(
+            &reader,
+            "/proc",
+            &Options::default(),
+            TOP_N,
+            &mut sampler,
+            CpuStat::default(),
+        )
+        .unwrap();
+        assert_eq!(snapshot.processes.len(), 2);
+        assert_eq!((snapshot.pids, snapshot.parse_errors), (4, 2));
+        let warning = snapshot
+            .parse_error_warning(Options::default().parse_error_threshold())
+            .unwrap();
+        assert!(warning.contains("2 of 4 processes"), "{warning}");
+        assert_eq!(snapshot.parse_error_warning(0.5), None);
+    }
+
+    #[test]
+    fn test_kernel_threads_need_include_flag() {
+        let mut reader = MockReader::default();
+        reader.add_process(42, "bash", 3104);
+        reader.add_file("/proc/2/comm", "kthreadd\n");
+        reader.add_file("/proc/2/status", "Name:\tkthreadd\nState:\tS (sleeping)\n");
+        reader.add_file("/proc/2/cmdline", "");
+        reader.add_file(
+            "/proc/2/stat",
+            "2 (kthreadd) S 0 0 0 0 -1 0 0 0 0 0 5 9 0 0 20 0 1 0 2",
+        );
+
+        let collect = |args: &[&str]| {
+            let options = Options::parse(args.iter().map(|a| a.to_string())).unwrap();
+            let snapshot = collect_processes(
+                &reader,
+                "/proc",
+                &options,
+                TOP_N,
+                &mut CpuSampler::default(),
+                CpuStat::default(),
+            )
+            .unwrap();
+            let rows: Vec<_> = snapshot
+                .processes
+                .iter()
+                .map(|p| (p.pid, p.rss_kb))
+                .collect();
+            (rows, snapshot.tasks.total)
+        };
+        assert_eq!(collect(&[]), (vec![(42, 3104)], 2), "still counted");
+        assert_eq!(
+            collect(&["--include-kernel-threads"]),
+            (vec![(42, 3104), (2, 0)], 2)
+        );
+    }
+
+    #[test]
+    fn test_threads_lists_each_task() {
+        let mut reader = MockReader::default();
+        reader.add_file("/proc/10/status", "Name:\tdb\n");
+        for (tid, name, utime) in [(10, "db", 30), (11, "db-worker", 70)] {
+            let task = format!("/proc/10/task/{tid}");
+            reader.add_file(&format!("{task}/comm"), format!("{name}\n"));
+            reader.add_file(
+                &format!("{task}/status"),
+                format!("Name:\t{name}\nVmRSS:\t800 kB\n"),
+            );
+            reader.add_file(
+                &format!("{task}/stat"),
+                format!("{tid} ({name}) S 1 10 10 0 -1 0 0 0 0 0 {utime} 0 0 0 20 0 2 0 100"),
+            );
+        }
+        let kthread = "/proc/2/task/2";
+        reader.add_file(&format!("{kthread}/comm"), "kthreadd\n");
+        reader.add_file(&format!("{kthread}/status"), "Name:\tkthreadd\nState:\tS\n");
+        reader.add_file(&format!("{kthread}/cmdline"), "");
+        reader.add_file("/proc/10/cmdline", "/usr/bin/postgres\0-D\0/srv\0");
+
+        let collect = |args: &[&str]| {
+            let options = Options::parse(args.iter().map(|a| a.to_string())).unwrap();
+            let mut sampler = CpuSampler::default();
+            let snapshot = collect_processes(
+                &reader,
+                "/proc",
+                &options,
+                TOP_N,
+                &mut sampler,
+                CpuStat::default(),
+            )
+            .unwrap();
+            (options, snapshot)
+        };
+        let args = ["--threads", "--include-kernel-threads", "--sort", "pid"];
+        let (options, snapshot) = collect(&args);
+        let rows: Vec<_> = snapshot
+            .processes
+            .iter()
+            .map(|p| (p.pid, p.tgid, p.name.as_str(), p.cpu_ticks))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                (2, Some(2), "kthreadd", 0),
+                (10, Some(10), "db", 30),
+                (11, Some(10), "db-worker", 70)
+            ]
+        );
+        let table = format_top_processes(&snapshot.processes, TOP_N, &options, false);
+        let kinds: Vec<&str> = table
+            .lines()
+            .map(|line| line.split_whitespace().nth(2).unwrap())
+            .collect();
+        assert_eq!(kinds, ["TASK", "kernel", "main", "thread"]);
+        assert!(table.starts_with("TID    TGID   TASK"), "{table}");
+
+        // Only the main thread takes its name from the command line.
+        let args = ["--threads", "--name-source", "cmdline", "--sort", "pid"];
+        let names: Vec<String> = collect(&args)
+            .1
+            .processes
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        assert_eq!(names, ["postgres", "db-worker"]);
+    }
+
+    #[test]
+    fn test_sort_key_warning_only_for_text_output() {
+        let warned = |args: &[&str]| {
+            let mut args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            args.extend(["--once", "--sort", "cpu"].map(String::from));
+            let options = Options::parse(args).unwrap();
+            let mut monitor = Monitor::with_reader(options, mock_root(50), "/proc");
+            monitor.run(&mut Vec::new()).unwrap();
+            monitor.warned
+        };
+        assert!(warned(&[]));
+        assert!(!warned(&["--format", "json"]));
+        assert!(!warned(&["--format", "json-map"]));
+        assert!(!warned(&["--field-separator", ","]));
+    }
+
+    #[test]
+    fn test_quiet_prints_only_rows() {
+        let mut reader = mock_root(50);
+        reader.add_process(2, "sshd", 300);
+        let args = ["--quiet", "--batch", "--cpu-detail", "--iterations", "2"];
+        let mut args: Vec<String> = args.map(String::from).to_vec();
+        args.extend(["--interval", "0", "--field-separator", ","].map(String::from));
+        let options = Options::parse(args).unwrap();
+        assert!(options.processes_only);
+        let mut out = Vec::new();
+        Monitor::with_reader(options, reader, "/proc")
+            .with_clock(MockClock::new(UNIX_EPOCH))
+            .run(&mut out)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2,sshd,300\n1,init,100\n2,sshd,300\n1,init,100\n"
+        );
+    }
+
+    #[test]
+    fn test_top_by_selects_before_sorting() {
+        let mut reader = MockReader::default();
+        for (pid, name, rss) in [
+            (1, "zeta", 300),
+            (2, "alpha", 50),
+            (3, "mid", 200),
+            (4, "beta", 10),
+        ] {
+            reader.add_process(pid, name, rss);
+        }
+        let collect = |args: &[&str]| {
+            let options = Options::parse(args.iter().map(|a| a.to_string())).unwrap();
+            let mut sampler = CpuSampler::default();
+            collect_processes(
+                &reader,
+                "/proc",
+                &options,
+                2,
+                &mut sampler,
+                CpuStat::default(),
+            )
+            .unwrap()
+            .processes
+            .into_iter()
+            .map(|p| p.name)
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            collect(&["--top-by", "mem", "--sort", "name"]),
+            ["mid", "zeta"]
+        );
+        assert_eq!(collect(&["--sort", "name"]), ["alpha", "beta"]);
+        assert_eq!(collect(&["--top-by", "mem"]), ["zeta", "mid"]);
+    }
+}
@@ -0,0 +1,95 @@
+//! NUMA memory placement from `/proc/<pid>/numa_maps` (`--show-numa`).
+
+use std::{collections::BTreeMap, fmt::Display};
+
+use crate::{parse::byte_lines, reader::ProcReader, system::page_size_kb};
+
+/// The NUMA node most of a process's memory lives on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NumaPlacement {
+    pub node: u32,
+    /// Share of the process's memory on `node`, in percent.
+    pub percent: f64,
+}
+
+impl Display for NumaPlacement {
+    /// `N<node> <percent>%`, e.g. `N1 87%`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "N{} {:.0}%", self.node, self.percent)
+    }
+}
+
+/// Sums the memory per NUMA node over the mappings of `/proc/<pid>/numa_maps`,
+/// in kB.
+///
+/// Each line is one mapping whose `N<node>=<pages>` tokens count its pages
+/// on each node, e.g.
+/// `7f3a1c000000 default file=/usr/lib/libc.so.6 mapped=40 N0=30 N1=10
+/// kernelpagesize_kB=4`. The pages are weighted by the line's
+/// `kernelpagesize_kB`, so huge pages count for their size; lines without
+/// it use the base page size.
+pub fn parse_numa_maps(content: &[u8]) -> BTreeMap<u32, u64> {
+    let base_kb = page_size_kb();
+    let mut nodes = BTreeMap::new();
+    for line in byte_lines(content) {
+        let line = String::from_utf8_lossy(line);
+        let tokens: Vec<(&str, &str)> = line
+            .split_whitespace()
+            .filter_map(|token| token.split_once('='))
+            .collect();
+        let page_kb = tokens
+            .iter()
+            .find(|(key, _)| *key == "kernelpagesize_kB")
+            .and_then(|(_, kb)| kb.parse().ok())
+            .unwrap_or(base_kb);
+        for (key, pages) in tokens {
+            if let Some(node) = key.strip_prefix('N')
+                && let (Ok(node), Ok(pages)) = (node.parse::<u32>(), pages.parse::<u64>())
+            {
+                *nodes.entry(node).or_default() += pages * page_kb;
+            }
+        }
+    }
+    nodes
+}
+
+/// The node with the most memory in `nodes`, the lowest on a tie, and its
+/// share. `None` when there is no memory at all, as for a kernel thread.
+pub fn dominant_node(nodes: &BTreeMap<u32, u64>) -> Option<NumaPlacement> {
+    let total: u64 = nodes.values().sum();
+    let (&node, &kb) = nodes
+        .iter()
+        .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))?;
+    (total > 0).then(|| NumaPlacement {
+        node,
+        percent: kb as f64 * 100.0 / total as f64,
+    })
+}
+
+/// Reads `/proc/<pid>/numa_maps`, see [`parse_numa_maps`]; `None` when it
+/// cannot be read (no NUMA support, or another user's process) or is empty.
+pub fn read_numa_placement(reader: &dyn ProcReader, base: &str, pid: u32) -> Option<NumaPlacement> {
+    let content = reader.read(&format!("{base}/{pid}/numa_maps")).ok()?;
+    dominant_node(&parse_numa_maps(&content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_numa_maps() {
+        let content = b"\
+55d0c4a00000 default file=/usr/bin/postgres mapped=300 active=0 N0=200 N1=100 kernelpagesize_kB=4
+7f3a1c000000 default anon=600 dirty=600 N1=600 kernelpagesize_kB=4
+7f3b00000000 default file=/anon_hugepage\\040(deleted) huge anon=2 dirty=2 N0=1 N1=1 kernelpagesize_kB=2048
+7ffd2e5c0000 default stack anon=3 dirty=3
+";
+        let nodes = parse_numa_maps(content);
+        assert_eq!(nodes, BTreeMap::from([(0, 800 + 2048), (1, 2800 + 2048)]));
+        let placement = dominant_node(&nodes).unwrap();
+        assert_eq!(placement.node, 1);
+        assert_eq!(placement.to_string(), "N1 63%");
+        assert_eq!(dominant_node(&parse_numa_maps(b"")), None);
+    }
+}
@@ -0,0 +1,71 @@
+//! OOM killer scores from `/proc/<pid>/oom_score` (`--oom-risk`).
+
+use std::fmt::Display;
+
+use crate::reader::ProcReader;
+
+/// Reads `/proc/<pid>/oom_score`, the kernel's current badness score: the
+/// process with the highest score is killed first when memory runs out.
+pub fn read_oom_score(reader: &dyn ProcReader, base: &str, pid: u32) -> Option<u32> {
+    reader
+        .read_to_string(&format!("{base}/{pid}/oom_score"))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Rough likelihood of being picked by the OOM killer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OomRisk {
+    Low,
+    Med,
+    High,
+}
+
+impl OomRisk {
+    /// Classifies an OOM score.
+    ///
+    /// The score is roughly the share of memory a process uses in tenths of
+    /// a percent, shifted by `oom_score_adj`, so 300 and up means about a
+    /// third of the machine's memory and 100 about a tenth.
+    pub fn classify(score: u32) -> Self {
+        match score {
+            300.. => Self::High,
+            100.. => Self::Med,
+            _ => Self::Low,
+        }
+    }
+}
+
+impl Display for OomRisk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Low => "LOW",
+            Self::Med => "MED",
+            Self::High => "HIGH",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockReader;
+
+    #[test]
+    fn test_classify_oom_risk() {
+        assert_eq!(OomRisk::classify(0), OomRisk::Low);
+        assert_eq!(OomRisk::classify(99), OomRisk::Low);
+        assert_eq!(OomRisk::classify(100), OomRisk::Med);
+        assert_eq!(OomRisk::classify(299), OomRisk::Med);
+        assert_eq!(OomRisk::classify(300), OomRisk::High);
+        assert_eq!(OomRisk::classify(1999), OomRisk::High);
+        assert_eq!(OomRisk::High.to_string(), "HIGH");
+
+        let mut reader = MockReader::default();
+        reader.add_file("/proc/7/oom_score", "667\n");
+        assert_eq!(read_oom_score(&reader, "/proc", 7), Some(667));
+        assert_eq!(read_oom_score(&reader, "/proc", 8), None);
+    }
+}
@@ -0,0 +1,68 @@
+//! When the output reaches stdout (`--flush`).
+
+use std::io::{self, Write};
+
+/// When buffered output is flushed (`--flush`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// After every write, so each part of a frame shows at once.
+    Always,
+    /// Whenever a complete line has been written, and after each frame.
+    Line,
+    /// Only when the buffer is full and at exit, for throughput when the
+    /// output goes to a file or a pipe.
+    Never,
+}
+
+impl FlushPolicy {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "always" => Ok(Self::Always),
+            "line" => Ok(Self::Line),
+            "never" => Ok(Self::Never),
+            other => Err(format!(
+                "unknown flush policy: {other} (expected always, line or never)"
+            )),
+        }
+    }
+
+    /// The default: [`FlushPolicy::Line`] on a terminal, where frames
+    /// should show as soon as they are drawn, and [`FlushPolicy::Never`]
+    /// otherwise.
+    pub fn for_terminal(tty: bool) -> Self {
+        if tty { Self::Line } else { Self::Never }
+    }
+}
+
+/// A writer that flushes `inner` as its [`FlushPolicy`] says; explicit
+/// [`flush`](Write::flush) calls are dropped with [`FlushPolicy::Never`].
+#[derive(Debug)]
+pub struct FlushWriter<W: Write> {
+    inner: W,
+    policy: FlushPolicy,
+}
+
+impl<W: Write> FlushWriter<W> {
+    pub fn new(inner: W, policy: FlushPolicy) -> Self {
+        Self { inner, policy }
+    }
+}
+
+impl<W: Write> Write for FlushWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        match self.policy {
+            FlushPolicy::Always => self.inner.flush()?,
+            FlushPolicy::Line if buf[..n].contains(&b'\n') => self.inner.flush()?,
+            FlushPolicy::Line | FlushPolicy::Never => {}
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.policy {
+            FlushPolicy::Never => Ok(()),
+            FlushPolicy::Always | FlushPolicy::Line => self.inner.flush(),
+        }
+    }
+}
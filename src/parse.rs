@@ -0,0 +1,128 @@
+//! Small parsing helpers shared by the `/proc` file parsers.
+
+use std::str::FromStr;
+
+/// Finds the first line starting with `prefix` and returns the
+/// whitespace-delimited field `index` (0 for the first) that follows it.
+///
+/// `None` when no line has the prefix or the line has too few fields. An
+/// empty prefix matches the first line, for single-line files such as
+/// `/proc/loadavg`.
+pub(crate) fn field_after_prefix<'a>(
+    content: &'a str,
+    prefix: &str,
+    index: usize,
+) -> Option<&'a str> {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix(prefix))?
+        .split_whitespace()
+        .nth(index)
+}
+
+/// [`field_after_prefix`] parsed as `T`; `None` as well when the field does
+/// not parse.
+pub(crate) fn parse_field<T: FromStr>(content: &str, prefix: &str, index: usize) -> Option<T> {
+    field_after_prefix(content, prefix, index)?.parse().ok()
+}
+
+/// The lines of `content` without their `\n`, or `\r\n` for snapshots that
+/// went through a system with CRLF line endings, like [`str::lines`].
+pub(crate) fn byte_lines(content: &[u8]) -> impl Iterator<Item = &[u8]> {
+    content
+        .split(|&b| b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+}
+
+/// Finds the line starting with `prefix` and parses the whitespace-delimited
+/// number that follows it.
+///
+/// Works directly on byte lines so that hot paths (one status file per
+/// process) don't build intermediate `String`s.
+pub(crate) fn parse_field_bytes(content: &[u8], prefix: &[u8]) -> Option<u64> {
+    let rest = byte_lines(content)
+        .find_map(|line| line.strip_prefix(prefix))?
+        .trim_ascii_start();
+    let token = rest
+        .split(|b| b.is_ascii_whitespace())
+        .next()
+        .filter(|token| !token.is_empty())?;
+    token.iter().try_fold(0u64, |acc, &b| {
+        if !b.is_ascii_digit() {
+            return None;
+        }
+        acc.checked_mul(10)?.checked_add(u64::from(b - b'0'))
+    })
+}
+
+/// The first line starting with one of `prefixes` whose number does not
+/// parse with [`parse_field_bytes`], with its 1-based line number.
+pub(crate) fn malformed_line<'a>(
+    content: &'a [u8],
+    prefixes: &[&str],
+) -> Option<(usize, &'a [u8])> {
+    byte_lines(content).zip(1..).find_map(|(line, number)| {
+        let prefix = prefixes.iter().find(|p| line.starts_with(p.as_bytes()))?;
+        parse_field_bytes(line, prefix.as_bytes())
+            .is_none()
+            .then_some((number, line))
+    })
+}
+
+/// Describes the [`malformed_line`] `line` at `number` for an error message,
+/// e.g. `line 2: "VmRSS:\tlots kB"`.
+pub(crate) fn describe_line(number: usize, line: &[u8]) -> String {
+    format!("line {number}: {:?}", String::from_utf8_lossy(line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_after_prefix() {
+        let content = "MemTotal:  16384 kB\nprocs_running 3\ncpu  10 20 30\n";
+        assert_eq!(field_after_prefix(content, "MemTotal:", 0), Some("16384"));
+        assert_eq!(field_after_prefix(content, "MemTotal:", 1), Some("kB"));
+        assert_eq!(field_after_prefix(content, "cpu ", 2), Some("30"));
+        assert_eq!(field_after_prefix(content, "", 0), Some("MemTotal:"));
+        assert_eq!(field_after_prefix(content, "cpu ", 3), None, "out of range");
+        assert_eq!(
+            field_after_prefix(content, "SwapTotal:", 0),
+            None,
+            "no line"
+        );
+        assert_eq!(
+            field_after_prefix(content, "Total:", 0),
+            None,
+            "not at the start"
+        );
+
+        assert_eq!(parse_field::<u64>(content, "procs_running", 0), Some(3));
+        assert_eq!(parse_field::<f64>(content, "cpu ", 1), Some(20.0));
+        assert_eq!(
+            parse_field::<u64>(content, "MemTotal:", 1),
+            None,
+            "not a number"
+        );
+        assert_eq!(parse_field::<u64>(content, "SwapTotal:", 0), None);
+    }
+
+    #[test]
+    fn test_malformed_line() {
+        let content = b"Name:\tbad\nVmHWM:\t10 kB\nVmRSS:\tlots kB\n";
+        let (number, line) = malformed_line(content, &["VmHWM:", "VmRSS:"]).unwrap();
+        assert_eq!(describe_line(number, line), r#"line 3: "VmRSS:\tlots kB""#);
+        assert_eq!(malformed_line(content, &["VmHWM:"]), None);
+    }
+
+    #[test]
+    fn test_byte_lines_strip_crlf() {
+        let lines: Vec<&[u8]> = byte_lines(b"a\r\nb\nc\r").collect();
+        assert_eq!(lines, [&b"a"[..], b"b", b"c"]);
+        assert_eq!(
+            parse_field_bytes(b"HugePages_Total:   12\r\n", b"HugePages_Total:"),
+            Some(12)
+        );
+    }
+}
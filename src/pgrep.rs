@@ -0,0 +1,59 @@
+//! Finding the pids of processes by name (`--pgrep`), for scripts.
+
+use std::io;
+
+use crate::{
+    process::{read_process_cmdline, scan_processes},
+    reader::ProcReader,
+    render::matches_pattern,
+};
+
+/// Returns the pids under `base` whose name matches `pattern`, in
+/// ascending order like `pgrep`.
+///
+/// With `full` the pattern is matched against the command line instead
+/// (`--pgrep-full`); processes without one, such as kernel threads, are
+/// matched by name. The pattern is a substring or glob as in
+/// [`matches_pattern`], not a regular expression. Processes that cannot be
+/// read are skipped.
+pub fn find_pids(
+    reader: &dyn ProcReader,
+    base: &str,
+    pattern: &str,
+    full: bool,
+) -> io::Result<Vec<u32>> {
+    let mut pids: Vec<u32> = scan_processes(reader, base, false, |_| {})?
+        .filter(|p| {
+            let cmdline = full.then(|| read_process_cmdline(reader, base, p.pid));
+            matches_pattern(cmdline.flatten().as_deref().unwrap_or(&p.name), pattern)
+        })
+        .map(|p| p.pid)
+        .collect();
+    pids.sort_unstable();
+    Ok(pids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockReader;
+
+    #[test]
+    fn test_find_pids() {
+        let mut reader = MockReader::default();
+        reader.add_process(30, "postgres", 2048);
+        reader.add_process(4, "bash", 512);
+        reader.add_process(12, "postgres", 1024);
+        reader.add_file("/proc/30/cmdline", "postgres\0-D\0/srv/pg\0");
+        reader.add_file("/proc/4/cmdline", "bash\0/srv/backup.sh\0");
+
+        assert_eq!(
+            find_pids(&reader, "/proc", "postgres", false).unwrap(),
+            [12, 30]
+        );
+        assert_eq!(find_pids(&reader, "/proc", "pg*", false).unwrap(), []);
+        assert_eq!(find_pids(&reader, "/proc", "/srv", true).unwrap(), [4, 30]);
+        // Without a cmdline the name is matched.
+        assert_eq!(find_pids(&reader, "/proc", "gres", true).unwrap(), [12, 30]);
+    }
+}
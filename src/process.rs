@@ -0,0 +1,1994 @@
+//! Parts 3–6 – Reading and listing processes from `/proc/<pid>/`.
+
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashSet},
+    fmt::Display,
+    io,
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "extras")]
+use crate::{caps::parse_cap_eff, numa::NumaPlacement};
+use crate::{
+    log,
+    oom::read_oom_score,
+    parse::{byte_lines, describe_line, malformed_line, parse_field_bytes},
+    reader::{FsReader, ProcReader},
+    system::page_size,
+};
+
+/// A single process as shown in the table.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Process {
+    pub pid: u32,
+    pub name: String,
+    /// Resident set size (`VmRSS`) in kB.
+    pub rss_kb: u64,
+    /// Peak resident set size (`VmHWM`) in kB.
+    pub vm_hwm: u64,
+    /// Memory swapped out (`VmSwap`) in kB; 0 when the line is absent, as
+    /// for kernel threads or without swap support.
+    pub vm_swap: u64,
+    /// Packed controlling terminal device number from `/proc/<pid>/stat`.
+    pub tty_nr: i32,
+    /// CPU time spent in user and kernel mode, in clock ticks.
+    pub cpu_ticks: u64,
+    /// Start time in clock ticks after boot, which tells a recycled pid
+    /// apart from the process that had it before; 0 until the details are
+    /// read.
+    pub start_time: u64,
+    /// CPU usage since the previous sample, where 100% is one full CPU.
+    pub cpu_percent: f64,
+    /// Current working directory, only read for `--show-cwd`.
+    pub cwd: Option<PathBuf>,
+    /// Command line with the arguments joined by spaces, only read for the
+    /// `cmd` column; `None` for kernel threads.
+    pub cmdline: Option<String>,
+    /// Split of `rss_kb` by page type, `None` on kernels before 4.5.
+    pub rss_breakdown: Option<RssBreakdown>,
+    /// Single-letter state from the `State:` line of `/proc/<pid>/status`
+    /// (`R`, `S`, `D`, `Z`, ...), `'?'` if unknown.
+    pub state: char,
+    /// Number of threads (`Threads:`).
+    pub threads: u64,
+    /// Real user id, the first field of `Uid:`.
+    pub uid: Option<u32>,
+    /// Effective capability mask (`CapEff:`), `None` when the status file
+    /// was not read.
+    #[cfg(feature = "extras")]
+    pub cap_eff: Option<u64>,
+    /// Parent pid (`PPid:`), `None` when the status file was not read.
+    pub ppid: Option<u32>,
+    /// The pid in the innermost PID namespace, the last field of `NSpid:`;
+    /// `None` on kernels without the line.
+    pub ns_pid: Option<u32>,
+    /// A kernel thread: no `VmRSS` and an empty cmdline, but not a zombie.
+    pub is_kernel_thread: bool,
+    /// Storage I/O counters, only read for the I/O columns and sort keys;
+    /// `None` when `/proc/<pid>/io` is not readable.
+    pub io: Option<IoCounters>,
+    /// Bytes read from storage per second since the previous sample, in kB/s.
+    pub io_read_rate: f64,
+    /// Bytes written to storage per second since the previous sample, in kB/s.
+    pub io_write_rate: f64,
+    /// Absolute change of `rss_kb` since the previous sample; the full RSS
+    /// for a process seen for the first time.
+    pub rss_delta: u64,
+    /// Absolute change of `cpu_percent` since the previous sample, in
+    /// percentage points.
+    pub cpu_delta: f64,
+    /// `/proc/<pid>/oom_score`, only read for the OOM column and sort key.
+    pub oom_score: Option<u32>,
+    /// Open sockets among the file descriptors, only read for the sockets
+    /// column and sort key; `None` when `/proc/<pid>/fd` is not readable.
+    pub sockets: Option<usize>,
+    /// Kernel function the process sleeps in (`/proc/<pid>/wchan`), only
+    /// read for the wchan column; `None` when running or unreadable.
+    pub wchan: Option<String>,
+    /// The systemd unit from `/proc/<pid>/cgroup`, only read for the unit
+    /// column; `None` when the process is not under one.
+    pub unit: Option<String>,
+    /// The NUMA node most of the memory is on, from
+    /// `/proc/<pid>/numa_maps`; only read for the numa column, `None` when
+    /// unreadable.
+    #[cfg(feature = "extras")]
+    pub numa: Option<NumaPlacement>,
+    /// RSS as a percentage of `MemTotal`, set by the monitor when it has
+    /// read `/proc/meminfo`.
+    pub mem_percent: Option<f64>,
+    /// Time spent runnable but waiting for a CPU since the process started,
+    /// in nanoseconds, from `/proc/<pid>/schedstat`; only read for the
+    /// schedwait column, `None` without `CONFIG_SCHED_INFO`.
+    pub sched_wait_ns: Option<u64>,
+    /// Milliseconds of run-queue wait per second since the previous sample.
+    pub sched_wait_rate: f64,
+    /// Minor page faults (no disk access) since the process started.
+    pub min_faults: u64,
+    /// Major page faults, which had to read the page from disk.
+    pub maj_faults: u64,
+    /// Minor page faults per second since the previous sample.
+    pub min_fault_rate: f64,
+    /// Major page faults per second since the previous sample.
+    pub maj_fault_rate: f64,
+    /// With `--threads`, the process (thread group) this thread belongs to;
+    /// `pid` is then the thread id, equal to `tgid` for the main thread.
+    pub tgid: Option<u32>,
+    /// Lowest and highest RSS and CPU usage the sampler has seen for this
+    /// process; `None` until the details are read.
+    pub usage_range: Option<UsageRange>,
+}
+
+impl Process {
+    /// The share of the memory footprint that is swapped out,
+    /// `VmSwap / (VmRSS + VmSwap)` in percent; `None` for a process with no
+    /// memory at all, such as a kernel thread.
+    pub fn swap_percent(&self) -> Option<f64> {
+        let footprint = self.rss_kb + self.vm_swap;
+        (footprint > 0).then(|| self.vm_swap as f64 * 100.0 / footprint as f64)
+    }
+}
+
+/// `read_bytes` and `write_bytes` from `/proc/<pid>/io`: bytes fetched from
+/// and sent to the storage layer since the process started.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IoCounters {
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+}
+
+/// Lowest and highest RSS (in kB) and CPU usage of a process over the
+/// session, tracked by the [`CpuSampler`](crate::CpuSampler).
+///
+/// The CPU bounds are `None` until the process has been sampled twice, as
+/// its usage is only known from the second sample.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct UsageRange {
+    pub rss_min: u64,
+    pub rss_max: u64,
+    pub cpu_min: Option<f64>,
+    pub cpu_max: Option<f64>,
+}
+
+impl UsageRange {
+    /// `prev` widened by one sample, or a range of just that sample for a
+    /// process seen for the first time.
+    pub fn observe(prev: Option<Self>, rss_kb: u64, cpu_percent: Option<f64>) -> Self {
+        let Some(prev) = prev else {
+            return Self {
+                rss_min: rss_kb,
+                rss_max: rss_kb,
+                cpu_min: cpu_percent,
+                cpu_max: cpu_percent,
+            };
+        };
+        let widen = |bound: Option<f64>, pick: fn(f64, f64) -> f64| match (bound, cpu_percent) {
+            (Some(bound), Some(cpu)) => Some(pick(bound, cpu)),
+            (bound, cpu) => bound.or(cpu),
+        };
+        Self {
+            rss_min: prev.rss_min.min(rss_kb),
+            rss_max: prev.rss_max.max(rss_kb),
+            cpu_min: widen(prev.cpu_min, f64::min),
+            cpu_max: widen(prev.cpu_max, f64::max),
+        }
+    }
+}
+
+/// Parses the [`IoCounters`] from the content of `/proc/<pid>/io`.
+pub fn parse_process_io(content: &[u8]) -> Option<IoCounters> {
+    Some(IoCounters {
+        read_bytes: parse_field_bytes(content, b"read_bytes:")?,
+        write_bytes: parse_field_bytes(content, b"write_bytes:")?,
+    })
+}
+
+/// Reads `/proc/<pid>/io`, which is only readable for the owner and root.
+pub fn read_process_io(reader: &dyn ProcReader, base: &str, pid: u32) -> Option<IoCounters> {
+    parse_process_io(&reader.read(&format!("{base}/{pid}/io")).ok()?)
+}
+
+/// `RssAnon`, `RssFile` and `RssShmem` from `/proc/<pid>/status`, in kB.
+///
+/// Anonymous pages are private to the process (heap, stack), so growth there
+/// is the usual sign of a leak; file-backed and shared memory pages are
+/// shared with the page cache or other processes. The three add up to
+/// `VmRSS`, give or take the kernel's per-CPU counter slack.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RssBreakdown {
+    pub anon: u64,
+    pub file: u64,
+    pub shmem: u64,
+}
+
+/// Parses the [`RssBreakdown`] from the content of `/proc/<pid>/status`.
+///
+/// Returns `None` unless all three fields are present; kernels before 4.5
+/// only report `VmRSS`.
+pub fn parse_rss_breakdown(status: &[u8]) -> Option<RssBreakdown> {
+    Some(RssBreakdown {
+        anon: parse_field_bytes(status, b"RssAnon:")?,
+        file: parse_field_bytes(status, b"RssFile:")?,
+        shmem: parse_field_bytes(status, b"RssShmem:")?,
+    })
+}
+
+/// Parses `VmRSS` from `/proc/<pid>/status`.
+///
+/// Example line: `VmRSS:   1234 kB`. Returns `None` when the line is missing
+/// (kernel threads have no `VmRSS`) or its value is not a number.
+///
+/// ```
+/// use procinfo_rust_exercise::parse_process_status;
+///
+/// let status = "Name:\tbash\nVmRSS:\t   3104 kB\n";
+/// assert_eq!(parse_process_status(status), Some(3104));
+/// assert_eq!(parse_process_status("Name:\tkthreadd\n"), None);
+/// ```
+pub fn parse_process_status(status: &str) -> Option<u64> {
+    parse_process_status_from_bytes(status.as_bytes())
+}
+
+/// Byte-based variant of [`parse_process_status`] that avoids allocating.
+pub fn parse_process_status_from_bytes(status: &[u8]) -> Option<u64> {
+    parse_field_bytes(status, b"VmRSS:")
+}
+
+/// Reads the `/proc/<pid>/status` file.
+///
+/// Returns `(rss_kb, vm_hwm)`. When `VmHWM` is absent the peak falls back to
+/// the current RSS.
+pub fn read_process_status(reader: &dyn ProcReader, base: &str, pid: &str) -> Option<(u64, u64)> {
+    let status = reader.read(&format!("{base}/{pid}/status")).ok()?;
+    parse_rss_and_peak(&status)
+}
+
+/// Parses the state letter from a status line such as `State:\tS (sleeping)`.
+fn parse_status_state(status: &[u8]) -> Option<char> {
+    let line = byte_lines(status).find_map(|line| line.strip_prefix(b"State:"))?;
+    line.iter()
+        .find(|b| !b.is_ascii_whitespace())
+        .map(|&b| char::from(b))
+}
+
+/// Parses the pid in the innermost PID namespace from the `NSpid:` line of
+/// `/proc/<pid>/status`, e.g. `1` from `NSpid:\t1234\t1` for the init
+/// process of a container seen from the host.
+pub fn parse_ns_pid(status: &[u8]) -> Option<u32> {
+    let line = byte_lines(status).find_map(|line| line.strip_prefix(b"NSpid:"))?;
+    let last = line
+        .split(|b| b.is_ascii_whitespace())
+        .rfind(|field| !field.is_empty())?;
+    std::str::from_utf8(last).ok()?.parse().ok()
+}
+
+fn parse_rss_and_peak(status: &[u8]) -> Option<(u64, u64)> {
+    let rss_kb = parse_process_status_from_bytes(status)?;
+    let vm_hwm = parse_field_bytes(status, b"VmHWM:").unwrap_or(rss_kb);
+    Some((rss_kb, vm_hwm))
+}
+
+/// Reads the `/proc/<pid>/comm` file to get the process name.
+///
+/// A process can give itself a name that is not valid UTF-8; such bytes are
+/// replaced rather than losing the whole name.
+pub fn read_process_comm(reader: &dyn ProcReader, base: &str, pid: &str) -> String {
+    reader
+        .read(&format!("{base}/{pid}/comm"))
+        .map(|comm| String::from_utf8_lossy(&comm).trim().to_string())
+        .unwrap_or_default()
+}
+
+/// The process name from `/proc/<pid>/comm`, or, when that can't be read,
+/// the command name in `/proc/<pid>/stat`, so the name column isn't left
+/// blank. Empty when neither can be read.
+fn read_process_name(reader: &dyn ProcReader, base: &str, pid: &str) -> String {
+    match reader.read(&format!("{base}/{pid}/comm")) {
+        Ok(comm) => String::from_utf8_lossy(&comm).trim().to_string(),
+        Err(_) => reader
+            .read(&format!("{base}/{pid}/stat"))
+            .ok()
+            .and_then(|stat| parse_stat(&String::from_utf8_lossy(&stat)))
+            .map(|stat| stat.comm)
+            .unwrap_or_default(),
+    }
+}
+
+/// Fields of `/proc/<pid>/stat` used by the monitor.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProcStat {
+    /// Command name without the surrounding parentheses.
+    pub comm: String,
+    /// Single-character process state (`R`, `S`, `D`, `Z`, ...).
+    pub state: char,
+    /// Packed controlling terminal device number (field 7).
+    pub tty_nr: i32,
+    /// Minor page faults (field 10).
+    pub minflt: u64,
+    /// Major page faults (field 12).
+    pub majflt: u64,
+    /// User-mode CPU time in clock ticks (field 14).
+    pub utime: u64,
+    /// Kernel-mode CPU time in clock ticks (field 15).
+    pub stime: u64,
+    /// Start time in clock ticks after boot (field 22).
+    pub starttime: u64,
+}
+
+/// Parses the content of `/proc/<pid>/stat`.
+///
+/// The command name may itself contain spaces and parentheses, so it is taken
+/// as everything between the first `(` and the *last* `)`; the remaining
+/// fields are whitespace-separated after that.
+pub fn parse_stat(content: &str) -> Option<ProcStat> {
+    let open = content.find('(')?;
+    let close = content.rfind(')')?;
+    let comm = content.get(open + 1..close)?.to_string();
+    // Fields after the command name, starting with field 3 (state).
+    let fields: Vec<&str> = content[close + 1..].split_whitespace().collect();
+    let field = |n: usize| fields.get(n - 3).copied();
+    Some(ProcStat {
+        comm,
+        state: field(3)?.chars().next()?,
+        tty_nr: field(7)?.parse().ok()?,
+        minflt: field(10)?.parse().ok()?,
+        majflt: field(12)?.parse().ok()?,
+        utime: field(14)?.parse().ok()?,
+        stime: field(15)?.parse().ok()?,
+        starttime: field(22)?.parse().ok()?,
+    })
+}
+
+/// Reads and parses the `/proc/<pid>/stat` file. Invalid UTF-8 in the
+/// command name is replaced, like in [`read_process_comm`].
+pub fn read_process_stat(reader: &dyn ProcReader, base: &str, pid: u32) -> Option<ProcStat> {
+    let content = reader.read(&format!("{base}/{pid}/stat")).ok()?;
+    parse_stat(&String::from_utf8_lossy(&content))
+}
+
+/// Sums the user and kernel CPU time of the threads in
+/// `/proc/<pid>/task/<tid>/stat` (`--merge-threads`).
+///
+/// Returns `None` when the task directory cannot be listed or no thread's
+/// `stat` can be read. Threads that have exited are missing from the sum,
+/// whereas the kernel keeps their time in `/proc/<pid>/stat`.
+pub fn read_thread_cpu_ticks(reader: &dyn ProcReader, base: &str, pid: u32) -> Option<u64> {
+    let task_base = format!("{base}/{pid}/task");
+    reader
+        .read_dir(&task_base)
+        .ok()?
+        .iter()
+        .filter_map(|tid| read_process_stat(reader, &task_base, tid.parse().ok()?))
+        .map(|stat| stat.utime + stat.stime)
+        .reduce(|a, b| a + b)
+}
+
+/// Translates a packed `tty_nr` into a device name such as `pts/3`.
+///
+/// The kernel packs the device number as `minor[7:0] | major[19:8] |
+/// minor[31:20]`. Processes without a controlling terminal report `0`, shown
+/// as `?` like `ps` does.
+pub fn decode_tty(tty_nr: i32) -> String {
+    if tty_nr == 0 {
+        return "?".to_string();
+    }
+    let nr = tty_nr as u32;
+    let major = (nr >> 8) & 0xfff;
+    let minor = (nr & 0xff) | ((nr >> 12) & 0xfff00);
+    match major {
+        // Unix98 pseudo-terminals span majors 136..=143.
+        136..=143 => format!("pts/{}", (major - 136) * 256 + minor),
+        4 if minor < 64 => format!("tty{minor}"),
+        4 => format!("ttyS{}", minor - 64),
+        _ => format!("{major}:{minor}"),
+    }
+}
+
+/// Combines the process name and memory usage.
+///
+/// Only the cheap fields (name and memory) are read here; see
+/// [`read_process_details`] for the rest. Processes without `VmRSS` (kernel
+/// threads and zombies) are kept with an RSS of 0; for those the cmdline is
+/// read as well to set [`Process::is_kernel_thread`]. Returns `None` for
+/// processes that exited while being read; see [`try_read_process`] for the
+/// reason.
+pub fn read_process(reader: &dyn ProcReader, base: &str, pid: &str) -> Option<Process> {
+    try_read_process(reader, base, pid).ok()
+}
+
+/// Like [`read_process`], but says why a process could not be read. A
+/// `VmRSS` line that is not a number is an `InvalidData` error.
+pub fn try_read_process(reader: &dyn ProcReader, base: &str, pid: &str) -> io::Result<Process> {
+    let path = format!("{base}/{pid}/status");
+    let status = reader.read(&path)?;
+    let invalid =
+        |what: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{what} in {path}"));
+    let state = parse_status_state(&status).unwrap_or('?');
+    let (rss_kb, vm_hwm, is_kernel_thread) = match parse_rss_and_peak(&status) {
+        Some((rss_kb, vm_hwm)) => (rss_kb, vm_hwm, false),
+        None if let Some((number, line)) = malformed_line(&status, &["VmRSS:"]) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "malformed VmRSS in {path} ({})",
+                    describe_line(number, line)
+                ),
+            ));
+        }
+        None => {
+            let cmdline = reader.read(&format!("{base}/{pid}/cmdline"))?;
+            (0, 0, state != 'Z' && parse_cmdline(&cmdline).is_none())
+        }
+    };
+    Ok(Process {
+        pid: pid.parse().map_err(|_| invalid("invalid pid"))?,
+        name: read_process_name(reader, base, pid),
+        rss_kb,
+        vm_hwm,
+        vm_swap: parse_field_bytes(&status, b"VmSwap:").unwrap_or(0),
+        rss_breakdown: parse_rss_breakdown(&status),
+        state,
+        threads: parse_field_bytes(&status, b"Threads:").unwrap_or(0),
+        uid: parse_field_bytes(&status, b"Uid:").and_then(|uid| uid.try_into().ok()),
+        #[cfg(feature = "extras")]
+        cap_eff: parse_cap_eff(&status),
+        ppid: parse_field_bytes(&status, b"PPid:").and_then(|ppid| ppid.try_into().ok()),
+        ns_pid: parse_ns_pid(&status),
+        is_kernel_thread,
+        ..Process::default()
+    })
+}
+
+/// Parses the resident set size from `/proc/<pid>/statm` into kB.
+///
+/// Example content: `2817 1034 830 45 0 211 0`, sizes in pages of
+/// `page_size` bytes, the second being the RSS. Returns `None` for a process
+/// without an address space (total size 0), such as a kernel thread.
+pub fn parse_statm(content: &str, page_size: u64) -> Option<u64> {
+    let mut fields = content.split_whitespace().map(str::parse::<u64>);
+    let size = fields.next()?.ok()?;
+    let resident = fields.next()?.ok()?;
+    (size > 0).then(|| resident * page_size / 1024)
+}
+
+/// Reads the RSS in kB from `/proc/<pid>/statm`, see [`parse_statm`].
+pub fn read_process_statm(reader: &dyn ProcReader, base: &str, pid: &str) -> Option<u64> {
+    let content = reader.read_to_string(&format!("{base}/{pid}/statm")).ok()?;
+    parse_statm(&content, page_size())
+}
+
+/// Like [`read_process`], but reads the RSS from the much shorter
+/// `/proc/<pid>/statm` and leaves the other `status` fields at their
+/// defaults, with the peak equal to the RSS. Falls back to [`read_process`]
+/// when statm has no RSS.
+pub fn read_process_rss_only(reader: &dyn ProcReader, base: &str, pid: &str) -> Option<Process> {
+    try_read_process_rss_only(reader, base, pid).ok()
+}
+
+fn try_read_process_rss_only(
+    reader: &dyn ProcReader,
+    base: &str,
+    pid: &str,
+) -> io::Result<Process> {
+    let Some(rss_kb) = read_process_statm(reader, base, pid) else {
+        return try_read_process(reader, base, pid);
+    };
+    Ok(Process {
+        pid: pid
+            .parse()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid pid"))?,
+        name: read_process_name(reader, base, pid),
+        rss_kb,
+        vm_hwm: rss_kb,
+        state: '?',
+        ..Process::default()
+    })
+}
+
+/// Fills in the fields that need `/proc/<pid>/stat` (TTY and CPU ticks).
+///
+/// These cost an extra file read per process, so callers only do it for the
+/// rows they are going to show, unless the sort order depends on them.
+pub fn read_process_details(reader: &dyn ProcReader, base: &str, process: &mut Process) {
+    if let Some(stat) = read_process_stat(reader, base, process.pid) {
+        process.tty_nr = stat.tty_nr;
+        process.cpu_ticks = stat.utime + stat.stime;
+        process.min_faults = stat.minflt;
+        process.maj_faults = stat.majflt;
+        process.start_time = stat.starttime;
+    }
+}
+
+/// Reads the `/proc/<pid>/cwd` symlink.
+///
+/// Returns `None` when the link cannot be read, which is the case for other
+/// users' processes without root and for processes that have exited. The
+/// target is returned as the kernel reports it, including a ` (deleted)`
+/// suffix when the directory has been removed.
+pub fn read_process_cwd(reader: &dyn ProcReader, base: &str, pid: u32) -> Option<PathBuf> {
+    reader.read_link(&format!("{base}/{pid}/cwd")).ok()
+}
+
+/// Counts the entries of `/proc/<pid>/fd` that link to a socket
+/// (`socket:[<inode>]`).
+///
+/// Returns `None` when the directory cannot be listed, which without root is
+/// the case for other users' processes. Descriptors closed while counting are
+/// skipped.
+pub fn count_sockets(reader: &dyn ProcReader, base: &str, pid: u32) -> Option<usize> {
+    let dir = format!("{base}/{pid}/fd");
+    let fds = reader.read_dir(&dir).ok()?;
+    let sockets = fds
+        .iter()
+        .filter_map(|fd| reader.read_link(&format!("{dir}/{fd}")).ok())
+        .filter(|target| target.to_str().is_some_and(|t| t.starts_with("socket:[")))
+        .count();
+    Some(sockets)
+}
+
+/// Parses `/proc/<pid>/wchan`, the kernel symbol a sleeping process waits
+/// in such as `do_select`. `0` or an empty file mean it is not sleeping.
+pub fn parse_wchan(content: &str) -> Option<String> {
+    let symbol = content.trim();
+    (!symbol.is_empty() && symbol != "0").then(|| symbol.to_string())
+}
+
+/// Reads `/proc/<pid>/wchan`, see [`parse_wchan`]; `None` as well when the
+/// file is missing or not readable.
+pub fn read_process_wchan(reader: &dyn ProcReader, base: &str, pid: u32) -> Option<String> {
+    parse_wchan(&reader.read_to_string(&format!("{base}/{pid}/wchan")).ok()?)
+}
+
+/// Parses `/proc/<pid>/schedstat`: the time spent on a CPU and waiting to
+/// run, both in nanoseconds, and the number of timeslices run, e.g.
+/// `4160000 1230000 42`.
+pub fn parse_schedstat(content: &str) -> Option<(u64, u64, u64)> {
+    let mut fields = content.split_whitespace().map(str::parse::<u64>);
+    let mut next = || fields.next()?.ok();
+    Some((next()?, next()?, next()?))
+}
+
+/// Reads `/proc/<pid>/schedstat`, see [`parse_schedstat`]; `None` when the
+/// file is missing, as on kernels built without `CONFIG_SCHED_INFO`.
+pub fn read_schedstat(reader: &dyn ProcReader, base: &str, pid: u32) -> Option<(u64, u64, u64)> {
+    parse_schedstat(
+        &reader
+            .read_to_string(&format!("{base}/{pid}/schedstat"))
+            .ok()?,
+    )
+}
+
+/// Joins the NUL-separated arguments of `/proc/<pid>/cmdline` with spaces.
+///
+/// Returns `None` for an empty file, which is what kernel threads and
+/// zombies have. Invalid UTF-8 is replaced rather than rejected.
+pub fn parse_cmdline(content: &[u8]) -> Option<String> {
+    let args: Vec<String> = content
+        .split(|&b| b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect();
+    (!args.is_empty()).then(|| args.join(" "))
+}
+
+/// Where the displayed process name comes from, selected with
+/// `--name-source`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NameSource {
+    /// `/proc/<pid>/comm`, cut by the kernel to 15 bytes.
+    #[default]
+    Comm,
+    /// The file name of the first `/proc/<pid>/cmdline` argument.
+    Cmdline,
+    /// The file name of the `/proc/<pid>/exe` link target.
+    Exe,
+}
+
+impl NameSource {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "comm" => Ok(Self::Comm),
+            "cmdline" => Ok(Self::Cmdline),
+            "exe" => Ok(Self::Exe),
+            other => Err(format!(
+                "unknown name source: {other} (expected comm, cmdline or exe)"
+            )),
+        }
+    }
+}
+
+/// Picks the name for `source` from the raw `cmdline` content or the `exe`
+/// link target, falling back to `comm` when that source is missing or
+/// empty, as it is for kernel threads.
+///
+/// Both give the file name of the program; an argv0 with a space that does
+/// not start with `/` is a process title and used whole.
+pub fn resolve_name(
+    source: NameSource,
+    comm: &str,
+    cmdline: Option<&[u8]>,
+    exe: Option<&Path>,
+) -> String {
+    let base_name = |path: &str| path.rsplit('/').next().unwrap_or(path).to_string();
+    // A title set with setproctitle, like `sshd: alice@pts/0`, is no path.
+    let program = |argv0: &str| {
+        if argv0.starts_with('/') || !argv0.contains(' ') {
+            base_name(argv0)
+        } else {
+            argv0.to_string()
+        }
+    };
+    let name = match source {
+        NameSource::Comm => None,
+        NameSource::Cmdline => cmdline
+            .and_then(|content| content.split(|&b| b == 0).next())
+            .map(|argv0| program(&String::from_utf8_lossy(argv0))),
+        NameSource::Exe => exe.map(|target| {
+            let target = target.to_string_lossy();
+            base_name(target.strip_suffix(" (deleted)").unwrap_or(&target))
+        }),
+    };
+    name.filter(|name| !name.is_empty())
+        .unwrap_or_else(|| comm.to_string())
+}
+
+/// Replaces the name of `process` according to `source`, see
+/// [`resolve_name`]. Only reads a file for the non-default sources.
+pub fn apply_name_source(
+    reader: &dyn ProcReader,
+    base: &str,
+    source: NameSource,
+    process: &mut Process,
+) {
+    let pid = process.pid;
+    let name = match source {
+        NameSource::Comm => return,
+        NameSource::Cmdline => {
+            let cmdline = reader.read(&format!("{base}/{pid}/cmdline")).ok();
+            resolve_name(source, &process.name, cmdline.as_deref(), None)
+        }
+        NameSource::Exe => {
+            let exe = reader.read_link(&format!("{base}/{pid}/exe")).ok();
+            resolve_name(source, &process.name, None, exe.as_deref())
+        }
+    };
+    process.name = name;
+}
+
+/// `name` with the file name of argument `n` of the raw `cmdline` as a
+/// suffix, e.g. `python (train.py)` for `python /srv/train.py` and `n` 1,
+/// telling apart processes of the same program (`--classify-by-arg`).
+///
+/// Argument 0 is the program itself. Without an argument `n`, or with an
+/// empty one, the name stays as it is.
+pub fn classify_by_arg(name: &str, cmdline: &[u8], n: usize) -> String {
+    let arg = cmdline
+        .strip_suffix(b"\0")
+        .unwrap_or(cmdline)
+        .split(|&b| b == 0)
+        .nth(n)
+        .map(String::from_utf8_lossy);
+    let suffix = arg
+        .as_deref()
+        .map(|arg| arg.rsplit('/').next().unwrap_or(arg));
+    match suffix {
+        Some(suffix) if !suffix.is_empty() => format!("{name} ({suffix})"),
+        _ => name.to_string(),
+    }
+}
+
+/// Applies [`classify_by_arg`] to the name of `process`, reading its
+/// cmdline; a process whose cmdline cannot be read keeps its name.
+pub fn apply_classify_by_arg(reader: &dyn ProcReader, base: &str, n: usize, process: &mut Process) {
+    if let Ok(cmdline) = reader.read(&format!("{base}/{}/cmdline", process.pid)) {
+        process.name = classify_by_arg(&process.name, &cmdline, n);
+    }
+}
+
+/// Reads and joins `/proc/<pid>/cmdline`, see [`parse_cmdline`].
+pub fn read_process_cmdline(reader: &dyn ProcReader, base: &str, pid: u32) -> Option<String> {
+    parse_cmdline(&reader.read(&format!("{base}/{pid}/cmdline")).ok()?)
+}
+
+/// Reads every field of one process from the live `/proc`-like tree at
+/// `base`; see [`read_one_process_from`].
+pub fn read_one_process(base: &str, pid: u32) -> io::Result<Process> {
+    read_one_process_from(&FsReader, base, pid)
+}
+
+/// Reads one process with all the fields a single file read can give: the
+/// [`try_read_process`] figures, [`read_process_details`], I/O counters,
+/// working directory, command line and OOM score. Rates need two samples
+/// and stay zero.
+///
+/// Unlike [`list_processes_from`] this does not list `base`. A pid without
+/// a process is a `NotFound` error that names it.
+pub fn read_one_process_from(reader: &dyn ProcReader, base: &str, pid: u32) -> io::Result<Process> {
+    let mut process =
+        try_read_process(reader, base, &pid.to_string()).map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no process {pid} in {base}"),
+            ),
+            _ => err,
+        })?;
+    read_process_details(reader, base, &mut process);
+    process.io = read_process_io(reader, base, pid);
+    process.cwd = read_process_cwd(reader, base, pid);
+    process.cmdline = read_process_cmdline(reader, base, pid);
+    process.oom_score = read_oom_score(reader, base, pid);
+    Ok(process)
+}
+
+// ----------------------------------------------------------------------------
+// Part 6 – Process listing
+// ----------------------------------------------------------------------------
+
+/// Scans `/proc`, filters numeric directories, and collects process info.
+///
+/// Processes are returned in directory enumeration order.
+pub fn list_processes_from(reader: &dyn ProcReader, base: &str) -> io::Result<Vec<Process>> {
+    Ok(scan_processes(reader, base, true, |_| {})?.collect())
+}
+
+/// Like [`list_processes_from`], but with [`read_process_rss_only`] for
+/// callers that need nothing from `status` beyond the RSS.
+pub fn list_processes_rss_only(reader: &dyn ProcReader, base: &str) -> io::Result<Vec<Process>> {
+    Ok(scan_processes(reader, base, false, |_| {})?.collect())
+}
+
+/// Lazily reads every process, with [`try_read_process`] if `status` is set
+/// and [`try_read_process_rss_only`] otherwise, reporting each process it
+/// fails on through [`log::warn`] and `on_skip`.
+///
+/// A captured or merged snapshot root can list a pid twice, e.g. as `42`
+/// and `042`; only the first entry is read and the others are reported as
+/// `InvalidData`, so `--strict` fails on them.
+pub(crate) fn scan_processes<'a>(
+    reader: &'a dyn ProcReader,
+    base: &'a str,
+    status: bool,
+    mut on_skip: impl FnMut(&io::Error) + 'a,
+) -> io::Result<impl Iterator<Item = Process> + 'a> {
+    let mut seen = HashSet::new();
+    let mut pids = Vec::new();
+    for name in reader.read_dir(base)? {
+        if !name.chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        match name.parse::<u32>() {
+            Ok(pid) if !seen.insert(pid) => {
+                let err = io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("duplicate pid {pid} in {base}"),
+                );
+                log::warn(&format!("skipping {base}/{name}: {err}"));
+                on_skip(&err);
+            }
+            _ => pids.push(name),
+        }
+    }
+    Ok(scan_pids(reader, base, pids, status, on_skip))
+}
+
+/// Like [`scan_processes`], but reads only `pids` instead of listing `base`,
+/// e.g. the members of a cgroup. A pid that has exited in the meantime is
+/// skipped like an unreadable process.
+pub(crate) fn scan_pids<'a>(
+    reader: &'a dyn ProcReader,
+    base: &'a str,
+    pids: impl IntoIterator<Item = String> + 'a,
+    status: bool,
+    mut on_skip: impl FnMut(&io::Error) + 'a,
+) -> impl Iterator<Item = Process> + 'a {
+    let read = if status {
+        try_read_process
+    } else {
+        try_read_process_rss_only
+    };
+    pids.into_iter()
+        .filter_map(move |pid| match read(reader, base, &pid) {
+            Ok(process) => Some(process),
+            Err(err) => {
+                log::warn(&format!("skipping {base}/{pid}: {}", skip_reason(&err)));
+                on_skip(&err);
+                None
+            }
+        })
+}
+
+/// Like [`scan_processes`], but yields one entry per thread from
+/// `/proc/<pid>/task/<tid>` with [`Process::tgid`] set, named by the
+/// thread's own `comm`. The `stat` details are read per thread right away,
+/// since `/proc/<tid>` itself is not listed.
+pub(crate) fn scan_threads<'a>(
+    reader: &'a dyn ProcReader,
+    base: &'a str,
+    mut on_skip: impl FnMut(&io::Error) + 'a,
+) -> io::Result<impl Iterator<Item = Process> + 'a> {
+    let mut skip = move |path: &str, err: io::Error| {
+        log::warn(&format!("skipping {path}: {}", skip_reason(&err)));
+        on_skip(&err);
+    };
+    Ok(reader
+        .read_dir(base)?
+        .into_iter()
+        .filter(|name| name.chars().all(|c| c.is_ascii_digit()))
+        .flat_map(move |pid| {
+            let task_base = format!("{base}/{pid}/task");
+            let tgid = pid.parse().ok();
+            let tids = reader.read_dir(&task_base).unwrap_or_else(|err| {
+                skip(&format!("{base}/{pid}"), err);
+                Vec::new()
+            });
+            let mut threads = Vec::with_capacity(tids.len());
+            for tid in tids {
+                match try_read_process(reader, &task_base, &tid) {
+                    Ok(mut thread) => {
+                        read_process_details(reader, &task_base, &mut thread);
+                        thread.tgid = tgid;
+                        threads.push(thread);
+                    }
+                    Err(err) => skip(&format!("{task_base}/{tid}"), err),
+                }
+            }
+            threads
+        }))
+}
+
+/// Describes why a process could not be read.
+fn skip_reason(err: &io::Error) -> String {
+    match err.kind() {
+        io::ErrorKind::PermissionDenied => "permission denied".to_string(),
+        io::ErrorKind::NotFound => "not found (the process exited)".to_string(),
+        _ => err.to_string(),
+    }
+}
+
+/// Process counts by state, like the `Tasks:` line of `top`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StateSummary {
+    pub total: usize,
+    /// `R`
+    pub running: usize,
+    /// `S`, `D` and `I`
+    pub sleeping: usize,
+    /// `T` and `t`
+    pub stopped: usize,
+    /// `Z`
+    pub zombie: usize,
+    /// Threads summed over all processes.
+    pub threads: u64,
+}
+
+/// Tallies the states of `procs`, which should be the full process list
+/// rather than just the displayed rows.
+pub fn summarize_states(procs: &[Process]) -> StateSummary {
+    let mut summary = StateSummary::default();
+    for p in procs {
+        summary.add(p);
+    }
+    summary
+}
+
+impl StateSummary {
+    /// Counts one more process.
+    pub fn add(&mut self, p: &Process) {
+        self.total += 1;
+        match p.state {
+            'R' => self.running += 1,
+            'S' | 'D' | 'I' => self.sleeping += 1,
+            'T' | 't' => self.stopped += 1,
+            'Z' => self.zombie += 1,
+            _ => {}
+        }
+        self.threads += p.threads;
+    }
+
+    /// The `Tasks:` line with the change of the total since a previous
+    /// frame that had `previous` processes, e.g. `Tasks: 312 total (+4), ...`.
+    /// Without a previous frame it is the plain [`Display`] line.
+    pub fn format_with_change(&self, previous: Option<usize>) -> String {
+        let change = previous.map_or_else(String::new, |previous| {
+            format!(" ({:+})", self.total as i64 - previous as i64)
+        });
+        format!(
+            "Tasks: {} total{change}, {} running, {} sleeping, {} stopped, {} zombie; {} threads",
+            self.total, self.running, self.sleeping, self.stopped, self.zombie, self.threads
+        )
+    }
+}
+
+impl Display for StateSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.format_with_change(None))
+    }
+}
+
+/// Field the process list is ordered by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortKey {
+    /// Current RSS, largest first.
+    #[default]
+    Mem,
+    /// Peak RSS (`VmHWM`), largest first.
+    Peak,
+    /// Swapped-out memory (`VmSwap`), largest first.
+    VmSwap,
+    /// Process id, ascending.
+    Pid,
+    /// Process name, ascending.
+    Name,
+    /// CPU usage since the previous sample, largest first.
+    Cpu,
+    /// Total CPU time consumed (`utime + stime`).
+    Time,
+    /// Storage read rate since the previous sample, largest first.
+    Read,
+    /// Storage write rate since the previous sample, largest first.
+    Write,
+    /// OOM killer score, largest first.
+    Oom,
+    /// Major page fault rate since the previous sample, largest first.
+    MajFlt,
+    /// Open sockets, largest first.
+    Sockets,
+}
+
+impl SortKey {
+    /// Whether ordering by this key needs [`read_process_details`] for every
+    /// process rather than just the displayed ones.
+    pub fn needs_details(self) -> bool {
+        matches!(
+            self,
+            Self::Cpu
+                | Self::Time
+                | Self::Read
+                | Self::Write
+                | Self::Oom
+                | Self::MajFlt
+                | Self::Sockets
+        )
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "mem" | "rss" => Ok(Self::Mem),
+            "peak" => Ok(Self::Peak),
+            "vmswap" | "swap" => Ok(Self::VmSwap),
+            "pid" => Ok(Self::Pid),
+            "name" => Ok(Self::Name),
+            "cpu" => Ok(Self::Cpu),
+            "time" => Ok(Self::Time),
+            "read" => Ok(Self::Read),
+            "write" => Ok(Self::Write),
+            "oom" => Ok(Self::Oom),
+            "majflt" => Ok(Self::MajFlt),
+            "sockets" => Ok(Self::Sockets),
+            other => Err(format!(
+                "unknown sort key: {other} (expected mem, peak, vmswap, cpu, time, read, write, \
+                 oom, majflt, sockets, pid or name)"
+            )),
+        }
+    }
+
+    /// Compares two processes by this key, smallest first.
+    fn compare_ascending(self, a: &Process, b: &Process) -> Ordering {
+        match self {
+            Self::Mem => a.rss_kb.cmp(&b.rss_kb),
+            Self::Peak => a.vm_hwm.cmp(&b.vm_hwm),
+            Self::VmSwap => a.vm_swap.cmp(&b.vm_swap),
+            Self::Pid => a.pid.cmp(&b.pid),
+            Self::Name => a.name.cmp(&b.name),
+            Self::Cpu => a.cpu_percent.total_cmp(&b.cpu_percent),
+            Self::Time => a.cpu_ticks.cmp(&b.cpu_ticks),
+            Self::Read => a.io_read_rate.total_cmp(&b.io_read_rate),
+            Self::Write => a.io_write_rate.total_cmp(&b.io_write_rate),
+            Self::Oom => a.oom_score.cmp(&b.oom_score),
+            Self::MajFlt => a.maj_fault_rate.total_cmp(&b.maj_fault_rate),
+            Self::Sockets => a.sockets.cmp(&b.sockets),
+        }
+    }
+
+    /// Whether this key sorts largest first unless a direction is given.
+    fn descending_by_default(self) -> bool {
+        !matches!(self, Self::Pid | Self::Name)
+    }
+}
+
+/// One entry of a `--sort` list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortField {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl SortField {
+    /// Parses a key optionally suffixed with `:asc` or `:desc`, e.g.
+    /// `cpu:asc`.
+    pub fn parse(item: &str) -> Result<Self, String> {
+        let (key, direction) = match item.split_once(':') {
+            Some((key, direction)) => (key, Some(direction)),
+            None => (item, None),
+        };
+        let mut field = SortField::from(SortKey::parse(key)?);
+        match direction {
+            None => {}
+            Some("asc") => field.descending = false,
+            Some("desc") => field.descending = true,
+            Some(other) => {
+                return Err(format!(
+                    "unknown sort direction: {other} (expected asc or desc)"
+                ));
+            }
+        }
+        Ok(field)
+    }
+}
+
+impl From<SortKey> for SortField {
+    /// Sorts by `key` in its natural direction.
+    fn from(key: SortKey) -> Self {
+        Self {
+            key,
+            descending: key.descending_by_default(),
+        }
+    }
+}
+
+/// The sort order after the key for `pressed` is chosen interactively:
+/// choosing the current key again flips the direction, another key starts
+/// in its natural direction.
+pub fn apply_sort_key(current: SortField, pressed: SortKey) -> SortField {
+    if current.key == pressed {
+        SortField {
+            descending: !current.descending,
+            ..current
+        }
+    } else {
+        pressed.into()
+    }
+}
+
+/// Ordered list of sort keys: later keys only break ties of earlier ones,
+/// and ascending PID breaks whatever ties remain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortOrder {
+    pub fields: Vec<SortField>,
+}
+
+impl SortOrder {
+    /// Parses a comma-separated list of keys, each optionally suffixed with
+    /// `:asc` or `:desc`, e.g. `mem,name` or `cpu:asc,pid`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let fields = value
+            .split(',')
+            .map(SortField::parse)
+            .collect::<Result<_, String>>()?;
+        Ok(Self { fields })
+    }
+
+    /// The first key, which decides the order for all but ties.
+    pub fn primary(&self) -> SortKey {
+        self.fields.first().map_or(SortKey::default(), |f| f.key)
+    }
+
+    pub fn contains(&self, key: SortKey) -> bool {
+        self.fields.iter().any(|f| f.key == key)
+    }
+
+    /// Whether any key needs [`read_process_details`] for every process.
+    pub fn needs_details(&self) -> bool {
+        self.fields.iter().any(|f| f.key.needs_details())
+    }
+
+    fn compare(&self, a: &Process, b: &Process) -> Ordering {
+        self.fields
+            .iter()
+            .map(|f| {
+                let ordering = f.key.compare_ascending(a, b);
+                if f.descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+            .then(a.pid.cmp(&b.pid))
+    }
+}
+
+impl Default for SortOrder {
+    fn default() -> Self {
+        SortKey::default().into()
+    }
+}
+
+impl From<SortKey> for SortOrder {
+    fn from(key: SortKey) -> Self {
+        Self {
+            fields: vec![key.into()],
+        }
+    }
+}
+
+/// Metric ordered by with `--sort-by-delta`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeltaMetric {
+    Mem,
+    Cpu,
+}
+
+impl DeltaMetric {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "mem" => Ok(Self::Mem),
+            "cpu" => Ok(Self::Cpu),
+            other => Err(format!(
+                "unknown delta metric: {other} (expected mem or cpu)"
+            )),
+        }
+    }
+}
+
+/// Compares two processes by their change in `metric` since the previous
+/// sample, largest change first. See [`Process::rss_delta`] and
+/// [`Process::cpu_delta`].
+pub fn compare_delta(metric: DeltaMetric, a: &Process, b: &Process) -> Ordering {
+    match metric {
+        DeltaMetric::Mem => b.rss_delta.cmp(&a.rss_delta),
+        DeltaMetric::Cpu => b.cpu_delta.total_cmp(&a.cpu_delta),
+    }
+}
+
+/// Metric that picks the kept rows with `--top-by`, independently of the
+/// display order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TopMetric {
+    /// `rss_kb`
+    Mem,
+    /// `cpu_percent`
+    Cpu,
+    /// The sum of the I/O read and write rates.
+    Io,
+}
+
+impl TopMetric {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "mem" => Ok(Self::Mem),
+            "cpu" => Ok(Self::Cpu),
+            "io" => Ok(Self::Io),
+            other => Err(format!(
+                "unknown top metric: {other} (expected mem, cpu or io)"
+            )),
+        }
+    }
+
+    /// Whether the metric needs [`read_process_details`] (and a previous
+    /// sample) rather than the scan alone.
+    pub fn needs_details(self) -> bool {
+        self != Self::Mem
+    }
+}
+
+/// Compares two processes by `metric`, largest first, breaking ties by
+/// ascending PID.
+pub fn compare_top(metric: TopMetric, a: &Process, b: &Process) -> Ordering {
+    let io = |p: &Process| p.io_read_rate + p.io_write_rate;
+    match metric {
+        TopMetric::Mem => b.rss_kb.cmp(&a.rss_kb),
+        TopMetric::Cpu => b.cpu_percent.total_cmp(&a.cpu_percent),
+        TopMetric::Io => io(b).total_cmp(&io(a)),
+    }
+    .then(a.pid.cmp(&b.pid))
+}
+
+/// Sorts processes by a key or a [`SortOrder`], breaking ties by ascending
+/// PID.
+pub fn sort_processes(procs: &mut [Process], order: impl Into<SortOrder>) {
+    let order = order.into();
+    procs.sort_by(|a, b| order.compare(a, b));
+}
+
+/// Drops the processes whose CPU usage is below `min_percent`, for
+/// `--min-cpu`. The usage has to be sampled already.
+pub fn retain_min_cpu(procs: &mut Vec<Process>, min_percent: f64) {
+    procs.retain(|p| p.cpu_percent >= min_percent);
+}
+
+/// Returns the first `n` of `procs` in `order`, the same rows as sorting
+/// everything and truncating, but holding at most `n + 1` processes at a time.
+///
+/// Keeps a max-heap of the best `n` rows seen so far, whose top is the row
+/// that sorts last and is evicted when a better one arrives. `n == 0`
+/// returns everything sorted.
+pub fn top_processes(
+    procs: impl IntoIterator<Item = Process>,
+    order: &SortOrder,
+    n: usize,
+) -> Vec<Process> {
+    if n == 0 {
+        let mut all: Vec<Process> = procs.into_iter().collect();
+        sort_processes(&mut all, order.clone());
+        return all;
+    }
+    let mut heap = BinaryHeap::with_capacity(n + 1);
+    for process in procs {
+        heap.push(Ranked { process, order });
+        if heap.len() > n {
+            heap.pop();
+        }
+    }
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|ranked| ranked.process)
+        .collect()
+}
+
+/// The process with the largest `by`, without sorting; ties go to the
+/// lowest pid. `None` for an empty list.
+///
+/// ```
+/// use procinfo_rust_exercise::{Process, top_process};
+///
+/// let procs = [
+///     Process { pid: 7, rss_kb: 512, ..Process::default() },
+///     Process { pid: 3, rss_kb: 2048, ..Process::default() },
+/// ];
+/// assert_eq!(top_process(&procs, |p| p.rss_kb).map(|p| p.pid), Some(3));
+/// ```
+pub fn top_process(procs: &[Process], by: impl Fn(&Process) -> u64) -> Option<&Process> {
+    procs
+        .iter()
+        .max_by(|a, b| by(a).cmp(&by(b)).then_with(|| b.pid.cmp(&a.pid)))
+}
+
+/// A process ordered by its position in a [`SortOrder`].
+struct Ranked<'a> {
+    process: Process,
+    order: &'a SortOrder,
+}
+
+impl Ord for Ranked<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.order.compare(&self.process, &other.process)
+    }
+}
+
+impl PartialOrd for Ranked<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Ranked<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Ranked<'_> {}
+
+/// Sorts processes in ascending order of the key computed by `by`, breaking
+/// ties by ascending PID.
+///
+/// The sort is stable. Wrap the key in [`std::cmp::Reverse`] for descending
+/// order.
+///
+/// ```
+/// use std::cmp::Reverse;
+/// use procinfo_rust_exercise::{Process, sort_processes_by};
+///
+/// let mut procs = vec![
+///     Process { pid: 2, rss_kb: 10, ..Process::default() },
+///     Process { pid: 1, rss_kb: 30, ..Process::default() },
+/// ];
+/// sort_processes_by(&mut procs, |p| Reverse(p.rss_kb));
+/// assert_eq!(procs[0].pid, 1);
+/// ```
+pub fn sort_processes_by<K: Ord>(procs: &mut [Process], by: impl Fn(&Process) -> K) {
+    procs.sort_by(|a, b| by(a).cmp(&by(b)).then(a.pid.cmp(&b.pid)));
+}
+
+/// How many of `procs`, sorted by RSS largest first, it takes for their
+/// combined RSS to reach `percent` of `used_kb`, for `--limit-by-percent`.
+/// The process that crosses the mark is counted; when the whole list falls
+/// short, all of it is.
+pub fn count_until_percent(procs: &[Process], used_kb: u64, percent: f64) -> usize {
+    let target = used_kb as f64 * percent / 100.0;
+    let mut sum = 0;
+    procs
+        .iter()
+        .position(|p| {
+            sum += p.rss_kb;
+            sum as f64 >= target
+        })
+        .map_or(procs.len(), |i| i + 1)
+}
+
+/// Explains why the chosen sort key cannot order this snapshot meaningfully.
+///
+/// CPU usage needs two samples, so it is all zero on the first frame. Other
+/// numeric keys are flagged when no process reports a non-zero value (for
+/// example when the data is unreadable without extra permissions). Returns
+/// `None` when the ordering is meaningful.
+pub fn sort_key_warning(key: SortKey, procs: &[Process], first_sample: bool) -> Option<String> {
+    let (name, has_data) = match key {
+        SortKey::Pid | SortKey::Name => return None,
+        SortKey::Cpu if first_sample => {
+            return Some(
+                "warning: --sort cpu needs two samples; the first snapshot has no CPU usage, \
+                 so processes are ordered by PID"
+                    .to_string(),
+            );
+        }
+        SortKey::Cpu => ("cpu", procs.iter().any(|p| p.cpu_percent > 0.0)),
+        SortKey::Mem => ("mem", procs.iter().any(|p| p.rss_kb > 0)),
+        SortKey::Peak => ("peak", procs.iter().any(|p| p.vm_hwm > 0)),
+        // Without memory pressure nothing is swapped out.
+        SortKey::VmSwap => return None,
+        SortKey::Time => ("time", procs.iter().any(|p| p.cpu_ticks > 0)),
+        SortKey::Oom => ("oom", procs.iter().any(|p| p.oom_score.is_some())),
+        SortKey::Sockets => ("sockets", procs.iter().any(|p| p.sockets.is_some())),
+        // Most processes go whole intervals without a major fault.
+        SortKey::MajFlt => return None,
+        SortKey::Read | SortKey::Write => {
+            let unreadable = procs.iter().filter(|p| p.io.is_none()).count();
+            return (unreadable * 2 > procs.len()).then(|| {
+                format!(
+                    "warning: /proc/<pid>/io is unreadable for {unreadable} of {} processes; \
+                     run as root to see the I/O of other users' processes",
+                    procs.len()
+                )
+            });
+        }
+    };
+    (!has_data && !procs.is_empty())
+        .then(|| format!("warning: no process reports {name} data; the order may be arbitrary"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        CpuSampler, CpuStat, Options,
+        testing::{MockReader, TempPath, process},
+    };
+
+    #[test]
+    fn test_parse_process_status() {
+        let input = "Name: myproc\nVmRSS:   1234 kB\n";
+        let mem = parse_process_status(input);
+        assert_eq!(mem, Some(1234));
+        assert_eq!(
+            parse_process_status("Name:\tmyproc\r\nVmRSS:\t1234 kB\r\n"),
+            Some(1234),
+            "CRLF"
+        );
+    }
+
+    #[test]
+    fn test_parse_process_status_peak() {
+        let input = "Name:\tmyproc\nVmHWM:\t4096 kB\nVmRSS:\t1234 kB\n";
+        assert_eq!(parse_field_bytes(input.as_bytes(), b"VmHWM:"), Some(4096));
+        assert_eq!(parse_process_status(input), Some(1234));
+    }
+
+    #[test]
+    fn test_parse_ns_pid() {
+        let status = b"Name:\tnginx\nPid:\t1234\nNSpid:\t1234\t87\t1\nVmRSS:\t10 kB\n";
+        assert_eq!(parse_ns_pid(status), Some(1));
+        assert_eq!(parse_ns_pid(b"NSpid:\t1234\n"), Some(1234));
+        assert_eq!(parse_ns_pid(b"Name:\told-kernel\nPid:\t1234\n"), None);
+
+        let mut reader = MockReader::default();
+        reader.add_file("/proc/1234/status", status.to_vec());
+        let p = read_process(&reader, "/proc", "1234").unwrap();
+        assert_eq!(p.ns_pid, Some(1));
+    }
+
+    #[test]
+    fn test_byte_and_string_status_parsers_agree() {
+        for status in [
+            "VmRSS:\t  1234 kB\n",
+            "Name: x\nVmRSS: 12x kB\n",
+            "Name: x\n",
+        ] {
+            assert_eq!(
+                parse_process_status(status),
+                parse_process_status_from_bytes(status.as_bytes())
+            );
+        }
+        assert_eq!(parse_process_status_from_bytes(b"VmRSS: 12x kB"), None);
+    }
+
+    const SAMPLE_STAT: &str = "1234 (tmux: (server)) S 1 1234 1234 34816 1234 4194560 \
+        120 0 3 0 250 75 0 0 20 0 1 0 5000 10000000 500 18446744073709551615";
+
+    #[test]
+    fn test_parse_stat_with_spaces_in_comm() {
+        // Zeros up to starttime (field 22), the last field parse_stat needs.
+        let line = "1234 (tmux: server) S 1 1234 1234 34816 1234 4194560 0 0 0 0 0 0 0 0 0 0 0 0 0";
+        let stat = parse_stat(line).unwrap();
+        assert_eq!(stat.comm, "tmux: server");
+        assert_eq!(stat.state, 'S');
+        assert_eq!(stat.tty_nr, 34816);
+    }
+
+    #[test]
+    fn test_parse_stat_cpu_times() {
+        let stat = parse_stat(SAMPLE_STAT).unwrap();
+        assert_eq!(stat.comm, "tmux: (server)");
+        assert_eq!((stat.utime, stat.stime), (250, 75));
+        assert_eq!(stat.starttime, 5000);
+    }
+
+    #[test]
+    fn test_parse_stat_page_faults() {
+        let stat = parse_stat(SAMPLE_STAT).unwrap();
+        assert_eq!((stat.minflt, stat.majflt), (120, 3));
+
+        let mut reader = MockReader::default();
+        reader.add_file("/proc/9/stat", SAMPLE_STAT.replacen("1234", "9", 1));
+        let mut p = process(9, "tmux", 1);
+        read_process_details(&reader, "/proc", &mut p);
+        assert_eq!((p.min_faults, p.maj_faults), (120, 3));
+    }
+
+    #[test]
+    fn test_decode_tty() {
+        assert_eq!(decode_tty(0), "?");
+        // major 136, minor 3
+        assert_eq!(decode_tty(34819), "pts/3");
+        // minor 300 spills into the high minor bits
+        assert_eq!(
+            decode_tty((136 << 8) | (300 & 0xff) | ((300 & !0xff) << 12)),
+            "pts/300"
+        );
+        assert_eq!(decode_tty((4 << 8) | 1), "tty1");
+    }
+
+    #[test]
+    fn test_scan_threads_names_each_thread() {
+        let mut reader = MockReader::default();
+        reader.add_process(40, "java", 1024);
+        // A thread can rename itself with prctl(PR_SET_NAME).
+        for (tid, name) in [(40, "java"), (41, "GC Thread#0")] {
+            let task = format!("/proc/40/task/{tid}");
+            reader.add_file(&format!("{task}/comm"), format!("{name}\n"));
+            reader.add_file(&format!("{task}/status"), "Name:\tjava\nVmRSS:\t1024 kB\n");
+        }
+        let threads: Vec<(u32, String)> = scan_threads(&reader, "/proc", |_| {})
+            .unwrap()
+            .map(|thread| (thread.pid, thread.name))
+            .collect();
+        assert_eq!(
+            threads,
+            [(40, "java".to_string()), (41, "GC Thread#0".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_name_falls_back_to_stat_without_comm() {
+        let mut reader = MockReader::default();
+        reader.add_file(
+            "/proc/9/status",
+            "Name:\tsshd\nState:\tS (sleeping)\nVmRSS:\t512 kB\n",
+        );
+        reader.add_file(
+            "/proc/9/stat",
+            "9 (sshd: alice) S 1 9 9 0 -1 0 0 0 0 0 10 5 0 0 20 0 1 0 100",
+        );
+        let process = read_process(&reader, "/proc", "9").unwrap();
+        assert_eq!(process.name, "sshd: alice");
+        assert_eq!(read_process_comm(&reader, "/proc", "9"), "");
+    }
+
+    #[test]
+    fn test_read_thread_cpu_ticks() {
+        let mut reader = MockReader::default();
+        reader.add_file(
+            "/proc/40/task/40/stat",
+            "40 (java) S 1 40 40 0 -1 0 0 0 0 0 120 30 0 0 20 0 2 0 100",
+        );
+        reader.add_file(
+            "/proc/40/task/41/stat",
+            "41 (GC Thread) S 1 40 40 0 -1 0 0 0 0 0 500 50 0 0 20 0 2 0 101",
+        );
+        assert_eq!(read_thread_cpu_ticks(&reader, "/proc", 40), Some(700));
+        assert_eq!(read_thread_cpu_ticks(&reader, "/proc", 41), None);
+    }
+
+    #[test]
+    fn test_parse_statm() {
+        let statm = "2817 1034 830 45 0 211 0\n";
+        assert_eq!(parse_statm(statm, 4096), Some(4136));
+        assert_eq!(parse_statm(statm, 16384), Some(16544));
+        assert_eq!(parse_statm("0 0 0 0 0 0 0\n", 4096), None);
+        assert_eq!(parse_statm("", 4096), None);
+    }
+
+    #[test]
+    fn test_read_process_kernel_thread() {
+        let mut reader = MockReader::default();
+        reader.add_file("/proc/2/comm", "kthreadd\n");
+        reader.add_file("/proc/2/status", "Name:\tkthreadd\nState:\tS (sleeping)\n");
+        reader.add_file("/proc/2/cmdline", "");
+        reader.add_file("/proc/9/comm", "scrubber\n");
+        reader.add_file("/proc/9/status", "Name:\tscrubber\nState:\tS (sleeping)\n");
+        reader.add_file("/proc/9/cmdline", "scrubber\0--all\0");
+        reader.add_process(42, "bash", 3104);
+
+        let kthread = read_process(&reader, "/proc", "2").unwrap();
+        assert!(kthread.is_kernel_thread);
+        assert_eq!(kthread.rss_kb, 0);
+        assert!(
+            !read_process(&reader, "/proc", "9")
+                .unwrap()
+                .is_kernel_thread
+        );
+        assert!(
+            !read_process(&reader, "/proc", "42")
+                .unwrap()
+                .is_kernel_thread
+        );
+        assert!(!reader.reads().contains(&"/proc/42/cmdline".to_string()));
+    }
+
+    #[test]
+    fn test_parse_process_io() {
+        let io = b"rchar: 323934931\nwchar: 323929600\nsyscr: 632687\nsyscw: 632675\n\
+                   read_bytes: 4096\nwrite_bytes: 323932160\ncancelled_write_bytes: 0\n";
+        assert_eq!(
+            parse_process_io(io),
+            Some(IoCounters {
+                read_bytes: 4096,
+                write_bytes: 323932160,
+            })
+        );
+        assert_eq!(parse_process_io(b"rchar: 1\n"), None);
+    }
+
+    #[test]
+    fn test_sort_by_delta() {
+        let mut sampler = CpuSampler::default();
+        let mut first = vec![process(1, "big", 9000), process(2, "leak", 100)];
+        sampler.update(&mut first, CpuStat::default());
+        assert_eq!(first[0].rss_delta, 9000);
+
+        let mut second = vec![
+            process(1, "big", 9010),
+            process(2, "leak", 900),
+            process(3, "new", 500),
+        ];
+        sampler.update(&mut second, CpuStat::default());
+        second.sort_by(|a, b| compare_delta(DeltaMetric::Mem, a, b));
+        let order: Vec<(&str, u64)> = second
+            .iter()
+            .map(|p| (p.name.as_str(), p.rss_delta))
+            .collect();
+        assert_eq!(order, [("leak", 800), ("new", 500), ("big", 10)]);
+    }
+
+    #[test]
+    fn test_top_process() {
+        let with_ticks = |pid, rss, cpu_ticks| Process {
+            cpu_ticks,
+            ..process(pid, "p", rss)
+        };
+        let procs = [
+            with_ticks(9, 400, 10),
+            with_ticks(4, 900, 70),
+            with_ticks(2, 300, 70),
+            with_ticks(6, 900, 5),
+        ];
+        let pid = |p: Option<&Process>| p.map(|p| p.pid);
+        assert_eq!(
+            pid(top_process(&procs, |p| p.rss_kb)),
+            Some(4),
+            "tie on RSS"
+        );
+        assert_eq!(
+            pid(top_process(&procs, |p| p.cpu_ticks)),
+            Some(2),
+            "tie on CPU"
+        );
+        assert_eq!(pid(top_process(&[], |p| p.rss_kb)), None);
+    }
+
+    #[test]
+    fn test_skipped_processes_are_logged() {
+        let mut reader = MockReader::default();
+        reader.add_process(1, "init", 100);
+        reader.add_file("/proc/5/status", "Name:\tbad\nVmRSS:\tlots kB\n");
+        let mut procs = Vec::new();
+        let warnings = crate::log::testing::captured(|| {
+            procs = list_processes_from(&reader, "/proc").unwrap();
+        });
+        assert_eq!(procs.len(), 1);
+        assert_eq!(
+            warnings,
+            [r#"skipping /proc/5: malformed VmRSS in /proc/5/status (line 2: "VmRSS:\tlots kB")"#]
+        );
+    }
+
+    #[test]
+    fn test_invalid_utf8_name_is_replaced() {
+        let mut reader = MockReader::default();
+        reader.add_file("/proc/7/comm", b"caf\xe9\xff\n".to_vec());
+        reader.add_file(
+            "/proc/7/stat",
+            b"7 (caf\xe9\xff) S 1 7 7 0 -1 0 0 0 0 0 10 5 0 0 20 0 1 0 100".to_vec(),
+        );
+        reader.add_file("/proc/7/cmdline", b"/usr/bin/caf\xe9\0--fast\0".to_vec());
+        assert_eq!(
+            read_process_comm(&reader, "/proc", "7"),
+            "caf\u{fffd}\u{fffd}"
+        );
+        let stat = read_process_stat(&reader, "/proc", 7).unwrap();
+        assert_eq!(stat.comm, "caf\u{fffd}\u{fffd}");
+        assert_eq!(
+            read_process_cmdline(&reader, "/proc", 7).as_deref(),
+            Some("/usr/bin/caf\u{fffd} --fast")
+        );
+    }
+
+    #[test]
+    fn test_parse_wchan() {
+        assert_eq!(parse_wchan("do_select"), Some("do_select".to_string()));
+        assert_eq!(parse_wchan("ep_poll\n"), Some("ep_poll".to_string()));
+        assert_eq!(parse_wchan("0"), None);
+        assert_eq!(parse_wchan(""), None);
+
+        let mut reader = MockReader::default();
+        reader.add_file("/proc/9/wchan", "io_schedule");
+        assert_eq!(
+            read_process_wchan(&reader, "/proc", 9).as_deref(),
+            Some("io_schedule")
+        );
+        assert_eq!(read_process_wchan(&reader, "/proc", 10), None);
+    }
+
+    #[test]
+    fn test_parse_schedstat() {
+        assert_eq!(
+            parse_schedstat("183742538 24709464 1318\n"),
+            Some((183_742_538, 24_709_464, 1318))
+        );
+        assert_eq!(parse_schedstat("183742538 24709464"), None);
+
+        let mut reader = MockReader::default();
+        reader.add_file("/proc/9/schedstat", "0 0 0\n");
+        assert_eq!(read_schedstat(&reader, "/proc", 9), Some((0, 0, 0)));
+        // Kernels without CONFIG_SCHED_INFO have no schedstat file.
+        assert_eq!(read_schedstat(&reader, "/proc", 10), None);
+    }
+
+    #[test]
+    fn test_parse_cmdline() {
+        assert_eq!(
+            parse_cmdline(b"/usr/bin/python3\0-m\0http.server\0"),
+            Some("/usr/bin/python3 -m http.server".to_string())
+        );
+        assert_eq!(parse_cmdline(b""), None);
+    }
+
+    #[test]
+    fn test_read_one_process() {
+        let mut reader = MockReader::default();
+        reader.add_process(42, "worker", 512);
+        reader.add_file("/proc/42/cmdline", "worker\0--jobs=4\0");
+        reader.add_file("/proc/42/oom_score", "17\n");
+
+        let p = read_one_process_from(&reader, "/proc", 42).unwrap();
+        assert_eq!((p.pid, p.name.as_str(), p.rss_kb), (42, "worker", 512));
+        assert_eq!((p.tty_nr, p.cpu_ticks, p.start_time), (34816, 15, 100));
+        assert_eq!(p.cmdline.as_deref(), Some("worker --jobs=4"));
+        assert_eq!(p.oom_score, Some(17));
+        assert!(reader.dir_reads().is_empty());
+
+        let err = read_one_process_from(&reader, "/proc", 7).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(err.to_string(), "no process 7 in /proc");
+    }
+
+    #[test]
+    fn test_resolve_name() {
+        let comm = "systemd-journal";
+        let cmdline = b"/usr/lib/systemd/systemd-journald\0--user\0".as_slice();
+        let exe = Path::new("/usr/lib/systemd/systemd-journald (deleted)");
+        assert_eq!(
+            resolve_name(NameSource::Comm, comm, Some(cmdline), Some(exe)),
+            comm
+        );
+        assert_eq!(
+            resolve_name(NameSource::Cmdline, comm, Some(cmdline), None),
+            "systemd-journald"
+        );
+        assert_eq!(
+            resolve_name(NameSource::Exe, comm, None, Some(exe)),
+            "systemd-journald"
+        );
+        assert_eq!(
+            resolve_name(
+                NameSource::Cmdline,
+                "sshd",
+                Some(b"sshd: alice@pts/0\0"),
+                None
+            ),
+            "sshd: alice@pts/0",
+            "a process title is kept whole"
+        );
+        assert_eq!(
+            resolve_name(NameSource::Cmdline, "sh", Some(b"bin/sh\0-c\0"), None),
+            "sh"
+        );
+        // Kernel threads have an empty cmdline and no exe.
+        assert_eq!(
+            resolve_name(NameSource::Cmdline, "kworker/0:1", Some(b""), None),
+            "kworker/0:1"
+        );
+        assert_eq!(
+            resolve_name(NameSource::Exe, "kworker/0:1", None, None),
+            "kworker/0:1"
+        );
+    }
+
+    #[test]
+    fn test_classify_by_arg() {
+        let train = b"/usr/bin/python3\0/srv/ml/train.py\0--epochs\0\0".as_slice();
+        assert_eq!(classify_by_arg("python3", train, 1), "python3 (train.py)");
+        assert_eq!(classify_by_arg("python3", train, 2), "python3 (--epochs)");
+        assert_eq!(
+            classify_by_arg("python3", train, 3),
+            "python3",
+            "empty argument"
+        );
+        assert_eq!(
+            classify_by_arg("python3", train, 4),
+            "python3",
+            "too few arguments"
+        );
+        assert_eq!(
+            classify_by_arg("java", b"java\0-jar\0app.jar\0", 2),
+            "java (app.jar)"
+        );
+        // Kernel threads have no cmdline at all.
+        assert_eq!(classify_by_arg("kthreadd", b"", 1), "kthreadd");
+    }
+
+    #[test]
+    fn test_read_process_cwd() {
+        let base = TempPath::new("cwd");
+        let target = base.join("work dir");
+        std::fs::create_dir_all(base.join("1234")).unwrap();
+        std::fs::create_dir_all(&target).unwrap();
+        std::os::unix::fs::symlink(&target, base.join("1234/cwd")).unwrap();
+
+        let base_str = base.to_str().unwrap();
+        assert_eq!(read_process_cwd(&FsReader, base_str, 1234), Some(target));
+        assert_eq!(read_process_cwd(&FsReader, base_str, 99), None);
+    }
+
+    #[test]
+    fn test_count_sockets() {
+        let base = TempPath::new("fd");
+        let fd = base.join("1234/fd");
+        std::fs::create_dir_all(&fd).unwrap();
+        for (name, target) in [
+            ("0", "/dev/null"),
+            ("1", "socket:[4711]"),
+            ("2", "pipe:[99]"),
+            ("3", "socket:[4712]"),
+            ("4", "/var/log/syslog"),
+        ] {
+            std::os::unix::fs::symlink(target, fd.join(name)).unwrap();
+        }
+
+        let base_str = base.to_str().unwrap();
+        assert_eq!(count_sockets(&FsReader, base_str, 1234), Some(2));
+        assert_eq!(count_sockets(&FsReader, base_str, 99), None);
+    }
+
+    #[test]
+    fn test_parse_rss_breakdown() {
+        let status = b"Name:\tfirefox\nVmRSS:\t  524288 kB\nRssAnon:\t  400000 kB\n\
+                       RssFile:\t  120000 kB\nRssShmem:\t    4288 kB\n";
+        let breakdown = parse_rss_breakdown(status).unwrap();
+        assert_eq!(
+            breakdown,
+            RssBreakdown {
+                anon: 400000,
+                file: 120000,
+                shmem: 4288,
+            }
+        );
+        assert_eq!(breakdown.anon + breakdown.file + breakdown.shmem, 524288);
+        assert_eq!(parse_rss_breakdown(b"VmRSS:\t  524288 kB\n"), None);
+    }
+
+    #[test]
+    fn test_summarize_states() {
+        let procs: Vec<Process> = "RSSDIZTtZX"
+            .chars()
+            .enumerate()
+            .map(|(i, state)| Process {
+                pid: i as u32,
+                state,
+                threads: 2,
+                ..Process::default()
+            })
+            .collect();
+        let summary = summarize_states(&procs);
+        assert_eq!(
+            summary.to_string(),
+            "Tasks: 10 total, 1 running, 4 sleeping, 2 stopped, 2 zombie; 20 threads"
+        );
+        assert!(
+            summary
+                .format_with_change(Some(12))
+                .starts_with("Tasks: 10 total (-2), 1 running")
+        );
+        assert_eq!(
+            parse_status_state(b"Name:\tbash\nState:\tZ (zombie)\n"),
+            Some('Z')
+        );
+    }
+
+    #[test]
+    fn test_sort_processes_by_composite_key() {
+        let mut procs = vec![
+            process(4, "bash", 100),
+            process(3, "sshd", 100),
+            process(2, "bash", 300),
+            process(1, "bash", 100),
+        ];
+        sort_processes_by(&mut procs, |p| {
+            (p.name.clone(), std::cmp::Reverse(p.rss_kb))
+        });
+        let pids: Vec<u32> = procs.iter().map(|p| p.pid).collect();
+        assert_eq!(pids, [2, 1, 4, 3]);
+    }
+
+    #[test]
+    fn test_sort_processes_by_peak() {
+        let mut procs = vec![process(1, "small", 100), process(2, "spiky", 50)];
+        procs[1].vm_hwm = 9000;
+        sort_processes(&mut procs, SortKey::Peak);
+        assert_eq!(procs[0].name, "spiky");
+    }
+
+    #[test]
+    fn test_sort_processes_by_vm_swap() {
+        let mut reader = MockReader::default();
+        for (pid, swap) in [(1, Some(0)), (2, Some(4096)), (3, None), (4, Some(512))] {
+            let swap = swap
+                .map(|kb| format!("VmSwap:\t{kb} kB\n"))
+                .unwrap_or_default();
+            reader.add_file(
+                &format!("/proc/{pid}/status"),
+                format!("Name:\tp{pid}\nVmRSS:\t100 kB\n{swap}"),
+            );
+        }
+        let mut procs: Vec<Process> = (1..=4)
+            .map(|pid| read_process(&reader, "/proc", &pid.to_string()).unwrap())
+            .collect();
+        assert_eq!(procs[2].vm_swap, 0);
+        sort_processes(&mut procs, SortOrder::parse("vmswap").unwrap());
+        let pids: Vec<u32> = procs.iter().map(|p| p.pid).collect();
+        assert_eq!(pids, [2, 4, 1, 3]);
+    }
+
+    #[test]
+    fn test_swap_percent() {
+        let p = Process {
+            vm_swap: 300,
+            ..process(1, "java", 900)
+        };
+        assert_eq!(p.swap_percent(), Some(25.0));
+        assert_eq!(process(2, "init", 100).swap_percent(), Some(0.0));
+        assert_eq!(process(3, "kthreadd", 0).swap_percent(), None);
+    }
+
+    #[test]
+    fn test_apply_sort_key() {
+        let mem = SortField::from(SortKey::Mem);
+        assert!(mem.descending);
+        let flipped = apply_sort_key(mem, SortKey::Mem);
+        assert_eq!((flipped.key, flipped.descending), (SortKey::Mem, false));
+        assert_eq!(apply_sort_key(flipped, SortKey::Mem), mem);
+
+        // Another key resets to its own default, ascending for names.
+        let name = apply_sort_key(flipped, SortKey::Name);
+        assert_eq!((name.key, name.descending), (SortKey::Name, false));
+        assert!(apply_sort_key(name, SortKey::Name).descending);
+        assert_eq!(apply_sort_key(name, SortKey::Cpu), SortKey::Cpu.into());
+    }
+
+    #[test]
+    fn test_sort_by_multiple_keys() {
+        let procs = vec![
+            process(3, "c", 100),
+            process(1, "b", 200),
+            process(2, "a", 100),
+        ];
+        let order = |spec: &str| {
+            let mut procs = procs.clone();
+            sort_processes(&mut procs, SortOrder::parse(spec).unwrap());
+            procs.iter().map(|p| p.pid).collect::<Vec<u32>>()
+        };
+        assert_eq!(order("mem,pid"), [1, 2, 3]);
+        assert_eq!(order("mem,pid:desc"), [1, 3, 2]);
+        assert_eq!(order("mem:asc,name"), [2, 3, 1]);
+        assert!(SortOrder::parse("mem:up").is_err());
+    }
+
+    #[test]
+    fn test_retain_min_cpu() {
+        let mut procs: Vec<Process> = [(1, 0.0), (2, 4.9), (3, 5.0), (4, 37.5)]
+            .into_iter()
+            .map(|(pid, cpu)| Process {
+                cpu_percent: cpu,
+                ..process(pid, "p", 10)
+            })
+            .collect();
+        retain_min_cpu(&mut procs, 5.0);
+        assert_eq!(procs.iter().map(|p| p.pid).collect::<Vec<_>>(), [3, 4]);
+    }
+
+    #[test]
+    fn test_count_until_percent() {
+        // 1000 kB used: 500 + 250 + 125 + 125.
+        let procs: Vec<Process> = [500, 250, 125, 125]
+            .into_iter()
+            .enumerate()
+            .map(|(i, rss)| process(i as u32 + 1, "p", rss))
+            .collect();
+        assert_eq!(count_until_percent(&procs, 1000, 50.0), 1);
+        assert_eq!(count_until_percent(&procs, 1000, 60.0), 2);
+        assert_eq!(count_until_percent(&procs, 1000, 75.0), 2);
+        assert_eq!(count_until_percent(&procs, 1000, 90.0), 4);
+        // Kernel memory and the page cache are part of "used" too.
+        assert_eq!(count_until_percent(&procs, 2000, 100.0), 4);
+        assert_eq!(count_until_percent(&[], 1000, 50.0), 0);
+    }
+
+    #[test]
+    fn test_top_processes_matches_full_sort() {
+        // Many duplicate RSS values, so ties must be broken the same way.
+        let procs: Vec<Process> = (1..=500)
+            .map(|pid| process(pid, &format!("p{}", pid % 7), u64::from(pid * 37 % 101)))
+            .collect();
+        for spec in ["mem", "name,mem:asc", "pid:desc"] {
+            let order = SortOrder::parse(spec).unwrap();
+            let mut sorted = procs.clone();
+            sort_processes(&mut sorted, order.clone());
+            for n in [0, 1, 10, 500, 600] {
+                let expected = if n == 0 {
+                    &sorted[..]
+                } else {
+                    &sorted[..n.min(500)]
+                };
+                assert_eq!(
+                    top_processes(procs.clone(), &order, n),
+                    expected,
+                    "{spec} {n}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_sort_key_warning_for_cpu_in_once_mode() {
+        let procs = vec![process(1, "a", 10), process(2, "b", 20)];
+        let once = Options::parse(["--once", "--sort", "cpu"].map(String::from)).unwrap();
+        let mut sampler = CpuSampler::default();
+        assert!(once.once);
+        assert!(sort_key_warning(once.sort.primary(), &procs, sampler.is_first_sample()).is_some());
+
+        sampler.update(&mut procs.clone(), CpuStat::default());
+        assert!(sort_key_warning(SortKey::Mem, &procs, sampler.is_first_sample()).is_none());
+    }
+}
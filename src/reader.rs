@@ -0,0 +1,148 @@
+//! Access to procfs contents.
+//!
+//! All `/proc` reads go through [`ProcReader`], so the same parsing and
+//! collection logic can run against the live filesystem ([`FsReader`]), a
+//! remote host's `/proc` fetched through callbacks ([`CallbackReader`]), or
+//! an in-memory fixture in tests.
+
+use std::{fs, io, path::PathBuf};
+
+/// Source of procfs files and directory listings.
+///
+/// Paths are plain strings such as `/proc/1234/status`.
+pub trait ProcReader {
+    /// Reads the whole file at `path`.
+    fn read(&self, path: &str) -> io::Result<Vec<u8>>;
+
+    /// Lists the entry names of the directory at `path`.
+    fn read_dir(&self, path: &str) -> io::Result<Vec<String>>;
+
+    /// Reads the target of the symbolic link at `path`, such as
+    /// `/proc/<pid>/cwd`.
+    ///
+    /// Sources without symbolic links keep the default, which fails with
+    /// [`io::ErrorKind::Unsupported`].
+    fn read_link(&self, path: &str) -> io::Result<PathBuf> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("cannot read link {path}"),
+        ))
+    }
+
+    /// Reads the file at `path` as UTF-8 text.
+    fn read_to_string(&self, path: &str) -> io::Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+/// Lets a monitor own a reader chosen at runtime, e.g.
+/// `Monitor<Box<dyn ProcReader>>`.
+impl<R: ProcReader + ?Sized> ProcReader for Box<R> {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        (**self).read(path)
+    }
+
+    fn read_dir(&self, path: &str) -> io::Result<Vec<String>> {
+        (**self).read_dir(path)
+    }
+
+    fn read_link(&self, path: &str) -> io::Result<PathBuf> {
+        (**self).read_link(path)
+    }
+
+    fn read_to_string(&self, path: &str) -> io::Result<String> {
+        (**self).read_to_string(path)
+    }
+}
+
+/// Whether `root` looks like a procfs: it has a `meminfo` file and at least
+/// one numeric (pid) directory. A mistyped `--proc-root` otherwise only
+/// shows up as an empty table.
+pub fn looks_like_procfs(reader: &dyn ProcReader, root: &str) -> bool {
+    let root = root.trim_end_matches('/');
+    reader.read(&format!("{root}/meminfo")).is_ok()
+        && reader.read_dir(root).is_ok_and(|names| {
+            names
+                .iter()
+                .any(|name| !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit()))
+        })
+}
+
+/// Reads from the real filesystem.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsReader;
+
+impl ProcReader for FsReader {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn read_dir(&self, path: &str) -> io::Result<Vec<String>> {
+        Ok(fs::read_dir(path)?
+            .filter_map(Result::ok)
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect())
+    }
+
+    fn read_link(&self, path: &str) -> io::Result<PathBuf> {
+        fs::read_link(path)
+    }
+
+    fn read_to_string(&self, path: &str) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+}
+
+/// Serves files and directory listings from caller-supplied callbacks, such
+/// as ones that run `cat` and `ls` over SSH or ask a remote agent.
+///
+/// Symbolic links are not supported, so the `cwd` column shows `?`.
+pub struct CallbackReader<F, D> {
+    read: F,
+    read_dir: D,
+}
+
+impl<F, D> CallbackReader<F, D>
+where
+    F: Fn(&str) -> io::Result<Vec<u8>>,
+    D: Fn(&str) -> io::Result<Vec<String>>,
+{
+    /// Reads files with `read` and lists directories with `read_dir`.
+    pub fn new(read: F, read_dir: D) -> Self {
+        Self { read, read_dir }
+    }
+}
+
+impl<F, D> ProcReader for CallbackReader<F, D>
+where
+    F: Fn(&str) -> io::Result<Vec<u8>>,
+    D: Fn(&str) -> io::Result<Vec<String>>,
+{
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        (self.read)(path)
+    }
+
+    fn read_dir(&self, path: &str) -> io::Result<Vec<String>> {
+        (self.read_dir)(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockReader;
+
+    #[test]
+    fn test_looks_like_procfs() {
+        let mut reader = MockReader::default();
+        assert!(!looks_like_procfs(&reader, "/snap"), "empty root");
+        reader.add_file("/snap/meminfo", "MemTotal: 1000 kB\n");
+        reader.add_file("/snap/self/status", "Name:\tx\n");
+        assert!(!looks_like_procfs(&reader, "/snap"), "no pid directories");
+        reader.add_process(1, "init", 100);
+        reader.add_file("/snap/1/status", "Name:\tinit\n");
+        assert!(looks_like_procfs(&reader, "/snap/"));
+        assert!(!looks_like_procfs(&reader, "/proc"), "no meminfo");
+    }
+}
@@ -0,0 +1,107 @@
+//! Re-reading the config file on `SIGHUP` without restarting.
+//!
+//! The signal handler only sets a flag; the refresh loop checks it before
+//! each frame and reloads there, see [`Config::reload`](crate::Config::reload).
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::Options;
+
+/// Set by the `SIGHUP` handler; a [`Monitor`](crate::Monitor) watches it
+/// unless given its own flag.
+pub static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Makes `SIGHUP` request a reload instead of ending the process.
+#[cfg(unix)]
+pub fn install_sighup_handler() {
+    use std::ffi::c_int;
+
+    const SIGHUP: c_int = 1;
+
+    unsafe extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+
+    extern "C" fn on_sighup(_: c_int) {
+        request_reload();
+    }
+
+    // SAFETY: the handler only stores to an atomic, which is
+    // async-signal-safe.
+    unsafe {
+        signal(SIGHUP, on_sighup);
+    }
+}
+
+/// Without `SIGHUP` there is nothing to install.
+#[cfg(not(unix))]
+pub fn install_sighup_handler() {}
+
+/// Asks the refresh loop to reload before its next frame.
+pub fn request_reload() {
+    RELOAD_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Whether a reload was requested through `flag` since the last call.
+pub fn take_reload_request(flag: &AtomicBool) -> bool {
+    flag.swap(false, Ordering::Relaxed)
+}
+
+/// Copies the settings that can change while running (interval,
+/// iterations, sort and columns) from `reloaded` into `current`.
+///
+/// Every other difference is left alone; the returned notes say which
+/// were ignored, e.g. a changed `--format` when the output already went to
+/// a pipe in the old one.
+pub fn apply_reload(current: &mut Options, reloaded: &Options) -> Vec<String> {
+    current.interval = reloaded.interval;
+    current.iterations = reloaded.iterations;
+    current.sort = reloaded.sort.clone();
+    current.columns = reloaded.columns.clone();
+
+    let mut notes = Vec::new();
+    if current.format != reloaded.format {
+        notes.push("ignoring the changed --format until restart".to_string());
+    }
+    let rest = Options {
+        format: current.format,
+        exclude: current.exclude.clone(),
+        ..reloaded.clone()
+    };
+    if rest != *current {
+        notes.push(
+            "ignoring changed settings other than interval, iterations, sort and columns \
+             until restart"
+                .to_string(),
+        );
+    }
+    notes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{OutputFormat, SortKey};
+
+    #[test]
+    fn test_apply_reload() {
+        let parse = |args: &[&str]| Options::parse(args.iter().map(|a| a.to_string())).unwrap();
+        let mut current = parse(&["--interval", "2", "--sort", "rss"]);
+
+        let notes = apply_reload(&mut current, &parse(&["--interval", "5", "--sort", "cpu"]));
+        assert_eq!(current.interval, Some(Duration::from_secs(5)));
+        assert_eq!(current.sort, SortKey::Cpu.into());
+        assert!(notes.is_empty(), "{notes:?}");
+
+        let notes = apply_reload(
+            &mut current,
+            &parse(&["--interval", "1", "--format", "ascii-table"]),
+        );
+        assert_eq!(current.interval, Some(Duration::from_secs(1)));
+        assert_eq!(current.format, OutputFormat::Table);
+        assert_eq!(notes.len(), 1, "{notes:?}");
+        assert!(notes[0].contains("--format"));
+    }
+}
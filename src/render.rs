@@ -0,0 +1,2316 @@
+//! Part 7 – Printing the process table.
+
+use std::{
+    io::{self, IsTerminal},
+    time::SystemTime,
+};
+
+#[cfg(feature = "extras")]
+use crate::caps::{decode_capabilities, format_capabilities};
+use crate::{
+    CpuStat, MemField, MemInfo, MemUnit, Options, Process, RssUnit, StateSummary, UnitBase,
+    json::Json,
+    oom::OomRisk,
+    process::{RssBreakdown, decode_tty},
+    system::{LoadAvg, clock_ticks_per_second, kb_to_pages, page_size},
+    time::{format_cpu_time, format_uptime},
+};
+
+/// A column of the process table.
+///
+/// Implement this to add a column without touching the table formatting, and
+/// make it available by name through a [`ColumnRegistry`].
+pub trait ProcColumn {
+    /// Name used to select the column with `--columns`.
+    fn name(&self) -> &str;
+
+    fn header(&self) -> &str;
+
+    fn render(&self, p: &Process) -> String;
+
+    /// Pads `cell` to this column's width; by default left-aligned to the
+    /// header width.
+    fn pad(&self, cell: &str) -> String {
+        format!("{cell:<width$}", width = self.header().len())
+    }
+
+    /// The cell as a JSON value for `--format json`; by default the rendered
+    /// text as a string.
+    fn json(&self, p: &Process) -> Json {
+        Json::String(self.render(p))
+    }
+
+    /// Whether rendering needs [`read_process_details`](crate::read_process_details).
+    fn needs_details(&self) -> bool {
+        false
+    }
+}
+
+/// Shortens `name` to at most `width` characters, replacing the cut-off tail
+/// with `…`.
+///
+/// Counts and cuts at `char` boundaries, so multibyte characters are never
+/// split.
+pub fn truncate_name(name: &str, width: usize) -> String {
+    if name.chars().count() <= width {
+        return name.to_string();
+    }
+    let mut out: String = name.chars().take(width.saturating_sub(1)).collect();
+    if width > 0 {
+        out.push('…');
+    }
+    out
+}
+
+/// Shortens a command line to `width` characters like [`truncate_name`], but
+/// never cuts into the program, the first space-separated token.
+///
+/// When the program alone does not leave room for the ellipsis it is shown
+/// in full, followed by ` …` if arguments were dropped, so the result can
+/// be wider than `width`.
+pub fn truncate_cmdline(cmdline: &str, width: usize) -> String {
+    if cmdline.chars().count() <= width {
+        return cmdline.to_string();
+    }
+    let (program, args) = cmdline.split_once(' ').unwrap_or((cmdline, ""));
+    if program.chars().count() < width {
+        return truncate_name(cmdline, width);
+    }
+    if args.is_empty() {
+        program.to_string()
+    } else {
+        format!("{program} …")
+    }
+}
+
+/// The built-in columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Pid,
+    Name,
+    Rss,
+    Peak,
+    Tty,
+    Cpu,
+    Time,
+    Cwd,
+    RssAnon,
+    RssFile,
+    RssShmem,
+    Cmd,
+    IoRead,
+    IoWrite,
+    Oom,
+    OomRisk,
+    MinFlt,
+    MajFlt,
+    Tgid,
+    Task,
+    Sockets,
+    NsPid,
+    Wchan,
+    MaxRss,
+    MaxCpu,
+    Unit,
+    MemPercent,
+    SchedWait,
+    SwapRatio,
+    #[cfg(feature = "extras")]
+    Caps,
+    #[cfg(feature = "extras")]
+    Numa,
+}
+
+impl Column {
+    pub const ALL: &[Self] = &[
+        Self::Pid,
+        Self::Name,
+        Self::Rss,
+        Self::Peak,
+        Self::Tty,
+        Self::Cpu,
+        Self::Time,
+        Self::Cwd,
+        Self::RssAnon,
+        Self::RssFile,
+        Self::RssShmem,
+        Self::Cmd,
+        Self::IoRead,
+        Self::IoWrite,
+        Self::Oom,
+        Self::OomRisk,
+        Self::MinFlt,
+        Self::MajFlt,
+        Self::Tgid,
+        Self::Task,
+        Self::Sockets,
+        Self::NsPid,
+        Self::Wchan,
+        Self::MaxRss,
+        Self::MaxCpu,
+        Self::Unit,
+        Self::MemPercent,
+        Self::SchedWait,
+        Self::SwapRatio,
+        #[cfg(feature = "extras")]
+        Self::Caps,
+        #[cfg(feature = "extras")]
+        Self::Numa,
+    ];
+
+    /// The built-in column called `name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|c| c.name() == name)
+    }
+}
+
+impl ProcColumn for Column {
+    fn name(&self) -> &str {
+        match self {
+            Self::Pid => "pid",
+            Self::Name => "name",
+            Self::Rss => "rss",
+            Self::Peak => "peak",
+            Self::Tty => "tty",
+            Self::Cpu => "cpu",
+            Self::Time => "time",
+            Self::Cwd => "cwd",
+            Self::RssAnon => "anon",
+            Self::RssFile => "file",
+            Self::RssShmem => "shmem",
+            Self::Cmd => "cmd",
+            Self::IoRead => "read",
+            Self::IoWrite => "write",
+            Self::Oom => "oom",
+            Self::OomRisk => "risk",
+            Self::MinFlt => "minflt",
+            Self::MajFlt => "majflt",
+            Self::Tgid => "tgid",
+            Self::Task => "task",
+            Self::Sockets => "sockets",
+            Self::NsPid => "nspid",
+            Self::Wchan => "wchan",
+            Self::MaxRss => "maxrss",
+            Self::MaxCpu => "maxcpu",
+            Self::Unit => "unit",
+            Self::MemPercent => "mem",
+            Self::SchedWait => "schedwait",
+            Self::SwapRatio => "swapratio",
+            #[cfg(feature = "extras")]
+            Self::Caps => "caps",
+            #[cfg(feature = "extras")]
+            Self::Numa => "numa",
+        }
+    }
+
+    fn header(&self) -> &str {
+        match self {
+            Self::Pid => "PID",
+            Self::Name => "NAME",
+            Self::Rss => "RSS(kB)",
+            Self::Peak => "PEAK(kB)",
+            Self::Tty => "TTY",
+            Self::Cpu => "%CPU",
+            Self::Time => "TIME+",
+            Self::Cwd => "CWD",
+            Self::RssAnon => "ANON(kB)",
+            Self::RssFile => "FILE(kB)",
+            Self::RssShmem => "SHMEM(kB)",
+            Self::Cmd => "COMMAND",
+            Self::IoRead => "READ(kB/s)",
+            Self::IoWrite => "WRITE(kB/s)",
+            Self::Oom => "OOM",
+            Self::OomRisk => "RISK",
+            Self::MinFlt => "MINFLT/s",
+            Self::MajFlt => "MAJFLT/s",
+            Self::Tgid => "TGID",
+            Self::Task => "TASK",
+            Self::Sockets => "SOCK",
+            Self::NsPid => "NSPID",
+            Self::Wchan => "WCHAN",
+            Self::MaxRss => "MAXRSS(kB)",
+            Self::MaxCpu => "MAX%CPU",
+            Self::Unit => "UNIT",
+            Self::MemPercent => "%MEM",
+            Self::SchedWait => "WAIT(ms/s)",
+            Self::SwapRatio => "%SWAP",
+            #[cfg(feature = "extras")]
+            Self::Caps => "CAPS",
+            #[cfg(feature = "extras")]
+            Self::Numa => "NUMA",
+        }
+    }
+
+    fn render(&self, p: &Process) -> String {
+        match self {
+            Self::Pid => p.pid.to_string(),
+            Self::Name => p.name.clone(),
+            Self::Rss => p.rss_kb.to_string(),
+            Self::Peak => p.vm_hwm.to_string(),
+            Self::Tty => decode_tty(p.tty_nr),
+            Self::Cpu
+            | Self::IoRead
+            | Self::IoWrite
+            | Self::MinFlt
+            | Self::MajFlt
+            | Self::MaxCpu
+            | Self::MemPercent
+            | Self::SchedWait
+            | Self::SwapRatio => decimal_cell(self.decimal(p), 1),
+            Self::Time => format_cpu_time(p.cpu_ticks, clock_ticks_per_second()),
+            Self::Cwd => p
+                .cwd
+                .as_ref()
+                .map_or_else(|| "?".to_string(), |cwd| cwd.display().to_string()),
+            Self::RssAnon => rss_part(p, |b| b.anon),
+            Self::RssFile => rss_part(p, |b| b.file),
+            Self::RssShmem => rss_part(p, |b| b.shmem),
+            Self::Cmd => p.cmdline.clone().unwrap_or_else(|| format!("[{}]", p.name)),
+            Self::Oom => p
+                .oom_score
+                .map_or_else(|| "-".to_string(), |s| s.to_string()),
+            Self::OomRisk => p
+                .oom_score
+                .map_or_else(|| "-".to_string(), |s| OomRisk::classify(s).to_string()),
+            Self::Tgid => p
+                .tgid
+                .map_or_else(|| "-".to_string(), |tgid| tgid.to_string()),
+            Self::Task => task_kind(p).to_string(),
+            Self::Sockets => p.sockets.map_or_else(|| "-".to_string(), |n| n.to_string()),
+            Self::NsPid => p.ns_pid.unwrap_or(p.pid).to_string(),
+            Self::Wchan => p.wchan.clone().unwrap_or_else(|| "-".to_string()),
+            Self::Unit => p.unit.clone().unwrap_or_else(|| "-".to_string()),
+            #[cfg(feature = "extras")]
+            Self::Caps => p
+                .cap_eff
+                .map_or_else(|| "?".to_string(), format_capabilities),
+            #[cfg(feature = "extras")]
+            Self::Numa => p
+                .numa
+                .map_or_else(|| "-".to_string(), |numa| numa.to_string()),
+            Self::MaxRss => self
+                .kb(p)
+                .map_or_else(|| "-".to_string(), |kb| kb.to_string()),
+        }
+    }
+
+    fn pad(&self, cell: &str) -> String {
+        match self {
+            Self::Pid | Self::Tgid | Self::NsPid => format!("{cell:<6}"),
+            Self::Task => format!("{cell:<6}"),
+            Self::Name => format!("{:<20}", truncate_name(cell, 20)),
+            Self::Tty => format!("{cell:<8}"),
+            #[cfg(feature = "extras")]
+            Self::Numa => format!("{cell:<8}"),
+            Self::Wchan => format!("{cell:<20}"),
+            Self::Unit => format!("{cell:<24}"),
+            Self::Cwd | Self::Cmd => cell.to_string(),
+            #[cfg(feature = "extras")]
+            Self::Caps => cell.to_string(),
+            Self::Cpu | Self::MemPercent | Self::SwapRatio => format!("{cell:>6}"),
+            Self::MaxCpu => format!("{cell:>7}"),
+            Self::Time => format!("{cell:>9}"),
+            Self::IoRead | Self::SchedWait => format!("{cell:>10}"),
+            Self::IoWrite => format!("{cell:>11}"),
+            Self::Oom | Self::Sockets => format!("{cell:>5}"),
+            Self::OomRisk => format!("{cell:<4}"),
+            Self::MinFlt | Self::MajFlt => format!("{cell:>8}"),
+            Self::Rss
+            | Self::Peak
+            | Self::RssAnon
+            | Self::RssFile
+            | Self::RssShmem
+            | Self::MaxRss => format!("{cell:>10}"),
+        }
+    }
+
+    fn json(&self, p: &Process) -> Json {
+        let part = |part: fn(&RssBreakdown) -> u64| {
+            p.rss_breakdown
+                .as_ref()
+                .map_or(Json::Null, |b| Json::UInt(part(b)))
+        };
+        match self {
+            Self::Pid => Json::UInt(p.pid.into()),
+            Self::Rss => Json::UInt(p.rss_kb),
+            Self::Peak => Json::UInt(p.vm_hwm),
+            Self::Cpu
+            | Self::IoRead
+            | Self::IoWrite
+            | Self::MinFlt
+            | Self::MajFlt
+            | Self::MaxCpu
+            | Self::MemPercent
+            | Self::SchedWait
+            | Self::SwapRatio => decimal_json(self.decimal(p), 1),
+            Self::Time => Json::Float(p.cpu_ticks as f64 / clock_ticks_per_second() as f64),
+            Self::Cwd => p
+                .cwd
+                .as_ref()
+                .map_or(Json::Null, |cwd| Json::String(cwd.display().to_string())),
+            Self::RssAnon => part(|b| b.anon),
+            Self::RssFile => part(|b| b.file),
+            Self::RssShmem => part(|b| b.shmem),
+            Self::Cmd => p.cmdline.clone().map_or(Json::Null, Json::String),
+            Self::Oom => p.oom_score.map_or(Json::Null, |s| Json::UInt(s.into())),
+            Self::OomRisk => p.oom_score.map_or(Json::Null, |s| {
+                Json::String(OomRisk::classify(s).to_string())
+            }),
+            Self::Tgid => p.tgid.map_or(Json::Null, |tgid| Json::UInt(tgid.into())),
+            Self::Sockets => p.sockets.map_or(Json::Null, |n| Json::UInt(n as u64)),
+            Self::NsPid => Json::UInt(p.ns_pid.unwrap_or(p.pid).into()),
+            Self::Wchan => p.wchan.clone().map_or(Json::Null, Json::String),
+            Self::Unit => p.unit.clone().map_or(Json::Null, Json::String),
+            #[cfg(feature = "extras")]
+            Self::Caps => p.cap_eff.map_or(Json::Null, |mask| {
+                Json::Array(
+                    decode_capabilities(mask)
+                        .into_iter()
+                        .map(Json::String)
+                        .collect(),
+                )
+            }),
+            #[cfg(feature = "extras")]
+            Self::Numa => p.numa.map_or(Json::Null, |numa| {
+                Json::Object(vec![
+                    ("node".to_string(), Json::UInt(numa.node.into())),
+                    ("percent".to_string(), decimal_json(Some(numa.percent), 1)),
+                ])
+            }),
+            Self::MaxRss => self.kb(p).map_or(Json::Null, Json::UInt),
+            Self::Name | Self::Tty | Self::Task => Json::String(self.render(p)),
+        }
+    }
+
+    fn needs_details(&self) -> bool {
+        #[cfg(feature = "extras")]
+        if *self == Self::Numa {
+            return true;
+        }
+        matches!(
+            self,
+            Self::Tty
+                | Self::Cpu
+                | Self::Time
+                | Self::Cwd
+                | Self::Cmd
+                | Self::IoRead
+                | Self::IoWrite
+                | Self::Oom
+                | Self::OomRisk
+                | Self::MinFlt
+                | Self::MajFlt
+                | Self::Sockets
+                | Self::Wchan
+                | Self::MaxRss
+                | Self::MaxCpu
+                | Self::Unit
+                | Self::SchedWait
+        )
+    }
+}
+
+impl Column {
+    fn is_decimal(self) -> bool {
+        matches!(
+            self,
+            Self::Cpu
+                | Self::IoRead
+                | Self::IoWrite
+                | Self::MinFlt
+                | Self::MajFlt
+                | Self::MaxCpu
+                | Self::MemPercent
+                | Self::SchedWait
+                | Self::SwapRatio
+        )
+    }
+
+    fn is_memory(self) -> bool {
+        matches!(
+            self,
+            Self::Rss | Self::Peak | Self::RssAnon | Self::RssFile | Self::RssShmem | Self::MaxRss
+        )
+    }
+
+    /// The figure of a column shown with decimals (CPU usage and the I/O,
+    /// fault and wait rates), `None` for other columns and for unreadable
+    /// I/O or schedstat.
+    fn decimal(self, p: &Process) -> Option<f64> {
+        match self {
+            Self::Cpu => Some(p.cpu_percent),
+            Self::IoRead => p.io.map(|_| p.io_read_rate),
+            Self::IoWrite => p.io.map(|_| p.io_write_rate),
+            Self::MinFlt => Some(p.min_fault_rate),
+            Self::MajFlt => Some(p.maj_fault_rate),
+            Self::MaxCpu => p.usage_range.and_then(|range| range.cpu_max),
+            Self::MemPercent => p.mem_percent,
+            Self::SchedWait => p.sched_wait_ns.map(|_| p.sched_wait_rate),
+            Self::SwapRatio => p.swap_percent(),
+            _ => None,
+        }
+    }
+
+    /// The figure of a memory column in kB, `None` for other columns and for
+    /// a missing RSS breakdown.
+    fn kb(self, p: &Process) -> Option<u64> {
+        let part = |part: fn(&RssBreakdown) -> u64| p.rss_breakdown.as_ref().map(part);
+        match self {
+            Self::Rss => Some(p.rss_kb),
+            Self::Peak => Some(p.vm_hwm),
+            Self::RssAnon => part(|b| b.anon),
+            Self::RssFile => part(|b| b.file),
+            Self::RssShmem => part(|b| b.shmem),
+            Self::MaxRss => p.usage_range.map(|range| range.rss_max),
+            _ => None,
+        }
+    }
+}
+
+/// A memory [`Column`] shown in a fixed `--mem-unit`. JSON keeps the kB
+/// figures so the schema does not depend on the option.
+struct UnitColumn {
+    column: Column,
+    unit: MemUnit,
+    base: Option<UnitBase>,
+    header: String,
+}
+
+impl ProcColumn for UnitColumn {
+    fn name(&self) -> &str {
+        self.column.name()
+    }
+
+    fn header(&self) -> &str {
+        &self.header
+    }
+
+    fn render(&self, p: &Process) -> String {
+        self.column
+            .kb(p)
+            .map_or_else(|| "-".to_string(), |kb| self.unit.format_in(kb, self.base))
+    }
+
+    fn pad(&self, cell: &str) -> String {
+        self.column.pad(cell)
+    }
+
+    fn json(&self, p: &Process) -> Json {
+        self.column.json(p)
+    }
+}
+
+/// An I/O rate [`Column`] scaled like [`MemUnit::Human`] for `--human`.
+/// JSON keeps the kB/s figures.
+struct HumanRateColumn {
+    column: Column,
+}
+
+impl ProcColumn for HumanRateColumn {
+    fn name(&self) -> &str {
+        self.column.name()
+    }
+
+    fn header(&self) -> &str {
+        match self.column {
+            Column::IoRead => "READ/s",
+            _ => "WRITE/s",
+        }
+    }
+
+    fn render(&self, p: &Process) -> String {
+        let rate = match self.column {
+            Column::IoRead => p.io_read_rate,
+            _ => p.io_write_rate,
+        };
+        match p.io {
+            Some(_) => MemUnit::Human.format(rate.round() as u64),
+            None => "-".to_string(),
+        }
+    }
+
+    fn pad(&self, cell: &str) -> String {
+        self.column.pad(cell)
+    }
+
+    fn json(&self, p: &Process) -> Json {
+        self.column.json(p)
+    }
+
+    fn needs_details(&self) -> bool {
+        self.column.needs_details()
+    }
+}
+
+/// Formats a count with a decimal SI suffix, e.g. `1.2k`, `3.4M` or `5.6G`,
+/// with one decimal. Counts below 1000 stay as they are.
+///
+/// Unlike [`MemUnit::Human`], which scales kB by 1024, this is for figures
+/// that are not sizes, such as fault rates and socket counts.
+pub fn format_si(n: u64) -> String {
+    const SUFFIXES: [&str; 6] = ["k", "M", "G", "T", "P", "E"];
+    if n < 1000 {
+        return n.to_string();
+    }
+    let mut value = n as f64 / 1000.0;
+    let mut suffix = 0;
+    // Move up when rounding to one decimal would show 1000.0.
+    while (value * 10.0).round() >= 10_000.0 && suffix + 1 < SUFFIXES.len() {
+        value /= 1000.0;
+        suffix += 1;
+    }
+    format!("{value:.1}{}", SUFFIXES[suffix])
+}
+
+/// A count [`Column`] (the fault rates and sockets) shown with
+/// [`format_si`] for `--compact-numbers`. JSON keeps the exact figures.
+struct CompactColumn {
+    column: Column,
+}
+
+impl ProcColumn for CompactColumn {
+    fn name(&self) -> &str {
+        self.column.name()
+    }
+
+    fn header(&self) -> &str {
+        self.column.header()
+    }
+
+    fn render(&self, p: &Process) -> String {
+        let count = match self.column {
+            Column::Sockets => p.sockets.map(|n| n as u64),
+            column => column.decimal(p).map(|rate| rate.round() as u64),
+        };
+        count.map_or_else(|| "-".to_string(), format_si)
+    }
+
+    fn pad(&self, cell: &str) -> String {
+        self.column.pad(cell)
+    }
+
+    fn json(&self, p: &Process) -> Json {
+        self.column.json(p)
+    }
+
+    fn needs_details(&self) -> bool {
+        self.column.needs_details()
+    }
+}
+
+/// The `cmd` column cut to `--cmd-width`, see [`truncate_cmdline`].
+struct CmdColumn {
+    width: usize,
+}
+
+impl ProcColumn for CmdColumn {
+    fn name(&self) -> &str {
+        Column::Cmd.name()
+    }
+
+    fn header(&self) -> &str {
+        Column::Cmd.header()
+    }
+
+    fn render(&self, p: &Process) -> String {
+        truncate_cmdline(&Column::Cmd.render(p), self.width)
+    }
+
+    fn pad(&self, cell: &str) -> String {
+        Column::Cmd.pad(cell)
+    }
+
+    fn json(&self, p: &Process) -> Json {
+        Column::Cmd.json(p)
+    }
+
+    fn needs_details(&self) -> bool {
+        true
+    }
+}
+
+/// The `name` column cut to `--max-name-width` instead of 20 characters.
+struct NameColumn {
+    width: usize,
+}
+
+impl ProcColumn for NameColumn {
+    fn name(&self) -> &str {
+        Column::Name.name()
+    }
+
+    fn header(&self) -> &str {
+        Column::Name.header()
+    }
+
+    fn render(&self, p: &Process) -> String {
+        Column::Name.render(p)
+    }
+
+    fn pad(&self, cell: &str) -> String {
+        format!(
+            "{:<width$}",
+            truncate_name(cell, self.width),
+            width = self.width
+        )
+    }
+
+    fn json(&self, p: &Process) -> Json {
+        Column::Name.json(p)
+    }
+}
+
+/// Renders a [`Column::decimal`] figure, `-` when there is none.
+fn decimal_cell(value: Option<f64>, decimals: usize) -> String {
+    value.map_or_else(|| "-".to_string(), |v| format!("{v:.decimals$}"))
+}
+
+/// A [`Column::decimal`] figure rounded to `decimals` places, `null` when
+/// there is none.
+fn decimal_json(value: Option<f64>, decimals: usize) -> Json {
+    let scale = 10f64.powi(decimals as i32);
+    value.map_or(Json::Null, |v| Json::Float((v * scale).round() / scale))
+}
+
+/// A decimal [`Column`] with `--precision` decimals instead of one.
+struct PrecisionColumn {
+    column: Column,
+    decimals: usize,
+}
+
+impl ProcColumn for PrecisionColumn {
+    fn name(&self) -> &str {
+        self.column.name()
+    }
+
+    fn header(&self) -> &str {
+        self.column.header()
+    }
+
+    fn render(&self, p: &Process) -> String {
+        decimal_cell(self.column.decimal(p), self.decimals)
+    }
+
+    fn pad(&self, cell: &str) -> String {
+        self.column.pad(cell)
+    }
+
+    fn json(&self, p: &Process) -> Json {
+        decimal_json(self.column.decimal(p), self.decimals)
+    }
+
+    fn needs_details(&self) -> bool {
+        self.column.needs_details()
+    }
+}
+
+/// What a `--threads` row is: the `main` thread of its process, another
+/// `thread`, or a `kernel` thread; `-` for a process row.
+fn task_kind(p: &Process) -> &'static str {
+    match p.tgid {
+        None => "-",
+        Some(_) if p.is_kernel_thread => "kernel",
+        Some(tgid) if tgid == p.pid => "main",
+        Some(_) => "thread",
+    }
+}
+
+/// The `rss` column in memory pages for `--rss-unit pages`. JSON keeps kB.
+struct PagesColumn {
+    page_size: u64,
+}
+
+impl ProcColumn for PagesColumn {
+    fn name(&self) -> &str {
+        Column::Rss.name()
+    }
+
+    fn header(&self) -> &str {
+        "RSS(pages)"
+    }
+
+    fn render(&self, p: &Process) -> String {
+        kb_to_pages(p.rss_kb, self.page_size).to_string()
+    }
+
+    fn pad(&self, cell: &str) -> String {
+        Column::Rss.pad(cell)
+    }
+
+    fn json(&self, p: &Process) -> Json {
+        Column::Rss.json(p)
+    }
+}
+
+/// The `pid` column headed `TID` for `--threads`.
+struct TidColumn;
+
+impl ProcColumn for TidColumn {
+    fn name(&self) -> &str {
+        Column::Pid.name()
+    }
+
+    fn header(&self) -> &str {
+        "TID"
+    }
+
+    fn render(&self, p: &Process) -> String {
+        Column::Pid.render(p)
+    }
+
+    fn pad(&self, cell: &str) -> String {
+        Column::Pid.pad(cell)
+    }
+
+    fn json(&self, p: &Process) -> Json {
+        Column::Pid.json(p)
+    }
+}
+
+/// The `pid` column padded to at least `width` characters, so that pids
+/// longer than the usual six digits keep the table aligned.
+struct WidePidColumn<'a> {
+    column: &'a dyn ProcColumn,
+    width: usize,
+}
+
+impl ProcColumn for WidePidColumn<'_> {
+    fn name(&self) -> &str {
+        self.column.name()
+    }
+
+    fn header(&self) -> &str {
+        self.column.header()
+    }
+
+    fn render(&self, p: &Process) -> String {
+        self.column.render(p)
+    }
+
+    fn pad(&self, cell: &str) -> String {
+        format!("{:<width$}", self.column.pad(cell), width = self.width)
+    }
+
+    fn json(&self, p: &Process) -> Json {
+        self.column.json(p)
+    }
+
+    fn needs_details(&self) -> bool {
+        self.column.needs_details()
+    }
+}
+
+/// Width of the `pid` column for `procs`: the digits of the largest pid,
+/// but at least the usual six.
+fn pid_width(procs: &[Process]) -> usize {
+    procs
+        .iter()
+        .map(|p| p.pid.to_string().len())
+        .max()
+        .unwrap_or(0)
+        .max(6)
+}
+
+/// Renders one part of the RSS breakdown, `-` when the kernel has none.
+fn rss_part(p: &Process, part: impl Fn(&RssBreakdown) -> u64) -> String {
+    p.rss_breakdown
+        .as_ref()
+        .map_or_else(|| "-".to_string(), |b| part(b).to_string())
+}
+
+/// Columns available by name, in registration order.
+pub struct ColumnRegistry {
+    columns: Vec<Box<dyn ProcColumn>>,
+}
+
+impl ColumnRegistry {
+    /// A registry without any columns.
+    pub fn empty() -> Self {
+        Self {
+            columns: Vec::new(),
+        }
+    }
+
+    /// Adds `column`, replacing a registered column of the same name in
+    /// place.
+    pub fn register(&mut self, column: Box<dyn ProcColumn>) {
+        match self.columns.iter().position(|c| c.name() == column.name()) {
+            Some(i) => self.columns[i] = column,
+            None => self.columns.push(column),
+        }
+    }
+
+    /// The built-in columns as configured by `options`: memory figures in
+    /// `--mem-unit`, CPU usage and rates with `--precision` decimals (I/O
+    /// rates scaled instead with `--human`, fault rates and socket counts
+    /// with `--compact-numbers`), the RSS in pages with `--rss-unit pages`,
+    /// the pid headed `TID` with `--threads`, the name cut to
+    /// `--max-name-width` and the command line cut to `--cmd-width`.
+    pub fn for_options(options: &Options) -> Self {
+        let mut registry = Self::default();
+        let unit = options.mem_unit;
+        if options.threads {
+            registry.register(Box::new(TidColumn));
+        }
+        if options.rss_unit == RssUnit::Pages {
+            registry.register(Box::new(PagesColumn {
+                page_size: page_size(),
+            }));
+        }
+        if let Some(width) = options.max_name_width {
+            registry.register(Box::new(NameColumn { width }));
+        }
+        if let Some(width) = options.cmd_width {
+            registry.register(Box::new(CmdColumn { width }));
+        }
+        if unit != MemUnit::Kb || options.unit_base.is_some() {
+            let base = options.unit_base;
+            let suffix = match unit.suffix_in(base) {
+                "" => String::new(),
+                suffix => format!("({suffix})"),
+            };
+            for column in Column::ALL.iter().copied().filter(|c| c.is_memory()) {
+                let header = column.header().replace("(kB)", &suffix);
+                registry.register(Box::new(UnitColumn {
+                    column,
+                    unit,
+                    base,
+                    header,
+                }));
+            }
+        }
+        if let Some(decimals) = options.precision {
+            for column in Column::ALL.iter().copied().filter(|c| c.is_decimal()) {
+                registry.register(Box::new(PrecisionColumn { column, decimals }));
+            }
+        }
+        if options.human() {
+            for column in [Column::IoRead, Column::IoWrite] {
+                registry.register(Box::new(HumanRateColumn { column }));
+            }
+        }
+        if options.compact_numbers {
+            for column in [Column::MinFlt, Column::MajFlt, Column::Sockets] {
+                registry.register(Box::new(CompactColumn { column }));
+            }
+        }
+        registry
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn ProcColumn> {
+        self.columns
+            .iter()
+            .find(|c| c.name() == name)
+            .map(|c| c.as_ref())
+    }
+
+    /// Names of the registered columns, in registration order.
+    pub fn names(&self) -> Vec<&str> {
+        self.columns.iter().map(|c| c.name()).collect()
+    }
+}
+
+/// The built-in [`Column`]s.
+impl Default for ColumnRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        for &column in Column::ALL {
+            registry.register(Box::new(column));
+        }
+        registry
+    }
+}
+
+/// Formats the top N processes as a table with the built-in columns, see
+/// [`ColumnRegistry::for_options`] and [`format_top_processes_with`].
+pub fn format_top_processes(procs: &[Process], n: usize, options: &Options, color: bool) -> String {
+    let registry = ColumnRegistry::for_options(options);
+    format_top_processes_with(&registry, procs, n, options, color)
+}
+
+/// Formats the top N processes as a table, one row per line, looking up the
+/// columns chosen by `options` in `registry`.
+///
+/// With a field separator, columns are joined by that character without any
+/// alignment padding, which keeps the output easy to split in scripts.
+/// `--quiet` leaves out the header row.
+/// When `color` is enabled, rows matching `--highlight` are rendered in bold
+/// reverse video, and the header row in reverse video with
+/// `--highlight-header`. Column names missing from `registry` are skipped.
+pub fn format_top_processes_with(
+    registry: &ColumnRegistry,
+    procs: &[Process],
+    n: usize,
+    options: &Options,
+    color: bool,
+) -> String {
+    let names = options.column_names();
+    let shown = &procs[..n.min(procs.len())];
+    let pid = registry
+        .get(Column::Pid.name())
+        .map(|column| WidePidColumn {
+            column,
+            width: pid_width(shown),
+        });
+    let columns: Vec<&dyn ProcColumn> = names
+        .iter()
+        .filter_map(|n| match &pid {
+            Some(pid) if n == Column::Pid.name() => Some(pid as &dyn ProcColumn),
+            _ => registry.get(n),
+        })
+        .collect();
+    let mut out = String::new();
+    if !options.quiet {
+        let header: Vec<String> = columns.iter().map(|c| c.header().to_string()).collect();
+        let header = format_row(&columns, &header, options.field_separator);
+        if color && options.highlight_header {
+            out.push_str(&format!("{REVERSE}{header}{RESET}"));
+        } else {
+            out.push_str(&header);
+        }
+        out.push('\n');
+    }
+    let mem = columns
+        .iter()
+        .position(|c| c.name() == Column::MemPercent.name());
+    for p in shown {
+        let cells: Vec<String> = columns.iter().map(|c| c.render(p)).collect();
+        let highlighted = options
+            .highlight
+            .as_deref()
+            .is_some_and(|pattern| matches_pattern(&p.name, pattern));
+        if color && highlighted {
+            let row = format_row(&columns, &cells, options.field_separator);
+            out.push_str(&format!("{HIGHLIGHT}{row}{RESET}"));
+        } else {
+            let mut cells = pad_cells(&columns, cells, options.field_separator);
+            if color
+                && let Some(i) = mem
+                && let Some(role) = p.mem_percent.and_then(mem_cell_color)
+            {
+                cells[i] = options.color_scheme.paint(role, &cells[i]);
+            }
+            out.push_str(&join_cells(&cells, options.field_separator));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// The color of a `%MEM` cell: none for the many small processes, warn from
+/// 10% and critical from 25% of the memory, so the few large ones stand
+/// out.
+pub fn mem_cell_color(percent: f64) -> Option<ColorRole> {
+    match percent {
+        p if p >= 25.0 => Some(ColorRole::Critical),
+        p if p >= 10.0 => Some(ColorRole::Warn),
+        _ => None,
+    }
+}
+
+/// Formats a single table row, without the trailing newline.
+pub(crate) fn format_row(
+    columns: &[&dyn ProcColumn],
+    cells: &[String],
+    separator: Option<char>,
+) -> String {
+    join_cells(&pad_cells(columns, cells.to_vec(), separator), separator)
+}
+
+/// Pads each cell to its column's width, unless there is a separator.
+fn pad_cells(
+    columns: &[&dyn ProcColumn],
+    cells: Vec<String>,
+    separator: Option<char>,
+) -> Vec<String> {
+    match separator {
+        Some(_) => cells,
+        None => columns
+            .iter()
+            .zip(&cells)
+            .map(|(column, cell)| column.pad(cell))
+            .collect(),
+    }
+}
+
+/// Joins row cells by the separator, or by a space between padded cells.
+fn join_cells(cells: &[String], separator: Option<char>) -> String {
+    cells.join(&separator.unwrap_or(' ').to_string())
+}
+
+/// Characters that draw the `--format ascii-table` borders.
+struct Borders {
+    horizontal: char,
+    vertical: char,
+    /// Left, inner and right corners of the top, middle and bottom rules.
+    top: [char; 3],
+    middle: [char; 3],
+    bottom: [char; 3],
+}
+
+const BOX_BORDERS: Borders = Borders {
+    horizontal: '─',
+    vertical: '│',
+    top: ['┌', '┬', '┐'],
+    middle: ['├', '┼', '┤'],
+    bottom: ['└', '┴', '┘'],
+};
+
+/// [`BOX_BORDERS`] for `--ascii-only`.
+const ASCII_BORDERS: Borders = Borders {
+    horizontal: '-',
+    vertical: '|',
+    top: ['+'; 3],
+    middle: ['+'; 3],
+    bottom: ['+'; 3],
+};
+
+/// Formats the top N processes for `--format ascii-table`: the columns of
+/// [`format_top_processes_with`] inside box-drawing borders, or `+-|` with
+/// `--ascii-only`.
+///
+/// Each column is as wide as its widest cell and keeps the alignment of the
+/// plain table. When the table would be wider than `max_width`, the widest
+/// columns are narrowed down to their header and their cells cut with
+/// [`truncate_name`], except the `cmd` column with `--no-truncate-cmd`.
+pub fn format_bordered_table(
+    registry: &ColumnRegistry,
+    procs: &[Process],
+    n: usize,
+    options: &Options,
+    color: bool,
+    max_width: Option<usize>,
+) -> String {
+    let borders = if options.ascii_only {
+        &ASCII_BORDERS
+    } else {
+        &BOX_BORDERS
+    };
+    let names = options.column_names();
+    let columns: Vec<&dyn ProcColumn> = names.iter().filter_map(|n| registry.get(n)).collect();
+    let headers: Vec<String> = columns.iter().map(|c| c.header().to_string()).collect();
+    let rows: Vec<(&Process, Vec<String>)> = procs
+        .iter()
+        .take(n)
+        .map(|p| {
+            let cells = columns
+                .iter()
+                .map(|c| c.pad(&c.render(p)).trim().to_string())
+                .collect();
+            (p, cells)
+        })
+        .collect();
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for (_, cells) in &rows {
+        for (width, cell) in widths.iter_mut().zip(cells) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    if let Some(max_width) = max_width {
+        // Each column takes its width plus a space on either side and a border.
+        let mut total = widths.iter().sum::<usize>() + 3 * widths.len() + 1;
+        while total > max_width {
+            let Some((widest, _)) = widths
+                .iter()
+                .enumerate()
+                .filter(|&(i, &width)| width > headers[i].chars().count())
+                .filter(|&(i, _)| !(options.no_truncate_cmd && columns[i].name() == "cmd"))
+                .max_by_key(|&(_, &width)| width)
+            else {
+                break;
+            };
+            widths[widest] -= 1;
+            total -= 1;
+        }
+    }
+    // Right-aligned columns pad on the left.
+    let right: Vec<bool> = columns
+        .iter()
+        .map(|c| c.pad("x").starts_with(' '))
+        .collect();
+    let line = |cells: &[String]| {
+        let mut out = String::from(borders.vertical);
+        for ((cell, &width), &right) in cells.iter().zip(&widths).zip(&right) {
+            let cell = truncate_name(cell, width);
+            if right {
+                out.push_str(&format!(" {cell:>width$} {}", borders.vertical));
+            } else {
+                out.push_str(&format!(" {cell:<width$} {}", borders.vertical));
+            }
+        }
+        out
+    };
+    let rule = |[left, inner, right]: [char; 3]| {
+        let segments: Vec<String> = widths
+            .iter()
+            .map(|&width| borders.horizontal.to_string().repeat(width + 2))
+            .collect();
+        format!("{left}{}{right}\n", segments.join(&inner.to_string()))
+    };
+
+    let mut out = rule(borders.top);
+    if !options.quiet {
+        if color && options.highlight_header {
+            out.push_str(&format!("{REVERSE}{}{RESET}", line(&headers)));
+        } else {
+            out.push_str(&line(&headers));
+        }
+        out.push('\n');
+        out.push_str(&rule(borders.middle));
+    }
+    for (p, cells) in &rows {
+        let row = line(cells);
+        let highlighted = options
+            .highlight
+            .as_deref()
+            .is_some_and(|pattern| matches_pattern(&p.name, pattern));
+        if color && highlighted {
+            out.push_str(&format!("{HIGHLIGHT}{row}{RESET}"));
+        } else {
+            out.push_str(&row);
+        }
+        out.push('\n');
+    }
+    out.push_str(&rule(borders.bottom));
+    out
+}
+
+/// Output format selected with `--format`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The aligned text table.
+    #[default]
+    Table,
+    /// The table inside borders, see [`format_bordered_table`].
+    AsciiTable,
+    /// One JSON object per frame, see [`snapshot_json`].
+    Json,
+    /// Like `Json`, with the processes keyed by pid instead of in an array.
+    JsonMap,
+}
+
+impl OutputFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "table" => Ok(Self::Table),
+            "json" => Ok(Self::Json),
+            "json-map" => Ok(Self::JsonMap),
+            "ascii-table" => Ok(Self::AsciiTable),
+            other => Err(format!(
+                "unknown output format: {other} (expected table, ascii-table, json or json-map)"
+            )),
+        }
+    }
+
+    /// Whether frames are written as JSON.
+    pub fn is_json(self) -> bool {
+        matches!(self, Self::Json | Self::JsonMap)
+    }
+}
+
+/// Version of the JSON frame layout, the leading `schema_version` field of
+/// every frame. Bumped whenever a field is removed or changes meaning;
+/// added fields keep the version.
+pub const JSON_SCHEMA_VERSION: u64 = 1;
+
+/// Builds the `--format json` object for one frame.
+///
+/// The object starts with [`JSON_SCHEMA_VERSION`] as `schema_version`.
+/// `memory` holds the meminfo figures in kB and is left out with
+/// `--processes-only`; `processes` lists the top `n` processes with one key
+/// per displayed column (named as for `--columns`) and is left out with
+/// `--memory-only`. Numbers are JSON numbers, and values that could not be
+/// read are `null`. Decimal figures are rounded to `--precision` places.
+///
+/// With `--format json-map` there is no `processes` array; each process is
+/// a top-level field named by its pid instead, next to `memory`.
+pub fn snapshot_json(
+    meminfo: Option<&MemInfo>,
+    procs: &[Process],
+    n: usize,
+    options: &Options,
+) -> Json {
+    let mut fields = vec![(
+        "schema_version".to_string(),
+        Json::UInt(JSON_SCHEMA_VERSION),
+    )];
+    if let Some(m) = meminfo {
+        let memory = Json::object([
+            ("total_kb", Json::UInt(m.total)),
+            ("available_kb", Json::UInt(m.available)),
+            ("free_kb", Json::UInt(m.free)),
+            ("buffers_kb", Json::UInt(m.buffers)),
+            ("cached_kb", Json::UInt(m.cached)),
+            ("used_kb", Json::UInt(m.used_by(options.mem_mode))),
+        ]);
+        fields.push(("memory".to_string(), memory));
+    }
+    if !options.memory_only {
+        // Only `--precision` changes the JSON of a column; the other options
+        // keep the raw figures.
+        let registry = ColumnRegistry::for_options(options);
+        let names = options.column_names();
+        let columns: Vec<&dyn ProcColumn> = names.iter().filter_map(|n| registry.get(n)).collect();
+        let rows = procs.iter().take(n).map(|p| {
+            (
+                p,
+                Json::object(columns.iter().map(|c| (c.name(), c.json(p)))),
+            )
+        });
+        if options.format == OutputFormat::JsonMap {
+            // Object keys are strings, so the pid is too.
+            fields.extend(rows.map(|(p, row)| (p.pid.to_string(), row)));
+        } else {
+            let rows = rows.map(|(_, row)| row).collect();
+            fields.push(("processes".to_string(), Json::Array(rows)));
+        }
+    }
+    Json::Object(fields)
+}
+
+/// Formats the per-cycle header of `--batch` mode.
+///
+/// The first line follows the layout of `top -b`
+/// (`top - <time> up <uptime>,  load average: <1>, <5>, <15>`) so existing
+/// log parsers can pick it up; the time is printed in `--time-format`, a UTC
+/// date and time by default, and the load averages with two decimals unless
+/// `--precision` is given. The second line is the usual memory summary, see
+/// [`MemInfo::summary_fields`], unless `meminfo` is `None`
+/// (`--processes-only`).
+pub fn format_batch_header(
+    now: SystemTime,
+    uptime_secs: f64,
+    load: &LoadAvg,
+    meminfo: Option<&MemInfo>,
+    options: &Options,
+) -> String {
+    let decimals = options.precision.unwrap_or(2);
+    let mut out = format!(
+        "top - {} up {},  load average: {:.decimals$}, {:.decimals$}, {:.decimals$}\n",
+        options.time_format.unwrap_or_default().format(now),
+        format_uptime(uptime_secs),
+        load.one,
+        load.five,
+        load.fifteen,
+    );
+    if let Some(meminfo) = meminfo {
+        out.push_str(&meminfo.summary_fields_in(
+            options.mem_fields(),
+            options.mem_mode,
+            options.mem_unit,
+            options.unit_base,
+        ));
+        out.push('\n');
+    }
+    out
+}
+
+/// Formats the `--summary-only` block of system-wide figures: used memory
+/// and swap in `--mem-unit` like the usual header, the load averages, the
+/// CPU busy percentage `cpu_busy` and the task counts, with their change
+/// since a previous frame that counted `previous_tasks` processes.
+pub fn format_summary(
+    meminfo: &MemInfo,
+    load: &LoadAvg,
+    cpu_busy: f64,
+    tasks: &StateSummary,
+    previous_tasks: Option<usize>,
+    options: &Options,
+) -> String {
+    let memory = meminfo.summary_fields_in(
+        &[MemField::Total, MemField::Used, MemField::Swap],
+        options.mem_mode,
+        options.mem_unit,
+        options.unit_base,
+    );
+    let load_decimals = options.precision.unwrap_or(2);
+    let decimals = options.precision();
+    format!(
+        "{memory}\n\
+         Load average: {:.load_decimals$}, {:.load_decimals$}, {:.load_decimals$}\n\
+         CPU: {cpu_busy:.decimals$}% busy\n\
+         {}\n",
+        load.one,
+        load.five,
+        load.fifteen,
+        tasks.format_with_change(previous_tasks),
+    )
+}
+
+/// Formats the `--mem-detail` block listing the individual meminfo figures.
+pub fn format_mem_detail(meminfo: &MemInfo) -> String {
+    let mut out = format!(
+        "  MemTotal:     {:>12} kB\n  MemAvailable: {:>12} kB\n  MemFree:      {:>12} kB\n  \
+         Buffers:      {:>12} kB\n  Cached:       {:>12} kB\n  Used:         {:>12} kB\n  \
+         Used(strict): {:>12} kB\n",
+        meminfo.total,
+        meminfo.available,
+        meminfo.free,
+        meminfo.buffers,
+        meminfo.cached,
+        meminfo.used(),
+        meminfo.used_strict()
+    );
+    if meminfo.hugepages_total > 0 {
+        out.push_str(&format!(
+            "  HugePages:    total={} free={} used={} size={}kB\n",
+            meminfo.hugepages_total,
+            meminfo.hugepages_free,
+            meminfo.hugepages_used(),
+            meminfo.hugepage_size_kb
+        ));
+    }
+    out
+}
+
+/// Formats the `--cpu-detail` block: CPU time since boot per category, in
+/// seconds and as a share of all accounted time, with `decimals` decimals.
+///
+/// The counters are summed over all CPUs, so the seconds add up to uptime
+/// times the number of CPUs.
+pub fn format_cpu_detail(cpu: &CpuStat, clk_tck: u64, decimals: usize) -> String {
+    let total = cpu.total().max(1) as f64;
+    let clk_tck = clk_tck.max(1) as f64;
+    let categories = [
+        ("user", cpu.user),
+        ("nice", cpu.nice),
+        ("system", cpu.system),
+        ("idle", cpu.idle),
+        ("iowait", cpu.iowait),
+        ("irq", cpu.irq),
+        ("softirq", cpu.softirq),
+        ("steal", cpu.steal),
+    ];
+    let mut out = String::from("  CPU time since boot:\n");
+    for (name, jiffies) in categories {
+        out.push_str(&format!(
+            "  {:<8} {:>12.2} s {:>width$.decimals$}%\n",
+            format!("{name}:"),
+            jiffies as f64 / clk_tck,
+            jiffies as f64 * 100.0 / total,
+            width = decimals + 4,
+        ));
+    }
+    out
+}
+
+/// Formats the `--show-cpu-share` line: the CPU usage summed over the
+/// `shown` rows next to the system-wide `busy` percent, e.g.
+/// `top 5 = 78% of 85% busy`.
+///
+/// Per-process usage counts one CPU as 100%, so the sum is divided by
+/// `cpus` to be comparable with `busy`.
+pub fn format_cpu_share(shown: &[Process], busy: f64, cpus: u64, decimals: usize) -> String {
+    let share = shown.iter().map(|p| p.cpu_percent).sum::<f64>() / cpus.max(1) as f64;
+    format!(
+        "top {} = {share:.decimals$}% of {busy:.decimals$}% busy",
+        shown.len()
+    )
+}
+
+/// Formats the `--explain-mem` note on MemFree versus MemAvailable.
+pub fn format_mem_explanation(meminfo: &MemInfo) -> String {
+    format!(
+        "  Note: MemFree ({} kB) is memory nothing uses at all. MemAvailable ({} kB)\n  \
+         \x20     also counts page cache and other memory the kernel can reclaim, so it\n  \
+         \x20     is the better estimate of what new programs can still get.\n",
+        meminfo.free, meminfo.available
+    )
+}
+
+/// What the column named `name` (as for `--columns`) shows, for
+/// `--explain`.
+pub fn column_explanation(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "pid" => "process id",
+        "name" => "program name, from /proc/<pid>/comm unless --name-source says otherwise",
+        "rss" => "VmRSS: resident physical memory, the RAM the process occupies now",
+        "peak" => "VmHWM: the highest RSS the process ever had",
+        "tty" => "controlling terminal, ? for none",
+        "cpu" => "share of one CPU used since the previous refresh",
+        "time" => "CPU time used since the process started (user + system)",
+        "cwd" => "current working directory",
+        "anon" => "RssAnon: private memory such as heap and stack",
+        "file" => "RssFile: mapped files, shared with the page cache",
+        "shmem" => "RssShmem: shared memory",
+        "cmd" => "the command line, [name] for kernel threads",
+        "read" => "data read from storage per second",
+        "write" => "data written to storage per second",
+        "oom" => "oom_score: the process with the highest is killed first when memory runs out",
+        "risk" => "the OOM score as HIGH, MED or LOW",
+        "minflt" => "minor page faults per second, served without the disk",
+        "majflt" => "major page faults per second, which had to read from disk",
+        "tgid" => "thread group id: the process a thread belongs to",
+        "task" => "main thread, other thread or kernel thread",
+        "sockets" => "open sockets among the file descriptors",
+        "nspid" => "the pid inside the process's PID namespace",
+        "wchan" => "kernel function a sleeping process waits in, - when running",
+        "maxrss" => "the highest RSS seen since monitoring started",
+        "maxcpu" => "the highest CPU usage seen since monitoring started",
+        "unit" => "the systemd unit the process runs in, - when none",
+        "mem" => "RSS as a share of the total memory",
+        "schedwait" => "time spent waiting for a CPU per second; high values mean CPU starvation",
+        "swapratio" => {
+            "VmSwap as a share of VmRSS + VmSwap; high when the process no longer fits in RAM"
+        }
+        "caps" => "CapEff: the effective capabilities, all for a full set as root has",
+        "numa" => "the NUMA node most of the memory is on and its share, - when unknown",
+        _ => return None,
+    })
+}
+
+/// Formats the `--explain` notes: one line per figure of the memory header
+/// and per column of the process table that the options show.
+pub fn format_explanation(options: &Options) -> String {
+    let mut out = String::from("What the figures mean:\n");
+    if !options.processes_only {
+        for field in options.mem_fields() {
+            let note = field.explanation(options.mem_mode);
+            out.push_str(&format!("  {:<12} {note}\n", field.name()));
+        }
+    }
+    if !options.memory_only
+        && !options.by_user
+        && !options.histogram
+        && options.subtree_mem.is_none()
+    {
+        let registry = ColumnRegistry::for_options(options);
+        for name in options.column_names() {
+            if let (Some(column), Some(note)) = (registry.get(&name), column_explanation(&name)) {
+                out.push_str(&format!("  {:<12} {note}\n", column.header()));
+            }
+        }
+    }
+    out.push('\n');
+    out
+}
+
+/// The heartbeat characters, one per frame.
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+/// The heartbeat shown after the memory summary of frame `frame`
+/// (counting from 0), so that a slow refresh does not look frozen.
+pub fn heartbeat(frame: u64) -> char {
+    SPINNER[(frame % SPINNER.len() as u64) as usize]
+}
+
+/// ANSI "reset to initial state", used by `--clear-mode full` to clear the
+/// screen between frames.
+pub const CLEAR: &str = "\u{001b}c";
+/// ANSI cursor home and erase to the end of the screen, the default way to
+/// clear between frames.
+pub const CLEAR_HOME: &str = "\u{001b}[H\u{001b}[J";
+/// ANSI bold + reverse video, used for highlighted rows.
+pub const HIGHLIGHT: &str = "\u{001b}[1;7m";
+/// ANSI reverse video, used for the header lines with `--highlight-header`.
+pub const REVERSE: &str = "\u{001b}[7m";
+/// ANSI attribute reset.
+pub const RESET: &str = "\u{001b}[0m";
+
+/// Removes ANSI escape sequences from `text`: control sequences such as
+/// [`HIGHLIGHT`] (`ESC [ ... letter`) and two-character ones such as
+/// [`CLEAR`].
+pub fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\u{001b}' {
+            out.push(c);
+            continue;
+        }
+        if chars.next() == Some('[') {
+            // Parameters and intermediates up to the final byte.
+            chars.by_ref().find(|c| ('@'..='~').contains(c));
+        }
+    }
+    out
+}
+
+/// Returns `true` if `name` matches `pattern`.
+///
+/// Patterns containing `*` or `?` are globs that must match the whole name;
+/// anything else matches as a plain substring.
+pub fn matches_pattern(name: &str, pattern: &str) -> bool {
+    if pattern.contains(['*', '?']) {
+        let name: Vec<char> = name.chars().collect();
+        let pattern: Vec<char> = pattern.chars().collect();
+        glob_match(&name, &pattern)
+    } else {
+        name.contains(pattern)
+    }
+}
+
+fn glob_match(name: &[char], pattern: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| glob_match(&name[skip..], rest)),
+        Some(('?', rest)) => !name.is_empty() && glob_match(&name[1..], rest),
+        Some((c, rest)) => name.first() == Some(c) && glob_match(&name[1..], rest),
+    }
+}
+
+/// Whether stdout is a terminal, which is what `--color auto` goes by.
+pub fn stdout_is_tty() -> bool {
+    io::stdout().is_terminal()
+}
+
+/// When to emit ANSI colors, attributes and screen clearing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Only when stdout is a terminal.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            other => Err(format!(
+                "unknown color mode: {other} (expected auto, always or never)"
+            )),
+        }
+    }
+
+    /// Resolves the mode against whether stdout is a terminal.
+    pub fn enabled(self, is_tty: bool) -> bool {
+        match self {
+            Self::Auto => is_tty,
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+/// How the screen is cleared before each frame (`--clear-mode`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ClearMode {
+    /// [`CLEAR`], a full terminal reset, which some terminals answer by
+    /// also dropping the scrollback or the colors.
+    Full,
+    /// [`CLEAR_HOME`]: redraw from the top without flicker.
+    #[default]
+    Home,
+    /// Nothing is cleared; frames follow each other separated by a blank
+    /// line.
+    Scroll,
+}
+
+impl ClearMode {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "full" => Ok(Self::Full),
+            "home" => Ok(Self::Home),
+            "scroll" => Ok(Self::Scroll),
+            other => Err(format!(
+                "unknown clear mode: {other} (expected full, home or scroll)"
+            )),
+        }
+    }
+
+    /// What to write before a frame; `first` is the first frame drawn,
+    /// which [`ClearMode::Scroll`] does not separate from anything.
+    pub fn sequence(self, first: bool) -> &'static str {
+        match self {
+            Self::Full => CLEAR,
+            Self::Home => CLEAR_HOME,
+            Self::Scroll if first => "",
+            Self::Scroll => "\n",
+        }
+    }
+}
+
+/// What a colored figure says about the system, looked up in a
+/// [`ColorScheme`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorRole {
+    Ok,
+    Warn,
+    Critical,
+}
+
+impl ColorRole {
+    /// The role of a used memory percentage: warn from 75%, critical from
+    /// 90%.
+    pub fn for_memory(used_percent: f64) -> Self {
+        match used_percent {
+            p if p >= 90.0 => Self::Critical,
+            p if p >= 75.0 => Self::Warn,
+            _ => Self::Ok,
+        }
+    }
+}
+
+/// Palette selected with `--color-scheme`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorScheme {
+    /// The 8 basic terminal colors: green, yellow and bold red.
+    #[default]
+    Default,
+    /// The Solarized accents from the 256-color palette.
+    Solarized,
+    /// No colors, only attributes: nothing, bold, and bold reverse video.
+    Mono,
+}
+
+impl ColorScheme {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "default" => Ok(Self::Default),
+            "solarized" => Ok(Self::Solarized),
+            "mono" => Ok(Self::Mono),
+            other => Err(format!(
+                "unknown color scheme: {other} (expected default, solarized or mono)"
+            )),
+        }
+    }
+
+    /// The ANSI escape that starts text in `role`, empty for none.
+    pub fn code(self, role: ColorRole) -> &'static str {
+        match (self, role) {
+            (Self::Default, ColorRole::Ok) => "\u{001b}[32m",
+            (Self::Default, ColorRole::Warn) => "\u{001b}[33m",
+            (Self::Default, ColorRole::Critical) => "\u{001b}[1;31m",
+            (Self::Solarized, ColorRole::Ok) => "\u{001b}[38;5;64m",
+            (Self::Solarized, ColorRole::Warn) => "\u{001b}[38;5;136m",
+            (Self::Solarized, ColorRole::Critical) => "\u{001b}[1;38;5;160m",
+            (Self::Mono, ColorRole::Ok) => "",
+            (Self::Mono, ColorRole::Warn) => "\u{001b}[1m",
+            (Self::Mono, ColorRole::Critical) => HIGHLIGHT,
+        }
+    }
+
+    /// `text` in the color of `role`, followed by a reset if there was one.
+    pub fn paint(self, role: ColorRole, text: &str) -> String {
+        match self.code(role) {
+            "" => text.to_string(),
+            code => format!("{code}{text}{RESET}"),
+        }
+    }
+}
+
+/// Prints the top N processes using iter().take(n).
+pub fn print_top_processes(procs: &[Process], n: usize, options: &Options, color: bool) {
+    print!("{}", format_top_processes(procs, n, options, color));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assert_golden, process};
+
+    #[test]
+    fn test_format_top_processes_tab_separated() {
+        let procs = vec![process(42, "firefox", 524288), process(7, "sshd", 4096)];
+        let options = Options::parse(["--field-separator".to_string(), "\\t".to_string()]).unwrap();
+        let out = format_top_processes(&procs, 5, &options, false);
+        assert_eq!(
+            out,
+            "PID\tNAME\tRSS(kB)\n42\tfirefox\t524288\n7\tsshd\t4096\n"
+        );
+    }
+
+    #[test]
+    fn test_format_top_processes_golden() {
+        let mut procs = vec![
+            process(4242, "firefox", 1_048_576),
+            process(1337, "gnome-shell", 262_144),
+            process(2, "kthreadd", 0),
+            process(88, "ünïcödé-daemon", 512),
+        ];
+        procs[0].vm_hwm = 1_310_720;
+        procs[0].tty_nr = 34816;
+        procs[0].cpu_percent = 12.345;
+        procs[0].cmdline = Some("/usr/lib/firefox/firefox -contentproc -childID 7".to_string());
+        procs[1].cpu_percent = 3.0;
+        procs[1].cmdline = Some("/usr/bin/gnome-shell".to_string());
+        procs[3].cmdline = Some("/opt/ü/daemon --flag".to_string());
+        let options = Options {
+            columns: Some(
+                ["pid", "name", "rss", "peak", "tty", "cpu", "cmd"]
+                    .map(str::to_string)
+                    .to_vec(),
+            ),
+            cmd_width: Some(24),
+            ..Options::default()
+        };
+        let out = format_top_processes(&procs, 10, &options, false);
+        assert_golden("top_processes.txt", &out);
+    }
+
+    #[test]
+    fn test_format_bordered_table_golden() {
+        let mut procs = vec![
+            process(4242, "firefox", 1_048_576),
+            process(88, "ünïcödé-daemon", 512),
+        ];
+        procs[0].cmdline = Some("/usr/lib/firefox/firefox -contentproc".to_string());
+        procs[1].cmdline = Some("/opt/ü/daemon".to_string());
+        let mut options = Options {
+            format: OutputFormat::AsciiTable,
+            columns: Some(["pid", "name", "rss", "cmd"].map(str::to_string).to_vec()),
+            ..Options::default()
+        };
+        let registry = ColumnRegistry::for_options(&options);
+        let mut out = format_bordered_table(&registry, &procs, 10, &options, false, None);
+        out.push_str(&format_bordered_table(
+            &registry,
+            &procs,
+            10,
+            &options,
+            false,
+            Some(50),
+        ));
+        options.ascii_only = true;
+        out.push_str(&format_bordered_table(
+            &registry, &procs, 1, &options, false, None,
+        ));
+        assert_golden("bordered_table.txt", &out);
+    }
+
+    #[test]
+    fn test_bordered_table_no_truncate_cmd() {
+        let cmdline = "/usr/bin/java -Xmx4g -jar /srv/app/server.jar --config /etc/app.yml";
+        let mut procs = vec![process(4242, "java", 1_048_576)];
+        procs[0].cmdline = Some(cmdline.to_string());
+        let options = Options::parse(
+            [
+                "--format",
+                "ascii-table",
+                "--columns",
+                "pid,name,cmd",
+                "--no-truncate-cmd",
+            ]
+            .map(String::from),
+        )
+        .unwrap();
+        let registry = ColumnRegistry::for_options(&options);
+        let out = format_bordered_table(&registry, &procs, 10, &options, false, Some(40));
+        assert!(out.contains(cmdline), "{out}");
+
+        let args = ["--no-truncate-cmd", "--cmd-width", "20"].map(String::from);
+        assert!(Options::parse(args).is_err());
+    }
+
+    struct DoubleRss;
+
+    impl ProcColumn for DoubleRss {
+        fn name(&self) -> &str {
+            "double-rss"
+        }
+
+        fn header(&self) -> &str {
+            "2xRSS"
+        }
+
+        fn render(&self, p: &Process) -> String {
+            (p.rss_kb * 2).to_string()
+        }
+    }
+
+    #[test]
+    fn test_custom_column_from_registry() {
+        let mut registry = ColumnRegistry::default();
+        registry.register(Box::new(DoubleRss));
+        assert_eq!(registry.names().last(), Some(&"double-rss"));
+
+        let options = Options {
+            columns: Some(vec!["pid".to_string(), "double-rss".to_string()]),
+            ..Options::default()
+        };
+        let out =
+            format_top_processes_with(&registry, &[process(7, "sshd", 21)], 5, &options, false);
+        assert_eq!(out, "PID    2xRSS\n7      42   \n");
+    }
+
+    #[test]
+    fn test_truncate_cmdline() {
+        let cmd = "/usr/bin/python3 -m http.server 8000";
+        assert_eq!(truncate_cmdline(cmd, 40), cmd);
+        assert_eq!(truncate_cmdline(cmd, 24), "/usr/bin/python3 -m htt…");
+        assert_eq!(truncate_cmdline(cmd, 17), "/usr/bin/python3…");
+        // The program alone is wider than the column: keep it whole.
+        assert_eq!(truncate_cmdline(cmd, 8), "/usr/bin/python3 …");
+        assert_eq!(
+            truncate_cmdline("/usr/lib/firefox/firefox", 8),
+            "/usr/lib/firefox/firefox"
+        );
+        assert_eq!(truncate_cmdline("ünïcödé --flag", 9), "ünïcödé …");
+    }
+
+    #[test]
+    fn test_mem_unit_columns() {
+        let options = Options {
+            columns: Some(vec![
+                "pid".to_string(),
+                "rss".to_string(),
+                "anon".to_string(),
+            ]),
+            mem_unit: MemUnit::Mb,
+            field_separator: Some('\t'),
+            ..Options::default()
+        };
+        let out = format_top_processes(&[process(7, "sshd", 1536)], 5, &options, false);
+        assert_eq!(out, "PID\tRSS(MB)\tANON(MB)\n7\t1.50\t-\n");
+    }
+
+    #[test]
+    fn test_precision() {
+        let mut p = process(7, "sshd", 1);
+        p.cpu_percent = 12.3456;
+        let render = |precision: &str| {
+            let args = [
+                "--columns",
+                "pid,cpu",
+                "--field-separator",
+                ",",
+                "--precision",
+            ];
+            let options =
+                Options::parse(args.into_iter().chain([precision]).map(String::from)).unwrap();
+            let json = snapshot_json(None, std::slice::from_ref(&p), 5, &options);
+            (
+                format_top_processes(&[p.clone()], 5, &options, false),
+                json.to_compact(),
+            )
+        };
+        assert_eq!(
+            render("0"),
+            (
+                "PID,%CPU\n7,12\n".to_string(),
+                r#"{"schema_version":1,"processes":[{"pid":7,"cpu":12}]}"#.to_string()
+            )
+        );
+        assert_eq!(
+            render("3"),
+            (
+                "PID,%CPU\n7,12.346\n".to_string(),
+                r#"{"schema_version":1,"processes":[{"pid":7,"cpu":12.346}]}"#.to_string()
+            )
+        );
+        assert!(Options::parse(["--precision", "7"].map(String::from)).is_err());
+    }
+
+    #[test]
+    fn test_pid_column_widens_for_long_pids() {
+        let procs = vec![process(4194301, "worker", 2048), process(7, "sshd", 1024)];
+        let options = Options::default();
+        let out = format_top_processes(&procs, 5, &options, false);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "PID     NAME                    RSS(kB)");
+        assert_eq!(lines[1], "4194301 worker                     2048");
+        assert_eq!(lines[2], "7       sshd                       1024");
+        // Short pids keep the usual width.
+        let out = format_top_processes(&procs[1..], 5, &options, false);
+        assert!(out.starts_with("PID    NAME"), "{out}");
+    }
+
+    #[test]
+    fn test_format_si() {
+        for (n, expected) in [
+            (0, "0"),
+            (999, "999"),
+            (1000, "1.0k"),
+            (1234, "1.2k"),
+            (999_949, "999.9k"),
+            (999_950, "1.0M"),
+            (3_400_000, "3.4M"),
+            (5_600_000_000, "5.6G"),
+            (u64::MAX, "18.4E"),
+        ] {
+            assert_eq!(format_si(n), expected, "{n}");
+        }
+    }
+
+    #[test]
+    fn test_compact_number_columns() {
+        let p = Process {
+            maj_fault_rate: 12.4,
+            min_fault_rate: 48_213.0,
+            sockets: Some(1500),
+            ..process(1, "db", 100)
+        };
+        let args = [
+            "--columns",
+            "pid,minflt,majflt,sockets",
+            "--compact-numbers",
+        ];
+        let options = Options::parse(args.map(String::from)).unwrap();
+        let out = format_top_processes(&[p], 5, &options, false);
+        assert!(out.ends_with("1         48.2k       12  1.5k\n"), "{out}");
+    }
+
+    #[cfg(feature = "extras")]
+    #[test]
+    fn test_human_columns() {
+        let mut p = process(7, "sshd", 1536);
+        p.vm_hwm = 3 << 20;
+        p.io = Some(crate::IoCounters::default());
+        p.io_read_rate = 2048.4;
+        p.io_write_rate = 0.2;
+        let args = [
+            "--columns",
+            "pid,rss,peak,read,write",
+            "--field-separator",
+            ",",
+        ];
+        let raw = Options::parse(args.map(String::from)).unwrap();
+        assert_eq!(
+            format_top_processes(std::slice::from_ref(&p), 5, &raw, false),
+            "PID,RSS(kB),PEAK(kB),READ(kB/s),WRITE(kB/s)\n7,1536,3145728,2048.4,0.2\n"
+        );
+        let human = Options::parse(args.into_iter().chain(["-H"]).map(String::from)).unwrap();
+        assert_eq!(
+            format_top_processes(&[p], 5, &human, false),
+            "PID,RSS,PEAK,READ/s,WRITE/s\n7,1.5M,3.0G,2.0M,0K\n"
+        );
+    }
+
+    #[test]
+    fn test_truncate_name() {
+        let exact = "a".repeat(20);
+        assert_eq!(truncate_name(&exact, 20), exact);
+        assert_eq!(
+            truncate_name(&format!("{exact}b"), 20),
+            format!("{}…", "a".repeat(19))
+        );
+        assert_eq!(truncate_name("🦀🦀🦀 crab-server", 5), "🦀🦀🦀 …");
+        assert_eq!(truncate_name("🦀🦀", 2), "🦀🦀");
+        assert_eq!(
+            Column::Name.pad("very-long-process-name-from-cmdline"),
+            "very-long-process-n…"
+        );
+    }
+
+    #[test]
+    fn test_max_name_width() {
+        let procs = [process(1, "systemd-networkd-wait-online", 10)];
+        let options = Options {
+            columns: Some(["name", "pid"].map(str::to_string).to_vec()),
+            max_name_width: Some(12),
+            ..Options::default()
+        };
+        assert_eq!(
+            format_top_processes(&procs, 5, &options, false),
+            "NAME         PID   \nsystemd-net… 1     \n"
+        );
+    }
+
+    #[test]
+    fn test_color_schemes() {
+        let schemes = [
+            ColorScheme::Default,
+            ColorScheme::Solarized,
+            ColorScheme::Mono,
+        ];
+        let critical: Vec<&str> = schemes
+            .iter()
+            .map(|scheme| scheme.code(ColorRole::Critical))
+            .collect();
+        assert_eq!(
+            critical,
+            ["\u{001b}[1;31m", "\u{001b}[1;38;5;160m", HIGHLIGHT]
+        );
+        for scheme in schemes {
+            let codes =
+                [ColorRole::Ok, ColorRole::Warn, ColorRole::Critical].map(|r| scheme.code(r));
+            assert!(codes[0] != codes[1] && codes[1] != codes[2] && codes[0] != codes[2]);
+        }
+        assert_eq!(ColorScheme::Mono.paint(ColorRole::Ok, "used"), "used");
+        assert_eq!(ColorRole::for_memory(92.0), ColorRole::Critical);
+    }
+
+    #[test]
+    fn test_format_explanation() {
+        let options = Options::parse(
+            ["--explain", "--show-cpu", "--mem-fields", "total,used"].map(String::from),
+        )
+        .unwrap();
+        let text = format_explanation(&options);
+        assert!(
+            text.contains("  RSS(kB)      VmRSS: resident physical memory"),
+            "{text}"
+        );
+        assert!(text.contains("  %CPU         share of one CPU"), "{text}");
+        assert!(
+            text.contains("  used         total - available\n"),
+            "{text}"
+        );
+        assert!(!text.contains("MemFree"), "only displayed figures: {text}");
+        for column in Column::ALL {
+            assert!(
+                column_explanation(column.name()).is_some(),
+                "{}",
+                column.name()
+            );
+        }
+    }
+
+    #[test]
+    fn test_mem_cell_color() {
+        assert_eq!(mem_cell_color(0.4), None);
+        assert_eq!(mem_cell_color(9.9), None);
+        assert_eq!(mem_cell_color(12.0), Some(ColorRole::Warn));
+        assert_eq!(mem_cell_color(25.0), Some(ColorRole::Critical));
+        assert_eq!(mem_cell_color(80.0), Some(ColorRole::Critical));
+
+        let args = ["--columns", "pid,mem"];
+        let options = Options::parse(args.map(String::from)).unwrap();
+        let procs = [
+            Process {
+                mem_percent: Some(30.0),
+                ..process(1, "java", 300)
+            },
+            Process {
+                mem_percent: Some(1.0),
+                ..process(2, "sh", 10)
+            },
+        ];
+        let table = format_top_processes(&procs, 2, &options, true);
+        assert_eq!(
+            table,
+            "PID      %MEM\n1      \u{001b}[1;31m  30.0\u{001b}[0m\n2         1.0\n"
+        );
+        let plain = format_top_processes(&procs, 2, &options, false);
+        assert_eq!(plain, strip_ansi(&table));
+    }
+
+    #[test]
+    fn test_clear_mode_sequence() {
+        assert_eq!(ClearMode::default(), ClearMode::Home);
+        assert_eq!(
+            ClearMode::parse("full").unwrap().sequence(true),
+            "\u{001b}c"
+        );
+        assert_eq!(
+            ClearMode::parse("home").unwrap().sequence(false),
+            "\u{001b}[H\u{001b}[J"
+        );
+        let scroll = ClearMode::parse("scroll").unwrap();
+        assert_eq!((scroll.sequence(true), scroll.sequence(false)), ("", "\n"));
+        assert!(ClearMode::parse("reset").is_err());
+        assert_eq!(strip_ansi(CLEAR_HOME), "");
+    }
+
+    #[test]
+    fn test_strip_ansi() {
+        let painted = format!("{CLEAR}{HIGHLIGHT}nginx{RESET}  \u{001b}[38;5;160m95%{RESET}\n");
+        assert_eq!(strip_ansi(&painted), "nginx  95%\n");
+        assert_eq!(strip_ansi("plain"), "plain");
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("postgres", "gre"));
+        assert!(matches_pattern("postgres", "post*"));
+        assert!(matches_pattern("sshd", "ss?d"));
+        assert!(!matches_pattern("postgres", "*sql"));
+        assert!(!matches_pattern("bash", "zsh"));
+    }
+
+    #[test]
+    fn test_highlight_respects_color() {
+        let procs = vec![process(1, "nginx", 10), process(2, "bash", 5)];
+        let options = Options {
+            highlight: Some("nginx".to_string()),
+            ..Options::default()
+        };
+        let colored = format_top_processes(&procs, 5, &options, true);
+        let nginx_row = colored.lines().nth(1).unwrap();
+        assert!(nginx_row.starts_with(HIGHLIGHT) && nginx_row.ends_with(RESET));
+        assert!(!colored.lines().nth(2).unwrap().contains('\u{001b}'));
+
+        let never = ColorMode::parse("never").unwrap().enabled(true);
+        let plain = format_top_processes(&procs, 5, &options, never);
+        assert!(!plain.contains('\u{001b}'));
+    }
+
+    #[test]
+    fn test_highlight_header() {
+        let procs = [process(1, "nginx", 10)];
+        let options = Options::parse(["--highlight-header"].map(String::from)).unwrap();
+        let colored = format_top_processes(&procs, 5, &options, true);
+        assert_eq!(
+            colored.lines().next().unwrap(),
+            format!("{REVERSE}PID    NAME                    RSS(kB){RESET}")
+        );
+        assert!(!colored.lines().nth(1).unwrap().contains('\u{001b}'));
+        let plain = format_top_processes(&procs, 5, &options, false);
+        assert!(
+            plain.starts_with("PID    NAME                    RSS(kB)\n"),
+            "{plain}"
+        );
+    }
+
+    #[test]
+    fn test_mem_detail_shows_free_and_available() {
+        let meminfo = MemInfo {
+            total: 8000,
+            available: 5000,
+            free: 1000,
+            ..MemInfo::default()
+        };
+        let detail = format_mem_detail(&meminfo);
+        assert!(detail.contains("  MemAvailable:         5000 kB\n"));
+        assert!(detail.contains("  MemFree:              1000 kB\n"));
+        let note = format_mem_explanation(&meminfo);
+        assert!(note.contains("MemFree (1000 kB)"));
+        assert!(note.contains("MemAvailable (5000 kB)"));
+    }
+
+    #[test]
+    fn test_format_cpu_detail() {
+        let cpu = CpuStat {
+            user: 25_000,
+            system: 5_000,
+            idle: 65_000,
+            iowait: 5_000,
+            cpus: 2,
+            ..CpuStat::default()
+        };
+        assert_eq!(
+            format_cpu_detail(&cpu, 100, 1),
+            "  CPU time since boot:\n\
+             \x20 user:          250.00 s  25.0%\n\
+             \x20 nice:            0.00 s   0.0%\n\
+             \x20 system:         50.00 s   5.0%\n\
+             \x20 idle:          650.00 s  65.0%\n\
+             \x20 iowait:         50.00 s   5.0%\n\
+             \x20 irq:             0.00 s   0.0%\n\
+             \x20 softirq:         0.00 s   0.0%\n\
+             \x20 steal:           0.00 s   0.0%\n"
+        );
+    }
+
+    #[test]
+    fn test_format_cpu_share() {
+        let procs: Vec<Process> = [120.0, 30.0, 6.0]
+            .into_iter()
+            .enumerate()
+            .map(|(i, cpu_percent)| Process {
+                cpu_percent,
+                ..process(i as u32 + 1, "worker", 100)
+            })
+            .collect();
+        assert_eq!(
+            format_cpu_share(&procs, 85.0, 2, 0),
+            "top 3 = 78% of 85% busy"
+        );
+        assert_eq!(
+            format_cpu_share(&procs[..1], 85.0, 4, 1),
+            "top 1 = 30.0% of 85.0% busy"
+        );
+    }
+
+    #[test]
+    fn test_snapshot_json_map() {
+        let meminfo = MemInfo {
+            total: 2048,
+            available: 512,
+            ..MemInfo::default()
+        };
+        let options = Options::parse(["--format", "json-map"].map(String::from)).unwrap();
+        let procs = [process(7, "sshd", 4096), process(1234, "nginx", 1024)];
+        let json = snapshot_json(Some(&meminfo), &procs, 5, &options);
+        let Json::Object(fields) = &json else {
+            panic!("not an object: {json:?}");
+        };
+        let keys: Vec<&str> = fields.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, ["schema_version", "memory", "7", "1234"]);
+        let nginx = json.get("1234").unwrap();
+        assert_eq!(nginx.get("pid").and_then(Json::as_u64), Some(1234));
+        assert_eq!(nginx.get("name").and_then(Json::as_str), Some("nginx"));
+        assert_eq!(
+            json.get("memory")
+                .and_then(|m| m.get("used_kb"))
+                .and_then(Json::as_u64),
+            Some(1536)
+        );
+    }
+
+    #[test]
+    fn test_snapshot_json_fields() {
+        let args = ["--format", "json", "--json-fields", "pid,rss", "--show-cpu"];
+        let options = Options::parse(args.map(String::from)).unwrap();
+        let procs = [process(7, "sshd", 4096)];
+        let json = snapshot_json(None, &procs, 5, &options);
+        let Some(Json::Array(rows)) = json.get("processes") else {
+            panic!("no processes array: {json:?}");
+        };
+        let Json::Object(fields) = &rows[0] else {
+            panic!("not an object: {:?}", rows[0]);
+        };
+        let keys: Vec<&str> = fields.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, ["pid", "rss"]);
+
+        let parse = |args: &[&str]| Options::parse(args.iter().map(|a| a.to_string()));
+        assert!(parse(&["--format", "json", "--json-fields", "pid,bogus"]).is_err());
+        assert!(parse(&["--json-fields", "pid"]).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_json_pretty() {
+        let meminfo = MemInfo {
+            total: 2048,
+            available: 512,
+            ..MemInfo::default()
+        };
+        let options = Options::parse(["--json-pretty"].map(String::from)).unwrap();
+        let json = snapshot_json(Some(&meminfo), &[process(7, "sshd", 4096)], 5, &options);
+        assert_eq!(
+            json.get("schema_version").and_then(Json::as_u64),
+            Some(JSON_SCHEMA_VERSION)
+        );
+        assert_eq!(
+            json.to_pretty(),
+            r#"{
+  "schema_version": 1,
+  "memory": {
+    "total_kb": 2048,
+    "available_kb": 512,
+    "free_kb": 0,
+    "buffers_kb": 0,
+    "cached_kb": 0,
+    "used_kb": 1536
+  },
+  "processes": [
+    {
+      "pid": 7,
+      "name": "sshd",
+      "rss": 4096
+    }
+  ]
+}"#
+        );
+    }
+
+    #[test]
+    fn test_format_batch_header() {
+        let load = LoadAvg {
+            one: 0.15,
+            five: 0.1,
+            fifteen: 0.05,
+        };
+        let meminfo = MemInfo {
+            total: 2048,
+            available: 512,
+            ..MemInfo::default()
+        };
+        let now = std::time::UNIX_EPOCH + std::time::Duration::from_secs(86_400 + 3723);
+        assert_eq!(
+            format_batch_header(
+                now,
+                3.0 * 86_400.0 + 7800.0,
+                &load,
+                Some(&meminfo),
+                &Options::default()
+            ),
+            "top - 1970-01-02 01:02:03 UTC up 3 days,  2:10,  load average: 0.15, 0.10, 0.05\n\
+             Memory: total=2048kB free=512kB used=1536kB\n"
+        );
+    }
+}
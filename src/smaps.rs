@@ -0,0 +1,161 @@
+//! Per-process memory map summary from `/proc/<pid>/smaps` (`--maps`).
+
+use std::{fmt::Display, io};
+
+use crate::{parse::parse_field, reader::ProcReader};
+
+/// What a mapping is backed by, judged from its pathname field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapKind {
+    /// `[heap]`
+    Heap,
+    /// `[stack]`, or `[stack:<tid>]` on older kernels.
+    Stack,
+    /// No pathname, or a kernel pseudo-mapping such as `[vdso]`.
+    Anon,
+    /// A mapped file.
+    File,
+}
+
+impl MapKind {
+    /// Classifies a mapping by the pathname field of its header line.
+    pub fn classify(pathname: &str) -> Self {
+        match pathname {
+            "[heap]" => Self::Heap,
+            p if p == "[stack]" || p.starts_with("[stack:") => Self::Stack,
+            "" => Self::Anon,
+            p if p.starts_with('[') => Self::Anon,
+            _ => Self::File,
+        }
+    }
+}
+
+/// RSS of a process summed per [`MapKind`], in kB.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SmapsSummary {
+    pub heap: u64,
+    pub stack: u64,
+    pub anon: u64,
+    pub file: u64,
+}
+
+impl SmapsSummary {
+    /// Parses the content of `/proc/<pid>/smaps`.
+    ///
+    /// Each mapping starts with a header line
+    /// (`address perms offset dev inode [pathname]`) followed by `Key: value`
+    /// lines, of which only `Rss:` is used.
+    pub fn parse_from_str(content: &str) -> Self {
+        let mut summary = Self::default();
+        let mut kind = MapKind::Anon;
+        for line in content.lines() {
+            let Some(first) = line.split_whitespace().next() else {
+                continue;
+            };
+            if !first.ends_with(':') {
+                kind = MapKind::classify(pathname(line));
+            } else if let Some(kb) = parse_field::<u64>(line, "Rss:", 0) {
+                *summary.slot(kind) += kb;
+            }
+        }
+        summary
+    }
+
+    fn slot(&mut self, kind: MapKind) -> &mut u64 {
+        match kind {
+            MapKind::Heap => &mut self.heap,
+            MapKind::Stack => &mut self.stack,
+            MapKind::Anon => &mut self.anon,
+            MapKind::File => &mut self.file,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.heap + self.stack + self.anon + self.file
+    }
+}
+
+/// Returns the pathname field of a mapping header line: everything after the
+/// fifth field, which may itself contain spaces.
+fn pathname(header: &str) -> &str {
+    let mut rest = header;
+    for _ in 0..5 {
+        rest = rest.trim_start();
+        rest = rest
+            .find(char::is_whitespace)
+            .map_or("", |end| &rest[end..]);
+    }
+    rest.trim()
+}
+
+impl Display for SmapsSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "heap:  {:>10} kB", self.heap)?;
+        writeln!(f, "stack: {:>10} kB", self.stack)?;
+        writeln!(f, "anon:  {:>10} kB", self.anon)?;
+        writeln!(f, "file:  {:>10} kB", self.file)?;
+        write!(f, "total: {:>10} kB", self.total())
+    }
+}
+
+/// Reads and summarizes `{base}/{pid}/smaps`.
+///
+/// The smaps of other users' processes are only readable by root, so a
+/// permission error is reported with that hint rather than the bare OS error.
+pub fn read_smaps(reader: &dyn ProcReader, base: &str, pid: u32) -> io::Result<SmapsSummary> {
+    let path = format!("{base}/{pid}/smaps");
+    match reader.read_to_string(&path) {
+        Ok(content) => Ok(SmapsSummary::parse_from_str(&content)),
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => Err(io::Error::new(
+            err.kind(),
+            format!(
+                "cannot read {path}: permission denied (run as root to inspect other users' processes)"
+            ),
+        )),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Err(io::Error::new(
+            err.kind(),
+            format!("no process with pid {pid}"),
+        )),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+55d0c6a00000-55d0c6a21000 rw-p 00000000 00:00 0                          [heap]
+Size:                132 kB
+Rss:                  24 kB
+Pss:                  24 kB
+7f2b1c000000-7f2b1c021000 rw-p 00000000 00:00 0
+Rss:                   8 kB
+7f2b1d000000-7f2b1d1c5000 r-xp 00000000 08:01 1835123                    /usr/lib/my lib.so
+Rss:                 812 kB
+Swap:                  0 kB
+7ffd5a7e0000-7ffd5a801000 rw-p 00000000 00:00 0                          [stack]
+Rss:                  16 kB
+7ffd5a9f2000-7ffd5a9f4000 r-xp 00000000 00:00 0                          [vdso]
+Rss:                   4 kB
+";
+
+    #[test]
+    fn test_parse_smaps() {
+        let summary = SmapsSummary::parse_from_str(SAMPLE);
+        assert_eq!(
+            summary,
+            SmapsSummary {
+                heap: 24,
+                stack: 16,
+                anon: 12,
+                file: 812,
+            }
+        );
+        assert_eq!(summary.total(), 864);
+        assert_eq!(
+            pathname("7f2b1d000000-7f2b1d1c5000 r-xp 00000000 08:01 1835123   /usr/lib/my lib.so"),
+            "/usr/lib/my lib.so"
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! Memory summed over a process and its descendants (`--subtree-mem`).
+
+use std::collections::{HashMap, HashSet};
+
+use crate::Process;
+
+/// RSS of a process tree, as built by [`subtree`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subtree {
+    pub root: u32,
+    /// Name of the root process.
+    pub name: String,
+    pub rss_kb: u64,
+    /// The root and its descendants, each parent before its children.
+    pub pids: Vec<u32>,
+}
+
+/// Sums the RSS of `root` and every process below it, following the parent
+/// pids of `procs`; `None` when `root` is not among them.
+///
+/// Each process is counted once, so a parent chain that loops back on
+/// itself (possible in a snapshot taken while pids were recycled) ends the
+/// walk instead of repeating it.
+pub fn subtree(procs: &[Process], root: u32) -> Option<Subtree> {
+    let top = procs.iter().find(|p| p.pid == root)?;
+    let mut children: HashMap<u32, Vec<&Process>> = HashMap::new();
+    for p in procs {
+        if let Some(ppid) = p.ppid {
+            children.entry(ppid).or_default().push(p);
+        }
+    }
+    let mut seen = HashSet::from([root]);
+    let mut stack = vec![top];
+    let mut tree = Subtree {
+        root,
+        name: top.name.clone(),
+        rss_kb: 0,
+        pids: Vec::new(),
+    };
+    while let Some(p) = stack.pop() {
+        tree.rss_kb += p.rss_kb;
+        tree.pids.push(p.pid);
+        let kids = children.get(&p.pid).map_or(&[][..], Vec::as_slice);
+        // Reversed so that the children are visited in the order of `procs`.
+        for &child in kids.iter().rev() {
+            if seen.insert(child.pid) {
+                stack.push(child);
+            }
+        }
+    }
+    Some(tree)
+}
+
+/// Formats the `--subtree-mem` lines for `tree`, or a note that the root
+/// process `root` is not running.
+pub fn format_subtree(tree: Option<&Subtree>, root: u32) -> String {
+    let Some(tree) = tree else {
+        return format!("Process {root} is not running\n");
+    };
+    let pids: Vec<String> = tree.pids.iter().map(u32::to_string).collect();
+    format!(
+        "Subtree of {} ({}): {} kB RSS in {} processes\nPids: {}\n",
+        tree.root,
+        tree.name,
+        tree.rss_kb,
+        tree.pids.len(),
+        pids.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::process;
+
+    fn child(pid: u32, ppid: u32, rss_kb: u64) -> Process {
+        Process {
+            ppid: Some(ppid),
+            ..process(pid, &format!("p{pid}"), rss_kb)
+        }
+    }
+
+    #[test]
+    fn test_subtree_sums_descendants() {
+        // 1 ─ 10 ─┬ 11 ─ 13
+        //         └ 12
+        //   └ 20
+        let procs = [
+            child(1, 0, 1),
+            child(10, 1, 100),
+            child(11, 10, 20),
+            child(12, 10, 30),
+            child(13, 11, 4),
+            child(20, 1, 500),
+        ];
+        let tree = subtree(&procs, 10).unwrap();
+        assert_eq!(
+            (tree.rss_kb, tree.pids.as_slice()),
+            (154, &[10, 11, 13, 12][..])
+        );
+        assert_eq!(
+            format_subtree(Some(&tree), 10),
+            "Subtree of 10 (p10): 154 kB RSS in 4 processes\nPids: 10, 11, 13, 12\n"
+        );
+        assert_eq!(subtree(&procs, 1).unwrap().rss_kb, 655);
+        assert_eq!(subtree(&procs, 99), None);
+        assert_eq!(format_subtree(None, 99), "Process 99 is not running\n");
+    }
+
+    #[test]
+    fn test_subtree_survives_a_parent_cycle() {
+        let procs = [child(5, 6, 10), child(6, 5, 20), child(7, 7, 40)];
+        let tree = subtree(&procs, 5).unwrap();
+        assert_eq!((tree.rss_kb, tree.pids.as_slice()), (30, &[5, 6][..]));
+        assert_eq!(subtree(&procs, 7).unwrap().pids, [7]);
+    }
+}
@@ -0,0 +1,213 @@
+//! System-wide figures: load average, uptime and open file handles.
+
+use std::{fmt::Display, fs, io, sync::OnceLock};
+
+use crate::parse::parse_field;
+
+/// Load averages from `/proc/loadavg`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LoadAvg {
+    pub one: f64,
+    pub five: f64,
+    pub fifteen: f64,
+}
+
+impl LoadAvg {
+    /// Reads `/proc/loadavg` from a path and delegates to parse_from_str.
+    pub fn from_file(path: &str) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        Self::parse_from_str(&content)
+    }
+
+    /// Parses the first three fields of `/proc/loadavg`.
+    ///
+    /// Example content: `0.15 0.10 0.05 1/423 12345`
+    pub fn parse_from_str(content: &str) -> io::Result<Self> {
+        let field = |index| {
+            parse_field(content, "", index).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "invalid loadavg content")
+            })
+        };
+        Ok(Self {
+            one: field(0)?,
+            five: field(1)?,
+            fifteen: field(2)?,
+        })
+    }
+}
+
+/// System-wide file handles from `/proc/sys/fs/file-nr`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileNr {
+    pub allocated: u64,
+    /// Allocated but unused; always 0 since Linux 2.6.
+    pub unused: u64,
+    /// `fs.file-max`
+    pub max: u64,
+}
+
+impl FileNr {
+    /// Reads `/proc/sys/fs/file-nr` from a path and delegates to
+    /// parse_from_str.
+    pub fn from_file(path: &str) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        Self::parse_from_str(&content)
+    }
+
+    /// Parses the three fields of `/proc/sys/fs/file-nr`.
+    ///
+    /// Example content: `1632\t0\t9223372036854775807`
+    pub fn parse_from_str(content: &str) -> io::Result<Self> {
+        let field = |index| {
+            parse_field(content, "", index).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "invalid file-nr content")
+            })
+        };
+        Ok(Self {
+            allocated: field(0)?,
+            unused: field(1)?,
+            max: field(2)?,
+        })
+    }
+
+    /// Allocated handles in percent of the maximum.
+    pub fn used_percent(&self) -> f64 {
+        if self.max == 0 {
+            return 0.0;
+        }
+        self.allocated as f64 * 100.0 / self.max as f64
+    }
+}
+
+/// `FDs: allocated/max`
+impl Display for FileNr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "FDs: {}/{}", self.allocated, self.max)
+    }
+}
+
+/// Reads the system uptime in seconds from `/proc/uptime`.
+pub fn read_uptime(path: &str) -> io::Result<f64> {
+    let content = fs::read_to_string(path)?;
+    parse_uptime(&content)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid uptime content"))
+}
+
+/// Parses the first field of `/proc/uptime` (seconds since boot).
+///
+/// Example content: `350735.47 234388.90`
+pub fn parse_uptime(content: &str) -> Option<f64> {
+    parse_field(content, "", 0)
+}
+
+/// `AT_PAGESZ` entry type in the ELF auxiliary vector.
+const AT_PAGESZ: u64 = 6;
+/// `AT_CLKTCK` entry type in the ELF auxiliary vector.
+const AT_CLKTCK: u64 = 17;
+
+/// Memory page size in bytes, the unit of `/proc/<pid>/statm`.
+///
+/// Read once from `/proc/self/auxv` like [`clock_ticks_per_second`]; falls
+/// back to 4096.
+pub fn page_size() -> u64 {
+    static PAGE_SIZE: OnceLock<u64> = OnceLock::new();
+    *PAGE_SIZE.get_or_init(|| {
+        fs::read("/proc/self/auxv")
+            .ok()
+            .and_then(|auxv| parse_auxv(&auxv, AT_PAGESZ))
+            .unwrap_or(4096)
+    })
+}
+
+/// [`page_size`] in kB, rounded up for a page size that is not a whole
+/// number of kB.
+pub fn page_size_kb() -> u64 {
+    page_size().div_ceil(1024)
+}
+
+/// Converts `kb` into pages of `page_size` bytes. The conversion goes
+/// through bytes, so it is exact for any page size; a last partial page
+/// counts as a page.
+pub fn kb_to_pages(kb: u64, page_size: u64) -> u64 {
+    kb.saturating_mul(1024).div_ceil(page_size.max(1))
+}
+
+/// Clock ticks per second (`sysconf(_SC_CLK_TCK)`), the unit of the `utime`
+/// and `stime` fields in `/proc/<pid>/stat`.
+///
+/// Read once from this process's auxiliary vector in `/proc/self/auxv`,
+/// which is where libc gets it from as well; falls back to the almost
+/// universal 100 Hz.
+pub fn clock_ticks_per_second() -> u64 {
+    static CLK_TCK: OnceLock<u64> = OnceLock::new();
+    *CLK_TCK.get_or_init(|| {
+        fs::read("/proc/self/auxv")
+            .ok()
+            .and_then(|auxv| parse_auxv_clk_tck(&auxv))
+            .unwrap_or(100)
+    })
+}
+
+/// Finds the `AT_CLKTCK` value in the raw content of `/proc/<pid>/auxv`, a
+/// list of native-endian `(type, value)` word pairs.
+pub fn parse_auxv_clk_tck(auxv: &[u8]) -> Option<u64> {
+    parse_auxv(auxv, AT_CLKTCK)
+}
+
+/// Finds the non-zero value of the `kind` entry of an auxiliary vector.
+fn parse_auxv(auxv: &[u8], kind: u64) -> Option<u64> {
+    const WORD: usize = size_of::<usize>();
+    let word = |bytes: &[u8]| usize::from_ne_bytes(bytes.try_into().unwrap()) as u64;
+    auxv.chunks_exact(2 * WORD)
+        .map(|pair| (word(&pair[..WORD]), word(&pair[WORD..])))
+        .find(|&(entry, _)| entry == kind)
+        .map(|(_, value)| value)
+        .filter(|&value| value > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kb_to_pages() {
+        assert_eq!(kb_to_pages(8192, 4096), 2048);
+        assert_eq!(kb_to_pages(0, 4096), 0);
+        assert_eq!(kb_to_pages(1, 4096), 1);
+        assert_eq!(kb_to_pages(10, 3000), 4);
+        assert_eq!(kb_to_pages(64, 65_536), 1);
+    }
+
+    #[test]
+    fn test_parse_auxv_clk_tck() {
+        let auxv: Vec<u8> = [6usize, 4096, 17, 250, 0, 0]
+            .iter()
+            .flat_map(|word| word.to_ne_bytes())
+            .collect();
+        assert_eq!(parse_auxv_clk_tck(&auxv), Some(250));
+        assert_eq!(parse_auxv(&auxv, AT_PAGESZ), Some(4096));
+        assert_eq!(parse_auxv_clk_tck(&auxv[..2 * size_of::<usize>()]), None);
+    }
+
+    #[test]
+    fn test_parse_loadavg_and_uptime() {
+        let load = LoadAvg::parse_from_str("0.15 0.10 0.05 1/423 12345\n").unwrap();
+        assert_eq!((load.one, load.five, load.fifteen), (0.15, 0.10, 0.05));
+        assert!(LoadAvg::parse_from_str("0.15\n").is_err());
+        assert_eq!(parse_uptime("350735.47 234388.90\n"), Some(350735.47));
+    }
+
+    #[test]
+    fn test_parse_file_nr() {
+        let fds = FileNr::parse_from_str("1632\t0\t9223372036854775807\n").unwrap();
+        assert_eq!(
+            (fds.allocated, fds.unused, fds.max),
+            (1632, 0, i64::MAX as u64)
+        );
+        assert_eq!(fds.to_string(), "FDs: 1632/9223372036854775807");
+
+        let fds = FileNr::parse_from_str("95000\t0\t100000\n").unwrap();
+        assert_eq!(fds.used_percent(), 95.0);
+        assert!(FileNr::parse_from_str("1632 0\n").is_err());
+    }
+}
@@ -0,0 +1,165 @@
+//! Wall-clock and duration formatting without a date/time dependency.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::Json;
+
+/// How timestamps are written, selected with `--time-format`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimeFormat {
+    /// `2023-11-14 22:13:20 UTC`
+    #[default]
+    Utc,
+    /// Seconds since the epoch: `1700000000`
+    Epoch,
+    /// Milliseconds since the epoch: `1700000000123`
+    Millis,
+    /// `2023-11-14T22:13:20Z`
+    Rfc3339,
+}
+
+impl TimeFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "utc" => Ok(Self::Utc),
+            "epoch" => Ok(Self::Epoch),
+            "millis" => Ok(Self::Millis),
+            "rfc3339" => Ok(Self::Rfc3339),
+            other => Err(format!(
+                "unknown time format: {other} (expected utc, epoch, millis or rfc3339)"
+            )),
+        }
+    }
+
+    /// Formats `time`; times before the epoch count as the epoch.
+    pub fn format(self, time: SystemTime) -> String {
+        let since = since_epoch(time);
+        match self {
+            Self::Utc => format!("{} UTC", format_utc(time)),
+            Self::Epoch => since.as_secs().to_string(),
+            Self::Millis => since.as_millis().to_string(),
+            Self::Rfc3339 => format!("{}Z", format_utc(time).replace(' ', "T")),
+        }
+    }
+
+    /// `time` as a JSON value: a number for the epoch formats, otherwise a
+    /// string.
+    pub fn json(self, time: SystemTime) -> Json {
+        let since = since_epoch(time);
+        match self {
+            Self::Epoch => Json::UInt(since.as_secs()),
+            Self::Millis => Json::UInt(since.as_millis() as u64),
+            Self::Utc | Self::Rfc3339 => Json::String(self.format(time)),
+        }
+    }
+}
+
+fn since_epoch(time: SystemTime) -> Duration {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+/// Formats `time` as `YYYY-MM-DD HH:MM:SS` in UTC.
+pub fn format_utc(time: SystemTime) -> String {
+    let secs = since_epoch(time).as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let rem = secs % 86_400;
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Converts days since 1970-01-01 into a `(year, month, day)` date.
+///
+/// This is Howard Hinnant's `civil_from_days` algorithm for the proleptic
+/// Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Formats an uptime like `top` does: `45 min`, `2:10` or `3 days,  2:10`.
+pub fn format_uptime(secs: f64) -> String {
+    let total_minutes = (secs.max(0.0) / 60.0) as u64;
+    let days = total_minutes / 1440;
+    let hours = total_minutes % 1440 / 60;
+    let minutes = total_minutes % 60;
+    let clock = if hours == 0 {
+        format!("{minutes} min")
+    } else {
+        format!("{hours}:{minutes:02}")
+    };
+    match days {
+        0 => clock,
+        1 => format!("1 day, {clock:>5}"),
+        _ => format!("{days} days, {clock:>5}"),
+    }
+}
+
+/// Formats accumulated CPU time like the `TIME+` column of `top`:
+/// `MM:SS.cc`, with minutes growing past 59 instead of rolling over into
+/// hours.
+///
+/// `ticks` is in units of `clk_tck` per second.
+pub fn format_cpu_time(ticks: u64, clk_tck: u64) -> String {
+    let clk_tck = clk_tck.max(1);
+    let centis = ticks * 100 / clk_tck;
+    format!(
+        "{}:{:02}.{:02}",
+        centis / 6000,
+        centis / 100 % 60,
+        centis % 100
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_utc() {
+        assert_eq!(format_utc(UNIX_EPOCH), "1970-01-01 00:00:00");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951_782_400 + 3661);
+        assert_eq!(format_utc(leap_day), "2000-02-29 01:01:01");
+    }
+
+    #[test]
+    fn test_time_formats() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        assert_eq!(TimeFormat::Utc.format(time), "2023-11-14 22:13:20 UTC");
+        assert_eq!(TimeFormat::Epoch.format(time), "1700000000");
+        assert_eq!(TimeFormat::Millis.format(time), "1700000000123");
+        assert_eq!(TimeFormat::Rfc3339.format(time), "2023-11-14T22:13:20Z");
+        assert_eq!(TimeFormat::Millis.json(time), Json::UInt(1_700_000_000_123));
+        assert_eq!(
+            TimeFormat::Rfc3339.json(time),
+            Json::String("2023-11-14T22:13:20Z".to_string())
+        );
+        assert!(TimeFormat::parse("iso").is_err());
+    }
+
+    #[test]
+    fn test_format_uptime() {
+        assert_eq!(format_uptime(45.0 * 60.0), "45 min");
+        assert_eq!(format_uptime(2.0 * 3600.0 + 600.0), "2:10");
+        assert_eq!(format_uptime(3.0 * 86_400.0 + 7800.0), "3 days,  2:10");
+    }
+
+    #[test]
+    fn test_format_cpu_time() {
+        assert_eq!(format_cpu_time(0, 100), "0:00.00");
+        assert_eq!(format_cpu_time(325, 100), "0:03.25");
+        assert_eq!(format_cpu_time(6000 * 125 + 340, 100), "125:03.40");
+        assert_eq!(format_cpu_time(1000, 1000), "0:01.00");
+    }
+}
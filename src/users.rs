@@ -0,0 +1,202 @@
+//! Memory use summed per user (`--by-user`).
+
+use std::{collections::HashMap, fs, io};
+
+use crate::Process;
+
+/// Parses `/etc/passwd` into a map from user id to user name.
+///
+/// Lines look like `alice:x:1000:1000:Alice:/home/alice:/bin/bash`; lines
+/// that do not have a numeric third field are skipped.
+pub fn parse_passwd(content: &str) -> HashMap<u32, String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let uid = fields.nth(1)?.parse().ok()?;
+            Some((uid, name.to_string()))
+        })
+        .collect()
+}
+
+/// Reads and parses a passwd file, see [`parse_passwd`].
+pub fn read_passwd(path: &str) -> io::Result<HashMap<u32, String>> {
+    Ok(parse_passwd(&fs::read_to_string(path)?))
+}
+
+/// RSS summed over the processes of one user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserUsage {
+    /// User name, or the numeric uid when it is not in the passwd map.
+    pub user: String,
+    pub rss_kb: u64,
+    pub processes: usize,
+}
+
+/// Name of the row that [`aggregate_by_user`] rolls the smaller users into.
+pub const OTHER_USERS: &str = "(other)";
+
+/// Sums the RSS of `procs` per user, largest first, ties by user name.
+///
+/// Processes whose uid is unknown are grouped under `?`. With `top`, only
+/// the first `top` users are kept and the rest are summed into one
+/// [`OTHER_USERS`] row at the end.
+pub fn aggregate_by_user(
+    procs: &[Process],
+    users: &HashMap<u32, String>,
+    top: Option<usize>,
+) -> Vec<UserUsage> {
+    let mut totals: HashMap<String, (u64, usize)> = HashMap::new();
+    for p in procs {
+        let entry = totals.entry(user_name(p, users)).or_default();
+        entry.0 += p.rss_kb;
+        entry.1 += 1;
+    }
+    let mut usage: Vec<UserUsage> = totals
+        .into_iter()
+        .map(|(user, (rss_kb, processes))| UserUsage {
+            user,
+            rss_kb,
+            processes,
+        })
+        .collect();
+    usage.sort_by(|a, b| b.rss_kb.cmp(&a.rss_kb).then_with(|| a.user.cmp(&b.user)));
+    if let Some(top) = top
+        && usage.len() > top
+    {
+        let rest = usage.split_off(top);
+        usage.push(UserUsage {
+            user: OTHER_USERS.to_string(),
+            rss_kb: rest.iter().map(|u| u.rss_kb).sum(),
+            processes: rest.iter().map(|u| u.processes).sum(),
+        });
+    }
+    usage
+}
+
+/// Keeps the first `k` of the already sorted `procs` for each user
+/// (`--per-user-top`).
+///
+/// Users are grouped by name as [`aggregate_by_user`] resolves it; the groups
+/// follow each other in the order of their first process, so the user with
+/// the top process comes first.
+pub fn top_per_user(procs: Vec<Process>, users: &HashMap<u32, String>, k: usize) -> Vec<Process> {
+    let mut groups: Vec<(String, Vec<Process>)> = Vec::new();
+    for p in procs {
+        let user = user_name(&p, users);
+        match groups.iter_mut().find(|(name, _)| *name == user) {
+            Some((_, group)) => group.push(p),
+            None => groups.push((user, vec![p])),
+        }
+    }
+    groups
+        .into_iter()
+        .flat_map(|(_, group)| group.into_iter().take(k))
+        .collect()
+}
+
+/// The name of the owner of `p`, its numeric uid when that is not in
+/// `users`, or `?` when the uid is unknown.
+fn user_name(p: &Process, users: &HashMap<u32, String>) -> String {
+    match p.uid {
+        Some(uid) => users.get(&uid).cloned().unwrap_or_else(|| uid.to_string()),
+        None => "?".to_string(),
+    }
+}
+
+/// Formats the `--by-user` table.
+pub fn format_user_table(usage: &[UserUsage]) -> String {
+    let mut out = format!("{:<16} {:>10} {:>6}\n", "USER", "RSS(kB)", "PROCS");
+    for u in usage {
+        out.push_str(&format!(
+            "{:<16} {:>10} {:>6}\n",
+            u.user, u.rss_kb, u.processes
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::process;
+
+    #[test]
+    fn test_aggregate_by_user() {
+        let users = parse_passwd(
+            "root:x:0:0:root:/root:/bin/bash\nalice:x:1000:1000::/home/alice:/bin/sh\n# broken\n",
+        );
+        assert_eq!(users.len(), 2);
+        let with_uid = |pid, rss, uid| Process {
+            uid,
+            ..process(pid, "p", rss)
+        };
+        let procs = [
+            with_uid(1, 100, Some(0)),
+            with_uid(2, 300, Some(1000)),
+            with_uid(3, 250, Some(1000)),
+            with_uid(4, 400, Some(1001)),
+            with_uid(5, 50, Some(0)),
+        ];
+        let usage = aggregate_by_user(&procs, &users, None);
+        let rows: Vec<(&str, u64, usize)> = usage
+            .iter()
+            .map(|u| (u.user.as_str(), u.rss_kb, u.processes))
+            .collect();
+        assert_eq!(
+            rows,
+            [("alice", 550, 2), ("1001", 400, 1), ("root", 150, 2)]
+        );
+        assert!(format_user_table(&usage).contains("alice                   550      2\n"));
+    }
+
+    #[test]
+    fn test_aggregate_top_rolls_up_the_rest() {
+        let procs: Vec<Process> = [(0, 500), (1, 400), (2, 30), (2, 20), (3, 40), (4, 10)]
+            .into_iter()
+            .zip(1..)
+            .map(|((uid, rss), pid)| Process {
+                uid: Some(uid),
+                ..process(pid, "p", rss)
+            })
+            .collect();
+        let rows: Vec<(String, u64, usize)> = aggregate_by_user(&procs, &HashMap::new(), Some(2))
+            .into_iter()
+            .map(|u| (u.user, u.rss_kb, u.processes))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("0".to_string(), 500, 1),
+                ("1".to_string(), 400, 1),
+                (OTHER_USERS.to_string(), 100, 4),
+            ]
+        );
+        // No rollup row when everything fits.
+        assert_eq!(aggregate_by_user(&procs, &HashMap::new(), Some(5)).len(), 5);
+    }
+
+    #[test]
+    fn test_top_per_user() {
+        let users = parse_passwd("root:x:0:0::/root:/bin/sh\nalice:x:1000:1000::/:/bin/sh\n");
+        // Sorted by RSS, root owns the three largest.
+        let procs: Vec<Process> = [
+            (1, 900, 0),
+            (2, 800, 0),
+            (3, 700, 0),
+            (4, 300, 1000),
+            (5, 200, 1000),
+        ]
+        .into_iter()
+        .map(|(pid, rss, uid)| Process {
+            uid: Some(uid),
+            ..process(pid, "p", rss)
+        })
+        .collect();
+        let pids = |procs: Vec<Process>| procs.iter().map(|p| p.pid).collect::<Vec<_>>();
+        assert_eq!(pids(top_per_user(procs.clone(), &users, 1)), [1, 4]);
+        assert_eq!(pids(top_per_user(procs.clone(), &users, 2)), [1, 2, 4, 5]);
+        assert_eq!(pids(top_per_user(procs, &users, 0)), [0u32; 0]);
+    }
+}
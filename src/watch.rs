@@ -0,0 +1,156 @@
+//! Following a fixed list of processes (`--watch`).
+
+use std::collections::HashSet;
+
+use crate::{
+    Options, Process,
+    render::{ColumnRegistry, ProcColumn, format_row},
+};
+
+/// The processes given to `--watch`, and which of them have exited.
+///
+/// The list keeps its order and length for the whole run so the table does
+/// not change shape. A pid stays exited once it has gone, even if the kernel
+/// later reuses it for an unrelated process.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WatchSet {
+    pids: Vec<u32>,
+    exited: HashSet<u32>,
+}
+
+/// One row of the watch table.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Watched {
+    Running(Box<Process>),
+    Exited(u32),
+}
+
+impl WatchSet {
+    pub fn new(pids: Vec<u32>) -> Self {
+        Self {
+            pids,
+            exited: HashSet::new(),
+        }
+    }
+
+    /// Watched pids that have not exited yet, in watch order.
+    pub fn running(&self) -> impl Iterator<Item = u32> + '_ {
+        self.pids
+            .iter()
+            .copied()
+            .filter(|pid| !self.exited.contains(pid))
+    }
+
+    /// Matches this frame's `procs` against the list, marking every watched
+    /// process missing from them as exited, and returns one row per pid.
+    pub fn update(&mut self, procs: Vec<Process>) -> Vec<Watched> {
+        let mut procs: Vec<Option<Process>> = procs.into_iter().map(Some).collect();
+        let mut rows = Vec::with_capacity(self.pids.len());
+        for &pid in &self.pids {
+            let found = (!self.exited.contains(&pid))
+                .then(|| {
+                    procs
+                        .iter_mut()
+                        .find(|p| p.as_ref().is_some_and(|p| p.pid == pid))
+                })
+                .flatten()
+                .and_then(Option::take);
+            match found {
+                Some(p) => rows.push(Watched::Running(Box::new(p))),
+                None => {
+                    self.exited.insert(pid);
+                    rows.push(Watched::Exited(pid));
+                }
+            }
+        }
+        rows
+    }
+
+    pub fn all_exited(&self) -> bool {
+        self.pids.iter().all(|pid| self.exited.contains(pid))
+    }
+
+    pub fn len(&self) -> usize {
+        self.pids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pids.is_empty()
+    }
+}
+
+/// Formats the watch table with the columns chosen by `options`: running
+/// processes as usual, exited ones as their pid followed by `exited`.
+pub fn format_watch_table(
+    rows: &[Watched],
+    registry: &ColumnRegistry,
+    options: &Options,
+) -> String {
+    let names = options.column_names();
+    let columns: Vec<&dyn ProcColumn> = names.iter().filter_map(|n| registry.get(n)).collect();
+    let header: Vec<String> = columns.iter().map(|c| c.header().to_string()).collect();
+    // Where the `exited` marker goes: the name column, or else the first
+    // column that is not the pid.
+    let marker = columns
+        .iter()
+        .position(|c| c.name() == "name")
+        .or_else(|| columns.iter().position(|c| c.name() != "pid"));
+    let mut out = format_row(&columns, &header, options.field_separator);
+    out.push('\n');
+    for row in rows {
+        let cells: Vec<String> = match row {
+            Watched::Running(p) => columns.iter().map(|c| c.render(p)).collect(),
+            Watched::Exited(pid) => columns
+                .iter()
+                .enumerate()
+                .map(|(i, c)| match c.name() {
+                    "pid" => pid.to_string(),
+                    _ if Some(i) == marker => "exited".to_string(),
+                    _ => String::new(),
+                })
+                .collect(),
+        };
+        let line = format_row(&columns, &cells, options.field_separator);
+        match (row, options.field_separator) {
+            (Watched::Exited(_), None) => out.push_str(line.trim_end()),
+            _ => out.push_str(&line),
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::process;
+
+    #[test]
+    fn test_watch_set_keeps_exited_rows() {
+        let mut watch = WatchSet::new(vec![7, 42]);
+        let rows = watch.update(vec![process(42, "sshd", 10), process(7, "bash", 20)]);
+        assert_eq!(
+            rows,
+            [
+                Watched::Running(Box::new(process(7, "bash", 20))),
+                Watched::Running(Box::new(process(42, "sshd", 10)))
+            ]
+        );
+
+        let rows = watch.update(vec![process(42, "sshd", 11)]);
+        assert_eq!(rows[0], Watched::Exited(7));
+        assert!(!watch.all_exited());
+        assert_eq!(watch.running().collect::<Vec<_>>(), [42]);
+
+        // A reused pid does not bring the row back.
+        let rows = watch.update(vec![process(7, "other", 1)]);
+        assert_eq!(rows, [Watched::Exited(7), Watched::Exited(42)]);
+        assert!(watch.all_exited());
+
+        let table = format_watch_table(&rows, &ColumnRegistry::default(), &Options::default());
+        assert_eq!(
+            table,
+            "PID    NAME                    RSS(kB)\n7      exited\n42     exited\n"
+        );
+    }
+}